@@ -0,0 +1,14 @@
+use std::process::Command;
+
+fn main() {
+    let git_commit = Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=AGENT_HUB_GIT_COMMIT={}", git_commit);
+    println!("cargo:rerun-if-changed=.git/HEAD");
+}
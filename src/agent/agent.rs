@@ -2,23 +2,39 @@ use std::net::SocketAddr;
 use std::ops::Drop;
 use std::sync::Arc;
 use std::sync::{RwLock, RwLockReadGuard};
-use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use chrono;
+use jsonrpc_core::types::ErrorCode;
 use jsonrpc_core::Output;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json;
 use serde_json::Value;
+use ws;
 use ws::CloseCode as WSCloseCode;
 
 use super::super::common_rpc_types::{
-    BlockId, HardwareInfo, NodeName, NodeStatus, NodeVersion, ShellStartCodeChainRequest, ShellUpdateCodeChainRequest,
+    AgentCapability, AgentVersion, BlockId, HardwareInfo, NodeClock, NodeName, NodeStatus, NodeVersion,
+    ShellStartCodeChainRequest, ShellUpdateCodeChainRequest, StructuredLog,
 };
 use super::super::db;
 use super::super::jsonrpc;
-use super::super::rpc::RPCResult;
+use super::super::jsonrpc::{Cancellation, Priority};
+use super::super::rpc::{RPCError, RPCResult};
 use super::codechain_rpc::CodeChainRPC;
+use super::handler::send_backpressure;
+use super::log_inbox::{LogInbox, LogInboxMetrics};
+use super::protocol;
 use super::service::{Message as ServiceMessage, ServiceSender};
-use super::types::{AgentGetInfoResponse, CodeChainCallRPCResponse};
+use super::types::{
+    AgentGetInfoResponse, AgentHandshakeRequest, AgentHandshakeResponse, BinaryChunkRequest, BinaryInstallRequest,
+    ChaosBlockPeersRequest, ChaosPauseRequest, CodeChainCallRPCResponse, CommandTimeouts, LogChecksumResponse,
+    LogRangeRequest, LogRangeResponse, SnapshotInfo,
+};
+
+/// The number of the pushed logs which are written on each update of an agent
+const LOGS_PER_UPDATE: usize = 1000;
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum State {
@@ -89,19 +105,117 @@ impl State {
 pub struct AgentSender {
     jsonrpc_context: jsonrpc::Context,
     state: Arc<RwLock<State>>,
+    log_inbox: Arc<LogInbox>,
+    /// Set by the handshake
+    capabilities: Arc<RwLock<Option<Vec<AgentCapability>>>>,
+    timeouts: Arc<CommandTimeouts>,
+    /// The name in the state, which is kept apart because the agent worker holds the state while it calls RPCs.
+    /// None until the agent is initialized
+    name: Arc<RwLock<Option<NodeName>>>,
+    /// The calls time out when it passes. Only the sender of the agent worker has it, to bound an update step
+    deadline: Option<Instant>,
 }
 
 impl AgentSender {
-    pub fn new(jsonrpc_context: jsonrpc::Context, state: Arc<RwLock<State>>) -> Self {
+    pub fn new(
+        jsonrpc_context: jsonrpc::Context,
+        state: Arc<RwLock<State>>,
+        log_inbox: Arc<LogInbox>,
+        timeouts: Arc<CommandTimeouts>,
+    ) -> Self {
         Self {
             jsonrpc_context,
             state,
+            log_inbox,
+            capabilities: Default::default(),
+            timeouts,
+            name: Default::default(),
+            deadline: None,
+        }
+    }
+
+    /// Changes the name in the state after the node is renamed.
+    pub fn rename(&self, to: &NodeName) {
+        let mut state = self.state.write().expect("Should success writing state");
+        match *state {
+            State::Initializing => {}
+            State::Normal {
+                ref mut name,
+                ..
+            } => *name = to.clone(),
+            State::Stop {
+                ref mut name,
+                ..
+            } => *name = to.clone(),
+        }
+        if state.name().is_some() {
+            self.set_name(to);
         }
     }
 
     pub fn read_state(&self) -> RwLockReadGuard<State> {
         self.state.read().expect("Should success reading state")
     }
+
+    pub fn channel_metrics(&self) -> jsonrpc::ChannelMetrics {
+        self.jsonrpc_context.channel_metrics()
+    }
+
+    pub fn log_inbox_metrics(&self) -> LogInboxMetrics {
+        self.log_inbox.metrics()
+    }
+
+    /// Fails if the agent advertised its capabilities without the given one
+    pub fn require(&self, capability: AgentCapability) -> RPCResult<()> {
+        let capabilities = self.capabilities.read().expect("Should success reading capabilities");
+        match &*capabilities {
+            Some(capabilities) if !capabilities.contains(&capability) => {
+                let node_name = self.state.try_read().ok().and_then(|state| state.name());
+                Err(RPCError::CapabilityNotSupported(node_name, capability))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// True only if the agent advertised the capability. The agents older than the capability discovery have none
+    pub fn has(&self, capability: AgentCapability) -> bool {
+        let capabilities = self.capabilities.read().expect("Should success reading capabilities");
+        capabilities.as_ref().map_or(false, |capabilities| capabilities.contains(&capability))
+    }
+
+    /// Asks the agent to stop the job which it reports with job_progress
+    pub fn cancel_job(&self, job_id: u64) {
+        let notification = jsonrpc::serialize_notification("job_cancel", json!({ "jobId": job_id }));
+        self.jsonrpc_context.notify(Priority::Control, notification);
+    }
+
+    /// The agent worker may hold the write lock of the state while it calls RPCs, so it doesn't wait for the lock.
+    fn call_error(&self, err: jsonrpc::CallError) -> RPCError {
+        let node_name = self.state.try_read().ok().and_then(|state| state.name());
+        RPCError::from_agent_call(node_name, err)
+    }
+
+    /// Closes the connection as if the agent is disconnected.
+    pub fn drop_connection(&self) -> Result<(), String> {
+        self.close("Dropped by the injected fault")
+    }
+
+    pub fn is_connected_through(&self, connection: &ws::Sender) -> bool {
+        self.jsonrpc_context.ws_sender == *connection
+    }
+
+    /// None until the agent is initialized
+    pub fn name(&self) -> Option<NodeName> {
+        self.name.read().expect("Should success reading name").clone()
+    }
+
+    fn set_name(&self, name: &NodeName) {
+        *self.name.write().expect("Should success writing name") = Some(name.clone());
+    }
+
+    pub fn close(&self, reason: &str) -> Result<(), String> {
+        self.jsonrpc_context.ws_sender.close_with_reason(WSCloseCode::Away, reason).map_err(|err| format!("{}", err))
+    }
 }
 
 pub struct Agent {
@@ -110,8 +224,16 @@ pub struct Agent {
     state: Arc<RwLock<State>>,
     service_sender: ServiceSender,
     closed: bool,
+    /// Set after the agent is added to the service
+    started: bool,
     db_service: db::ServiceSender,
     codechain_rpc: CodeChainRPC,
+    /// Set by the handshake
+    agent_version: Option<AgentVersion>,
+    log_inbox: Arc<LogInbox>,
+    max_clock_skew_ms: i64,
+    /// The offset measured by the last round trip which was short enough
+    clock_offset_ms: Option<i64>,
 }
 
 pub enum AgentCleanupReason {
@@ -122,85 +244,108 @@ pub enum AgentCleanupReason {
 }
 
 impl Agent {
-    fn new(
+    pub fn new(
         id: i32,
         jsonrpc_context: jsonrpc::Context,
+        log_inbox: Arc<LogInbox>,
         service_sender: ServiceSender,
         db_service: db::ServiceSender,
+        timeouts: Arc<CommandTimeouts>,
+        max_clock_skew_ms: i64,
     ) -> Self {
         let state = Arc::new(RwLock::new(State::new()));
-        let sender = AgentSender::new(jsonrpc_context, Arc::clone(&state));
+        let sender = AgentSender::new(jsonrpc_context, Arc::clone(&state), Arc::clone(&log_inbox), timeouts);
         Self {
             id,
             state,
             sender: sender.clone(),
             service_sender,
             closed: false,
+            started: false,
             db_service,
             codechain_rpc: CodeChainRPC::new(sender),
+            agent_version: None,
+            log_inbox,
+            max_clock_skew_ms,
+            clock_offset_ms: None,
         }
     }
 
-    pub fn run_thread(
-        id: i32,
-        jsonrpc_context: jsonrpc::Context,
-        service_sender: ServiceSender,
-        db_service: db::ServiceSender,
-    ) -> AgentSender {
-        let mut agent = Self::new(id, jsonrpc_context, service_sender, db_service);
-        let sender = agent.sender.clone();
-
-        thread::Builder::new()
-            .name(format!("agent-{}", id))
-            .spawn(move || match agent.run() {
-                Ok(StopCause::AlreadyConnected) => {
-                    agent.clean_up(AgentCleanupReason::AlreadyConnected);
-                }
-                Err(err) => {
-                    cerror!("Agent failed : {}", err);
-                    agent.clean_up(AgentCleanupReason::Error(err));
-                }
-            })
-            .expect("Should success running agent thread");
-
-        sender
+    /// Runs a step of the agent and returns the cause if the agent stops.
+    /// The first step registers the agent to the service after the handshake.
+    /// The calls of the step time out at the deadline, so an unresponsive agent doesn't hold the worker longer.
+    pub fn step(&mut self, deadline: Instant) -> Result<Option<StopCause>, String> {
+        self.set_deadline(Some(deadline));
+        let result = self.run_step();
+        self.set_deadline(None);
+        result
     }
 
-    fn run(&mut self) -> Result<StopCause, String> {
-        cinfo!("Agent-{} started", self.id);
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.sender.deadline = deadline;
+        self.codechain_rpc = CodeChainRPC::new(self.sender.clone());
+    }
 
+    fn run_step(&mut self) -> Result<Option<StopCause>, String> {
+        if !self.started {
+            cinfo!("Agent-{} started", self.id);
+            self.handshake()?;
+        }
+        ctrace!("Agent-{} update", self.id);
         self.update()?;
         if let State::Stop {
             cause,
             ..
         } = *self.state.read().unwrap()
         {
-            return Ok(cause)
+            return Ok(Some(cause))
         }
-        self.service_sender
-            .send(ServiceMessage::AddAgent(self.id, self.sender.clone()))
-            .map_err(|err| format!("AddAgent failed {}", err))?;
-
-        loop {
-            ctrace!("Agent-{} update", self.id);
-            self.update()?;
-            if let State::Stop {
-                cause,
-                ..
-            } = *self.state.read().unwrap()
-            {
-                return Ok(cause)
+        if !self.started {
+            self.started = true;
+            // The others call the agent without the deadline of the step
+            let mut sender = self.sender.clone();
+            sender.deadline = None;
+            self.service_sender
+                .send(ServiceMessage::AddAgent(self.id, sender))
+                .map_err(|err| format!("AddAgent failed {}", err))?;
+        }
+        Ok(None)
+    }
+
+    /// Rejects the agent if there is no protocol which both of the hub and the agent speak.
+    fn handshake(&mut self) -> Result<(), String> {
+        let response = match self.sender.agent_handshake(protocol::handshake_request()) {
+            Ok(response) => Some(response),
+            Err(RPCError::FromAgent(_, ref err)) if err.code == ErrorCode::MethodNotFound => {
+                cdebug!("Agent-{} doesn't support the handshake", self.id);
+                None
             }
-            thread::sleep(Duration::new(1, 0));
+            Err(err) => return Err(format!("Handshake failed : {}", err)),
+        };
+        let agent_version = protocol::negotiate(response).map_err(|err| format!("Incompatible agent : {}", err))?;
+        if agent_version.degraded {
+            cwarn!(
+                "Agent-{} speaks the old protocol {}. Some features are unavailable",
+                self.id,
+                agent_version.protocol_version
+            );
         }
+        *self.sender.capabilities.write().expect("Should success writing capabilities") =
+            agent_version.capabilities.clone();
+        self.agent_version = Some(agent_version);
+        Ok(())
     }
 
     fn update(&mut self) -> Result<(), String> {
+        let requested_at = chrono::Utc::now();
         let info = self.sender.agent_get_info().map_err(|err| format!("{}", err))?;
+        let clock = self.clock_of(&info, requested_at);
+        // The agent of a renamed node still reports the old name
+        let name = self.db_service.resolve_name(&info.name);
 
         let mut state = self.state.write().expect("Should success getting agent state");
         let new_state = State::Normal {
-            name: info.name.clone(),
+            name: name.clone(),
             address: info.address,
             status: info.status,
         };
@@ -209,20 +354,21 @@ impl Agent {
             let success = self
                 .db_service
                 .initialize_agent_query_result(db::AgentQueryResult {
-                    name: info.name.clone(),
+                    name: name.clone(),
                     status: info.status,
                     address: info.address,
                     version: Some(NodeVersion {
                         version: String::new(),
                         hash: info.codechain_commit_hash,
                     }),
+                    agent_version: self.agent_version.clone(),
                     ..Default::default()
                 })
                 .map_err(|_| "DB timeout")?;
 
             if !success {
                 *state = State::Stop {
-                    name: info.name.clone(),
+                    name: name.clone(),
                     address: info.address,
                     status: info.status,
                     cause: StopCause::AlreadyConnected,
@@ -231,6 +377,8 @@ impl Agent {
             }
 
             *state = new_state;
+            self.sender.set_name(&name);
+            self.backfill_logs(&name);
             return Ok(())
         }
 
@@ -255,7 +403,7 @@ impl Agent {
 
         ctrace!("Update state from {:?} to {:?}", state, new_state);
         self.db_service.update_agent_query_result(db::AgentQueryResult {
-            name: info.name.clone(),
+            name: name.clone(),
             status: info.status,
             address: info.address,
             peers,
@@ -265,18 +413,88 @@ impl Agent {
             whitelist,
             blacklist,
             hardware: Some(hardware),
+            agent_version: self.agent_version.clone(),
+            clock,
+            // The service keeps what it knows
+            sealing: None,
         });
         *state = new_state;
 
-        let logs = self.codechain_rpc.get_logs(info.status)?;
-        if let Some(logs) = logs {
-            self.db_service.write_logs(&info.name, logs);
+        if self.pushes_logs() {
+            self.write_pushed_logs(&name);
+        } else if let Some(logs) = self.codechain_rpc.get_logs(info.status)? {
+            self.db_service.write_logs(&name, logs);
         }
 
         Ok(())
     }
 
-    fn clean_up(&mut self, reason: AgentCleanupReason) {
+    /// The system time of the host is compared with the middle of the round trip of agent_getInfo.
+    /// The error of the offset is up to the half of the round trip, so the offset of a round trip longer than
+    /// `max_clock_skew_ms` is discarded and the last offset is kept.
+    fn clock_of(
+        &mut self,
+        info: &AgentGetInfoResponse,
+        requested_at: chrono::DateTime<chrono::Utc>,
+    ) -> Option<NodeClock> {
+        let system_time = info.system_time?;
+        let responded_at = chrono::Utc::now();
+        let round_trip = responded_at - requested_at;
+        if round_trip.num_milliseconds() <= self.max_clock_skew_ms {
+            let hub_time = requested_at + round_trip / 2;
+            self.clock_offset_ms = Some((system_time - hub_time).num_milliseconds());
+        } else {
+            let round_trip_ms = round_trip.num_milliseconds();
+            cdebug!("Agent-{} discards the clock sample of the round trip {} ms", self.id, round_trip_ms);
+        }
+        let offset_ms = self.clock_offset_ms?;
+        let exceeds = |offset_ms: i64| offset_ms.abs() > self.max_clock_skew_ms;
+        Some(NodeClock {
+            offset_ms,
+            ntp_offset_ms: info.ntp_offset_ms,
+            skewed: exceeds(offset_ms) || info.ntp_offset_ms.map_or(false, exceeds),
+        })
+    }
+
+    fn pushes_logs(&self) -> bool {
+        self.agent_version
+            .as_ref()
+            .map(|version| version.protocol_version >= protocol::LOG_PUSH_PROTOCOL_VERSION)
+            .unwrap_or(false)
+    }
+
+    /// Writes a batch of the pushed logs, so a noisy node writes at most LOGS_PER_UPDATE logs per update.
+    fn write_pushed_logs(&self, name: &NodeName) {
+        let drained = self.log_inbox.drain(LOGS_PER_UPDATE);
+        if drained.dropped > 0 {
+            cwarn!("Agent-{} dropped {} logs because the hub couldn't keep up", self.id, drained.dropped);
+        }
+        if !drained.logs.is_empty() {
+            self.db_service.write_logs(name, drained.logs);
+        }
+        if let Some(backpressure) = drained.backpressure {
+            send_backpressure(&self.sender.jsonrpc_context, backpressure);
+        }
+    }
+
+    /// Writes the logs which the agent buffered while the hub was unreachable.
+    /// The agents don't buffer the metrics, so the metric history has a gap for the time.
+    fn backfill_logs(&self, name: &NodeName) {
+        match self.sender.log_get_buffered() {
+            Ok(logs) => {
+                if !logs.is_empty() {
+                    cinfo!("Agent-{} backfills {} logs", self.id, logs.len());
+                    self.db_service.write_backfilled_logs(name, logs);
+                }
+            }
+            Err(RPCError::FromAgent(_, ref err)) if err.code == ErrorCode::MethodNotFound => {
+                cdebug!("Agent-{} doesn't support log buffering", self.id);
+            }
+            Err(err) => cwarn!("Agent-{} failed to send buffered logs : {}", self.id, err),
+        }
+    }
+
+    pub fn clean_up(&mut self, reason: AgentCleanupReason) {
         if self.closed {
             return
         }
@@ -310,7 +528,7 @@ impl Agent {
             ..
         } = state.clone()
         {
-            self.db_service.update_agent_query_result(db::AgentQueryResult {
+            self.db_service.disconnect_agent(db::AgentQueryResult {
                 name,
                 status: NodeStatus::Error,
                 address,
@@ -330,6 +548,7 @@ impl Agent {
         if let Err(err) = ws_close_result {
             cerror!("Agent cleanup error {}", err);
         }
+        self.sender.jsonrpc_context.close();
     }
 }
 
@@ -342,54 +561,155 @@ impl Drop for Agent {
 pub trait SendAgentRPC {
     fn shell_start_codechain(&self, _req: ShellStartCodeChainRequest) -> RPCResult<()>;
     fn shell_stop_codechain(&self) -> RPCResult<()>;
-    fn shell_update_codechain(&self, _req: ShellUpdateCodeChainRequest) -> RPCResult<()>;
+    fn shell_update_codechain(
+        &self,
+        _req: ShellUpdateCodeChainRequest,
+        cancellation: Option<&Cancellation>,
+    ) -> RPCResult<()>;
     fn shell_get_codechain_log(&self) -> RPCResult<String>;
+    fn shell_get_codechain_log_range(&self, req: LogRangeRequest) -> RPCResult<LogRangeResponse>;
+    fn shell_get_codechain_log_checksum(&self) -> RPCResult<LogChecksumResponse>;
+    fn agent_handshake(&self, req: AgentHandshakeRequest) -> RPCResult<AgentHandshakeResponse>;
     fn agent_get_info(&self) -> RPCResult<AgentGetInfoResponse>;
     fn codechain_call_rpc_raw(&self, args: (String, Vec<Value>)) -> RPCResult<CodeChainCallRPCResponse>;
-    fn codechain_call_rpc(&self, args: (String, Vec<Value>)) -> RPCResult<Output>;
+    fn codechain_call_rpc(&self, priority: Priority, args: (String, Vec<Value>)) -> RPCResult<Output>;
     fn hardware_get(&self) -> RPCResult<HardwareInfo>;
+    fn log_get_buffered(&self) -> RPCResult<Vec<StructuredLog>>;
+    fn snapshot_list(&self) -> RPCResult<Vec<SnapshotInfo>>;
+    fn snapshot_restore(&self, id: String) -> RPCResult<()>;
+    fn binary_upload_chunk(&self, req: BinaryChunkRequest, cancellation: Option<&Cancellation>) -> RPCResult<()>;
+    fn binary_install(&self, req: BinaryInstallRequest, cancellation: Option<&Cancellation>) -> RPCResult<()>;
+    fn chaos_kill(&self) -> RPCResult<()>;
+    fn chaos_pause(&self, req: ChaosPauseRequest) -> RPCResult<()>;
+    fn chaos_block_peers(&self, req: ChaosBlockPeersRequest) -> RPCResult<()>;
+}
+
+impl AgentSender {
+    /// Waits for the response in the timeout of the method
+    fn call<Arg, Res>(
+        &self,
+        priority: Priority,
+        method: &str,
+        args: Arg,
+        cancellation: Option<&Cancellation>,
+    ) -> RPCResult<Res>
+    where
+        Arg: Serialize,
+        Res: DeserializeOwned, {
+        let mut timeout = self.timeouts.of(method);
+        if let Some(deadline) = self.deadline {
+            let now = Instant::now();
+            if deadline <= now {
+                return Err(self.call_error(jsonrpc::CallError::Timeout(Duration::from_secs(0))))
+            }
+            timeout = timeout.min(deadline - now);
+        }
+        jsonrpc::call_with_timeout(self.jsonrpc_context.clone(), priority, method, args, timeout, cancellation)
+            .map_err(|err| self.call_error(err))
+    }
 }
 
 impl SendAgentRPC for AgentSender {
     fn shell_start_codechain(&self, req: ShellStartCodeChainRequest) -> RPCResult<()> {
-        jsonrpc::call_one_arg(self.jsonrpc_context.clone(), "shell_startCodeChain", req)?;
+        self.call::<_, Value>(Priority::Control, "shell_startCodeChain", vec![req], None)?;
         Ok(())
     }
 
     fn shell_stop_codechain(&self) -> RPCResult<()> {
-        jsonrpc::call_no_arg(self.jsonrpc_context.clone(), "shell_stopCodeChain")?;
+        self.call::<_, Value>(Priority::Control, "shell_stopCodeChain", vec![Value::Null], None)?;
         Ok(())
     }
 
-    fn shell_update_codechain(&self, args: ShellUpdateCodeChainRequest) -> RPCResult<()> {
-        jsonrpc::call_one_arg(self.jsonrpc_context.clone(), "shell_updateCodeChain", args)?;
+    fn shell_update_codechain(
+        &self,
+        args: ShellUpdateCodeChainRequest,
+        cancellation: Option<&Cancellation>,
+    ) -> RPCResult<()> {
+        self.call::<_, Value>(Priority::Control, "shell_updateCodeChain", vec![args], cancellation)?;
         Ok(())
     }
 
     fn shell_get_codechain_log(&self) -> RPCResult<String> {
-        let message = jsonrpc::call_no_arg(self.jsonrpc_context.clone(), "shell_getCodeChainLog")?;
-        Ok(message)
+        self.call(Priority::Telemetry, "shell_getCodeChainLog", vec![Value::Null], None)
+    }
+
+    fn shell_get_codechain_log_range(&self, req: LogRangeRequest) -> RPCResult<LogRangeResponse> {
+        self.call(Priority::Telemetry, "shell_getCodeChainLogRange", vec![req], None)
+    }
+
+    fn shell_get_codechain_log_checksum(&self) -> RPCResult<LogChecksumResponse> {
+        self.call(Priority::Telemetry, "shell_getCodeChainLogChecksum", vec![Value::Null], None)
+    }
+
+    fn agent_handshake(&self, req: AgentHandshakeRequest) -> RPCResult<AgentHandshakeResponse> {
+        self.call(Priority::Control, "agent_handshake", vec![req], None)
     }
 
     fn agent_get_info(&self) -> RPCResult<AgentGetInfoResponse> {
-        let result: AgentGetInfoResponse = jsonrpc::call_no_arg(self.jsonrpc_context.clone(), "agent_getInfo")?;
-        Ok(result)
+        self.call(Priority::Telemetry, "agent_getInfo", vec![Value::Null], None)
     }
 
     fn codechain_call_rpc_raw(&self, args: (String, Vec<Value>)) -> RPCResult<CodeChainCallRPCResponse> {
-        let result = jsonrpc::call_many_args(self.jsonrpc_context.clone(), "codechain_callRPC", args)?;
-        Ok(result)
+        self.call(Priority::Telemetry, "codechain_callRPC", args, None)
     }
 
-    fn codechain_call_rpc(&self, args: (String, Vec<Value>)) -> RPCResult<Output> {
-        let result: CodeChainCallRPCResponse =
-            jsonrpc::call_many_args(self.jsonrpc_context.clone(), "codechain_callRPC", args)?;
+    fn codechain_call_rpc(&self, priority: Priority, args: (String, Vec<Value>)) -> RPCResult<Output> {
+        let result: CodeChainCallRPCResponse = self.call(priority, "codechain_callRPC", args, None)?;
         let output: Output = serde_json::from_value(result.inner_response)?;
         Ok(output)
     }
 
     fn hardware_get(&self) -> RPCResult<HardwareInfo> {
-        let result = jsonrpc::call_no_arg(self.jsonrpc_context.clone(), "hardware_get")?;
-        Ok(result)
+        self.call(Priority::Telemetry, "hardware_get", vec![Value::Null], None)
+    }
+
+    fn log_get_buffered(&self) -> RPCResult<Vec<StructuredLog>> {
+        self.call(Priority::Telemetry, "log_getBuffered", vec![Value::Null], None)
+    }
+
+    fn snapshot_list(&self) -> RPCResult<Vec<SnapshotInfo>> {
+        self.require(AgentCapability::Snapshot)?;
+        self.call(Priority::Control, "snapshot_list", vec![Value::Null], None)
+    }
+
+    /// Replaces the chain data of the stopped node with the snapshot
+    fn snapshot_restore(&self, id: String) -> RPCResult<()> {
+        self.require(AgentCapability::Snapshot)?;
+        self.call::<_, Value>(Priority::Control, "snapshot_restore", vec![id], None)?;
+        Ok(())
+    }
+
+    /// The chunks go in the telemetry lane, so they don't delay the control requests
+    fn binary_upload_chunk(&self, req: BinaryChunkRequest, cancellation: Option<&Cancellation>) -> RPCResult<()> {
+        self.require(AgentCapability::BinaryInstall)?;
+        self.call::<_, Value>(Priority::Telemetry, "binary_uploadChunk", vec![req], cancellation)?;
+        Ok(())
+    }
+
+    fn binary_install(&self, req: BinaryInstallRequest, cancellation: Option<&Cancellation>) -> RPCResult<()> {
+        self.require(AgentCapability::BinaryInstall)?;
+        self.call::<_, Value>(Priority::Control, "binary_install", vec![req], cancellation)?;
+        Ok(())
+    }
+
+    /// SIGKILLs CodeChain. The agent doesn't restart it
+    fn chaos_kill(&self) -> RPCResult<()> {
+        self.require(AgentCapability::Chaos)?;
+        self.call::<_, Value>(Priority::Control, "chaos_kill", vec![Value::Null], None)?;
+        Ok(())
+    }
+
+    /// The agent resumes CodeChain by itself, so the node recovers even if the hub goes down meanwhile
+    fn chaos_pause(&self, req: ChaosPauseRequest) -> RPCResult<()> {
+        self.require(AgentCapability::Chaos)?;
+        self.call::<_, Value>(Priority::Control, "chaos_pause", vec![req], None)?;
+        Ok(())
+    }
+
+    /// The agent unblocks the peers by itself like chaos_pause
+    fn chaos_block_peers(&self, req: ChaosBlockPeersRequest) -> RPCResult<()> {
+        self.require(AgentCapability::Chaos)?;
+        self.call::<_, Value>(Priority::Control, "chaos_blockPeers", vec![req], None)?;
+        Ok(())
     }
 }
@@ -1,10 +1,12 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 
 use jsonrpc_core::types::{Failure, Output, Success};
 use serde::de::DeserializeOwned;
 use serde_json;
+use serde_json::Value;
 
 use super::super::common_rpc_types::{BlackList, BlockId, NodeStatus, PendingParcel, StructuredLog, WhiteList};
+use super::super::jsonrpc::Priority;
 use super::agent::{AgentSender, SendAgentRPC};
 use super::types::ChainGetBestBlockIdResponse;
 
@@ -57,7 +59,58 @@ impl CodeChainRPC {
         self.call_rpc(status, "slog")
     }
 
+    pub fn get_coinbase(&self, status: NodeStatus) -> Result<Option<String>, String> {
+        self.call_rpc(status, "engine_getCoinbase")
+    }
+
+    pub fn get_block_author(&self, status: NodeStatus, block_number: i64) -> Result<Option<String>, String> {
+        let params = vec![json!(block_number)];
+        let block: Option<Value> =
+            self.call_rpc_with_params(status, Priority::Telemetry, "chain_getBlockByNumber", params)?;
+        Ok(block.and_then(|block| block["author"].as_str().map(|author| author.to_string())))
+    }
+
+    pub fn net_connect(&self, status: NodeStatus, address: &SocketAddr) -> Result<(), String> {
+        let params = vec![json!(address.ip()), json!(address.port())];
+        self.call_rpc_with_params(status, Priority::Control, "net_connect", params)
+    }
+
+    pub fn net_disconnect(&self, status: NodeStatus, address: &SocketAddr) -> Result<(), String> {
+        let params = vec![json!(address.ip()), json!(address.port())];
+        self.call_rpc_with_params(status, Priority::Control, "net_disconnect", params)
+    }
+
+    pub fn add_to_whitelist(&self, status: NodeStatus, ip: &IpAddr, tag: &str) -> Result<(), String> {
+        self.call_rpc_with_params(status, Priority::Control, "net_addToWhitelist", vec![json!(ip), json!(tag)])
+    }
+
+    /// `filter` is what RUST_LOG accepts, e.g. "trace" or "sync=trace,info"
+    pub fn set_log_level(&self, status: NodeStatus, filter: &str) -> Result<(), String> {
+        self.call_rpc_with_params(status, Priority::Control, "debug_setLogLevel", vec![json!(filter)])
+    }
+
+    pub fn start_sealing(&self, status: NodeStatus) -> Result<(), String> {
+        self.call_rpc_with_params(status, Priority::Control, "devel_startSealing", Vec::new())
+    }
+
+    /// The node keeps syncing and relaying, but doesn't propose or seal the blocks
+    pub fn stop_sealing(&self, status: NodeStatus) -> Result<(), String> {
+        self.call_rpc_with_params(status, Priority::Control, "devel_stopSealing", Vec::new())
+    }
+
     fn call_rpc<T>(&self, status: NodeStatus, method: &str) -> Result<T, String>
+    where
+        T: Default + DeserializeOwned, {
+        self.call_rpc_with_params(status, Priority::Telemetry, method, Vec::new())
+    }
+
+    fn call_rpc_with_params<T>(
+        &self,
+        status: NodeStatus,
+        priority: Priority,
+        method: &str,
+        params: Vec<Value>,
+    ) -> Result<T, String>
     where
         T: Default + DeserializeOwned, {
         if status != NodeStatus::Run {
@@ -65,7 +118,7 @@ impl CodeChainRPC {
         }
 
         let response =
-            self.sender.codechain_call_rpc((method.to_string(), Vec::new())).map_err(|err| format!("{}", err))?;
+            self.sender.codechain_call_rpc(priority, (method.to_string(), params)).map_err(|err| format!("{}", err))?;
 
         let response: T = match response {
             Output::Success(Success {
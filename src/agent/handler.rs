@@ -1,38 +1,131 @@
 use std::cell::Cell;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
+use serde_json;
+use serde_json::Value;
 use ws;
 use ws::{CloseCode, Error as WSError, Handler, Handshake, Result, Sender as WSSender};
 
 use super::super::agent;
+use super::super::allowlist::Allowlist;
+use super::super::job::{JobProgress, Jobs};
 use super::super::jsonrpc;
+use super::super::jsonrpc::Priority;
+use super::super::rate_limit::RateLimiter;
+use super::super::session::{SessionRole, Sessions};
+use super::super::shutdown::SHUTTING_DOWN_REASON;
+use super::log_inbox::{Backpressure, LogInbox};
+use super::types::LogPushRequest;
 
 pub struct WebSocketHandler {
     pub out: WSSender,
     pub count: Rc<Cell<u32>>,
     pub agent_service: agent::ServiceSender,
     pub jsonrpc_context: jsonrpc::Context,
+    pub shutting_down: Arc<AtomicBool>,
+    /// Paces the connections when many agents reconnect at once, e.g. after the hub restarts
+    pub accept_limiter: Arc<RateLimiter>,
+    pub allowlist: Arc<Allowlist>,
+    /// The logs which the agent pushed and the agent worker hasn't written yet
+    pub log_inbox: Arc<LogInbox>,
+    pub jobs: Arc<Jobs>,
+    pub sessions: Arc<Sessions>,
+    /// None until the connection is accepted
+    pub session_id: Option<u64>,
 }
 
 impl WebSocketHandler {
-    pub fn new(out: WSSender, count: Rc<Cell<u32>>, agent_service: agent::ServiceSender) -> Self {
+    pub fn new(
+        out: WSSender,
+        count: Rc<Cell<u32>>,
+        agent_service: agent::ServiceSender,
+        shutting_down: Arc<AtomicBool>,
+        accept_limiter: Arc<RateLimiter>,
+        allowlist: Arc<Allowlist>,
+        jobs: Arc<Jobs>,
+        sessions: Arc<Sessions>,
+    ) -> Self {
         let jsonrpc_context = jsonrpc::Context::new(out.clone());
-        agent_service
-            .send(agent::Message::InitializeAgent(jsonrpc_context.clone()))
-            .expect("Should success send InitializeAgent to service");
         Self {
             out,
             count,
             agent_service,
             jsonrpc_context,
+            shutting_down,
+            accept_limiter,
+            allowlist,
+            log_inbox: Default::default(),
+            jobs,
+            sessions,
+            session_id: None,
+        }
+    }
+
+    fn on_notification(&self, method: &str, params: Value) {
+        match method {
+            "log_push" => match serde_json::from_value::<LogPushRequest>(params) {
+                Ok(req) => {
+                    if let Some(backpressure) = self.log_inbox.push(req.logs, req.dropped) {
+                        send_backpressure(&self.jsonrpc_context, backpressure);
+                    }
+                }
+                Err(err) => cwarn!("Invalid log_push from agent : {}", err),
+            },
+            "job_progress" => match serde_json::from_value::<JobProgress>(params) {
+                Ok(progress) => {
+                    let node_name = self.agent_service.get_agent_name(&self.out);
+                    self.jobs.on_progress(node_name.as_ref(), progress)
+                }
+                Err(err) => cwarn!("Invalid job_progress from agent : {}", err),
+            },
+            _ => cwarn!("Unknown notification {} from agent", method),
         }
     }
 }
 
+/// Asks the agent to pause or to resume pushing logs
+pub fn send_backpressure(jsonrpc_context: &jsonrpc::Context, backpressure: Backpressure) {
+    let paused = backpressure == Backpressure::Pause;
+    cdebug!("Ask the agent to {:?} pushing logs", backpressure);
+    let notification = jsonrpc::serialize_notification("log_setBackpressure", json!({ "paused": paused }));
+    jsonrpc_context.notify(Priority::Control, notification);
+}
+
 impl Handler for WebSocketHandler {
-    fn on_open(&mut self, _: Handshake) -> Result<()> {
+    fn on_open(&mut self, handshake: Handshake) -> Result<()> {
         // We have a new connection, so we increment the connection counter
-        Ok(self.count.set(self.count.get() + 1))
+        self.count.set(self.count.get() + 1);
+
+        // The peer address, not X-Forwarded-For, which the client can forge. An unknown address is refused.
+        // ws tells the peer address only after the upgrade, so the refused agent is closed with the policy code.
+        let peer_addr = match handshake.peer_addr {
+            Some(peer_addr) if self.allowlist.allows(&peer_addr.ip()) => peer_addr,
+            peer_addr => {
+                cwarn!("Refuse the agent from {:?} which is not in the allowlist", peer_addr);
+                return self.out.close_with_reason(CloseCode::Policy, "The address is not allowed")
+            }
+        };
+        if self.sessions.is_banned(&peer_addr.ip()) {
+            cwarn!("Refuse the agent from {} which is banned", peer_addr);
+            return self.out.close_with_reason(CloseCode::Policy, "The address is banned")
+        }
+
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return self.out.close_with_reason(CloseCode::Away, SHUTTING_DOWN_REASON)
+        }
+        // The agent reconnects later by itself
+        if let Err(retry_after) = self.accept_limiter.acquire(1.0) {
+            cdebug!("Too many agents are connecting. Retry after {:?}", retry_after);
+            let reason = format!("The hub is busy. Retry after {:?}", retry_after);
+            return self.out.close_with_reason(CloseCode::Again, reason)
+        }
+        self.session_id = Some(self.sessions.open(SessionRole::Agent, Some(peer_addr), None, self.out.clone()));
+        self.agent_service
+            .send(agent::Message::InitializeAgent(self.jsonrpc_context.clone(), Arc::clone(&self.log_inbox)))
+            .expect("Should success send InitializeAgent to service");
+        Ok(())
     }
 
     fn on_message(&mut self, msg: ws::Message) -> Result<()> {
@@ -40,7 +133,10 @@ impl Handler for WebSocketHandler {
         ctrace!("The number of live connections is {}", self.count.get());
 
         match msg {
-            ws::Message::Text(text) => jsonrpc::on_receive(self.jsonrpc_context.clone(), text),
+            ws::Message::Text(text) => match jsonrpc::parse_notification(&text) {
+                Some((method, params)) => self.on_notification(&method, params),
+                None => jsonrpc::on_receive(self.jsonrpc_context.clone(), text),
+            },
             _ => {
                 cwarn!("Byte data received from agent");
             }
@@ -56,6 +152,10 @@ impl Handler for WebSocketHandler {
             _ => cinfo!("The client encountered an error: {}", reason),
         }
 
+        self.jsonrpc_context.close();
+        if let Some(session_id) = self.session_id {
+            self.sessions.close(session_id);
+        }
         // The connection is going down, so we need to decrement the count
         self.count.set(self.count.get() - 1)
     }
@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::super::common_rpc_types::StructuredLog;
+
+/// The number of the pushed logs which the hub holds for an agent
+const CAPACITY: usize = 10_000;
+/// The agent is asked to pause the pushes when the queue is filled over this
+const PAUSE_THRESHOLD: usize = CAPACITY * 3 / 4;
+/// The agent is asked to resume the pushes when the queue is drained under this
+const RESUME_THRESHOLD: usize = CAPACITY / 4;
+
+/// Asks the agent to pause or to resume pushing logs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backpressure {
+    Pause,
+    Resume,
+}
+
+#[derive(Clone, Default, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LogInboxMetrics {
+    pub queued: usize,
+    /// The number of the oldest logs which the hub dropped because the queue was full
+    pub dropped: u64,
+    /// The number of the logs which the agent reported that it dropped before pushing
+    pub dropped_by_agent: u64,
+    pub paused: bool,
+}
+
+pub struct Drained {
+    pub logs: Vec<StructuredLog>,
+    /// The number of the logs dropped since the last drain
+    pub dropped: u64,
+    pub backpressure: Option<Backpressure>,
+}
+
+#[derive(Default)]
+struct Queue {
+    logs: VecDeque<StructuredLog>,
+    metrics: LogInboxMetrics,
+    dropped_since_drain: u64,
+}
+
+/// The bounded queue of the logs which an agent pushes.
+/// The websocket thread fills it and the agent worker drains it a batch at a time,
+/// so a noisy node can't flood the database service which also writes the states of the other nodes.
+/// When the queue is full, the oldest logs are dropped and counted.
+#[derive(Default)]
+pub struct LogInbox {
+    queue: Mutex<Queue>,
+}
+
+impl LogInbox {
+    /// `dropped_by_agent` is the number of the logs which the agent dropped since its last push
+    pub fn push(&self, logs: Vec<StructuredLog>, dropped_by_agent: u64) -> Option<Backpressure> {
+        let mut queue = self.queue.lock().expect("Should success get log inbox");
+        queue.metrics.dropped_by_agent += dropped_by_agent;
+        queue.dropped_since_drain += dropped_by_agent;
+        for log in logs {
+            if queue.logs.len() >= CAPACITY {
+                queue.logs.pop_front();
+                queue.metrics.dropped += 1;
+                queue.dropped_since_drain += 1;
+            }
+            queue.logs.push_back(log);
+        }
+        queue.metrics.queued = queue.logs.len();
+
+        if !queue.metrics.paused && queue.logs.len() >= PAUSE_THRESHOLD {
+            queue.metrics.paused = true;
+            return Some(Backpressure::Pause)
+        }
+        None
+    }
+
+    /// Takes the oldest logs up to `max`
+    pub fn drain(&self, max: usize) -> Drained {
+        let mut queue = self.queue.lock().expect("Should success get log inbox");
+        let count = max.min(queue.logs.len());
+        let logs = queue.logs.drain(..count).collect();
+        queue.metrics.queued = queue.logs.len();
+        let dropped = queue.dropped_since_drain;
+        queue.dropped_since_drain = 0;
+
+        let backpressure = if queue.metrics.paused && queue.logs.len() <= RESUME_THRESHOLD {
+            queue.metrics.paused = false;
+            Some(Backpressure::Resume)
+        } else {
+            None
+        };
+        Drained {
+            logs,
+            dropped,
+            backpressure,
+        }
+    }
+
+    pub fn metrics(&self) -> LogInboxMetrics {
+        self.queue.lock().expect("Should success get log inbox").metrics.clone()
+    }
+}
@@ -1,9 +1,18 @@
 pub mod agent;
 mod codechain_rpc;
 pub mod handler;
+mod log_inbox;
+pub mod protocol;
+mod scheduler;
 pub mod service;
 mod types;
 
-pub use self::agent::{SendAgentRPC, State};
+pub use self::agent::{AgentSender, SendAgentRPC, State};
+pub use self::codechain_rpc::CodeChainRPC;
 pub use self::handler::WebSocketHandler;
+pub use self::log_inbox::LogInboxMetrics;
 pub use self::service::{Message, Service, ServiceSender};
+pub use self::types::{
+    BinaryChunkRequest, BinaryInstallRequest, ChaosBlockPeersRequest, ChaosPauseRequest, CommandTimeouts,
+    LogChecksumResponse, LogRangeRequest, LogRangeResponse, SnapshotInfo,
+};
@@ -0,0 +1,71 @@
+use super::super::common_rpc_types::{AgentCapability, AgentVersion};
+use super::types::{AgentHandshakeRequest, AgentHandshakeResponse};
+
+/// The version of the protocol between the hub and the agents.
+/// Bump it when a method is added to or changed in the agent API.
+pub const PROTOCOL_VERSION: u32 = 5;
+/// The oldest protocol which the hub still speaks
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+/// The agents of this protocol push the logs with log_push instead of being polled
+pub const LOG_PUSH_PROTOCOL_VERSION: u32 = 3;
+/// The agents of this protocol can start the pre-installed binary with binaryPath
+pub const BINARY_PATH_PROTOCOL_VERSION: u32 = 4;
+/// The agents of this protocol advertise their capabilities in the handshake
+pub const CAPABILITY_PROTOCOL_VERSION: u32 = 5;
+/// The agents which don't know agent_handshake speak this protocol
+const LEGACY_PROTOCOL_VERSION: u32 = 1;
+
+pub fn handshake_request() -> AgentHandshakeRequest {
+    AgentHandshakeRequest {
+        protocol_version: PROTOCOL_VERSION,
+        min_protocol_version: MIN_PROTOCOL_VERSION,
+        hub_version: env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+/// Agrees on the newest protocol which both sides speak.
+/// `response` is None when the agent doesn't support agent_handshake.
+pub fn negotiate(response: Option<AgentHandshakeResponse>) -> Result<AgentVersion, String> {
+    let (protocol_version, min_protocol_version, binary_version, capabilities) = match response {
+        Some(response) => (
+            response.protocol_version,
+            response.min_protocol_version,
+            Some(response.agent_version),
+            response.capabilities,
+        ),
+        None => (LEGACY_PROTOCOL_VERSION, LEGACY_PROTOCOL_VERSION, None, None),
+    };
+
+    let agreed = protocol_version.min(PROTOCOL_VERSION);
+    if agreed < MIN_PROTOCOL_VERSION || agreed < min_protocol_version {
+        return Err(format!(
+            "The agent speaks the protocol {}~{} but the hub speaks {}~{}",
+            min_protocol_version, protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+        ))
+    }
+    let capabilities = if agreed >= CAPABILITY_PROTOCOL_VERSION {
+        Some(parse_capabilities(capabilities.unwrap_or_default()))
+    } else {
+        None
+    };
+    Ok(AgentVersion {
+        protocol_version: agreed,
+        binary_version,
+        degraded: agreed < PROTOCOL_VERSION,
+        capabilities,
+    })
+}
+
+/// The capabilities of the newer agents which the hub doesn't know are ignored
+fn parse_capabilities(names: Vec<String>) -> Vec<AgentCapability> {
+    names
+        .iter()
+        .filter_map(|name| {
+            let capability = AgentCapability::parse(name);
+            if capability.is_none() {
+                cdebug!("Unknown agent capability {}", name);
+            }
+            capability
+        })
+        .collect()
+}
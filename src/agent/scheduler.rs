@@ -0,0 +1,117 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::agent::{Agent, AgentCleanupReason, StopCause};
+
+/// The longest time which an update step of an agent takes, however many calls it makes
+const STEP_TIMEOUT: Duration = Duration::from_secs(20);
+
+struct Scheduled {
+    due: Instant,
+    agent: Agent,
+}
+
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Self) -> bool {
+        self.due == other.due
+    }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scheduled {
+    /// The earliest due comes first in the max heap
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.due.cmp(&self.due)
+    }
+}
+
+struct Queue {
+    agents: Mutex<BinaryHeap<Scheduled>>,
+    condvar: Condvar,
+    /// The interval between the updates of an agent
+    update_interval: Duration,
+}
+
+/// Updates the agents on a fixed number of worker threads instead of a thread for each agent,
+/// so the number of the threads doesn't grow with the number of the agents.
+/// It is not an async runtime. A step of an agent blocks its worker, but all the calls of the step time out
+/// at STEP_TIMEOUT together, so an unresponsive agent can't hold a worker longer than that.
+#[derive(Clone)]
+pub struct Scheduler {
+    queue: Arc<Queue>,
+}
+
+impl Scheduler {
+    pub fn run_threads(workers: usize, update_interval: Duration) -> Self {
+        let queue = Arc::new(Queue {
+            agents: Mutex::new(BinaryHeap::new()),
+            condvar: Condvar::new(),
+            update_interval,
+        });
+        for index in 0..workers.max(1) {
+            let queue = Arc::clone(&queue);
+            thread::Builder::new()
+                .name(format!("agent worker-{}", index))
+                .spawn(move || run_worker(&queue))
+                .expect("Should success running agent worker thread");
+        }
+        Self {
+            queue,
+        }
+    }
+
+    /// The agent is updated as soon as a worker is available
+    pub fn add(&self, agent: Agent) {
+        schedule(&self.queue, agent, Instant::now());
+    }
+}
+
+fn schedule(queue: &Queue, agent: Agent, due: Instant) {
+    let mut agents = queue.agents.lock().expect("Should success get scheduled agents");
+    agents.push(Scheduled {
+        due,
+        agent,
+    });
+    queue.condvar.notify_one();
+}
+
+fn run_worker(queue: &Queue) {
+    loop {
+        let mut agent = next_due(queue);
+        match agent.step(Instant::now() + STEP_TIMEOUT) {
+            Ok(None) => schedule(queue, agent, Instant::now() + queue.update_interval),
+            Ok(Some(StopCause::AlreadyConnected)) => agent.clean_up(AgentCleanupReason::AlreadyConnected),
+            Err(err) => {
+                cerror!("Agent failed : {}", err);
+                agent.clean_up(AgentCleanupReason::Error(err));
+            }
+        }
+    }
+}
+
+/// Waits until the earliest scheduled agent is due and takes it
+fn next_due(queue: &Queue) -> Agent {
+    let mut agents = queue.agents.lock().expect("Should success get scheduled agents");
+    loop {
+        let now = Instant::now();
+        let wait = match agents.peek().map(|scheduled| scheduled.due) {
+            Some(due) if due <= now => return agents.pop().expect("Peeked").agent,
+            Some(due) => Some(due - now),
+            None => None,
+        };
+        agents = match wait {
+            Some(wait) => queue.condvar.wait_timeout(agents, wait).expect("Should success wait agents").0,
+            None => queue.condvar.wait(agents).expect("Should success wait agents"),
+        };
+    }
+}
@@ -1,12 +1,18 @@
 use std::sync::mpsc::{channel, SendError, Sender};
 use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::Duration;
 use std::vec::Vec;
 
+use ws;
+
 use super::super::common_rpc_types::NodeName;
 use super::super::db;
 use super::super::jsonrpc;
 use super::agent::{Agent, AgentSender};
+use super::log_inbox::LogInbox;
+use super::types::CommandTimeouts;
+use super::scheduler::Scheduler;
 
 pub struct State {
     agents: Vec<(i32, AgentSender)>,
@@ -43,6 +49,28 @@ impl ServiceSender {
 
         find_result.map(|(_, agent)| agent.clone())
     }
+
+    /// The name of the node whose agent is connected through the connection. None while the agent is initializing
+    pub fn get_agent_name(&self, connection: &ws::Sender) -> Option<NodeName> {
+        let state = self.state.read().expect("Should access read service state");
+        state
+            .agents
+            .iter()
+            .find(|(_, agent)| agent.is_connected_through(connection))
+            .and_then(|(_, agent)| agent.name())
+    }
+
+    pub fn agent_count(&self) -> usize {
+        let state = self.state.read().expect("Should access read service state");
+        state.agents.len()
+    }
+
+    /// Fails if the service thread is stuck
+    pub fn ping(&self, timeout: Duration) -> Result<(), String> {
+        let (tx, rx) = channel();
+        self.sender.send(Message::Ping(tx)).map_err(|err| err.to_string())?;
+        rx.recv_timeout(timeout).map_err(|err| err.to_string())
+    }
 }
 
 pub struct Service {
@@ -50,16 +78,28 @@ pub struct Service {
     next_id: i32,
     sender: ServiceSender,
     db_service: db::ServiceSender,
+    scheduler: Scheduler,
+    timeouts: Arc<CommandTimeouts>,
+    max_clock_skew_ms: i64,
 }
 
 pub enum Message {
-    InitializeAgent(jsonrpc::Context),
+    InitializeAgent(jsonrpc::Context, Arc<LogInbox>),
     AddAgent(i32, AgentSender),
     RemoveAgent(i32),
+    /// The callback is called when the service thread handles it
+    Ping(Sender<()>),
 }
 
 impl Service {
-    pub fn run_thread(db_service: db::ServiceSender) -> ServiceSender {
+    /// `workers` is the number of the threads which update the agents
+    pub fn run_thread(
+        db_service: db::ServiceSender,
+        workers: usize,
+        update_interval: Duration,
+        timeouts: CommandTimeouts,
+        max_clock_skew_ms: i64,
+    ) -> ServiceSender {
         let (tx, rx) = channel();
         let state = Arc::new(RwLock::new(State::new()));
         let service_sender = ServiceSender {
@@ -67,15 +107,17 @@ impl Service {
             state: state.clone(),
         };
 
-        let mut service = Service::new(service_sender.clone(), state, db_service);
+        let scheduler = Scheduler::run_threads(workers, update_interval);
+        let mut service =
+            Service::new(service_sender.clone(), state, db_service, scheduler, Arc::new(timeouts), max_clock_skew_ms);
 
         thread::Builder::new()
             .name("agent service".to_string())
             .spawn(move || {
                 for message in rx {
                     match message {
-                        Message::InitializeAgent(jsonrpc_context) => {
-                            service.create_agent(jsonrpc_context);
+                        Message::InitializeAgent(jsonrpc_context, log_inbox) => {
+                            service.create_agent(jsonrpc_context, log_inbox);
                         }
                         Message::AddAgent(id, agent_sender) => {
                             service.add_agent(id, agent_sender);
@@ -83,6 +125,11 @@ impl Service {
                         Message::RemoveAgent(id) => {
                             service.remove_agent(id);
                         }
+                        Message::Ping(callback) => {
+                            if let Err(err) = callback.send(()) {
+                                cerror!("Cannot send callback : {}", err);
+                            }
+                        }
                     }
                 }
             })
@@ -91,19 +138,38 @@ impl Service {
         service_sender
     }
 
-    fn new(sender: ServiceSender, state: Arc<RwLock<State>>, db_service: db::ServiceSender) -> Self {
+    fn new(
+        sender: ServiceSender,
+        state: Arc<RwLock<State>>,
+        db_service: db::ServiceSender,
+        scheduler: Scheduler,
+        timeouts: Arc<CommandTimeouts>,
+        max_clock_skew_ms: i64,
+    ) -> Self {
         Service {
             state,
             next_id: 0_i32,
             sender,
             db_service,
+            scheduler,
+            timeouts,
+            max_clock_skew_ms,
         }
     }
 
-    fn create_agent(&mut self, jsonrpc_context: jsonrpc::Context) {
+    fn create_agent(&mut self, jsonrpc_context: jsonrpc::Context, log_inbox: Arc<LogInbox>) {
         let id = self.next_id;
         self.next_id += 1;
-        Agent::run_thread(id, jsonrpc_context, self.sender.clone(), self.db_service.clone());
+        let agent = Agent::new(
+            id,
+            jsonrpc_context,
+            log_inbox,
+            self.sender.clone(),
+            self.db_service.clone(),
+            Arc::clone(&self.timeouts),
+            self.max_clock_skew_ms,
+        );
+        self.scheduler.add(agent);
         cdebug!("Agent {} initialization starts", id);
     }
 
@@ -1,9 +1,143 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::time::Duration;
 
+use chrono;
 use cprimitives::H256;
 use serde_json::Value;
 
-use super::super::common_rpc_types::{NodeName, NodeStatus};
+use super::super::common_rpc_types::{NodeName, NodeStatus, StructuredLog};
+use super::super::jsonrpc::DEFAULT_CALL_TIMEOUT;
+
+/// The commands which take longer than DEFAULT_CALL_TIMEOUT, and their timeouts in seconds
+const COMMAND_TIMEOUTS: &[(&str, u64)] = &[
+    ("shell_startCodeChain", 60),
+    ("shell_stopCodeChain", 60),
+    ("shell_updateCodeChain", 300),
+    ("snapshot_restore", 600),
+    ("binary_install", 300),
+];
+
+/// The timeouts of the agent RPCs in seconds by their methods, e.g. `shell_updateCodeChain = 3600`.
+/// They override the built-in timeouts of the methods.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CommandTimeouts(HashMap<String, u64>);
+
+impl CommandTimeouts {
+    pub fn of(&self, method: &str) -> Duration {
+        let seconds = self.0.get(method).cloned().or_else(|| {
+            COMMAND_TIMEOUTS.iter().find(|(command, _)| *command == method).map(|(_, seconds)| *seconds)
+        });
+        seconds.map(Duration::from_secs).unwrap_or(DEFAULT_CALL_TIMEOUT)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentHandshakeRequest {
+    pub protocol_version: u32,
+    pub min_protocol_version: u32,
+    pub hub_version: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentHandshakeResponse {
+    pub protocol_version: u32,
+    pub min_protocol_version: u32,
+    pub agent_version: String,
+    /// The names of the capabilities. The agents older than the protocol 5 don't send it
+    #[serde(default)]
+    pub capabilities: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogRangeRequest {
+    pub offset: u64,
+    pub length: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogRangeResponse {
+    /// Changes when the log file is rotated
+    pub file_id: String,
+    pub total_size: u64,
+    pub offset: u64,
+    /// Base64 encoded bytes
+    pub data: String,
+    /// The hex encoded SHA-256 of the decoded bytes
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogChecksumResponse {
+    pub file_id: String,
+    pub total_size: u64,
+    /// The hex encoded SHA-256 of the whole file
+    pub sha256: String,
+}
+
+/// A part of the binary which the hub transfers. The agent writes it at the offset of a temporary file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinaryChunkRequest {
+    pub commit_hash: String,
+    pub total_size: u64,
+    pub offset: u64,
+    /// Base64 encoded bytes
+    pub data: String,
+}
+
+/// Replaces the binary of CodeChain with the transferred one and restarts the node like shell_updateCodeChain.
+/// The agent refuses it if the SHA-256 of the transferred file differs.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinaryInstallRequest {
+    pub commit_hash: String,
+    /// The hex encoded SHA-256 of the whole binary
+    pub sha256: String,
+    pub env: String,
+    pub args: String,
+}
+
+/// SIGSTOPs CodeChain and SIGCONTs it after the duration
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChaosPauseRequest {
+    pub duration_secs: u64,
+}
+
+/// Drops the traffic between CodeChain and the addresses, and allows it again after the duration
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChaosBlockPeersRequest {
+    pub addresses: Vec<SocketAddr>,
+    pub duration_secs: u64,
+}
+
+/// A snapshot of the chain data which the agent can restore
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    pub id: String,
+    /// The node whose data is in the snapshot
+    pub node_name: NodeName,
+    pub block_number: i64,
+    pub created_at: chrono::DateTime<chrono::Local>,
+}
+
+/// The params of log_push, which the agents of the protocol 3 send with the batched logs
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogPushRequest {
+    pub logs: Vec<StructuredLog>,
+    /// The number of the logs which the agent dropped since the last push
+    #[serde(default)]
+    pub dropped: u64,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -12,6 +146,12 @@ pub struct AgentGetInfoResponse {
     pub name: NodeName,
     pub address: Option<SocketAddr>,
     pub codechain_commit_hash: String,
+    /// The system time of the host. The agents older than the clock monitoring don't send it
+    #[serde(default)]
+    pub system_time: Option<chrono::DateTime<chrono::Utc>>,
+    /// The offset of the host clock from the NTP servers in milliseconds
+    #[serde(default)]
+    pub ntp_offset_ms: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
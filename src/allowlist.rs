@@ -0,0 +1,98 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::de::Error as DeError;
+use serde::{Deserialize, Deserializer};
+
+/// A range of the addresses like "10.0.0.0/8". A single address like "10.0.0.1" is the range of itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    address: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.address, canonical(ip)) {
+            (IpAddr::V4(address), IpAddr::V4(ip)) => {
+                let mask = mask(32, self.prefix_len) as u32;
+                u32::from(address) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(address), IpAddr::V6(ip)) => {
+                let mask = mask(128, self.prefix_len);
+                u128::from(address) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The IPv4 clients of a listener bound to an IPv6 address come as the IPv4-mapped addresses
+fn canonical(ip: &IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, _, _] => IpAddr::V4(v6.to_ipv4().expect("IPv4-mapped")),
+            _ => *ip,
+        },
+        IpAddr::V4(_) => *ip,
+    }
+}
+
+fn mask(bits: u8, prefix_len: u8) -> u128 {
+    if prefix_len == 0 {
+        return 0
+    }
+    (!0u128 << (bits - prefix_len)) & (!0u128 >> (128 - bits))
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '/');
+        let address = parts.next().unwrap_or_default();
+        let address = address.parse::<IpAddr>().map_err(|err| format!("Invalid address {} : {}", address, err))?;
+        let address = canonical(&address);
+        let max_len = if address.is_ipv4() {
+            32
+        } else {
+            128
+        };
+        let prefix_len = match parts.next() {
+            Some(prefix_len) => prefix_len.parse::<u8>().map_err(|err| format!("Invalid CIDR {} : {}", s, err))?,
+            None => max_len,
+        };
+        if prefix_len > max_len {
+            return Err(format!("The prefix of {} should be at most {}", s, max_len))
+        }
+        Ok(Self {
+            address,
+            prefix_len,
+        })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>, {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+/// The addresses which a listener accepts the connections from. It allows every address when it is empty.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Allowlist(Vec<Cidr>);
+
+impl Allowlist {
+    pub fn allows(&self, ip: &IpAddr) -> bool {
+        self.0.is_empty() || self.0.iter().any(|cidr| cidr.contains(ip))
+    }
+}
@@ -0,0 +1,266 @@
+#[macro_use]
+extern crate serde_json;
+extern crate ws;
+
+use std::cell::RefCell;
+use std::env;
+use std::process;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+use ws::{CloseCode, Handler, Handshake, Message, Sender};
+
+const DEFAULT_HUB_URL: &str = "ws://127.0.0.1:3012";
+/// Overrides the default hub when --hub is not given
+const HUB_URL_ENV: &str = "AGENT_HUB_URL";
+/// The hub also broadcasts the events to the connection, which don't have this id
+const REQUEST_ID: u64 = 1;
+const TAIL_INTERVAL: Duration = Duration::from_secs(2);
+/// The logs more than this in an interval are skipped by logs tail
+const TAIL_PAGE_SIZE: usize = 200;
+
+const USAGE: &str = "\
+Usage: agent-hub-ctl [--hub <url>] <command>
+
+Commands:
+    nodes list
+    node start <name> [--env <env>] [--args <args>] [--binary-path <path>]
+    node stop <name>
+    node update <name> <commit hash>
+    logs tail [--node <name>]... [--level <level>]...
+    network export
+
+The hub is ws://127.0.0.1:3012 unless --hub or AGENT_HUB_URL is given.
+The options of node start which are not given are kept from the last start.";
+
+/**
+ * Operates the hub through the frontend RPC, for the environments which can't open the dashboard.
+ * Each request opens its own websocket connection.
+ */
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let url = take_option(&mut args, "--hub")
+        .or_else(|| env::var(HUB_URL_ENV).ok())
+        .unwrap_or_else(|| DEFAULT_HUB_URL.to_string());
+    let client = Client {
+        url,
+    };
+
+    let command = args.iter().take(2).map(String::as_str).collect::<Vec<_>>().join(" ");
+    let mut rest = args.split_off(args.len().min(2));
+    let result = match command.as_str() {
+        "nodes list" => nodes_list(&client),
+        "node start" => node_start(&client, &mut rest),
+        "node stop" => node_stop(&client, &rest),
+        "node update" => node_update(&client, &rest),
+        "logs tail" => logs_tail(&client, &mut rest),
+        "network export" => network_export(&client),
+        _ => Err(USAGE.to_string()),
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn nodes_list(client: &Client) -> Result<(), String> {
+    let network = client.call("dashboard_getNetwork", json!({}))?;
+    println!("{:<24} {:<14} {:<22} {}", "NAME", "STATUS", "ADDRESS", "COMMIT");
+    for node in network["nodes"].as_array().cloned().unwrap_or_default() {
+        println!(
+            "{:<24} {:<14} {:<22} {}",
+            text(&node["name"]),
+            text(&node["status"]),
+            text(&node["address"]),
+            text(&node["version"]["hash"])
+        );
+    }
+    Ok(())
+}
+
+fn node_start(client: &Client, args: &mut Vec<String>) -> Result<(), String> {
+    let env = take_option(args, "--env");
+    let start_args = take_option(args, "--args");
+    let binary_path = take_option(args, "--binary-path");
+    let name = single_arg(args, "node start <name>")?;
+
+    let info = client.call("node_getInfo", json!({ "name": name }))?;
+    let start_option = &info["startOption"];
+    let req = json!({
+        "env": env.unwrap_or_else(|| start_option["env"].as_str().unwrap_or_default().to_string()),
+        "args": start_args.unwrap_or_else(|| start_option["args"].as_str().unwrap_or_default().to_string()),
+        "binaryPath": binary_path,
+    });
+    client.call("node_start", json!({ "name": name, "req": req }))?;
+    println!("{} is started", name);
+    Ok(())
+}
+
+fn node_stop(client: &Client, args: &[String]) -> Result<(), String> {
+    let name = single_arg(args, "node stop <name>")?;
+    client.call("node_stop", json!({ "name": name }))?;
+    println!("{} is stopped", name);
+    Ok(())
+}
+
+fn node_update(client: &Client, args: &[String]) -> Result<(), String> {
+    let (name, commit_hash) = match args {
+        [name, commit_hash] => (name, commit_hash),
+        _ => return Err("Usage: agent-hub-ctl node update <name> <commit hash>".to_string()),
+    };
+    client.call("node_update", json!({ "name": name, "commitHash": commit_hash }))?;
+    println!("{} is updating to {}", name, commit_hash);
+    Ok(())
+}
+
+/// Polls the latest logs and prints the new ones until it is interrupted
+fn logs_tail(client: &Client, args: &mut Vec<String>) -> Result<(), String> {
+    let mut node_names = Vec::new();
+    while let Some(name) = take_option(args, "--node") {
+        node_names.push(name);
+    }
+    let mut levels = Vec::new();
+    while let Some(level) = take_option(args, "--level") {
+        levels.push(level.to_lowercase());
+    }
+    if !args.is_empty() {
+        return Err("Usage: agent-hub-ctl logs tail [--node <name>]... [--level <level>]...".to_string())
+    }
+    let req = json!({
+        "filter": {
+            "nodeNames": node_names,
+            "levels": levels,
+            "targets": [],
+        },
+        "itemPerPage": TAIL_PAGE_SIZE,
+        "orderBy": "DESC",
+    });
+
+    let mut last_id = None;
+    loop {
+        let response = client.call("log_get", json!({ "req": req }))?;
+        let logs = response["logs"].as_array().cloned().unwrap_or_default();
+        let new_logs: Vec<&Value> =
+            logs.iter().take_while(|log| last_id.map_or(true, |last_id| log["id"].as_i64() > Some(last_id))).collect();
+        for log in new_logs.iter().rev() {
+            println!(
+                "{} {} {:<5} [{}] {}",
+                text(&log["timestamp"]),
+                text(&log["nodeName"]),
+                text(&log["level"]),
+                text(&log["target"]),
+                text(&log["message"])
+            );
+        }
+        if let Some(id) = logs.first().and_then(|log| log["id"].as_i64()) {
+            last_id = Some(id);
+        }
+        thread::sleep(TAIL_INTERVAL);
+    }
+}
+
+/// Prints the scenario which network_apply takes
+fn network_export(client: &Client) -> Result<(), String> {
+    let scenario = client.call("network_export", json!({}))?;
+    let pretty = serde_json::to_string_pretty(&scenario).map_err(|err| err.to_string())?;
+    println!("{}", pretty);
+    Ok(())
+}
+
+struct Client {
+    url: String,
+}
+
+impl Client {
+    fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": REQUEST_ID,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+        let response = Rc::new(RefCell::new(None));
+        ws::connect(self.url.as_str(), |out| Call {
+            out,
+            request: request.clone(),
+            response: Rc::clone(&response),
+        })
+        .map_err(|err| format!("Cannot connect to {} : {}", self.url, err))?;
+
+        let response = response.borrow_mut().take();
+        response.unwrap_or_else(|| Err(format!("The hub closed the connection before responding to {}", method)))
+    }
+}
+
+/// A connection which sends a request and closes when the response arrives
+struct Call {
+    out: Sender,
+    request: String,
+    response: Rc<RefCell<Option<Result<Value, String>>>>,
+}
+
+impl Handler for Call {
+    fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+        self.out.send(self.request.as_str())
+    }
+
+    fn on_message(&mut self, msg: Message) -> ws::Result<()> {
+        let response: Value = match msg.as_text().ok().and_then(|text| serde_json::from_str(text).ok()) {
+            Some(response) => response,
+            None => return Ok(()),
+        };
+        if response["id"] != json!(REQUEST_ID) {
+            return Ok(())
+        }
+        let result = match response.get("error") {
+            Some(error) => Err(describe_error(error)),
+            None => Ok(response["result"].clone()),
+        };
+        *self.response.borrow_mut() = Some(result);
+        self.out.close(CloseCode::Normal)
+    }
+
+    fn on_close(&mut self, code: CloseCode, reason: &str) {
+        let mut response = self.response.borrow_mut();
+        if response.is_none() {
+            *response = Some(Err(format!("The hub closed the connection with {:?} : {}", code, reason)));
+        }
+    }
+}
+
+fn describe_error(error: &Value) -> String {
+    let mut description = format!("{} (code {})", text(&error["message"]), error["code"]);
+    if !error["data"].is_null() {
+        description.push_str(&format!(" {}", error["data"]));
+    }
+    description
+}
+
+/// Removes the option and its value from the args
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    if index + 1 >= args.len() {
+        return None
+    }
+    args.remove(index);
+    Some(args.remove(index))
+}
+
+fn single_arg(args: &[String], usage: &str) -> Result<String, String> {
+    match args {
+        [arg] => Ok(arg.clone()),
+        _ => Err(format!("Usage: agent-hub-ctl {}", usage)),
+    }
+}
+
+/// Shows a string without the quotes and the missing value as "-"
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        Value::Null => "-".to_string(),
+        value => value.to_string(),
+    }
+}
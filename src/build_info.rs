@@ -0,0 +1,43 @@
+use chrono;
+
+/**
+ * BuildInfo is the fingerprint of the running hub.
+ * It is printed on startup and exposed through admin_getBuildInfo so that what is deployed can be verified.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_commit: String,
+    pub features: Vec<String>,
+    pub config_hash: Option<String>,
+    pub schema_version: Option<i32>,
+    pub started_at: chrono::DateTime<chrono::Local>,
+}
+
+impl BuildInfo {
+    pub fn new() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: env!("AGENT_HUB_GIT_COMMIT").to_string(),
+            features: enabled_features(),
+            config_hash: None,
+            schema_version: None,
+            started_at: chrono::Local::now(),
+        }
+    }
+
+    pub fn print_banner(&self) {
+        cinfo!("CodeChain Agent Hub {} ({})", self.version, self.git_commit);
+        cinfo!("  features       : {}", self.features.join(", "));
+        cinfo!("  config hash    : {}", self.config_hash.as_ref().map(String::as_str).unwrap_or("-"));
+        let schema_version = self.schema_version.map(|version| version.to_string()).unwrap_or_else(|| "-".to_string());
+        cinfo!("  schema version : {}", schema_version);
+    }
+}
+
+/// The cargo features of Cargo.toml which the hub is built with
+fn enabled_features() -> Vec<String> {
+    let features: Vec<(&str, bool)> = vec![("mock-agent", cfg!(feature = "mock-agent"))];
+    features.into_iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name.to_string()).collect()
+}
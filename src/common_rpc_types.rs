@@ -6,7 +6,7 @@ use serde_json;
 pub type NodeName = String;
 pub type CommitHash = String;
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, JsonSchema)]
 pub enum NodeStatus {
     Starting,
     Run,
@@ -14,6 +14,8 @@ pub enum NodeStatus {
     Updating,
     Error,
     UFO,
+    /// The agent was connected before the hub restarted and has not reconnected yet
+    Reconnecting,
 }
 
 impl Default for NodeStatus {
@@ -22,11 +24,15 @@ impl Default for NodeStatus {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ShellStartCodeChainRequest {
     pub env: String,
     pub args: String,
+    /// Runs the pre-installed binary instead of the one built from the commit.
+    /// The agents older than the protocol 4 don't know it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary_path: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -35,29 +41,108 @@ pub struct ShellUpdateCodeChainRequest {
     pub env: String,
     pub args: String,
     pub commit_hash: String,
+    /// The agent sends job_progress with it. Only the agents with the jobProgress capability get it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<u64>,
 }
 
 pub type Connection = (NodeName, NodeName);
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Copy, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct BlockId {
     pub block_number: i64,
+    /// Serialized as a hex string
+    #[schemars(with = "String")]
     pub hash: H256,
 }
 
-#[derive(Debug, Serialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeVersion {
     pub version: String,
     pub hash: String,
 }
 
+/// The clock of the host of a node compared with the clock of the hub
+#[derive(Debug, Serialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeClock {
+    /// How far the system time of the host is ahead of the hub, measured at the middle of the round trip
+    pub offset_ms: i64,
+    /// The offset from the NTP servers which the agent reported. None if the agent doesn't know it
+    pub ntp_offset_ms: Option<i64>,
+    /// True if either offset exceeds `max_clock_skew_ms` of the agent config
+    pub skewed: bool,
+}
+
+/// The versions which the agent reported in the handshake
+#[derive(Debug, Serialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentVersion {
+    /// The protocol version which the hub and the agent agreed on
+    pub protocol_version: u32,
+    /// The version of the codechain-agent binary. None if the agent is older than the handshake
+    pub binary_version: Option<String>,
+    /// True if the agent speaks an older protocol than the hub, so some features are unavailable
+    pub degraded: bool,
+    /// The optional features which the agent advertised in the handshake.
+    /// None if the agent is older than the capability discovery, and then nothing is refused in advance
+    pub capabilities: Option<Vec<AgentCapability>>,
+}
+
+/// An optional feature of the agent. The RPCs which need a capability fail before they reach the agent without it
+#[derive(Debug, Serialize, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum AgentCapability {
+    Snapshot,
+    Docker,
+    Exec,
+    Profiling,
+    /// Installs the binary which the hub transfers instead of building CodeChain
+    BinaryInstall,
+    /// Sends job_progress while it runs the job of a request
+    JobProgress,
+    /// Kills, pauses and isolates CodeChain for the chaos testing
+    Chaos,
+}
+
+impl AgentCapability {
+    /// The same as the serialized value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AgentCapability::Snapshot => "snapshot",
+            AgentCapability::Docker => "docker",
+            AgentCapability::Exec => "exec",
+            AgentCapability::Profiling => "profiling",
+            AgentCapability::BinaryInstall => "binaryInstall",
+            AgentCapability::JobProgress => "jobProgress",
+            AgentCapability::Chaos => "chaos",
+        }
+    }
+
+    /// Returns None for the capabilities which the hub doesn't know
+    pub fn parse(name: &str) -> Option<Self> {
+        [
+            AgentCapability::Snapshot,
+            AgentCapability::Docker,
+            AgentCapability::Exec,
+            AgentCapability::Profiling,
+            AgentCapability::BinaryInstall,
+            AgentCapability::JobProgress,
+            AgentCapability::Chaos,
+        ]
+            .iter()
+            .find(|capability| capability.as_str() == name)
+            .cloned()
+    }
+}
+
 pub type PendingParcel = serde_json::Value;
 
 pub type Tag = String;
 
-#[derive(Debug, Serialize, PartialEq, Clone, Deserialize)]
+#[derive(Debug, Serialize, PartialEq, Clone, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct WhiteList {
     pub list: Vec<(IpAddr, Tag)>,
@@ -66,7 +151,7 @@ pub struct WhiteList {
 
 pub type BlackList = WhiteList;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct HardwareUsage {
     pub total: i64,
@@ -74,7 +159,7 @@ pub struct HardwareUsage {
     pub percentage_used: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct HardwareInfo {
     pub cpu_usage: Vec<f64>,
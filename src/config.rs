@@ -0,0 +1,263 @@
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+use toml;
+
+use super::agent::CommandTimeouts;
+use super::allowlist::{Allowlist, Cidr};
+use super::db::leader::LeaderElectionOption;
+use super::db::{LogStorageOption, StorageOption};
+use super::distribution::DistributionOption;
+use super::frontend::{ApiTokenOption, ConnectionOption, QuorumGuardOption, StartPolicyOption};
+use super::metrics_history::MetricsHistoryOption;
+use super::rate_limit::RateLimitOption;
+use super::release::ReleaseTrackingOption;
+use super::secrets::SecretsOption;
+use super::web::PublicMetricsOption;
+
+/// The environment variables which start with this override the config file.
+/// The sections are separated by ENV_SEPARATOR, e.g. AGENT_HUB_FRONTEND__RATE_LIMIT__BURST=60
+const ENV_PREFIX: &str = "AGENT_HUB_";
+const ENV_SEPARATOR: &str = "__";
+
+/**
+ * The settings of the hub. They are read from the TOML file given with --config,
+ * and the environment variables override them. The missing settings have the default values.
+ */
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub listen: ListenConfig,
+    /// Use `type = "sqlite"` to try the hub without Postgres
+    pub storage: StorageOption,
+    pub log_storage: LogStorageOption,
+    pub frontend: FrontendConfig,
+    pub agent: AgentConfig,
+    /// Serves /metrics/public when it is set
+    pub public_metrics: Option<PublicMetricsOption>,
+    /// node_update sends the binary built by the hub to the agents which can install it when it is set
+    pub distribution: Option<DistributionOption>,
+    /// Checks the git remote of CodeChain for new releases when it is set
+    pub release_tracking: Option<ReleaseTrackingOption>,
+    /// Runs as the standby until this hub holds the lock in the database when it is set
+    pub leader_election: Option<LeaderElectionOption>,
+    /// The samples of the block numbers and the peer counts which node_getHistory returns
+    pub metrics_history: MetricsHistoryOption,
+    /// node_setSecret is enabled when it is set
+    pub secrets: Option<SecretsOption>,
+    /// The SHA-256 of the settings after the overrides are applied
+    #[serde(skip)]
+    pub hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ListenConfig {
+    pub frontend: SocketAddr,
+    pub agent: SocketAddr,
+    pub web: SocketAddr,
+    /// The CIDRs which the dashboards can connect from, including the frontend routes of the web listener
+    pub frontend_allowlist: Allowlist,
+    /// The CIDRs which the agents can connect from
+    pub agent_allowlist: Allowlist,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            frontend: "0.0.0.0:3012".parse().unwrap(),
+            agent: "0.0.0.0:4012".parse().unwrap(),
+            web: "0.0.0.0:5012".parse().unwrap(),
+            frontend_allowlist: Default::default(),
+            agent_allowlist: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FrontendConfig {
+    pub ping_interval_secs: u64,
+    /// The connection is closed when nothing is received in this duration, including the pongs
+    pub idle_timeout_secs: u64,
+    /// The size of a message in bytes
+    pub max_message_size: usize,
+    /// Per websocket connection or per HTTP client address
+    pub rate_limit: RateLimitOption,
+    pub quorum_guard: QuorumGuardOption,
+    /// node_stop, node_update and the bulk operations wait until another operator approves them
+    pub approval_required: bool,
+    /// The pending actions which are not approved in this duration are dropped
+    pub approval_timeout_minutes: i64,
+    /// Limits what the start options can pass to the shell of the agents
+    pub start_policy: StartPolicyOption,
+    pub rest_gateway: bool,
+    /// Serves the nodes, the connections, the logs and the events as a GraphQL query at /graphql
+    pub graphql: bool,
+    /// Enables debug_injectFault. Never enable it in production
+    pub fault_injection: bool,
+    /// The clients need one of the tokens when it is not empty
+    pub api_tokens: Vec<ApiTokenOption>,
+    /// The reverse proxies whose X-Forwarded-For is trusted as the caller in the audit log
+    pub trusted_proxies: Vec<Cidr>,
+}
+
+impl Default for FrontendConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval_secs: 20,
+            idle_timeout_secs: 60,
+            max_message_size: 1024 * 1024,
+            rate_limit: RateLimitOption {
+                requests_per_second: 20.0,
+                burst: 40.0,
+            },
+            quorum_guard: QuorumGuardOption {
+                max_offline_fraction: 1.0 / 3.0,
+                override_callers: vec!["127.0.0.1".parse().unwrap()],
+            },
+            approval_required: false,
+            approval_timeout_minutes: 60,
+            start_policy: Default::default(),
+            rest_gateway: false,
+            graphql: false,
+            fault_injection: false,
+            api_tokens: Vec::new(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl FrontendConfig {
+    pub fn connection_option(&self) -> ConnectionOption {
+        ConnectionOption {
+            ping_interval: Duration::from_secs(self.ping_interval_secs),
+            idle_timeout: Duration::from_secs(self.idle_timeout_secs),
+            max_message_size: self.max_message_size,
+        }
+    }
+
+    /// The client address which X-Forwarded-For tells if the peer is a trusted proxy, or the peer address
+    pub fn caller(&self, peer_addr: SocketAddr, forwarded_for: Option<&str>) -> String {
+        let is_trusted = self.trusted_proxies.iter().any(|proxy| proxy.contains(&peer_addr.ip()));
+        // The first address is the client, and the proxies append theirs
+        match forwarded_for.and_then(|addresses| addresses.split(',').next()).map(str::trim) {
+            Some(client) if is_trusted && !client.is_empty() => client.to_string(),
+            _ => peer_addr.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AgentConfig {
+    /// The agents are updated by this number of threads however many agents are connected
+    pub workers: usize,
+    pub update_interval_secs: u64,
+    /// Paces the connections when many agents reconnect at once
+    pub accept_rate: RateLimitOption,
+    pub command_timeouts: CommandTimeouts,
+    /// The nodes whose host clocks drift from the hub or the NTP servers beyond this are flagged and alerted
+    pub max_clock_skew_ms: i64,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            workers: 16,
+            update_interval_secs: 1,
+            accept_rate: RateLimitOption {
+                requests_per_second: 20.0,
+                burst: 50.0,
+            },
+            command_timeouts: Default::default(),
+            max_clock_skew_ms: 500,
+        }
+    }
+}
+
+impl AgentConfig {
+    pub fn update_interval(&self) -> Duration {
+        Duration::from_secs(self.update_interval_secs)
+    }
+}
+
+impl Config {
+    /// Reads the config file if the path is given, and applies the overrides of the environment variables.
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        let value = match path {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|err| format!("Cannot read the config {} : {}", path, err))?
+                .parse::<toml::Value>()
+                .map_err(|err| format!("Cannot parse the config {} : {}", path, err))?,
+            None => toml::Value::Table(Default::default()),
+        };
+        let overrides: Vec<(String, String)> = env::vars().filter(|(key, _)| key.starts_with(ENV_PREFIX)).collect();
+
+        let config = match apply_overrides(value.clone(), &overrides, None) {
+            Ok(config) => config,
+            // A value like 1234 is parsed as an integer, so it is retried as a string for the string settings
+            Err(err) => (0..overrides.len())
+                .filter(|index| !parse_env_value(&overrides[*index].1).is_str())
+                .filter_map(|index| apply_overrides(value.clone(), &overrides, Some(index)).ok())
+                .next()
+                .ok_or(err)?,
+        };
+        if let Some(secrets) = &config.secrets {
+            secrets.validate()?;
+        }
+        Ok(config)
+    }
+}
+
+/// The override at `raw_index` is applied as a string without being parsed
+fn apply_overrides(
+    mut value: toml::Value,
+    overrides: &[(String, String)],
+    raw_index: Option<usize>,
+) -> Result<Config, String> {
+    for (index, (key, raw)) in overrides.iter().enumerate() {
+        let path: Vec<String> =
+            key[ENV_PREFIX.len()..].split(ENV_SEPARATOR).map(|section| section.to_lowercase()).collect();
+        let new_value = if Some(index) == raw_index {
+            toml::Value::String(raw.clone())
+        } else {
+            parse_env_value(raw)
+        };
+        set(&mut value, &path, new_value).map_err(|err| format!("Cannot apply {} : {}", key, err))?;
+    }
+
+    let serialized = toml::to_string(&value).map_err(|err| format!("Cannot serialize the config : {}", err))?;
+    let mut config: Config = value.try_into().map_err(|err| format!("Invalid config : {}", err))?;
+    config.hash = Some(format!("{:x}", Sha256::digest(serialized.as_bytes())));
+    Ok(config)
+}
+
+fn set(value: &mut toml::Value, path: &[String], new_value: toml::Value) -> Result<(), String> {
+    let (last, sections) = path.split_last().expect("A path has at least a key");
+    let mut table = value;
+    for section in sections {
+        table = table
+            .as_table_mut()
+            .ok_or_else(|| format!("The parent of {} is not a table", section))?
+            .entry(section.clone())
+            .or_insert_with(|| toml::Value::Table(Default::default()));
+    }
+    table
+        .as_table_mut()
+        .ok_or_else(|| format!("The parent of {} is not a table", last))?
+        .insert(last.clone(), new_value);
+    Ok(())
+}
+
+/// The value is parsed as a TOML value, e.g. 60, true or ["127.0.0.1"], and it is a string if it is not
+fn parse_env_value(raw: &str) -> toml::Value {
+    format!("value = {}", raw)
+        .parse::<toml::Value>()
+        .ok()
+        .and_then(|parsed| parsed.get("value").cloned())
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
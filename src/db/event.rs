@@ -1,4 +1,4 @@
-use super::super::common_rpc_types::NodeName;
+use super::super::common_rpc_types::{NodeName, StructuredLog};
 use super::types::{AgentExtra, AgentQueryResult};
 
 pub enum Event {
@@ -15,6 +15,31 @@ pub enum Event {
         before: Option<AgentExtra>,
         after: AgentExtra,
     },
+    AgentRemoved {
+        name: NodeName,
+    },
+    AgentRenamed {
+        from: NodeName,
+        to: NodeName,
+    },
+    /// The tags are added or removed
+    TagsChanged {
+        name: NodeName,
+    },
+    /// The agent is connected, or reconnected after it was disconnected
+    AgentConnected {
+        state: AgentQueryResult,
+    },
+    /// `last` is the state before the agent is disconnected
+    AgentDisconnected {
+        last: AgentQueryResult,
+        reason: Option<String>,
+    },
+    LogRuleMatched {
+        name: NodeName,
+        rule: String,
+        log: StructuredLog,
+    },
 }
 
 pub trait EventSubscriber: Send {
@@ -0,0 +1,75 @@
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use postgres::{self, TlsMode};
+
+use super::queries;
+use super::storage::StorageOption;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LeaderElectionOption {
+    /// The hubs sharing a database should use the same id
+    pub lock_id: i64,
+    /// How often the standby tries the lock and the leader checks its connection
+    pub interval_secs: u64,
+}
+
+impl Default for LeaderElectionOption {
+    fn default() -> Self {
+        Self {
+            lock_id: 0x6875_6200,
+            interval_secs: 5,
+        }
+    }
+}
+
+/// Blocks while another hub holds the lock, so the standby doesn't listen to the agents and the frontends.
+/// The process exits when the leader loses the connection holding the lock, because the standby can take over then.
+pub fn become_leader(storage: &StorageOption, option: &LeaderElectionOption) {
+    let uri = match storage {
+        StorageOption::Postgres {
+            user,
+            password,
+            host,
+            ..
+        } => format!("postgres://{}:{}@{}", user, password, host),
+        StorageOption::Sqlite {
+            ..
+        } => {
+            cwarn!("The leader election needs Postgres, so this hub runs as the leader");
+            return
+        }
+    };
+    let interval = Duration::from_secs(option.interval_secs);
+    let mut is_waiting = false;
+    let conn = loop {
+        match postgres::Connection::connect(uri.as_str(), TlsMode::None) {
+            Ok(conn) => match queries::leader::try_lock(&conn, option.lock_id) {
+                Ok(true) => break conn,
+                Ok(false) if !is_waiting => {
+                    cinfo!("Another hub is the leader. Wait as the standby");
+                    is_waiting = true;
+                }
+                Ok(false) => {}
+                Err(err) => cwarn!("Cannot try the leader lock : {}", err),
+            },
+            Err(err) => cwarn!("Cannot connect to the database for the leader election : {}", err),
+        }
+        thread::sleep(interval);
+    };
+    cinfo!("This hub is the leader");
+
+    thread::Builder::new()
+        .name("leader".to_string())
+        .spawn(move || loop {
+            thread::sleep(interval);
+            if let Err(err) = queries::leader::ping(&conn) {
+                // The standby may hold the lock already, so this hub stops instead of trying it again
+                cerror!("Lost the connection holding the leader lock : {}", err);
+                process::exit(1);
+            }
+        })
+        .expect("Should success running leader thread");
+}
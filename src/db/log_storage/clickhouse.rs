@@ -0,0 +1,282 @@
+use std::error;
+
+use chrono;
+use chrono::TimeZone;
+use hyper;
+use hyper::header::ContentType;
+use hyper::method::Method;
+use serde_json;
+
+use super::super::super::common_rpc_types::{NodeName, StructuredLog};
+use super::super::types::{Log, LogContext, LogQueryParams, OrderBy, Paging};
+use super::{http_client, http_request, parse_timestamp, LogStorage, MAX_SAFE_INTEGER};
+
+/// Stores the logs in ClickHouse through its HTTP interface.
+/// ReplacingMergeTree removes the duplicated logs in the background.
+/// The id is a column which ClickHouse computes from the log, so the copies made by the rename get the new ids.
+pub struct ClickHouseLogStorage {
+    client: hyper::Client,
+    url: String,
+    table: String,
+}
+
+#[derive(Serialize)]
+struct InsertRow<'a> {
+    name: &'a str,
+    level: &'a str,
+    target: &'a str,
+    message: &'a str,
+    thread_name: &'a str,
+    timestamp: String,
+    backfilled: bool,
+}
+
+#[derive(Deserialize)]
+struct SearchRow {
+    id: i64,
+    name: String,
+    level: String,
+    target: String,
+    message: String,
+    thread_name: String,
+    timestamp_micros: i64,
+    backfilled: bool,
+}
+
+#[derive(Deserialize)]
+struct TargetRow {
+    target: String,
+}
+
+impl ClickHouseLogStorage {
+    pub fn new(url: String, table: String) -> Self {
+        let storage = Self {
+            client: http_client(),
+            url,
+            table,
+        };
+        if let Err(err) = storage.create_table() {
+            cerror!("Cannot create the log table in ClickHouse : {}", err);
+        }
+        storage
+    }
+
+    fn create_table(&self) -> Result<(), Box<error::Error>> {
+        let query = format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+            name String,
+            level LowCardinality(String),
+            target LowCardinality(String),
+            message String,
+            thread_name String,
+            timestamp DateTime64(6),
+            backfilled Bool,
+            id UInt64 MATERIALIZED {id}
+        ) ENGINE = ReplacingMergeTree ORDER BY (name, timestamp, cityHash64(message))",
+            self.table,
+            id = id_expression()
+        );
+        self.query(&query)?;
+        // The tables created before the id was added compute it when they are read
+        self.query(&format!(
+            "ALTER TABLE {} ADD COLUMN IF NOT EXISTS id UInt64 MATERIALIZED {}",
+            self.table,
+            id_expression()
+        ))?;
+        Ok(())
+    }
+
+    /// The clauses after FROM are appended
+    fn select(&self, clauses: &str) -> Result<Vec<Log>, Box<error::Error>> {
+        let query = format!(
+            "SELECT id, name, level, target, message, thread_name, \
+             toUnixTimestamp64Micro(timestamp) AS timestamp_micros, backfilled FROM {} FINAL {} FORMAT JSONEachRow",
+            self.table, clauses
+        );
+        let response = self.query(&query)?;
+        let mut logs = Vec::new();
+        for line in response.lines().filter(|line| !line.is_empty()) {
+            let row: SearchRow = serde_json::from_str(line)?;
+            let timestamp = chrono::Local.timestamp(
+                row.timestamp_micros.div_euclid(1_000_000),
+                (row.timestamp_micros.rem_euclid(1_000_000) * 1000) as u32,
+            );
+            logs.push(Log {
+                id: row.id,
+                message: format!("{} {}", row.thread_name, row.message),
+                node_name: row.name,
+                level: row.level,
+                target: row.target,
+                timestamp,
+                backfilled: row.backfilled,
+            });
+        }
+        Ok(logs)
+    }
+
+    fn query(&self, query: &str) -> Result<String, Box<error::Error>> {
+        let url = format!("{}/?date_time_input_format=best_effort&output_format_json_quote_64bit_integers=0", self.url);
+        http_request(&self.client, Method::Post, &url, ContentType::plaintext(), query)
+    }
+}
+
+impl LogStorage for ClickHouseLogStorage {
+    fn insert(
+        &self,
+        node_name: &NodeName,
+        logs: Vec<StructuredLog>,
+        backfilled: bool,
+    ) -> Result<(), Box<error::Error>> {
+        if logs.is_empty() {
+            return Ok(())
+        }
+
+        let mut body = format!("INSERT INTO {} FORMAT JSONEachRow\n", self.table);
+        for log in &logs {
+            let row = InsertRow {
+                name: node_name,
+                level: &log.level,
+                target: &log.target,
+                message: &log.message,
+                thread_name: &log.thread_name,
+                timestamp: parse_timestamp(&log.timestamp)?.to_rfc3339(),
+                backfilled,
+            };
+            body.push_str(&serde_json::to_string(&row)?);
+            body.push('\n');
+        }
+        self.query(&body)?;
+        Ok(())
+    }
+
+    fn search(&self, params: LogQueryParams) -> Result<Vec<Log>, Box<error::Error>> {
+        let mut where_conditions = Vec::new();
+        if let Some(filter) = params.filter {
+            if !filter.node_names.is_empty() {
+                where_conditions.push(format!("name IN ({})", quote_all(&filter.node_names)));
+            }
+            if !filter.levels.is_empty() {
+                let uppercase_levels: Vec<String> =
+                    filter.levels.iter().map(|level| level.to_string().to_uppercase()).collect();
+                where_conditions.push(format!("level IN ({})", quote_all(&uppercase_levels)));
+            }
+            if !filter.targets.is_empty() {
+                where_conditions.push(format!("target IN ({})", quote_all(&filter.targets)));
+            }
+            if let Some(thread_name) = filter.thread_name {
+                where_conditions.push(format!("thread_name = {}", quote(&thread_name)));
+            }
+        }
+        if let Some(search) = params.search {
+            if search != "" {
+                where_conditions.push(format!("positionCaseInsensitiveUTF8(message, {}) > 0", quote(&search)));
+            }
+        }
+        if let Some(time) = params.time {
+            if let Some(from) = time.from_time {
+                let from = quote(&from.to_rfc3339());
+                where_conditions.push(format!("timestamp > parseDateTime64BestEffort({}, 6)", from));
+            }
+            if let Some(to) = time.to_time {
+                let to = quote(&to.to_rfc3339());
+                where_conditions.push(format!("timestamp < parseDateTime64BestEffort({}, 6)", to));
+            }
+        }
+        if let Some(after) = params.after.as_ref() {
+            let timestamp = quote(&after.timestamp.to_rfc3339());
+            where_conditions.push(format!("timestamp >= parseDateTime64BestEffort({}, 6)", timestamp));
+        }
+
+        let where_clause = if !where_conditions.is_empty() {
+            "WHERE ".to_string() + &where_conditions.join(" AND ")
+        } else {
+            "".to_string()
+        };
+
+        let order_by = if params.after.is_some() {
+            OrderBy::ASC
+        } else {
+            params.order_by.unwrap_or(OrderBy::ASC)
+        };
+        let order_by_clause = format!("ORDER BY timestamp {:?}, name {:?}, message {:?}", order_by, order_by, order_by);
+
+        let paging = Paging::new(params.page, params.item_per_page)?;
+        let limit_clause = format!("LIMIT {}", paging.limit);
+
+        // The logs at the timestamp of the cursor are skipped instead of the pages, because the ids are hashes
+        let offset = match params.after.as_ref() {
+            Some(after) => after.read_at_timestamp,
+            None => paging.offset,
+        };
+        let offset_clause = format!("OFFSET {}", offset);
+
+        self.select(&[&where_clause, &order_by_clause, &limit_clause, &offset_clause].join(" "))
+    }
+
+    /// The logs which have the same timestamp are ordered by the ids
+    fn get_context(&self, id: i64, before: i64, after: i64) -> Result<Option<LogContext>, Box<error::Error>> {
+        let log = match self.select(&format!("WHERE id = {} LIMIT 1", id))?.pop() {
+            Some(log) => log,
+            None => return Ok(None),
+        };
+        let micros = log.timestamp.timestamp() * 1_000_000 + i64::from(log.timestamp.timestamp_subsec_micros());
+        let position =
+            |operator: &str| format!("(toUnixTimestamp64Micro(timestamp), id) {} ({}, {})", operator, micros, log.id);
+        let mut before = self.select(&format!(
+            "WHERE name = {} AND {} ORDER BY timestamp DESC, id DESC LIMIT {}",
+            quote(&log.node_name),
+            position("<"),
+            before
+        ))?;
+        before.reverse();
+        let after = self.select(&format!(
+            "WHERE name = {} AND {} ORDER BY timestamp ASC, id ASC LIMIT {}",
+            quote(&log.node_name),
+            position(">"),
+            after
+        ))?;
+        Ok(Some(LogContext {
+            before,
+            log,
+            after,
+        }))
+    }
+
+    fn get_targets(&self) -> Result<Vec<String>, Box<error::Error>> {
+        let query = format!("SELECT DISTINCT target FROM {} ORDER BY target FORMAT JSONEachRow", self.table);
+        let response = self.query(&query)?;
+        let mut targets = Vec::new();
+        for line in response.lines().filter(|line| !line.is_empty()) {
+            let row: TargetRow = serde_json::from_str(line)?;
+            targets.push(row.target);
+        }
+        Ok(targets)
+    }
+
+    /// The name is a part of the sorting key which cannot be updated, so the logs are copied and deleted.
+    fn rename(&self, from: &NodeName, to: &NodeName) -> Result<(), Box<error::Error>> {
+        let copy = format!(
+            "INSERT INTO {table} SELECT {to} AS name, level, target, message, thread_name, timestamp, backfilled \
+             FROM {table} WHERE name = {from}",
+            table = self.table,
+            to = quote(to),
+            from = quote(from)
+        );
+        self.query(&copy)?;
+        self.query(&format!("ALTER TABLE {} DELETE WHERE name = {}", self.table, quote(from)))?;
+        Ok(())
+    }
+}
+
+fn id_expression() -> String {
+    format!("bitAnd(cityHash64(name, toString(timestamp), message), {})", MAX_SAFE_INTEGER)
+}
+
+/// Makes a ClickHouse string literal
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn quote_all(values: &[String]) -> String {
+    values.iter().map(|value| quote(value)).collect::<Vec<_>>().join(", ")
+}
@@ -0,0 +1,48 @@
+use std::error;
+use std::sync::Arc;
+
+use super::super::super::common_rpc_types::{NodeName, StructuredLog};
+use super::super::storage::Storage;
+use super::super::types::{Log, LogContext, LogQueryParams};
+use super::LogStorage;
+
+/// Stores the logs in the same database with the other states of the hub
+pub struct DatabaseLogStorage {
+    storage: Arc<Storage>,
+}
+
+impl DatabaseLogStorage {
+    pub fn new(storage: Arc<Storage>) -> Self {
+        Self {
+            storage,
+        }
+    }
+}
+
+impl LogStorage for DatabaseLogStorage {
+    fn insert(
+        &self,
+        node_name: &NodeName,
+        logs: Vec<StructuredLog>,
+        backfilled: bool,
+    ) -> Result<(), Box<error::Error>> {
+        Ok(self.storage.insert_logs(node_name, logs, backfilled)?)
+    }
+
+    fn search(&self, params: LogQueryParams) -> Result<Vec<Log>, Box<error::Error>> {
+        Ok(self.storage.search_logs(params)?)
+    }
+
+    fn get_context(&self, id: i64, before: i64, after: i64) -> Result<Option<LogContext>, Box<error::Error>> {
+        Ok(self.storage.get_log_context(id, before, after)?)
+    }
+
+    fn get_targets(&self) -> Result<Vec<String>, Box<error::Error>> {
+        Ok(self.storage.get_log_targets()?)
+    }
+
+    /// The logs are renamed with the other states by Storage::rename_node
+    fn rename(&self, _from: &NodeName, _to: &NodeName) -> Result<(), Box<error::Error>> {
+        Ok(())
+    }
+}
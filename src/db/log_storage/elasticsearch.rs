@@ -0,0 +1,375 @@
+use std::cmp;
+use std::error;
+
+use chrono;
+use hyper;
+use hyper::header::ContentType;
+use hyper::method::Method;
+use serde_json;
+use serde_json::Value;
+
+use super::super::super::common_rpc_types::{NodeName, StructuredLog};
+use super::super::types::{Log, LogContext, LogQueryParams, OrderBy, Paging};
+use super::{http_client, http_request, log_hash, log_id, parse_timestamp, LogStorage};
+
+const MAX_TARGETS: usize = 10000;
+/// index.max_result_window. The deeper pages are reached with search_after.
+const MAX_RESULT_WINDOW: i64 = 10000;
+const RENAME_BATCH: i64 = 1000;
+
+/// Stores the logs in an Elasticsearch index.
+/// The document id is the hash of the log, so the duplicated logs are rejected.
+pub struct ElasticsearchLogStorage {
+    client: hyper::Client,
+    url: String,
+    index: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    name: String,
+    level: String,
+    target: String,
+    message: String,
+    thread_name: String,
+    timestamp: chrono::DateTime<chrono::FixedOffset>,
+    backfilled: bool,
+    /// The same as the document id. It orders the logs which have the same timestamp.
+    #[serde(default)]
+    hash: String,
+    /// The id in the hub, which is 53 bits of the hash
+    #[serde(default)]
+    log_id: i64,
+}
+
+impl Document {
+    fn new(
+        name: String,
+        level: String,
+        target: String,
+        message: String,
+        thread_name: String,
+        timestamp: chrono::DateTime<chrono::FixedOffset>,
+        backfilled: bool,
+    ) -> Self {
+        let hash = log_hash(&name, &timestamp.to_rfc3339(), &message);
+        Self {
+            name,
+            level,
+            target,
+            message,
+            thread_name,
+            timestamp,
+            backfilled,
+            hash: format!("{:016x}", hash),
+            log_id: log_id(hash),
+        }
+    }
+
+    fn into_log(self) -> Log {
+        Log {
+            id: self.log_id,
+            message: format!("{} {}", self.thread_name, self.message),
+            node_name: self.name,
+            level: self.level,
+            target: self.target,
+            timestamp: self.timestamp.with_timezone(&chrono::Local),
+            backfilled: self.backfilled,
+        }
+    }
+}
+
+impl ElasticsearchLogStorage {
+    pub fn new(url: String, index: String) -> Self {
+        let storage = Self {
+            client: http_client(),
+            url,
+            index,
+        };
+        if let Err(err) = storage.create_index() {
+            cerror!("Cannot create the log index in Elasticsearch : {}", err);
+        }
+        storage
+    }
+
+    fn create_index(&self) -> Result<(), Box<error::Error>> {
+        let url = format!("{}/{}", self.url, self.index);
+        let exists = self.client.head(&url).send()?.status.is_success();
+        if exists {
+            return Ok(())
+        }
+
+        let mappings = json!({
+            "mappings": {
+                "properties": {
+                    "name": { "type": "keyword" },
+                    "level": { "type": "keyword" },
+                    "target": { "type": "keyword" },
+                    "message": { "type": "text" },
+                    "thread_name": { "type": "keyword" },
+                    "timestamp": { "type": "date" },
+                    "backfilled": { "type": "boolean" },
+                    "hash": { "type": "keyword" },
+                    "log_id": { "type": "long" },
+                }
+            }
+        });
+        http_request(&self.client, Method::Put, &url, ContentType::json(), &mappings.to_string())?;
+        Ok(())
+    }
+
+    fn search_index(&self, query: &Value) -> Result<Value, Box<error::Error>> {
+        let url = format!("{}/{}/_search", self.url, self.index);
+        let response = http_request(&self.client, Method::Post, &url, ContentType::json(), &query.to_string())?;
+        Ok(serde_json::from_str(&response)?)
+    }
+
+    fn hits(&self, query: &Value) -> Result<Vec<Value>, Box<error::Error>> {
+        Ok(self.search_index(query)?["hits"]["hits"].as_array().cloned().unwrap_or_default())
+    }
+
+    fn logs(&self, query: &Value) -> Result<Vec<Log>, Box<error::Error>> {
+        let mut logs = Vec::new();
+        for hit in self.hits(query)? {
+            let document: Document = serde_json::from_value(hit["_source"].clone())?;
+            logs.push(document.into_log());
+        }
+        Ok(logs)
+    }
+
+    /// Elasticsearch refuses the pages beyond the result window, so the hits before them are skipped with
+    /// search_after. The query should be sorted by the unique fields.
+    fn search_page(&self, mut query: Value, from: i64, size: i64) -> Result<Vec<Log>, Box<error::Error>> {
+        let mut skip = from;
+        while skip > 0 && skip + size > MAX_RESULT_WINDOW {
+            let step = cmp::min(skip, MAX_RESULT_WINDOW);
+            query["size"] = json!(step);
+            query["_source"] = json!(false);
+            let hits = self.hits(&query)?;
+            if (hits.len() as i64) < step {
+                return Ok(Vec::new())
+            }
+            query["search_after"] = hits[hits.len() - 1]["sort"].clone();
+            skip -= step;
+        }
+        query["from"] = json!(skip);
+        query["size"] = json!(size);
+        query["_source"] = json!(true);
+        self.logs(&query)
+    }
+
+    fn bulk(&self, body: &str, refresh: bool) -> Result<Value, Box<error::Error>> {
+        let url = format!("{}/_bulk?refresh={}", self.url, refresh);
+        let content_type = ContentType("application/x-ndjson".parse().expect("Valid mime"));
+        let response = http_request(&self.client, Method::Post, &url, content_type, body)?;
+        let response: Value = serde_json::from_str(&response)?;
+        Ok(response)
+    }
+}
+
+impl LogStorage for ElasticsearchLogStorage {
+    fn insert(
+        &self,
+        node_name: &NodeName,
+        logs: Vec<StructuredLog>,
+        backfilled: bool,
+    ) -> Result<(), Box<error::Error>> {
+        if logs.is_empty() {
+            return Ok(())
+        }
+
+        let mut body = String::new();
+        for log in logs {
+            let document = Document::new(
+                node_name.clone(),
+                log.level,
+                log.target,
+                log.message,
+                log.thread_name,
+                parse_timestamp(&log.timestamp)?,
+                backfilled,
+            );
+            let action = json!({
+                "create": {
+                    "_index": self.index,
+                    "_id": document.hash,
+                }
+            });
+            body.push_str(&action.to_string());
+            body.push('\n');
+            body.push_str(&serde_json::to_string(&document)?);
+            body.push('\n');
+        }
+
+        let response = self.bulk(&body, false)?;
+
+        // 409 means that the log is already stored
+        let failed = response["items"]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter(|item| {
+                        item["create"]["status"].as_u64().map_or(false, |status| status >= 300 && status != 409)
+                    })
+                    .count()
+            })
+            .unwrap_or(0);
+        if failed > 0 {
+            cwarn!("Elasticsearch failed to store {} logs of {}", failed, node_name);
+        }
+        Ok(())
+    }
+
+    fn search(&self, params: LogQueryParams) -> Result<Vec<Log>, Box<error::Error>> {
+        let mut filters = Vec::new();
+        if let Some(filter) = params.filter {
+            if !filter.node_names.is_empty() {
+                filters.push(json!({ "terms": { "name": filter.node_names } }));
+            }
+            if !filter.levels.is_empty() {
+                let uppercase_levels: Vec<String> =
+                    filter.levels.iter().map(|level| level.to_string().to_uppercase()).collect();
+                filters.push(json!({ "terms": { "level": uppercase_levels } }));
+            }
+            if !filter.targets.is_empty() {
+                filters.push(json!({ "terms": { "target": filter.targets } }));
+            }
+            if let Some(thread_name) = filter.thread_name {
+                filters.push(json!({ "term": { "thread_name": thread_name } }));
+            }
+        }
+        if let Some(search) = params.search {
+            if search != "" {
+                filters.push(json!({ "match_phrase": { "message": search } }));
+            }
+        }
+        if let Some(after) = params.after.as_ref() {
+            filters.push(json!({ "range": { "timestamp": { "gte": after.timestamp.to_rfc3339() } } }));
+        }
+        if let Some(time) = params.time {
+            let mut range = json!({});
+            if let Some(from) = time.from_time {
+                range["gt"] = json!(from.to_rfc3339());
+            }
+            if let Some(to) = time.to_time {
+                range["lt"] = json!(to.to_rfc3339());
+            }
+            filters.push(json!({ "range": { "timestamp": range } }));
+        }
+
+        let order = match (&params.after, params.order_by.unwrap_or(OrderBy::ASC)) {
+            (Some(_), _) | (None, OrderBy::ASC) => "asc",
+            (None, OrderBy::DESC) => "desc",
+        };
+        let paging = Paging::new(params.page, params.item_per_page)?;
+        // The logs at the timestamp of the cursor are skipped instead of the pages, because the ids are hashes
+        let from = match params.after.as_ref() {
+            Some(after) => after.read_at_timestamp,
+            None => paging.offset,
+        };
+
+        let query = json!({
+            "sort": [
+                { "timestamp": { "order": order } },
+                { "name": { "order": order } },
+                { "hash": { "order": order, "missing": "_last" } },
+            ],
+            "query": { "bool": { "filter": filters } },
+        });
+
+        self.search_page(query, from, paging.limit)
+    }
+
+    /// The logs which have the same timestamp are ordered by the ids
+    fn get_context(&self, id: i64, before: i64, after: i64) -> Result<Option<LogContext>, Box<error::Error>> {
+        let sort = |order: &str| json!([{ "timestamp": { "order": order } }, { "log_id": { "order": order } }]);
+        let hits = self.hits(&json!({
+            "size": 1,
+            "sort": sort("asc"),
+            "query": { "term": { "log_id": id } },
+        }))?;
+        let hit = match hits.into_iter().next() {
+            Some(hit) => hit,
+            None => return Ok(None),
+        };
+        let document: Document = serde_json::from_value(hit["_source"].clone())?;
+        let query = json!({ "term": { "name": document.name } });
+        let mut before = self.logs(&json!({
+            "size": before,
+            "sort": sort("desc"),
+            "search_after": hit["sort"],
+            "query": query,
+        }))?;
+        before.reverse();
+        let after = self.logs(&json!({
+            "size": after,
+            "sort": sort("asc"),
+            "search_after": hit["sort"],
+            "query": query,
+        }))?;
+        Ok(Some(LogContext {
+            before,
+            log: document.into_log(),
+            after,
+        }))
+    }
+
+    fn get_targets(&self) -> Result<Vec<String>, Box<error::Error>> {
+        let query = json!({
+            "size": 0,
+            "aggs": { "targets": { "terms": { "field": "target", "size": MAX_TARGETS } } },
+        });
+
+        let response = self.search_index(&query)?;
+
+        Ok(response["aggregations"]["targets"]["buckets"]
+            .as_array()
+            .map(|buckets| {
+                buckets.iter().filter_map(|bucket| bucket["key"].as_str().map(|key| key.to_string())).collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// The document ids are the hashes with the name, so the logs are indexed again with the new ids
+    /// and the old documents are deleted. Then the logs which the agent sends again are deduplicated.
+    fn rename(&self, from: &NodeName, to: &NodeName) -> Result<(), Box<error::Error>> {
+        let mut query = json!({
+            "size": RENAME_BATCH,
+            "sort": [{ "hash": { "order": "asc" } }],
+            "query": { "term": { "name": from } },
+        });
+        loop {
+            let hits = self.hits(&query)?;
+            let last = match hits.last() {
+                Some(last) => last["sort"].clone(),
+                None => break,
+            };
+            let mut body = String::new();
+            for hit in &hits {
+                let old: Document = serde_json::from_value(hit["_source"].clone())?;
+                let document = Document::new(
+                    to.clone(),
+                    old.level,
+                    old.target,
+                    old.message,
+                    old.thread_name,
+                    old.timestamp,
+                    old.backfilled,
+                );
+                let index = json!({ "index": { "_index": self.index, "_id": document.hash } });
+                let delete = json!({ "delete": { "_index": self.index, "_id": hit["_id"] } });
+                body.push_str(&format!("{}\n{}\n{}\n", index, serde_json::to_string(&document)?, delete));
+            }
+            let response = self.bulk(&body, true)?;
+            if response["errors"].as_bool().unwrap_or(false) {
+                return Err(format!("Elasticsearch failed to move the logs of {} to {}", from, to).into())
+            }
+            if (hits.len() as i64) < RENAME_BATCH {
+                break
+            }
+            query["search_after"] = last;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+mod clickhouse;
+mod database;
+mod elasticsearch;
+
+use std::error;
+use std::io;
+use std::io::Read;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, sync_channel, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use chrono;
+use hyper;
+use hyper::client::pool::Pool;
+use hyper::header::ContentType;
+use hyper::net::{HttpStream, NetworkConnector};
+
+use super::super::common_rpc_types::{NodeName, StructuredLog};
+use super::storage::Storage;
+use super::types::{Log, LogContext, LogQueryParams};
+
+/// Stores the logs of CodeChain. The other states of the hub are always stored in the Storage.
+pub trait LogStorage: Send + Sync {
+    /// Inserting the same logs again should be harmless.
+    fn insert(
+        &self,
+        node_name: &NodeName,
+        logs: Vec<StructuredLog>,
+        backfilled: bool,
+    ) -> Result<(), Box<error::Error>>;
+    fn search(&self, params: LogQueryParams) -> Result<Vec<Log>, Box<error::Error>>;
+    /// None if the log doesn't exist
+    fn get_context(&self, id: i64, before: i64, after: i64) -> Result<Option<LogContext>, Box<error::Error>>;
+    fn get_targets(&self) -> Result<Vec<String>, Box<error::Error>>;
+    fn rename(&self, from: &NodeName, to: &NodeName) -> Result<(), Box<error::Error>>;
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum LogStorageOption {
+    Database,
+    #[serde(rename_all = "camelCase")]
+    ClickHouse {
+        url: String,
+        table: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Elasticsearch {
+        url: String,
+        index: String,
+    },
+}
+
+impl Default for LogStorageOption {
+    fn default() -> Self {
+        LogStorageOption::Database
+    }
+}
+
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+/// The batches of the logs which wait for a remote storage. The new batches are dropped when it is full.
+const MAX_QUEUED_BATCHES: usize = 1000;
+
+/// The remote storages are written on their own thread, so a hung server doesn't block the db service
+pub fn create(option: LogStorageOption, storage: Arc<Storage>) -> Arc<LogStorage> {
+    match option {
+        LogStorageOption::Database => Arc::new(database::DatabaseLogStorage::new(storage)),
+        LogStorageOption::ClickHouse {
+            url,
+            table,
+        } => Shipper::run("clickhouse", Arc::new(clickhouse::ClickHouseLogStorage::new(url, table))),
+        LogStorageOption::Elasticsearch {
+            url,
+            index,
+        } => Shipper::run("elasticsearch", Arc::new(elasticsearch::ElasticsearchLogStorage::new(url, index))),
+    }
+}
+
+enum Shipment {
+    Insert(NodeName, Vec<StructuredLog>, bool),
+    /// Renamed after the logs queued before it are written
+    Rename(NodeName, NodeName, Sender<Result<(), String>>),
+}
+
+/// Writes the logs to a remote storage on its own thread. The reads go to the storage directly.
+struct Shipper {
+    storage: Arc<LogStorage>,
+    queue: Mutex<SyncSender<Shipment>>,
+}
+
+impl Shipper {
+    fn run(name: &str, storage: Arc<LogStorage>) -> Arc<LogStorage> {
+        let (tx, rx) = sync_channel(MAX_QUEUED_BATCHES);
+        let remote = Arc::clone(&storage);
+        thread::Builder::new()
+            .name(format!("{} log shipper", name))
+            .spawn(move || {
+                for shipment in rx {
+                    match shipment {
+                        Shipment::Insert(node_name, logs, backfilled) => {
+                            if let Err(err) = remote.insert(&node_name, logs, backfilled) {
+                                cerror!("Cannot write the logs of {} : {}", node_name, err);
+                            }
+                        }
+                        Shipment::Rename(from, to, callback) => {
+                            let result = remote.rename(&from, &to).map_err(|err| err.to_string());
+                            if callback.send(result).is_err() {
+                                cwarn!("The result of renaming the logs of {} is not received", from);
+                            }
+                        }
+                    }
+                }
+            })
+            .expect("Should success running the log shipper thread");
+        Arc::new(Self {
+            storage,
+            queue: Mutex::new(tx),
+        })
+    }
+
+    fn queue(&self) -> SyncSender<Shipment> {
+        self.queue.lock().expect("Should success get the log queue").clone()
+    }
+}
+
+impl LogStorage for Shipper {
+    fn insert(
+        &self,
+        node_name: &NodeName,
+        logs: Vec<StructuredLog>,
+        backfilled: bool,
+    ) -> Result<(), Box<error::Error>> {
+        match self.queue().try_send(Shipment::Insert(node_name.clone(), logs, backfilled)) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                cwarn!("The log storage is too slow. The logs of {} are dropped", node_name);
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => Err("The log shipper is stopped".into()),
+        }
+    }
+
+    fn search(&self, params: LogQueryParams) -> Result<Vec<Log>, Box<error::Error>> {
+        self.storage.search(params)
+    }
+
+    fn get_context(&self, id: i64, before: i64, after: i64) -> Result<Option<LogContext>, Box<error::Error>> {
+        self.storage.get_context(id, before, after)
+    }
+
+    fn get_targets(&self) -> Result<Vec<String>, Box<error::Error>> {
+        self.storage.get_targets()
+    }
+
+    fn rename(&self, from: &NodeName, to: &NodeName) -> Result<(), Box<error::Error>> {
+        let (tx, rx) = channel();
+        self.queue().send(Shipment::Rename(from.clone(), to.clone(), tx)).map_err(|err| err.to_string())?;
+        Ok(rx.recv()??)
+    }
+}
+
+/// hyper 0.10 has no connect timeout, so the connector has it
+struct TimeoutConnector;
+
+impl NetworkConnector for TimeoutConnector {
+    type Stream = HttpStream;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> hyper::Result<HttpStream> {
+        if scheme != "http" {
+            let err = io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not supported", scheme));
+            return Err(err.into())
+        }
+        let mut last_err = io::Error::new(io::ErrorKind::NotFound, format!("Cannot resolve {}", host));
+        for address in (host, port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+                Ok(stream) => return Ok(HttpStream(stream)),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err.into())
+    }
+}
+
+/// The client of the remote storages, which gives up on a hung server
+fn http_client() -> hyper::Client {
+    let mut client = hyper::Client::with_connector(Pool::with_connector(Default::default(), TimeoutConnector));
+    client.set_read_timeout(Some(READ_WRITE_TIMEOUT));
+    client.set_write_timeout(Some(READ_WRITE_TIMEOUT));
+    client
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<chrono::DateTime<chrono::FixedOffset>, chrono::ParseError> {
+    let rfc3339with_nano_second = "%Y-%m-%dT%H:%M:%S.%f%z";
+    chrono::DateTime::parse_from_str(timestamp, rfc3339with_nano_second)
+}
+
+/// FNV-1a hash of the fields which identify a log. It doesn't change between builds unlike DefaultHasher.
+fn log_hash(node_name: &str, timestamp: &str, message: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in node_name.bytes().chain(timestamp.bytes()).chain(message.bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// The id of a log in the storages which don't have a serial id.
+/// It is 53 bits of the hash, which a JavaScript number holds exactly.
+fn log_id(hash: u64) -> i64 {
+    (hash & MAX_SAFE_INTEGER) as i64
+}
+
+fn http_request(
+    client: &hyper::Client,
+    method: hyper::method::Method,
+    url: &str,
+    content_type: ContentType,
+    body: &str,
+) -> Result<String, Box<error::Error>> {
+    let mut response = client.request(method, url).header(content_type).body(body).send()?;
+    let mut text = String::new();
+    response.read_to_string(&mut text)?;
+    if !response.status.is_success() {
+        return Err(format!("{} responded {} : {}", url, response.status, text).into())
+    }
+    Ok(text)
+}
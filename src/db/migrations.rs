@@ -0,0 +1,502 @@
+use super::storage::Storage;
+use super::types::Error as DBError;
+
+/**
+ * A versioned change of the schema. Migrations are applied in the order of the versions on startup.
+ * Never modify a released migration. Add a new migration with the next version instead.
+ */
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub postgres: &'static str,
+    pub sqlite: &'static str,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_agent_extra_and_logs",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS agent_extra (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL UNIQUE,
+                prev_env VARCHAR NOT NULL,
+                prev_args VARCHAR NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS logs (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL,
+                level VARCHAR NOT NULL,
+                target VARCHAR NOT NULL,
+                thread_name VARCHAR NOT NULL,
+                message VARCHAR NOT NULL,
+                timestamp TIMESTAMP WITH TIME ZONE NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS logs_timestamp ON logs (timestamp);
+            CREATE INDEX IF NOT EXISTS logs_targets ON logs (target);
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS agent_extra (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                prev_env TEXT NOT NULL,
+                prev_args TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS logs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                level TEXT NOT NULL,
+                target TEXT NOT NULL,
+                thread_name TEXT NOT NULL,
+                message TEXT NOT NULL,
+                timestamp TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS logs_timestamp ON logs (timestamp);
+            CREATE INDEX IF NOT EXISTS logs_targets ON logs (target);
+        ",
+    },
+    Migration {
+        version: 2,
+        name: "create_node_tags",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS node_tags (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL,
+                tag VARCHAR NOT NULL,
+                UNIQUE (name, tag)
+            );
+            CREATE INDEX IF NOT EXISTS node_tags_tag ON node_tags (tag);
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS node_tags (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                UNIQUE (name, tag)
+            );
+            CREATE INDEX IF NOT EXISTS node_tags_tag ON node_tags (tag);
+        ",
+    },
+    Migration {
+        version: 3,
+        name: "create_audit_logs",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS audit_logs (
+                id SERIAL PRIMARY KEY,
+                timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+                caller VARCHAR,
+                method VARCHAR NOT NULL,
+                node_name VARCHAR,
+                arguments VARCHAR NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS audit_logs_timestamp ON audit_logs (timestamp);
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS audit_logs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                caller TEXT,
+                method TEXT NOT NULL,
+                node_name TEXT,
+                arguments TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS audit_logs_timestamp ON audit_logs (timestamp);
+        ",
+    },
+    Migration {
+        version: 4,
+        name: "create_node_events",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS node_events (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL,
+                kind VARCHAR NOT NULL,
+                detail VARCHAR NOT NULL,
+                timestamp TIMESTAMP WITH TIME ZONE NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS node_events_name_timestamp ON node_events (name, timestamp);
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS node_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                detail TEXT NOT NULL,
+                timestamp TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS node_events_name_timestamp ON node_events (name, timestamp);
+        ",
+    },
+    Migration {
+        version: 5,
+        name: "add_logs_backfilled_and_dedup",
+        postgres: "
+            ALTER TABLE logs ADD COLUMN IF NOT EXISTS backfilled BOOLEAN NOT NULL DEFAULT FALSE;
+            -- The logs written twice before the index keep the first copy
+            DELETE FROM logs duplicate USING logs original
+                WHERE duplicate.name = original.name AND duplicate.timestamp = original.timestamp
+                    AND md5(duplicate.message) = md5(original.message) AND duplicate.id > original.id;
+            CREATE UNIQUE INDEX IF NOT EXISTS logs_dedup ON logs (name, timestamp, md5(message));
+        ",
+        sqlite: "
+            ALTER TABLE logs ADD COLUMN backfilled INTEGER NOT NULL DEFAULT 0;
+            DELETE FROM logs WHERE id NOT IN (SELECT MIN(id) FROM logs GROUP BY name, timestamp, message);
+            CREATE UNIQUE INDEX IF NOT EXISTS logs_dedup ON logs (name, timestamp, message);
+        ",
+    },
+    Migration {
+        version: 6,
+        name: "create_slos",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS slos (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL UNIQUE,
+                tags VARCHAR[] NOT NULL,
+                target DOUBLE PRECISION NOT NULL,
+                window_minutes INTEGER NOT NULL,
+                burn_rate_threshold DOUBLE PRECISION NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS slo_samples (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL,
+                minute TIMESTAMP WITH TIME ZONE NOT NULL,
+                good BOOLEAN NOT NULL,
+                UNIQUE (name, minute)
+            );
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS slos (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                tags TEXT NOT NULL,
+                target REAL NOT NULL,
+                window_minutes INTEGER NOT NULL,
+                burn_rate_threshold REAL NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS slo_samples (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                minute TEXT NOT NULL,
+                good INTEGER NOT NULL,
+                UNIQUE (name, minute)
+            );
+        ",
+    },
+    Migration {
+        version: 7,
+        name: "create_agent_snapshots",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS agent_snapshots (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL UNIQUE,
+                snapshot VARCHAR NOT NULL,
+                updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+            );
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS agent_snapshots (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                snapshot TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+        ",
+    },
+    Migration {
+        version: 8,
+        name: "create_schedules",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS schedules (
+                id SERIAL PRIMARY KEY,
+                kind VARCHAR NOT NULL,
+                cron VARCHAR NOT NULL,
+                node_name VARCHAR NOT NULL,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS schedule_runs (
+                id SERIAL PRIMARY KEY,
+                schedule_id INTEGER NOT NULL,
+                node_name VARCHAR NOT NULL,
+                started_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                finished_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                result VARCHAR NOT NULL,
+                detail VARCHAR
+            );
+            CREATE INDEX IF NOT EXISTS schedule_runs_schedule_id ON schedule_runs (schedule_id, started_at);
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS schedules (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                cron TEXT NOT NULL,
+                node_name TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS schedule_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                schedule_id INTEGER NOT NULL,
+                node_name TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                finished_at TEXT NOT NULL,
+                result TEXT NOT NULL,
+                detail TEXT
+            );
+            CREATE INDEX IF NOT EXISTS schedule_runs_schedule_id ON schedule_runs (schedule_id, started_at);
+        ",
+    },
+    Migration {
+        version: 9,
+        name: "create_archived_nodes",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS archived_nodes (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL,
+                archived_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                detail VARCHAR NOT NULL
+            );
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS archived_nodes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                archived_at TEXT NOT NULL,
+                detail TEXT NOT NULL
+            );
+        ",
+    },
+    Migration {
+        version: 10,
+        name: "create_node_aliases",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS node_aliases (
+                id SERIAL PRIMARY KEY,
+                reported_name VARCHAR NOT NULL UNIQUE,
+                name VARCHAR NOT NULL
+            );
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS node_aliases (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                reported_name TEXT NOT NULL UNIQUE,
+                name TEXT NOT NULL
+            );
+        ",
+    },
+    Migration {
+        version: 11,
+        name: "add_agent_extra_binary_path",
+        postgres: "
+            ALTER TABLE agent_extra ADD COLUMN IF NOT EXISTS binary_path VARCHAR;
+        ",
+        sqlite: "
+            ALTER TABLE agent_extra ADD COLUMN binary_path TEXT;
+        ",
+    },
+    Migration {
+        version: 12,
+        name: "add_audit_logs_sandbox",
+        postgres: "
+            ALTER TABLE audit_logs ADD COLUMN IF NOT EXISTS sandbox BOOLEAN NOT NULL DEFAULT FALSE;
+        ",
+        sqlite: "
+            ALTER TABLE audit_logs ADD COLUMN sandbox INTEGER NOT NULL DEFAULT 0;
+        ",
+    },
+    Migration {
+        version: 13,
+        name: "create_start_templates",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS start_templates (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL UNIQUE,
+                env VARCHAR NOT NULL,
+                args VARCHAR NOT NULL,
+                commit_hash VARCHAR
+            );
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS start_templates (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                env TEXT NOT NULL,
+                args TEXT NOT NULL,
+                commit_hash TEXT
+            );
+        ",
+    },
+    Migration {
+        version: 14,
+        name: "create_log_rules",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS log_rules (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL UNIQUE,
+                pattern VARCHAR NOT NULL,
+                levels VARCHAR[] NOT NULL
+            );
+            INSERT INTO log_rules (name, pattern, levels) VALUES
+                ('panic', 'panicked at', '{}'),
+                ('bad-block', 'Bad block', '{}'),
+                ('db-corruption', 'Corruption', '{}')
+                ON CONFLICT (name) DO NOTHING;
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS log_rules (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                pattern TEXT NOT NULL,
+                levels TEXT NOT NULL
+            );
+            INSERT OR IGNORE INTO log_rules (name, pattern, levels) VALUES
+                ('panic', 'panicked at', '[]'),
+                ('bad-block', 'Bad block', '[]'),
+                ('db-corruption', 'Corruption', '[]');
+        ",
+    },
+    Migration {
+        version: 15,
+        name: "create_node_metrics",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS node_metrics (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL,
+                resolution VARCHAR NOT NULL,
+                time TIMESTAMP WITH TIME ZONE NOT NULL,
+                best_block_number BIGINT,
+                peer_count DOUBLE PRECISION NOT NULL,
+                samples INTEGER NOT NULL,
+                UNIQUE (name, resolution, time)
+            );
+            CREATE INDEX IF NOT EXISTS node_metrics_resolution_time ON node_metrics (resolution, time);
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS node_metrics (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                resolution TEXT NOT NULL,
+                time TEXT NOT NULL,
+                best_block_number INTEGER,
+                peer_count REAL NOT NULL,
+                samples INTEGER NOT NULL,
+                UNIQUE (name, resolution, time)
+            );
+            CREATE INDEX IF NOT EXISTS node_metrics_resolution_time ON node_metrics (resolution, time);
+        ",
+    },
+    Migration {
+        version: 16,
+        name: "create_desired_states",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS desired_states (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL UNIQUE,
+                status VARCHAR NOT NULL,
+                env VARCHAR NOT NULL,
+                args VARCHAR NOT NULL,
+                commit_hash VARCHAR
+            );
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS desired_states (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                status TEXT NOT NULL,
+                env TEXT NOT NULL,
+                args TEXT NOT NULL,
+                commit_hash TEXT
+            );
+        ",
+    },
+    Migration {
+        version: 17,
+        name: "create_node_secrets",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS node_secrets (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL,
+                secret_name VARCHAR NOT NULL,
+                sealed VARCHAR NOT NULL,
+                UNIQUE (name, secret_name)
+            );
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS node_secrets (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                secret_name TEXT NOT NULL,
+                sealed TEXT NOT NULL,
+                UNIQUE (name, secret_name)
+            );
+        ",
+    },
+    Migration {
+        version: 18,
+        name: "create_node_notes",
+        postgres: "
+            CREATE TABLE IF NOT EXISTS node_notes (
+                id SERIAL PRIMARY KEY,
+                name VARCHAR NOT NULL,
+                event_id INTEGER,
+                author VARCHAR,
+                text VARCHAR NOT NULL,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS node_notes_name ON node_notes (name);
+            CREATE INDEX IF NOT EXISTS node_notes_event_id ON node_notes (event_id);
+        ",
+        sqlite: "
+            CREATE TABLE IF NOT EXISTS node_notes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                event_id INTEGER,
+                author TEXT,
+                text TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS node_notes_name ON node_notes (name);
+            CREATE INDEX IF NOT EXISTS node_notes_event_id ON node_notes (event_id);
+        ",
+    },
+    Migration {
+        version: 19,
+        name: "add_audit_logs_identity",
+        postgres: "
+            ALTER TABLE audit_logs ADD COLUMN IF NOT EXISTS identity VARCHAR;
+        ",
+        sqlite: "
+            ALTER TABLE audit_logs ADD COLUMN identity TEXT;
+        ",
+    },
+];
+
+/// The schema version which this hub requires
+pub fn latest_version() -> i32 {
+    MIGRATIONS.last().map(|migration| migration.version).unwrap_or(0)
+}
+
+/// Returns the migrations which are not applied yet.
+/// It fails when the database is migrated by a newer hub, because this hub doesn't know the schema.
+pub fn pending(storage: &Storage) -> Result<Vec<&'static Migration>, DBError> {
+    let applied = storage.applied_migrations()?;
+    if let Some(unknown) = applied.iter().find(|version| **version > latest_version()) {
+        return Err(DBError::Query(format!(
+            "The database is migrated to version {} by a newer hub, but this hub knows up to version {}",
+            unknown,
+            latest_version()
+        )))
+    }
+    Ok(MIGRATIONS.iter().filter(|migration| !applied.contains(&migration.version)).collect())
+}
+
+/// Applies the pending migrations and returns the schema version.
+/// The hubs starting together apply them one by one, and the later ones find nothing pending.
+pub fn migrate(storage: &Storage) -> Result<i32, DBError> {
+    storage.with_migration_lock(&mut || {
+        for migration in pending(storage)? {
+            cinfo!("Apply migration {} {}", migration.version, migration.name);
+            storage.apply_migration(migration)?;
+        }
+        Ok(())
+    })?;
+    Ok(latest_version())
+}
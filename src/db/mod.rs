@@ -1,8 +1,20 @@
 pub mod event;
+pub mod leader;
+mod log_storage;
+pub mod migrations;
 mod queries;
 mod service;
+mod storage;
 mod types;
 
 pub use self::event::{Event, EventSubscriber};
+pub use self::log_storage::LogStorageOption;
 pub use self::service::{Service, ServiceNewArg, ServiceSender};
-pub use self::types::{AgentExtra, AgentQueryResult, Error, Log, LogQueryParams};
+pub use self::storage::{create as create_storage, Storage, StorageOption};
+pub use self::types::{
+    AgentExtra, AgentQueryResult, AgentSnapshot, ArchivedNode, Audit, AuditEntry, AuditFilter, AuditQueryParams,
+    DesiredState, DesiredStatus, Error, Log, LogContext, LogCursor, LogDuration, LogFilter, LogLevel, LogQueryParams,
+    LogRule, MetricQueryParams, MetricResolution, MetricSample, NodeEdit, NodeEvent, NodeEventKind,
+    NodeEventQueryParams, NodeSecret, Note, NoteEntry, OrderBy, Schedule, ScheduleEntry, ScheduleKind, ScheduleRun,
+    ScheduleRunQueryParams, ScheduleRunResult, Slo, SloStatus, StartTemplate,
+};
@@ -14,17 +14,19 @@ pub fn get(conn: &postgres::Connection, node_name: &NodeName) -> postgres::Resul
     Ok(Some(AgentExtra {
         prev_env: row.get("prev_env"),
         prev_args: row.get("prev_args"),
+        binary_path: row.get("binary_path"),
     }))
 }
 
 pub fn upsert(conn: &postgres::Connection, node_name: &NodeName, agent_extra: &AgentExtra) -> postgres::Result<()> {
     ctrace!("Upsert agent extra {:?}", agent_extra);
     let result = conn.execute(
-        "INSERT INTO agent_extra (name, prev_env, prev_args) VALUES ($1, $2, $3) \
+        "INSERT INTO agent_extra (name, prev_env, prev_args, binary_path) VALUES ($1, $2, $3, $4) \
          ON CONFLICT (name) DO UPDATE \
          SET prev_env=excluded.prev_env, \
-         prev_args=excluded.prev_args",
-        &[node_name, &agent_extra.prev_env, &agent_extra.prev_args],
+         prev_args=excluded.prev_args, \
+         binary_path=excluded.binary_path",
+        &[node_name, &agent_extra.prev_env, &agent_extra.prev_args, &agent_extra.binary_path],
     )?;
     ctrace!("Upsert result {}", result);
     Ok(())
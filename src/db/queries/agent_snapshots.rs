@@ -0,0 +1,42 @@
+use chrono;
+use postgres;
+use serde_json;
+
+use super::super::AgentSnapshot;
+
+pub fn get_all(conn: &postgres::Connection) -> postgres::Result<Vec<AgentSnapshot>> {
+    ctrace!("Query agent snapshots");
+
+    let rows = conn.query("SELECT name, snapshot FROM agent_snapshots", &[])?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let snapshot = serde_json::from_str(&row.get::<_, String>("snapshot"));
+            if let Err(err) = &snapshot {
+                cwarn!("Invalid snapshot of {} : {}", row.get::<_, String>("name"), err);
+            }
+            snapshot.ok()
+        })
+        .collect())
+}
+
+/// Writes the snapshots in a transaction.
+pub fn upsert(conn: &postgres::Connection, snapshots: &[AgentSnapshot]) -> postgres::Result<()> {
+    ctrace!("Upsert {} agent snapshots", snapshots.len());
+
+    let transaction = conn.transaction()?;
+    {
+        let statement = transaction.prepare(
+            "INSERT INTO agent_snapshots (name, snapshot, updated_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (name) DO UPDATE \
+             SET snapshot=excluded.snapshot, \
+             updated_at=excluded.updated_at",
+        )?;
+        let now = chrono::Local::now();
+        for snapshot in snapshots {
+            let serialized = serde_json::to_string(snapshot).expect("AgentSnapshot is serializable");
+            statement.execute(&[&snapshot.name, &serialized, &now])?;
+        }
+    }
+    transaction.commit()
+}
@@ -0,0 +1,48 @@
+use chrono;
+use postgres;
+use serde_json;
+use serde_json::Value;
+
+use super::super::super::common_rpc_types::NodeName;
+use super::super::types::ArchivedNode;
+
+/// The tables whose rows of the node are moved to the archive
+const NODE_TABLES: &[(&str, &str)] = &[
+    ("agent_snapshots", "name"),
+    ("agent_extra", "name"),
+    ("desired_states", "name"),
+    ("node_notes", "name"),
+    ("node_secrets", "name"),
+    ("node_tags", "name"),
+    ("schedules", "node_name"),
+];
+
+/// Moves the rows of the node to the archive in a transaction.
+pub fn archive(conn: &postgres::Connection, node_name: &NodeName, detail: &Value) -> postgres::Result<()> {
+    ctrace!("Archive node {}", node_name);
+
+    let transaction = conn.transaction()?;
+    transaction.execute(
+        "INSERT INTO archived_nodes (name, archived_at, detail) VALUES ($1, $2, $3)",
+        &[node_name, &chrono::Local::now(), &detail.to_string()],
+    )?;
+    for (table, column) in NODE_TABLES {
+        transaction.execute(&format!("DELETE FROM {} WHERE {}=$1", table, column), &[node_name])?;
+    }
+    transaction.commit()
+}
+
+pub fn get_all(conn: &postgres::Connection) -> postgres::Result<Vec<ArchivedNode>> {
+    ctrace!("Query archived nodes");
+
+    let rows = conn.query("SELECT * FROM archived_nodes ORDER BY archived_at DESC", &[])?;
+    Ok(rows
+        .iter()
+        .map(|row| ArchivedNode {
+            id: row.get("id"),
+            name: row.get("name"),
+            archived_at: row.get("archived_at"),
+            detail: serde_json::from_str(&row.get::<_, String>("detail")).unwrap_or_default(),
+        })
+        .collect())
+}
@@ -0,0 +1,92 @@
+use std::borrow::Borrow;
+use std::rc::Rc;
+
+use chrono;
+use postgres;
+use postgres::types::ToSql;
+use serde_json;
+
+use super::super::types::{Audit, AuditEntry, AuditQueryParams, OrderBy, Paging};
+use super::parameters::Parameters;
+
+pub fn insert(conn: &postgres::Connection, entry: &AuditEntry) -> postgres::Result<()> {
+    ctrace!("Add audit {:?}", entry);
+
+    let arguments = entry.arguments.to_string();
+    conn.execute(
+        "INSERT INTO audit_logs (timestamp, caller, identity, method, node_name, arguments, sandbox) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        &[
+            &chrono::Local::now(),
+            &entry.caller,
+            &entry.identity,
+            &entry.method,
+            &entry.node_name,
+            &arguments,
+            &entry.sandbox,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn search(conn: &postgres::Connection, params: AuditQueryParams, paging: Paging) -> postgres::Result<Vec<Audit>> {
+    ctrace!("Search audit with {:?}", params);
+    let mut parameters = Parameters::new();
+    let mut where_conditions = Vec::new();
+    if let Some(filter) = params.filter {
+        if !filter.node_names.is_empty() {
+            let node_names_index = parameters.add(Rc::new(filter.node_names));
+            where_conditions.push(format!("node_name = ANY(${})", node_names_index));
+        }
+        if !filter.methods.is_empty() {
+            let methods_index = parameters.add(Rc::new(filter.methods));
+            where_conditions.push(format!("method = ANY(${})", methods_index));
+        }
+        if !filter.callers.is_empty() {
+            let callers_index = parameters.add(Rc::new(filter.callers));
+            where_conditions.push(format!("caller = ANY(${})", callers_index));
+        }
+    }
+    if let Some(time) = params.time {
+        if let Some(from) = time.from_time {
+            let from_index = parameters.add(Rc::new(from));
+            where_conditions.push(format!("timestamp > ${}", from_index));
+        }
+        if let Some(to) = time.to_time {
+            let to_index = parameters.add(Rc::new(to));
+            where_conditions.push(format!("timestamp < ${}", to_index));
+        }
+    }
+
+    let where_clause = if !where_conditions.is_empty() {
+        "WHERE ".to_string() + &where_conditions.join(" AND ")
+    } else {
+        "".to_string()
+    };
+
+    let order_by = params.order_by.unwrap_or(OrderBy::DESC);
+    let order_by_clause = format!("ORDER BY timestamp {:?}", order_by);
+
+    let limit_clause = format!("LIMIT {}", paging.limit);
+    let offset_clause = format!("OFFSET {}", paging.offset);
+
+    let query_string =
+        vec!["SELECT * FROM audit_logs", &where_clause, &order_by_clause, &limit_clause, &offset_clause].join(" ");
+
+    let query_params: Vec<&ToSql> = parameters.get().iter().map(|param| param.borrow()).collect();
+    let rows = conn.query(&query_string, &query_params[..])?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Audit {
+            id: row.get("id"),
+            timestamp: row.get("timestamp"),
+            caller: row.get("caller"),
+            identity: row.get("identity"),
+            method: row.get("method"),
+            node_name: row.get("node_name"),
+            arguments: serde_json::from_str(&row.get::<_, String>("arguments")).unwrap_or_default(),
+            sandbox: row.get("sandbox"),
+        })
+        .collect())
+}
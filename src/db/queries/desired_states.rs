@@ -0,0 +1,47 @@
+use postgres;
+use serde_json;
+use serde_json::Value;
+
+use super::super::super::common_rpc_types::NodeName;
+use super::super::types::DesiredState;
+
+pub fn upsert(conn: &postgres::Connection, state: &DesiredState) -> postgres::Result<()> {
+    ctrace!("Set desired state {:?}", state);
+
+    conn.execute(
+        "INSERT INTO desired_states (name, status, env, args, commit_hash) VALUES ($1, $2, $3, $4, $5) \
+         ON CONFLICT (name) DO UPDATE SET status=excluded.status, env=excluded.env, args=excluded.args, \
+         commit_hash=excluded.commit_hash",
+        &[&state.node_name, &state.status.as_str(), &state.env, &state.args, &state.commit_hash],
+    )?;
+    Ok(())
+}
+
+pub fn remove(conn: &postgres::Connection, node_name: &NodeName) -> postgres::Result<()> {
+    ctrace!("Remove desired state of {}", node_name);
+
+    conn.execute("DELETE FROM desired_states WHERE name=$1", &[node_name])?;
+    Ok(())
+}
+
+pub fn get_all(conn: &postgres::Connection) -> postgres::Result<Vec<DesiredState>> {
+    ctrace!("Query desired states");
+
+    let rows = conn.query("SELECT * FROM desired_states ORDER BY name", &[])?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let status = serde_json::from_value(Value::String(row.get("status")));
+            if let Err(err) = &status {
+                cwarn!("Unknown desired status {}", err);
+            }
+            status.ok().map(|status| DesiredState {
+                node_name: row.get("name"),
+                status,
+                env: row.get("env"),
+                args: row.get("args"),
+                commit_hash: row.get("commit_hash"),
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,96 @@
+use std::borrow::Borrow;
+use std::rc::Rc;
+
+use chrono;
+use postgres;
+use postgres::types::ToSql;
+use serde_json;
+use serde_json::Value;
+
+use super::super::super::common_rpc_types::NodeName;
+use super::super::types::{NodeEvent, NodeEventKind, NodeEventQueryParams, OrderBy, Paging};
+use super::parameters::Parameters;
+
+pub fn insert(
+    conn: &postgres::Connection,
+    node_name: &NodeName,
+    kind: NodeEventKind,
+    detail: &Value,
+) -> postgres::Result<()> {
+    ctrace!("Add event {:?} of {}", kind, node_name);
+
+    let kind = format!("{:?}", kind);
+    let detail = detail.to_string();
+    conn.execute(
+        "INSERT INTO node_events (name, kind, detail, timestamp) VALUES ($1, $2, $3, $4)",
+        &[node_name, &kind, &detail, &chrono::Local::now()],
+    )?;
+    Ok(())
+}
+
+/// None if there is no event with the id
+pub fn get_node_name(conn: &postgres::Connection, id: i32) -> postgres::Result<Option<NodeName>> {
+    ctrace!("Query the node of event {}", id);
+
+    let rows = conn.query("SELECT name FROM node_events WHERE id=$1", &[&id])?;
+    Ok(rows.iter().next().map(|row| row.get("name")))
+}
+
+pub fn search(
+    conn: &postgres::Connection,
+    params: NodeEventQueryParams,
+    paging: Paging,
+) -> postgres::Result<Vec<NodeEvent>> {
+    ctrace!("Search events with {:?}", params);
+    let mut parameters = Parameters::new();
+    let mut where_conditions = Vec::new();
+    if !params.node_names.is_empty() {
+        let node_names_index = parameters.add(Rc::new(params.node_names));
+        where_conditions.push(format!("name = ANY(${})", node_names_index));
+    }
+    if let Some(time) = params.time {
+        if let Some(from) = time.from_time {
+            let from_index = parameters.add(Rc::new(from));
+            where_conditions.push(format!("timestamp > ${}", from_index));
+        }
+        if let Some(to) = time.to_time {
+            let to_index = parameters.add(Rc::new(to));
+            where_conditions.push(format!("timestamp < ${}", to_index));
+        }
+    }
+
+    let where_clause = if !where_conditions.is_empty() {
+        "WHERE ".to_string() + &where_conditions.join(" AND ")
+    } else {
+        "".to_string()
+    };
+
+    let order_by = params.order_by.unwrap_or(OrderBy::DESC);
+    let order_by_clause = format!("ORDER BY timestamp {:?}", order_by);
+
+    let limit_clause = format!("LIMIT {}", paging.limit);
+    let offset_clause = format!("OFFSET {}", paging.offset);
+
+    let query_string =
+        vec!["SELECT * FROM node_events", &where_clause, &order_by_clause, &limit_clause, &offset_clause].join(" ");
+
+    let query_params: Vec<&ToSql> = parameters.get().iter().map(|param| param.borrow()).collect();
+    let rows = conn.query(&query_string, &query_params[..])?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let kind = serde_json::from_value(Value::String(row.get("kind")));
+            if let Err(err) = &kind {
+                cwarn!("Unknown event kind {}", err);
+            }
+            kind.ok().map(|kind| NodeEvent {
+                id: row.get("id"),
+                node_name: row.get("name"),
+                kind,
+                detail: serde_json::from_str(&row.get::<_, String>("detail")).unwrap_or_default(),
+                timestamp: row.get("timestamp"),
+            })
+        })
+        .collect())
+}
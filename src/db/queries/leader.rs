@@ -0,0 +1,21 @@
+use postgres;
+
+/// The session-level advisory lock is released when the connection is closed, e.g. when the hub dies
+pub fn try_lock(conn: &postgres::Connection, lock_id: i64) -> postgres::Result<bool> {
+    ctrace!("Try the leader lock {}", lock_id);
+
+    let rows = conn.query("SELECT pg_try_advisory_lock($1) AS locked", &[&lock_id])?;
+    Ok(rows.get(0).get("locked"))
+}
+
+pub fn unlock(conn: &postgres::Connection, lock_id: i64) -> postgres::Result<()> {
+    ctrace!("Release the lock {}", lock_id);
+
+    conn.query("SELECT pg_advisory_unlock($1)", &[&lock_id])?;
+    Ok(())
+}
+
+pub fn ping(conn: &postgres::Connection) -> postgres::Result<()> {
+    conn.execute("SELECT 1", &[])?;
+    Ok(())
+}
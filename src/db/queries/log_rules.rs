@@ -0,0 +1,35 @@
+use postgres;
+
+use super::super::types::LogRule;
+
+pub fn upsert(conn: &postgres::Connection, rule: &LogRule) -> postgres::Result<()> {
+    ctrace!("Set log rule {:?}", rule);
+
+    conn.execute(
+        "INSERT INTO log_rules (name, pattern, levels) VALUES ($1, $2, $3) \
+         ON CONFLICT (name) DO UPDATE SET pattern=excluded.pattern, levels=excluded.levels",
+        &[&rule.name, &rule.pattern, &rule.levels],
+    )?;
+    Ok(())
+}
+
+pub fn remove(conn: &postgres::Connection, name: &str) -> postgres::Result<()> {
+    ctrace!("Remove log rule {}", name);
+
+    conn.execute("DELETE FROM log_rules WHERE name=$1", &[&name])?;
+    Ok(())
+}
+
+pub fn get_all(conn: &postgres::Connection) -> postgres::Result<Vec<LogRule>> {
+    ctrace!("Query log rules");
+
+    let rows = conn.query("SELECT * FROM log_rules ORDER BY name", &[])?;
+    Ok(rows
+        .iter()
+        .map(|row| LogRule {
+            name: row.get("name"),
+            pattern: row.get("pattern"),
+            levels: row.get("levels"),
+        })
+        .collect())
+}
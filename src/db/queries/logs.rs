@@ -7,41 +7,66 @@ use postgres::types::ToSql;
 
 use super::super::super::common_rpc_types::{NodeName, StructuredLog};
 use super::super::types::OrderBy;
-use super::super::types::{Log, LogQueryParams};
-
-pub fn insert(conn: &postgres::Connection, node_name: &NodeName, logs: Vec<StructuredLog>) -> postgres::Result<()> {
+use super::super::types::{Log, LogContext, LogQueryParams, Paging};
+use super::parameters::Parameters;
+
+// Postgres allows at most 65535 parameters in a query
+const MAX_LOGS_PER_INSERT: usize = 1000;
+
+/// Logs are deduplicated by (name, timestamp, message), so inserting the same logs again is harmless.
+pub fn insert(
+    conn: &postgres::Connection,
+    node_name: &NodeName,
+    logs: Vec<StructuredLog>,
+    backfilled: bool,
+) -> postgres::Result<()> {
     ctrace!("Add log {} : {:?}", node_name, logs);
 
+    for chunk in logs.chunks(MAX_LOGS_PER_INSERT) {
+        insert_chunk(conn, node_name, chunk, backfilled)?;
+    }
+
+    Ok(())
+}
+
+fn insert_chunk(
+    conn: &postgres::Connection,
+    node_name: &NodeName,
+    logs: &[StructuredLog],
+    backfilled: bool,
+) -> postgres::Result<()> {
     if logs.len() == 0 {
         return Ok(())
     }
 
-    let mut query = "";
     let mut parameters_positions: Vec<String> = Vec::new();
     let mut parameters: Vec<Box<ToSql>> = Vec::new();
-    for (row_index, log) in logs.into_iter().enumerate() {
-        let base_num = row_index * 6;
+    for (row_index, log) in logs.iter().enumerate() {
+        let base_num = row_index * 7;
         parameters_positions.push(format!(
-            "(${}, ${}, ${}, ${}, ${}, ${})",
+            "(${}, ${}, ${}, ${}, ${}, ${}, ${})",
             base_num + 1,
             base_num + 2,
             base_num + 3,
             base_num + 4,
             base_num + 5,
-            base_num + 6
+            base_num + 6,
+            base_num + 7
         ));
         let rfc3339with_nano_second = "%Y-%m-%dT%H:%M:%S.%f%z";
         let datetime = chrono::DateTime::parse_from_str(&log.timestamp, rfc3339with_nano_second).unwrap();
-        parameters.push(Box::new(node_name));
-        parameters.push(Box::new(log.level));
-        parameters.push(Box::new(log.target));
-        parameters.push(Box::new(log.message));
+        parameters.push(Box::new(node_name.clone()));
+        parameters.push(Box::new(log.level.clone()));
+        parameters.push(Box::new(log.target.clone()));
+        parameters.push(Box::new(log.message.clone()));
         parameters.push(Box::new(datetime));
-        parameters.push(Box::new(log.thread_name));
+        parameters.push(Box::new(log.thread_name.clone()));
+        parameters.push(Box::new(backfilled));
     }
 
     let full_sql = format!(
-        "INSERT INTO logs (name, level, target, message, timestamp, thread_name) VALUES {}",
+        "INSERT INTO logs (name, level, target, message, timestamp, thread_name, backfilled) VALUES {} \
+         ON CONFLICT DO NOTHING",
         parameters_positions.join(", ")
     );
     let parameters_ref: Vec<&ToSql> = parameters.iter().map(|param| param.as_ref()).collect();
@@ -51,7 +76,7 @@ pub fn insert(conn: &postgres::Connection, node_name: &NodeName, logs: Vec<Struc
     Ok(())
 }
 
-pub fn search(conn: &postgres::Connection, params: LogQueryParams) -> postgres::Result<Vec<Log>> {
+pub fn search(conn: &postgres::Connection, params: LogQueryParams, paging: Paging) -> postgres::Result<Vec<Log>> {
     ctrace!("Search log with {:?}", params);
     let mut parameters = Parameters::new();
     let mut where_conditions = Vec::new();
@@ -91,6 +116,14 @@ pub fn search(conn: &postgres::Connection, params: LogQueryParams) -> postgres::
             where_conditions.push(format!("timestamp < ${}", to_index));
         }
     }
+    if let Some(after) = params.after.as_ref() {
+        let timestamp_index = parameters.add(Rc::new(after.timestamp));
+        let id_index = parameters.add(Rc::new(after.id));
+        where_conditions.push(format!(
+            "(timestamp > ${} OR (timestamp = ${} AND id > ${}::BIGINT))",
+            timestamp_index, timestamp_index, id_index
+        ));
+    }
 
     let where_clause = if where_conditions.len() > 0 {
         "WHERE ".to_string() + &where_conditions.join(" AND ")
@@ -98,15 +131,20 @@ pub fn search(conn: &postgres::Connection, params: LogQueryParams) -> postgres::
         "".to_string()
     };
 
-    let order_by = params.order_by.unwrap_or(OrderBy::ASC);
-    let order_by_clause = format!("ORDER BY timestamp {:?}", order_by);
-
-    let limit = params.item_per_page.unwrap_or(100);
-    let limit_clause = format!("LIMIT {}", limit);
+    let order_by = if params.after.is_some() {
+        OrderBy::ASC
+    } else {
+        params.order_by.unwrap_or(OrderBy::ASC)
+    };
+    let order_by_clause = format!("ORDER BY timestamp {:?}, id {:?}", order_by, order_by);
 
-    // page starts from 1
-    let offset = params.page.unwrap_or(1) - 1;
-    let offset_clause = format!("OFFSET {}", offset * limit);
+    let limit_clause = format!("LIMIT {}", paging.limit);
+    let offset = if params.after.is_some() {
+        0
+    } else {
+        paging.offset
+    };
+    let offset_clause = format!("OFFSET {}", offset);
 
     let query_string =
         vec!["SELECT * FROM logs", &where_clause, &order_by_clause, &limit_clause, &offset_clause].join(" ");
@@ -114,40 +152,60 @@ pub fn search(conn: &postgres::Connection, params: LogQueryParams) -> postgres::
     let query_params: Vec<&ToSql> = parameters.get().iter().map(|param| param.borrow()).collect();
     let rows = conn.query(&query_string, &query_params[..])?;
 
-    Ok(rows
-        .into_iter()
-        .map(|row| Log {
-            id: row.get("id"),
-            node_name: row.get("name"),
-            level: row.get("level"),
-            target: row.get("target"),
-            timestamp: row.get("timestamp"),
-            message: format!("{} {}", row.get::<_, String>("thread_name"), row.get::<_, String>("message")),
-        })
-        .collect())
+    Ok(rows.into_iter().map(|row| log_from_row(&row)).collect())
 }
 
-struct Parameters {
-    parameter_count: i32,
-    parameters: Vec<Rc<ToSql>>,
+/// The logs which have the same timestamp are ordered by the ids
+pub fn get_context(
+    conn: &postgres::Connection,
+    id: i64,
+    before: i64,
+    after: i64,
+) -> postgres::Result<Option<LogContext>> {
+    ctrace!("Query the context of the log {}", id);
+
+    let rows = conn.query("SELECT * FROM logs WHERE id = $1::BIGINT", &[&id])?;
+    let log = match rows.iter().next() {
+        Some(row) => log_from_row(&row),
+        None => return Ok(None),
+    };
+    let params: [&ToSql; 4] = [&log.node_name, &log.timestamp, &log.id, &before];
+    let mut before: Vec<Log> = conn
+        .query(
+            "SELECT * FROM logs WHERE name = $1 AND (timestamp, id) < ($2, $3::BIGINT) \
+             ORDER BY timestamp DESC, id DESC LIMIT $4",
+            &params,
+        )?
+        .iter()
+        .map(|row| log_from_row(&row))
+        .collect();
+    before.reverse();
+    let params: [&ToSql; 4] = [&log.node_name, &log.timestamp, &log.id, &after];
+    let after = conn
+        .query(
+            "SELECT * FROM logs WHERE name = $1 AND (timestamp, id) > ($2, $3::BIGINT) \
+             ORDER BY timestamp ASC, id ASC LIMIT $4",
+            &params,
+        )?
+        .iter()
+        .map(|row| log_from_row(&row))
+        .collect();
+    Ok(Some(LogContext {
+        before,
+        log,
+        after,
+    }))
 }
 
-impl Parameters {
-    pub fn new() -> Parameters {
-        Parameters {
-            parameter_count: 0,
-            parameters: Vec::new(),
-        }
-    }
-
-    pub fn add(&mut self, param: Rc<ToSql>) -> i32 {
-        self.parameters.push(param);
-        self.parameter_count += 1;
-        self.parameter_count
-    }
-
-    pub fn get(&self) -> &Vec<Rc<ToSql>> {
-        &self.parameters
+fn log_from_row(row: &postgres::rows::Row) -> Log {
+    Log {
+        id: i64::from(row.get::<_, i32>("id")),
+        node_name: row.get("name"),
+        level: row.get("level"),
+        target: row.get("target"),
+        timestamp: row.get("timestamp"),
+        message: format!("{} {}", row.get::<_, String>("thread_name"), row.get::<_, String>("message")),
+        backfilled: row.get("backfilled"),
     }
 }
 
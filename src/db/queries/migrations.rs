@@ -0,0 +1,31 @@
+use postgres;
+
+/// Doesn't change the database, so the check mode can run with a read-only user
+pub fn get_applied(conn: &postgres::Connection) -> postgres::Result<Vec<i32>> {
+    ctrace!("Query applied migrations");
+
+    let rows = conn.query("SELECT to_regclass('schema_migrations') IS NOT NULL AS created", &[])?;
+    if !rows.get(0).get::<_, bool>("created") {
+        return Ok(Vec::new())
+    }
+    let rows = conn.query("SELECT version FROM schema_migrations ORDER BY version", &[])?;
+    Ok(rows.iter().map(|row| row.get("version")).collect())
+}
+
+/// The schema is changed and the version is recorded in a transaction, so a failed migration can be retried.
+pub fn apply(conn: &postgres::Connection, version: i32, name: &str, sql: &str) -> postgres::Result<()> {
+    let transaction = conn.transaction()?;
+    // Creating an index on a large table takes longer than the query timeout
+    transaction.execute("SET LOCAL statement_timeout TO 0", &[])?;
+    transaction.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name VARCHAR NOT NULL,
+            applied_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+        )",
+        &[],
+    )?;
+    transaction.batch_execute(sql)?;
+    transaction.execute("INSERT INTO schema_migrations (version, name) VALUES ($1, $2)", &[&version, &name])?;
+    transaction.commit()
+}
@@ -1,3 +1,20 @@
 pub mod agent_extra;
+pub mod archive;
+pub mod agent_snapshots;
+pub mod audit;
 pub mod config;
+pub mod desired_states;
+pub mod events;
+pub mod leader;
+pub mod log_rules;
 pub mod logs;
+pub mod migrations;
+pub mod node_metrics;
+pub mod notes;
+mod parameters;
+pub mod renames;
+pub mod schedules;
+pub mod secrets;
+pub mod slo;
+pub mod start_templates;
+pub mod tags;
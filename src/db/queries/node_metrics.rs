@@ -0,0 +1,75 @@
+use chrono;
+use postgres;
+
+use super::super::super::common_rpc_types::NodeName;
+use super::super::types::{MetricResolution, MetricSample};
+
+/// Writes the samples in a transaction. The rolled-up samples of the same period are replaced.
+pub fn upsert(
+    conn: &postgres::Connection,
+    resolution: MetricResolution,
+    samples: &[MetricSample],
+) -> postgres::Result<()> {
+    ctrace!("Upsert {} {} metric samples", samples.len(), resolution.as_str());
+
+    let transaction = conn.transaction()?;
+    {
+        let statement = transaction.prepare(
+            "INSERT INTO node_metrics (name, resolution, time, best_block_number, peer_count, samples) \
+             VALUES ($1, $2, $3, $4, $5, $6) \
+             ON CONFLICT (name, resolution, time) DO UPDATE \
+             SET best_block_number=excluded.best_block_number, \
+             peer_count=excluded.peer_count, \
+             samples=excluded.samples",
+        )?;
+        for sample in samples {
+            statement.execute(&[
+                &sample.node_name,
+                &resolution.as_str(),
+                &sample.time,
+                &sample.best_block_number,
+                &sample.peer_count,
+                &sample.samples,
+            ])?;
+        }
+    }
+    transaction.commit()
+}
+
+/// Returns the samples in [from, to) ordered by the node names and the times.
+pub fn get(
+    conn: &postgres::Connection,
+    resolution: MetricResolution,
+    node_name: Option<&NodeName>,
+    from: &chrono::DateTime<chrono::Local>,
+    to: &chrono::DateTime<chrono::Local>,
+) -> postgres::Result<Vec<MetricSample>> {
+    ctrace!("Query {} metric samples of {:?} from {} to {}", resolution.as_str(), node_name, from, to);
+
+    let rows = conn.query(
+        "SELECT * FROM node_metrics WHERE resolution=$1 AND time >= $2 AND time < $3 \
+         AND ($4::VARCHAR IS NULL OR name=$4) ORDER BY name, time",
+        &[&resolution.as_str(), from, to, &node_name],
+    )?;
+    Ok(rows
+        .iter()
+        .map(|row| MetricSample {
+            node_name: row.get("name"),
+            time: row.get("time"),
+            best_block_number: row.get("best_block_number"),
+            peer_count: row.get("peer_count"),
+            samples: row.get("samples"),
+        })
+        .collect())
+}
+
+pub fn remove_before(
+    conn: &postgres::Connection,
+    resolution: MetricResolution,
+    before: &chrono::DateTime<chrono::Local>,
+) -> postgres::Result<()> {
+    ctrace!("Remove {} metric samples before {}", resolution.as_str(), before);
+
+    conn.execute("DELETE FROM node_metrics WHERE resolution=$1 AND time < $2", &[&resolution.as_str(), before])?;
+    Ok(())
+}
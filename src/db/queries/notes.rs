@@ -0,0 +1,65 @@
+use chrono;
+use postgres;
+
+use super::super::super::common_rpc_types::NodeName;
+use super::super::types::{Note, NoteEntry};
+
+pub fn insert(conn: &postgres::Connection, entry: &NoteEntry) -> postgres::Result<Note> {
+    ctrace!("Add note {:?}", entry);
+
+    let created_at = chrono::Local::now();
+    let rows = conn.query(
+        "INSERT INTO node_notes (name, event_id, author, text, created_at) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        &[&entry.node_name, &entry.event_id, &entry.author, &entry.text, &created_at],
+    )?;
+    Ok(Note {
+        id: rows.get(0).get("id"),
+        node_name: entry.node_name.clone(),
+        event_id: entry.event_id,
+        author: entry.author.clone(),
+        text: entry.text.clone(),
+        created_at,
+    })
+}
+
+/// Returns false if there is no note with the id.
+pub fn remove(conn: &postgres::Connection, id: i32) -> postgres::Result<bool> {
+    ctrace!("Remove note {}", id);
+
+    let removed = conn.execute("DELETE FROM node_notes WHERE id=$1", &[&id])?;
+    Ok(removed > 0)
+}
+
+pub fn get(conn: &postgres::Connection, id: i32) -> postgres::Result<Option<Note>> {
+    ctrace!("Query note {}", id);
+
+    let rows = conn.query("SELECT * FROM node_notes WHERE id=$1", &[&id])?;
+    Ok(rows.iter().next().map(|row| note_from_row(&row)))
+}
+
+/// The notes on the node and on its events, the newest first
+pub fn get_by_node(conn: &postgres::Connection, node_name: &NodeName) -> postgres::Result<Vec<Note>> {
+    ctrace!("Query notes of {}", node_name);
+
+    let rows = conn.query("SELECT * FROM node_notes WHERE name=$1 ORDER BY created_at DESC", &[node_name])?;
+    Ok(rows.iter().map(|row| note_from_row(&row)).collect())
+}
+
+pub fn get_by_events(conn: &postgres::Connection, event_ids: &[i32]) -> postgres::Result<Vec<Note>> {
+    ctrace!("Query notes of events {:?}", event_ids);
+
+    let rows =
+        conn.query("SELECT * FROM node_notes WHERE event_id = ANY($1) ORDER BY created_at DESC", &[&event_ids])?;
+    Ok(rows.iter().map(|row| note_from_row(&row)).collect())
+}
+
+fn note_from_row(row: &postgres::rows::Row) -> Note {
+    Note {
+        id: row.get("id"),
+        node_name: row.get("name"),
+        event_id: row.get("event_id"),
+        author: row.get("author"),
+        text: row.get("text"),
+        created_at: row.get("created_at"),
+    }
+}
@@ -0,0 +1,27 @@
+use std::rc::Rc;
+
+use postgres::types::ToSql;
+
+pub struct Parameters {
+    parameter_count: i32,
+    parameters: Vec<Rc<ToSql>>,
+}
+
+impl Parameters {
+    pub fn new() -> Parameters {
+        Parameters {
+            parameter_count: 0,
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, param: Rc<ToSql>) -> i32 {
+        self.parameters.push(param);
+        self.parameter_count += 1;
+        self.parameter_count
+    }
+
+    pub fn get(&self) -> &Vec<Rc<ToSql>> {
+        &self.parameters
+    }
+}
@@ -0,0 +1,57 @@
+use postgres;
+
+use super::super::super::common_rpc_types::NodeName;
+use super::super::types::NodeSecret;
+
+/// The tables which have the rows of a node
+const NODE_TABLES: &[(&str, &str)] = &[
+    ("agent_extra", "name"),
+    ("agent_snapshots", "name"),
+    ("desired_states", "name"),
+    ("logs", "name"),
+    ("node_tags", "name"),
+    ("node_events", "name"),
+    ("node_metrics", "name"),
+    ("node_secrets", "name"),
+    ("node_notes", "name"),
+    ("schedules", "node_name"),
+    ("schedule_runs", "node_name"),
+];
+
+/// Renames the rows of the node in a transaction.
+/// The agent still reports the old name, so the alias from the reported name is updated together.
+/// The secrets are bound to the node name, so the secrets sealed again for the new name replace them.
+pub fn rename(
+    conn: &postgres::Connection,
+    from: &NodeName,
+    to: &NodeName,
+    resealed: &[NodeSecret],
+) -> postgres::Result<()> {
+    ctrace!("Rename node {} to {}", from, to);
+
+    let transaction = conn.transaction()?;
+    for (table, column) in NODE_TABLES {
+        transaction.execute(&format!("UPDATE {} SET {}=$1 WHERE {}=$2", table, column, column), &[to, from])?;
+    }
+    for secret in resealed {
+        transaction.execute(
+            "UPDATE node_secrets SET sealed=$1 WHERE name=$2 AND secret_name=$3",
+            &[&secret.sealed, to, &secret.name],
+        )?;
+    }
+    let updated = transaction.execute("UPDATE node_aliases SET name=$1 WHERE name=$2", &[to, from])?;
+    if updated == 0 {
+        transaction.execute("INSERT INTO node_aliases (reported_name, name) VALUES ($1, $2)", &[from, to])?;
+    }
+    // Renamed back to the reported name
+    transaction.execute("DELETE FROM node_aliases WHERE reported_name=name", &[])?;
+    transaction.commit()
+}
+
+/// Returns the pairs of the name which the agent reports and the name in the hub.
+pub fn get_aliases(conn: &postgres::Connection) -> postgres::Result<Vec<(NodeName, NodeName)>> {
+    ctrace!("Query node aliases");
+
+    let rows = conn.query("SELECT reported_name, name FROM node_aliases", &[])?;
+    Ok(rows.iter().map(|row| (row.get("reported_name"), row.get("name"))).collect())
+}
@@ -0,0 +1,100 @@
+use chrono;
+use postgres;
+use serde_json;
+use serde_json::Value;
+
+use super::super::types::{Paging, Schedule, ScheduleEntry, ScheduleRun, ScheduleRunQueryParams};
+
+pub fn insert(conn: &postgres::Connection, entry: &ScheduleEntry) -> postgres::Result<Schedule> {
+    ctrace!("Add schedule {:?}", entry);
+
+    let kind = entry.kind.as_str();
+    let created_at = chrono::Local::now();
+    let rows = conn.query(
+        "INSERT INTO schedules (kind, cron, node_name, created_at) VALUES ($1, $2, $3, $4) RETURNING id",
+        &[&kind, &entry.cron, &entry.node_name, &created_at],
+    )?;
+    Ok(Schedule {
+        id: rows.get(0).get("id"),
+        kind: entry.kind,
+        cron: entry.cron.clone(),
+        node_name: entry.node_name.clone(),
+        created_at,
+    })
+}
+
+/// Returns false if there is no schedule with the id.
+pub fn remove(conn: &postgres::Connection, id: i32) -> postgres::Result<bool> {
+    ctrace!("Remove schedule {}", id);
+
+    let removed = conn.execute("DELETE FROM schedules WHERE id=$1", &[&id])?;
+    Ok(removed > 0)
+}
+
+pub fn get_all(conn: &postgres::Connection) -> postgres::Result<Vec<Schedule>> {
+    ctrace!("Query schedules");
+
+    let rows = conn.query("SELECT * FROM schedules ORDER BY id", &[])?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let kind = serde_json::from_value(Value::String(row.get("kind")));
+            if let Err(err) = &kind {
+                cwarn!("Unknown schedule kind {}", err);
+            }
+            kind.ok().map(|kind| Schedule {
+                id: row.get("id"),
+                kind,
+                cron: row.get("cron"),
+                node_name: row.get("node_name"),
+                created_at: row.get("created_at"),
+            })
+        })
+        .collect())
+}
+
+pub fn insert_run(conn: &postgres::Connection, run: &ScheduleRun) -> postgres::Result<()> {
+    ctrace!("Add schedule run {:?}", run);
+
+    let result = run.result.as_str();
+    conn.execute(
+        "INSERT INTO schedule_runs (schedule_id, node_name, started_at, finished_at, result, detail) \
+         VALUES ($1, $2, $3, $4, $5, $6)",
+        &[&run.schedule_id, &run.node_name, &run.started_at, &run.finished_at, &result, &run.detail],
+    )?;
+    Ok(())
+}
+
+pub fn search_runs(
+    conn: &postgres::Connection,
+    params: ScheduleRunQueryParams,
+    paging: Paging,
+) -> postgres::Result<Vec<ScheduleRun>> {
+    ctrace!("Search schedule runs with {:?}", params);
+
+    let rows = conn.query(
+        &format!(
+            "SELECT * FROM schedule_runs WHERE $1::INTEGER IS NULL OR schedule_id=$1 \
+             ORDER BY started_at DESC LIMIT {} OFFSET {}",
+            paging.limit, paging.offset
+        ),
+        &[&params.schedule_id],
+    )?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let result = serde_json::from_value(Value::String(row.get("result")));
+            if let Err(err) = &result {
+                cwarn!("Unknown schedule run result {}", err);
+            }
+            result.ok().map(|result| ScheduleRun {
+                schedule_id: row.get("schedule_id"),
+                node_name: row.get("node_name"),
+                started_at: row.get("started_at"),
+                finished_at: row.get("finished_at"),
+                result,
+                detail: row.get("detail"),
+            })
+        })
+        .collect())
+}
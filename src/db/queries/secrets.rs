@@ -0,0 +1,36 @@
+use postgres;
+
+use super::super::super::common_rpc_types::NodeName;
+use super::super::types::NodeSecret;
+
+pub fn upsert(conn: &postgres::Connection, secret: &NodeSecret) -> postgres::Result<()> {
+    ctrace!("Set secret {} of {}", secret.name, secret.node_name);
+
+    conn.execute(
+        "INSERT INTO node_secrets (name, secret_name, sealed) VALUES ($1, $2, $3) \
+         ON CONFLICT (name, secret_name) DO UPDATE SET sealed=excluded.sealed",
+        &[&secret.node_name, &secret.name, &secret.sealed],
+    )?;
+    Ok(())
+}
+
+pub fn remove(conn: &postgres::Connection, node_name: &NodeName, name: &str) -> postgres::Result<()> {
+    ctrace!("Remove secret {} of {}", name, node_name);
+
+    conn.execute("DELETE FROM node_secrets WHERE name=$1 AND secret_name=$2", &[node_name, &name])?;
+    Ok(())
+}
+
+pub fn get(conn: &postgres::Connection, node_name: &NodeName) -> postgres::Result<Vec<NodeSecret>> {
+    ctrace!("Query secrets of {}", node_name);
+
+    let rows = conn.query("SELECT * FROM node_secrets WHERE name=$1 ORDER BY secret_name", &[node_name])?;
+    Ok(rows
+        .iter()
+        .map(|row| NodeSecret {
+            node_name: row.get("name"),
+            name: row.get("secret_name"),
+            sealed: row.get("sealed"),
+        })
+        .collect())
+}
@@ -0,0 +1,99 @@
+use chrono;
+use postgres;
+
+use super::super::types::Slo;
+
+pub fn upsert(conn: &postgres::Connection, slo: &Slo) -> postgres::Result<()> {
+    ctrace!("Set SLO {:?}", slo);
+
+    conn.execute(
+        "INSERT INTO slos (name, tags, target, window_minutes, burn_rate_threshold) VALUES ($1, $2, $3, $4, $5) \
+         ON CONFLICT (name) DO UPDATE SET tags=excluded.tags, target=excluded.target, \
+         window_minutes=excluded.window_minutes, burn_rate_threshold=excluded.burn_rate_threshold",
+        &[&slo.name, &slo.tags, &slo.target, &slo.window_minutes, &slo.burn_rate_threshold],
+    )?;
+    Ok(())
+}
+
+pub fn remove(conn: &postgres::Connection, name: &str) -> postgres::Result<()> {
+    ctrace!("Remove SLO {}", name);
+
+    conn.execute("DELETE FROM slos WHERE name=$1", &[&name])?;
+    conn.execute("DELETE FROM slo_samples WHERE name=$1", &[&name])?;
+    Ok(())
+}
+
+pub fn get_all(conn: &postgres::Connection) -> postgres::Result<Vec<Slo>> {
+    ctrace!("Query SLOs");
+
+    let rows = conn.query("SELECT * FROM slos ORDER BY name", &[])?;
+    Ok(rows
+        .iter()
+        .map(|row| Slo {
+            name: row.get("name"),
+            tags: row.get("tags"),
+            target: row.get("target"),
+            window_minutes: row.get("window_minutes"),
+            burn_rate_threshold: row.get("burn_rate_threshold"),
+        })
+        .collect())
+}
+
+pub fn insert_sample(
+    conn: &postgres::Connection,
+    name: &str,
+    minute: &chrono::DateTime<chrono::Local>,
+    good: bool,
+) -> postgres::Result<()> {
+    ctrace!("Add SLO sample of {} at {}", name, minute);
+
+    conn.execute(
+        "INSERT INTO slo_samples (name, minute, good) VALUES ($1, $2, $3) ON CONFLICT (name, minute) DO NOTHING",
+        &[&name, minute, &good],
+    )?;
+    Ok(())
+}
+
+/// Writes the samples of the minutes in a transaction
+pub fn insert_samples(
+    conn: &postgres::Connection,
+    name: &str,
+    minutes: &[chrono::DateTime<chrono::Local>],
+    good: bool,
+) -> postgres::Result<()> {
+    ctrace!("Add {} SLO samples of {}", minutes.len(), name);
+
+    let transaction = conn.transaction()?;
+    {
+        let statement = transaction.prepare(
+            "INSERT INTO slo_samples (name, minute, good) VALUES ($1, $2, $3) ON CONFLICT (name, minute) DO NOTHING",
+        )?;
+        for minute in minutes {
+            statement.execute(&[&name, minute, &good])?;
+        }
+    }
+    transaction.commit()
+}
+
+pub fn get_last_sample_minute(
+    conn: &postgres::Connection,
+    name: &str,
+) -> postgres::Result<Option<chrono::DateTime<chrono::Local>>> {
+    let rows = conn.query("SELECT MAX(minute) AS minute FROM slo_samples WHERE name=$1", &[&name])?;
+    Ok(rows.get(0).get("minute"))
+}
+
+/// Returns the number of the good samples and the number of all samples since the given time.
+pub fn count_samples(
+    conn: &postgres::Connection,
+    name: &str,
+    since: &chrono::DateTime<chrono::Local>,
+) -> postgres::Result<(i64, i64)> {
+    let rows = conn.query(
+        "SELECT COUNT(*) FILTER (WHERE good) AS good, COUNT(*) AS total FROM slo_samples \
+         WHERE name=$1 AND minute >= $2",
+        &[&name, since],
+    )?;
+    let row = rows.get(0);
+    Ok((row.get("good"), row.get("total")))
+}
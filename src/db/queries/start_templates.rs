@@ -0,0 +1,36 @@
+use postgres;
+
+use super::super::types::StartTemplate;
+
+pub fn upsert(conn: &postgres::Connection, template: &StartTemplate) -> postgres::Result<()> {
+    ctrace!("Set start template {:?}", template);
+
+    conn.execute(
+        "INSERT INTO start_templates (name, env, args, commit_hash) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT (name) DO UPDATE SET env=excluded.env, args=excluded.args, commit_hash=excluded.commit_hash",
+        &[&template.name, &template.env, &template.args, &template.commit_hash],
+    )?;
+    Ok(())
+}
+
+pub fn remove(conn: &postgres::Connection, name: &str) -> postgres::Result<()> {
+    ctrace!("Remove start template {}", name);
+
+    conn.execute("DELETE FROM start_templates WHERE name=$1", &[&name])?;
+    Ok(())
+}
+
+pub fn get_all(conn: &postgres::Connection) -> postgres::Result<Vec<StartTemplate>> {
+    ctrace!("Query start templates");
+
+    let rows = conn.query("SELECT * FROM start_templates ORDER BY name", &[])?;
+    Ok(rows
+        .iter()
+        .map(|row| StartTemplate {
+            name: row.get("name"),
+            env: row.get("env"),
+            args: row.get("args"),
+            commit_hash: row.get("commit_hash"),
+        })
+        .collect())
+}
@@ -0,0 +1,55 @@
+use postgres;
+
+use super::super::super::common_rpc_types::NodeName;
+use super::super::types::NodeEdit;
+
+pub fn get(conn: &postgres::Connection, node_name: &NodeName) -> postgres::Result<Vec<String>> {
+    ctrace!("Query tags by name {}", node_name);
+
+    let rows = conn.query("SELECT tag FROM node_tags WHERE name=$1 ORDER BY tag", &[node_name])?;
+    Ok(rows.iter().map(|row| row.get("tag")).collect())
+}
+
+pub fn add(conn: &postgres::Connection, node_name: &NodeName, tags: &[String]) -> postgres::Result<()> {
+    ctrace!("Add tags {:?} to {}", tags, node_name);
+
+    for tag in tags {
+        conn.execute(
+            "INSERT INTO node_tags (name, tag) VALUES ($1, $2) ON CONFLICT (name, tag) DO NOTHING",
+            &[node_name, tag],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn remove(conn: &postgres::Connection, node_name: &NodeName, tags: &[String]) -> postgres::Result<()> {
+    ctrace!("Remove tags {:?} from {}", tags, node_name);
+
+    conn.execute("DELETE FROM node_tags WHERE name=$1 AND tag = ANY($2)", &[node_name, &tags])?;
+    Ok(())
+}
+
+pub fn get_node_names(conn: &postgres::Connection, tags: &[String]) -> postgres::Result<Vec<NodeName>> {
+    ctrace!("Query node names by tags {:?}", tags);
+
+    let rows = conn.query("SELECT DISTINCT name FROM node_tags WHERE tag = ANY($1)", &[&tags])?;
+    Ok(rows.iter().map(|row| row.get("name")).collect())
+}
+
+/// Changes the tags of the nodes in a transaction
+pub fn edit(conn: &postgres::Connection, edits: &[NodeEdit]) -> postgres::Result<()> {
+    ctrace!("Edit tags {:?}", edits);
+
+    let transaction = conn.transaction()?;
+    for edit in edits {
+        transaction
+            .execute("DELETE FROM node_tags WHERE name=$1 AND tag = ANY($2)", &[&edit.node_name, &edit.remove_tags])?;
+        for tag in &edit.add_tags {
+            transaction.execute(
+                "INSERT INTO node_tags (name, tag) VALUES ($1, $2) ON CONFLICT (name, tag) DO NOTHING",
+                &[&edit.node_name, tag],
+            )?;
+        }
+    }
+    transaction.commit()
+}
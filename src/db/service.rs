@@ -1,41 +1,114 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::error;
 use std::net::SocketAddr;
 use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use postgres;
-use postgres::TlsMode;
+use chrono;
+use chrono::Timelike;
+use serde_json::{Map, Value};
 
 use super::super::common_rpc_types as rpc_type;
 use super::super::common_rpc_types::{NodeName, NodeStatus, StructuredLog};
+use super::super::secrets;
 use super::event::{Event, EventSubscriber};
-use super::queries;
-use super::types::{AgentExtra, AgentQueryResult, Connection, Connections, Error as DBError, Log, LogQueryParams};
+use super::log_storage;
+use super::log_storage::{LogStorage, LogStorageOption};
+use super::storage::Storage;
+use super::types::{
+    AgentExtra, AgentQueryResult, AgentSnapshot, ArchivedNode, Audit, AuditEntry, AuditQueryParams, Connection,
+    Connections, DesiredState, Error as DBError, Log, LogContext, LogQueryParams, LogRule, MetricResolution,
+    MetricSample, NodeEdit, NodeEvent, NodeEventKind, NodeEventQueryParams, NodeSecret, Note, NoteEntry, Paging,
+    Schedule, ScheduleEntry, ScheduleRun, ScheduleRunQueryParams, Slo, SloStatus, StartTemplate,
+};
 use util;
 
+/// The burn rate is calculated from the samples in this window
+const SLO_BURN_RATE_WINDOW_MINUTES: i64 = 60;
+
+/// The agents which don't reconnect in this period after the hub starts are regarded as disconnected
+const RECONNECT_GRACE_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+/// The snapshots of the agents are written together in this interval
+const SNAPSHOT_FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The pairs of the resolution and the coarser resolution which it is rolled up into
+const METRIC_ROLL_UPS: [(MetricResolution, MetricResolution); 2] =
+    [(MetricResolution::Raw, MetricResolution::Minute), (MetricResolution::Minute, MetricResolution::Hour)];
+
+/// The delay of each query and when the delay ends
+type InjectedDelay = Arc<Mutex<Option<(Duration, Instant)>>>;
+
+/// Writes are serialized by the service thread.
+/// Reads don't go through the service thread, so they run concurrently with writes.
 #[derive(Debug, Clone)]
 pub enum Message {
     InitializeAgent(AgentQueryResult, Sender<bool>),
     UpdateAgent(AgentQueryResult),
-    GetAgent(NodeName, Sender<Option<AgentQueryResult>>),
-    GetAgents(Sender<Vec<AgentQueryResult>>),
-    GetConnections(Sender<Vec<rpc_type::Connection>>),
-    SaveStartOption(NodeName, String, String),
-    GetAgentExtra(NodeName, Sender<Option<AgentExtra>>),
-    GetLogs(LogQueryParams, Sender<Vec<Log>>),
+    DisconnectAgent(AgentQueryResult),
+    SaveStartOption(NodeName, String, String, Option<String>),
     WriteLogs(NodeName, Vec<StructuredLog>),
-    GetLogTargets(Sender<Vec<String>>),
+    WriteBackfilledLogs(NodeName, Vec<StructuredLog>),
+    AddTags(NodeName, Vec<String>),
+    RemoveTags(NodeName, Vec<String>),
+    WriteAudit(AuditEntry),
+    WriteEvent(NodeName, NodeEventKind, Value),
+    SetSlo(Slo),
+    RemoveSlo(String),
+    RecordSloSamples,
+    RecordSloDowntime,
+    SetStartTemplate(StartTemplate),
+    RemoveStartTemplate(String),
+    SetLogRule(LogRule),
+    RemoveLogRule(String),
+    SetDesiredState(DesiredState),
+    RemoveDesiredState(NodeName),
+    SetSecret(NodeSecret),
+    RemoveSecret(NodeName, String),
+    RecordMetricSamples,
+    RollUpMetricSamples,
+    RemoveMetricSamples(MetricResolution, chrono::DateTime<chrono::Local>),
+    /// Marks the agents which are still reconnecting as disconnected
+    ExpireReconnecting,
+    /// Writes the snapshots which are changed since the last flush
+    FlushSnapshots,
+    CreateSchedule(ScheduleEntry, Sender<Result<Schedule, DBError>>),
+    RemoveSchedule(i32, Sender<Result<bool, DBError>>),
+    AddNote(NoteEntry, Sender<Result<Note, DBError>>),
+    RemoveNote(i32, Sender<Result<bool, DBError>>),
+    WriteScheduleRun(ScheduleRun),
+    SetMaintenance(NodeName, bool),
+    SetSealing(NodeName, bool),
+    ArchiveAgent(NodeName, Sender<Result<(), DBError>>),
+    /// The secrets sealed again for the new name
+    RenameAgent(NodeName, NodeName, Vec<NodeSecret>, Sender<Result<(), DBError>>),
+    EditNodes(Vec<NodeEdit>, Sender<Result<(), DBError>>),
+    /// The callback is called after all the writes requested before are done
+    Flush(Sender<()>),
+    /// The callback is called when the service thread handles it
+    Ping(Sender<()>),
 }
 
 #[derive(Clone)]
 pub struct ServiceSender {
     sender: Sender<Message>,
+    state: Arc<RwLock<State>>,
+    storage: Arc<Storage>,
+    log_storage: Arc<LogStorage>,
+    injected_delay: InjectedDelay,
 }
 
 struct State {
     agent_query_result: HashMap<NodeName, AgentQueryResult>,
     connection: Connections,
+    /// The nodes which are stopped on purpose, e.g. by a scheduled restart
+    maintenance: HashSet<NodeName>,
+    /// The names of the renamed nodes by the names which their agents report
+    aliases: HashMap<NodeName, NodeName>,
 }
 
 impl State {
@@ -43,90 +116,277 @@ impl State {
         Self {
             agent_query_result: HashMap::new(),
             connection: Connections::new(),
+            maintenance: HashSet::new(),
+            aliases: HashMap::new(),
         }
     }
+
+    fn socket_addrs_to_name(&self, addrs: &Connection) -> Option<rpc_type::Connection> {
+        let (first, second) = addrs;
+        let first_name = self.socket_addr_to_name(first);
+        let second_name = self.socket_addr_to_name(second);
+        first_name.and_then(|first_name| second_name.map(|second_name| (first_name, second_name)))
+    }
+
+    fn socket_addr_to_name(&self, addr: &SocketAddr) -> Option<NodeName> {
+        let find = self
+            .agent_query_result
+            .values()
+            .find(|agent| agent.address.map(|agent_address| agent_address == *addr).unwrap_or(false));
+
+        find.map(|agent| agent.name.clone())
+    }
 }
 
 pub struct Service {
-    state: State,
+    state: Arc<RwLock<State>>,
     event_subscriber: Box<EventSubscriber>,
-    db_conn: postgres::Connection,
+    storage: Arc<Storage>,
+    log_storage: Arc<LogStorage>,
+    injected_delay: InjectedDelay,
+    /// The states before the hub restarted, of the agents which have not sent their states yet
+    recovered: HashMap<NodeName, AgentSnapshot>,
+    /// The snapshots which are not written yet. Only the last snapshot of each agent is written.
+    pending_snapshots: RefCell<HashMap<NodeName, AgentSnapshot>>,
+    /// The incoming logs are matched against them
+    log_rules: Vec<LogRule>,
 }
 
 pub struct ServiceNewArg {
     pub event_subscriber: Box<EventSubscriber>,
-    pub db_user: String,
-    pub db_password: String,
+    pub storage: Arc<Storage>,
+    pub log_storage: LogStorageOption,
 }
 
 impl Service {
     fn new(
         ServiceNewArg {
             event_subscriber,
-            db_user,
-            db_password,
+            storage,
+            log_storage,
         }: ServiceNewArg,
     ) -> Self {
-        let conn_uri = format!("postgres://{}:{}@localhost", db_user, db_password);
+        let log_storage = log_storage::create(log_storage, Arc::clone(&storage));
 
-        let conn = postgres::Connection::connect(conn_uri, TlsMode::None).unwrap();
-        queries::config::set_query_timeout(&conn).unwrap();
+        let mut state = State::new();
+        let mut recovered = HashMap::new();
+        match storage.get_agent_snapshots() {
+            Ok(snapshots) => {
+                cinfo!("Wait for {} agents to reconnect", snapshots.len());
+                for snapshot in snapshots {
+                    let mut agent_query_result = AgentQueryResult::from(snapshot.clone());
+                    agent_query_result.status = NodeStatus::Reconnecting;
+                    state.agent_query_result.insert(snapshot.name.clone(), agent_query_result);
+                    recovered.insert(snapshot.name.clone(), snapshot);
+                }
+            }
+            Err(err) => cerror!("Cannot recover the agent states : {}", err),
+        }
+        match storage.get_node_aliases() {
+            Ok(aliases) => state.aliases = aliases.into_iter().collect(),
+            Err(err) => cerror!("Cannot read the node aliases : {}", err),
+        }
+        let log_rules = storage.get_log_rules().unwrap_or_else(|err| {
+            cerror!("Cannot read the log rules : {}", err);
+            Vec::new()
+        });
 
         Self {
-            state: State::new(),
+            state: Arc::new(RwLock::new(state)),
             event_subscriber,
-            db_conn: conn,
+            storage,
+            log_storage,
+            injected_delay: Default::default(),
+            recovered,
+            pending_snapshots: Default::default(),
+            log_rules,
         }
     }
 
     pub fn run_thread(arg: ServiceNewArg) -> ServiceSender {
         let (tx, rx) = channel();
-        let service_sender = ServiceSender::new(tx.clone());
 
         let mut service = Service::new(arg);
+        let service_sender = ServiceSender {
+            sender: tx,
+            state: Arc::clone(&service.state),
+            storage: Arc::clone(&service.storage),
+            log_storage: Arc::clone(&service.log_storage),
+            injected_delay: Arc::clone(&service.injected_delay),
+        };
+
+        if !service.recovered.is_empty() {
+            let sender = service_sender.sender.clone();
+            thread::Builder::new()
+                .name("db reconnect timer".to_string())
+                .spawn(move || {
+                    thread::sleep(RECONNECT_GRACE_PERIOD);
+                    sender.send(Message::ExpireReconnecting).expect("Should success send request");
+                })
+                .expect("Should success running db reconnect timer thread");
+        }
+
+        let sender = service_sender.sender.clone();
+        thread::Builder::new()
+            .name("db snapshot timer".to_string())
+            .spawn(move || loop {
+                thread::sleep(SNAPSHOT_FLUSH_INTERVAL);
+                sender.send(Message::FlushSnapshots).expect("Should success send request");
+            })
+            .expect("Should success running db snapshot timer thread");
 
         thread::Builder::new()
             .name("db service".to_string())
             .spawn(move || {
                 for message in rx {
+                    wait_injected_delay(&service.injected_delay);
                     match message {
                         Message::InitializeAgent(agent_query_result, callback) => {
                             service.initialize_agent(&agent_query_result, callback.clone());
                         }
                         Message::UpdateAgent(agent_query_result) => {
-                            service.update_agent(agent_query_result.clone());
-                        }
-                        Message::GetAgent(node_name, callback) => {
-                            service.get_agent(&node_name, callback.clone());
-                        }
-                        Message::GetAgents(callback) => {
-                            service.get_agents(callback.clone());
+                            service.update_agent(agent_query_result.clone(), false);
                         }
-                        Message::GetConnections(callback) => {
-                            service.get_connections(callback.clone());
+                        Message::DisconnectAgent(agent_query_result) => {
+                            service.update_agent(agent_query_result, true);
                         }
-                        Message::SaveStartOption(node_name, env, args) => {
-                            util::log_error(&node_name, service.save_start_option(&node_name, &env, &args));
+                        Message::SaveStartOption(node_name, env, args, binary_path) => {
+                            let result = service.save_start_option(&node_name, &env, &args, binary_path);
+                            util::log_error(&node_name, result);
                         }
-                        Message::GetAgentExtra(node_name, callback) => {
-                            util::log_error(&node_name, service.get_agent_extra(&node_name, callback.clone()));
-                        }
-                        Message::GetLogs(params, callback) => {
-                            let result = service.get_logs(params, callback);
+                        Message::WriteLogs(node_name, logs) => {
+                            let result = service.write_logs(&node_name, logs, false);
                             if let Err(err) = result {
                                 cerror!("Error at {}", err);
                             }
                         }
-                        Message::WriteLogs(node_name, logs) => {
-                            let result = service.write_logs(&node_name, logs);
+                        Message::WriteBackfilledLogs(node_name, logs) => {
+                            let result = service.write_logs(&node_name, logs, true);
                             if let Err(err) = result {
                                 cerror!("Error at {}", err);
                             }
                         }
-                        Message::GetLogTargets(callback) => {
-                            let result = service.get_log_targets(callback);
-                            if let Err(err) = result {
-                                cerror!("Error at {}", err);
+                        Message::AddTags(node_name, tags) => {
+                            util::log_error(&node_name, service.add_tags(&node_name, &tags));
+                        }
+                        Message::RemoveTags(node_name, tags) => {
+                            util::log_error(&node_name, service.remove_tags(&node_name, &tags));
+                        }
+                        Message::WriteAudit(entry) => {
+                            util::log_error(&entry.method, service.write_audit(&entry));
+                        }
+                        Message::WriteEvent(node_name, kind, detail) => {
+                            service.write_event(&node_name, kind, detail);
+                        }
+                        Message::SetSlo(slo) => {
+                            util::log_error(&slo.name, service.set_slo(&slo));
+                        }
+                        Message::RemoveSlo(name) => {
+                            util::log_error(&name, service.remove_slo(&name));
+                        }
+                        Message::RecordSloSamples => {
+                            util::log_error("record_slo_samples", service.record_slo_samples());
+                        }
+                        Message::RecordSloDowntime => {
+                            util::log_error("record_slo_downtime", service.record_slo_downtime());
+                        }
+                        Message::SetStartTemplate(template) => {
+                            util::log_error(&template.name, service.set_start_template(&template));
+                        }
+                        Message::RemoveStartTemplate(name) => {
+                            util::log_error(&name, service.remove_start_template(&name));
+                        }
+                        Message::SetLogRule(rule) => {
+                            util::log_error(&rule.name, service.set_log_rule(&rule));
+                        }
+                        Message::RemoveLogRule(name) => {
+                            util::log_error(&name, service.remove_log_rule(&name));
+                        }
+                        Message::SetDesiredState(state) => {
+                            let result = service.storage.upsert_desired_state(&state);
+                            util::log_error(&state.node_name, result.map_err(Into::into));
+                        }
+                        Message::RemoveDesiredState(node_name) => {
+                            let result = service.storage.remove_desired_state(&node_name);
+                            util::log_error(&node_name, result.map_err(Into::into));
+                        }
+                        Message::SetSecret(secret) => {
+                            let result = service.storage.upsert_secret(&secret);
+                            util::log_error(&secret.node_name, result.map_err(Into::into));
+                        }
+                        Message::RemoveSecret(node_name, name) => {
+                            let result = service.storage.remove_secret(&node_name, &name);
+                            util::log_error(&node_name, result.map_err(Into::into));
+                        }
+                        Message::RecordMetricSamples => {
+                            util::log_error("record_metric_samples", service.record_metric_samples());
+                        }
+                        Message::RollUpMetricSamples => {
+                            util::log_error("roll_up_metric_samples", service.roll_up_metric_samples());
+                        }
+                        Message::RemoveMetricSamples(resolution, before) => {
+                            let result = service.storage.remove_metric_samples(resolution, &before);
+                            util::log_error(resolution, result.map_err(Into::into));
+                        }
+                        Message::ExpireReconnecting => {
+                            service.expire_reconnecting();
+                        }
+                        Message::FlushSnapshots => {
+                            service.flush_snapshots();
+                        }
+                        Message::CreateSchedule(entry, callback) => {
+                            if let Err(err) = callback.send(service.storage.insert_schedule(&entry)) {
+                                cerror!("Cannot send callback : {}", err);
+                            }
+                        }
+                        Message::RemoveSchedule(id, callback) => {
+                            if let Err(err) = callback.send(service.storage.remove_schedule(id)) {
+                                cerror!("Cannot send callback : {}", err);
+                            }
+                        }
+                        Message::AddNote(entry, callback) => {
+                            if let Err(err) = callback.send(service.storage.insert_note(&entry)) {
+                                cerror!("Cannot send callback : {}", err);
+                            }
+                        }
+                        Message::RemoveNote(id, callback) => {
+                            if let Err(err) = callback.send(service.storage.remove_note(id)) {
+                                cerror!("Cannot send callback : {}", err);
+                            }
+                        }
+                        Message::WriteScheduleRun(run) => {
+                            util::log_error(&run.node_name, service.write_schedule_run(&run));
+                        }
+                        Message::SetMaintenance(node_name, maintenance) => {
+                            service.set_maintenance(&node_name, maintenance);
+                        }
+                        Message::SetSealing(node_name, sealing) => {
+                            service.set_sealing(&node_name, sealing);
+                        }
+                        Message::ArchiveAgent(node_name, callback) => {
+                            if let Err(err) = callback.send(service.archive_agent(&node_name)) {
+                                cerror!("Cannot send callback : {}", err);
+                            }
+                        }
+                        Message::RenameAgent(from, to, resealed, callback) => {
+                            if let Err(err) = callback.send(service.rename_agent(&from, &to, &resealed)) {
+                                cerror!("Cannot send callback : {}", err);
+                            }
+                        }
+                        Message::EditNodes(edits, callback) => {
+                            if let Err(err) = callback.send(service.edit_nodes(&edits)) {
+                                cerror!("Cannot send callback : {}", err);
+                            }
+                        }
+                        Message::Flush(callback) => {
+                            service.flush_snapshots();
+                            if let Err(err) = callback.send(()) {
+                                cerror!("Cannot send callback : {}", err);
+                            }
+                        }
+                        Message::Ping(callback) => {
+                            if let Err(err) = callback.send(()) {
+                                cerror!("Cannot send callback : {}", err);
                             }
                         }
                     }
@@ -137,119 +397,529 @@ impl Service {
         service_sender
     }
 
+    /// The events are sent and written after the state lock is released, because the subscribers and the DB can be slow
     fn initialize_agent(&mut self, state: &AgentQueryResult, callback: Sender<bool>) {
         let name = state.name.clone();
-        if !self.state.agent_query_result.contains_key(&name) {
-            self.event_subscriber.on_event(Event::AgentUpdated {
-                before: None,
-                after: state.clone(),
-            });
-            self.state.agent_query_result.insert(name, state.clone());
-            if let Err(err) = callback.send(true) {
-                cerror!("Cannot send callback : {}", err);
-            }
-            return
-        }
-
-        let before = self.state.agent_query_result.get_mut(&name).unwrap();
-        if before.status != NodeStatus::Error {
-            cinfo!("Node {}({:?}) try to connect but a node with the same name already connected", name, before.status);
-            if let Err(err) = callback.send(false) {
-                cerror!("Cannot send callback : {}", err);
+        let state = {
+            let mut service_state = self.state.write().expect("Should success write state");
+            if !service_state.agent_query_result.contains_key(&name) {
+                service_state.agent_query_result.insert(name.clone(), state.clone());
+                state.clone()
+            } else {
+                let before = service_state.agent_query_result.get_mut(&name).unwrap();
+                if before.status != NodeStatus::Error && before.status != NodeStatus::Reconnecting {
+                    cinfo!(
+                        "Node {}({:?}) try to connect but a node with the same name already connected",
+                        name,
+                        before.status
+                    );
+                    if let Err(err) = callback.send(false) {
+                        cerror!("Cannot send callback : {}", err);
+                    }
+                    return
+                }
+                let mut state = state.clone();
+                state.sealing = sealing_after(before, &state);
+                *before = state.clone();
+                state
             }
-            return
-        }
+        };
 
         self.event_subscriber.on_event(Event::AgentUpdated {
             before: None,
             after: state.clone(),
         });
-        *before = state.clone();
+        self.event_subscriber.on_event(Event::AgentConnected {
+            state: state.clone(),
+        });
+        self.write_event(&name, NodeEventKind::AgentConnected, json!({ "status": state.status }));
+        self.write_snapshot(&state);
         if let Err(err) = callback.send(true) {
             cerror!("Cannot send callback : {}", err);
         }
     }
 
-    fn update_agent(&mut self, after: AgentQueryResult) {
+    /// The events are sent and written after the state lock is released, because the subscribers and the DB can be slow
+    fn update_agent(&mut self, mut after: AgentQueryResult, is_disconnected: bool) {
         let name = after.name.clone();
-        debug_assert_ne!(None, self.state.agent_query_result.get(&name));
+        let (before, connection_changed) = {
+            let mut guard = self.state.write().expect("Should success write state");
+            let service_state = &mut *guard;
+            let before = match service_state.agent_query_result.get(&name) {
+                Some(before) => before.clone(),
+                None => {
+                    cdebug!("{} is updated after it is removed", name);
+                    return
+                }
+            };
+            after.sealing = sealing_after(&before, &after);
 
-        {
-            let before = self.state.agent_query_result.get(&name).expect("Checked");
+            let (added, removed) = service_state.connection.update(&before, &after);
+            let connection_changed = if !added.is_empty() || !removed.is_empty() {
+                Some(Event::ConnectionChanged {
+                    added: added.iter().filter_map(|addrs| service_state.socket_addrs_to_name(addrs)).collect(),
+                    removed: removed.iter().filter_map(|addrs| service_state.socket_addrs_to_name(addrs)).collect(),
+                })
+            } else {
+                None
+            };
+            service_state.agent_query_result.insert(name.clone(), after.clone());
+            (before, connection_changed)
+        };
 
-            let (added, removed) = self.state.connection.update(before, &after);
-            if !added.is_empty() || !removed.is_empty() {
-                self.event_subscriber.on_event(Event::ConnectionChanged {
-                    added: added.iter().filter_map(|addrs| self.socket_addrs_to_name(addrs)).collect(),
-                    removed: removed.iter().filter_map(|addrs| self.socket_addrs_to_name(addrs)).collect(),
-                });
+        if let Some(connection_changed) = connection_changed {
+            self.event_subscriber.on_event(connection_changed);
+        }
+        self.event_subscriber.on_event(Event::AgentUpdated {
+            before: Some(before.clone()),
+            after: after.clone(),
+        });
+        let event = if is_disconnected {
+            self.event_subscriber.on_event(Event::AgentDisconnected {
+                last: before.clone(),
+                reason: None,
+            });
+            Some((NodeEventKind::AgentDisconnected, json!({ "status": before.status })))
+        } else {
+            status_changed_event(before.status, after.status)
+        };
+
+        // The state of a disconnected agent is not persisted, so the state before the disconnection is recovered
+        let mut version_changed = None;
+        let mut clock_changed = None;
+        if !is_disconnected {
+            if AgentSnapshot::from(&before) != AgentSnapshot::from(&after) {
+                self.write_snapshot(&after);
+            }
+            if let Some(recovered) = self.recovered.remove(&name) {
+                self.write_recovery_events(&recovered, &AgentSnapshot::from(&after));
+            }
+            if let (Some(from), Some(to)) = (&before.version, &after.version) {
+                if from.hash != to.hash {
+                    version_changed = Some(json!({ "from": from.hash, "to": to.hash }));
+                }
+            }
+            if before.is_clock_skewed() != after.is_clock_skewed() {
+                let kind = if after.is_clock_skewed() {
+                    NodeEventKind::ClockSkewed
+                } else {
+                    NodeEventKind::ClockSynchronized
+                };
+                clock_changed = Some((kind, json!(after.clock)));
+            }
+        }
+
+        if let Some((kind, detail)) = event {
+            self.write_event(&name, kind, detail);
+        }
+        if let Some(detail) = version_changed {
+            self.write_event(&name, NodeEventKind::VersionChanged, detail);
+        }
+        if let Some((kind, detail)) = clock_changed {
+            self.write_event(&name, kind, detail);
+        }
+    }
+
+    fn write_snapshot(&self, state: &AgentQueryResult) {
+        self.pending_snapshots.borrow_mut().insert(state.name.clone(), AgentSnapshot::from(state));
+    }
+
+    fn flush_snapshots(&self) {
+        let snapshots: Vec<AgentSnapshot> =
+            self.pending_snapshots.borrow_mut().drain().map(|(_, snapshot)| snapshot).collect();
+        if snapshots.is_empty() {
+            return
+        }
+        if let Err(err) = self.storage.upsert_agent_snapshots(&snapshots) {
+            cerror!("Cannot write the snapshots of {} agents : {}", snapshots.len(), err);
+        }
+    }
+
+    /// Writes the events of the changes which happened while the hub was down.
+    fn write_recovery_events(&self, before: &AgentSnapshot, after: &AgentSnapshot) {
+        let mut changes = Map::new();
+        if before.status != after.status {
+            changes.insert("status".to_string(), json!({ "from": before.status, "to": after.status }));
+        }
+        if before.address != after.address {
+            changes.insert("address".to_string(), json!({ "from": before.address, "to": after.address }));
+        }
+        if before.version_hash != after.version_hash {
+            changes.insert("versionHash".to_string(), json!({ "from": before.version_hash, "to": after.version_hash }));
+        }
+        if changes.is_empty() {
+            return
+        }
+
+        cinfo!("{} changed while the hub was down : {:?}", after.name, changes);
+        self.write_event(&after.name, NodeEventKind::ChangedWhileHubDown, Value::Object(changes));
+        if let Some((kind, detail)) = status_changed_event(before.status, after.status) {
+            self.write_event(&after.name, kind, detail);
+        }
+    }
+
+    fn expire_reconnecting(&mut self) {
+        let mut service_state = self.state.write().expect("Should success write state");
+        for (name, recovered) in self.recovered.drain() {
+            let agent = match service_state.agent_query_result.get_mut(&name) {
+                Some(agent) => agent,
+                None => continue,
+            };
+            if agent.status != NodeStatus::Reconnecting {
+                continue
             }
+            cwarn!("{} didn't reconnect in {:?} after the hub started", name, RECONNECT_GRACE_PERIOD);
 
+            let before = agent.clone();
+            agent.status = NodeStatus::Error;
+            self.event_subscriber.on_event(Event::AgentDisconnected {
+                last: before.clone(),
+                reason: Some("Not reconnected after the hub restarted".to_string()),
+            });
             self.event_subscriber.on_event(Event::AgentUpdated {
-                before: Some(before.clone()),
-                after: after.clone(),
+                before: Some(before),
+                after: agent.clone(),
             });
+            if let Err(err) = self.storage.insert_event(
+                &name,
+                NodeEventKind::AgentDisconnected,
+                &json!({ "status": recovered.status, "reason": "Not reconnected after the hub restarted" }),
+            ) {
+                cerror!("Cannot write event of {} : {}", name, err);
+            }
         }
+    }
 
-        let before = self.state.agent_query_result.get_mut(&name).expect("Checked");
-        *before = after;
+    fn write_event(&self, node_name: &NodeName, kind: NodeEventKind, detail: Value) {
+        if let Err(err) = self.storage.insert_event(node_name, kind, &detail) {
+            cerror!("Cannot write event {:?} of {} : {}", kind, node_name, err);
+        }
     }
 
-    fn socket_addrs_to_name(&self, addrs: &Connection) -> Option<rpc_type::Connection> {
-        let (first, second) = addrs;
-        let first_name = self.socket_addr_to_name(first);
-        let second_name = self.socket_addr_to_name(second);
-        first_name.and_then(|first_name| second_name.map(|second_name| (first_name, second_name)))
+    /// Moves the node to the archive and removes it from the dashboard.
+    fn archive_agent(&mut self, name: &NodeName) -> Result<(), DBError> {
+        let extra = self.storage.get_agent_extra(name)?;
+        let desired_state = self.storage.get_desired_states()?.into_iter().find(|state| state.node_name == *name);
+        let tags = self.storage.get_tags(name)?;
+        let notes = self.storage.get_node_notes(name)?;
+        let schedules: Vec<Schedule> =
+            self.storage.get_schedules()?.into_iter().filter(|schedule| schedule.node_name == *name).collect();
+
+        let mut guard = self.state.write().expect("Should success write state");
+        let service_state = &mut *guard;
+        let state = service_state.agent_query_result.get(name).cloned();
+        let detail = json!({
+            "lastState": state.as_ref().map(AgentSnapshot::from),
+            "startOption": extra.map(|extra| {
+                json!({ "env": extra.prev_env, "args": extra.prev_args, "binaryPath": extra.binary_path })
+            }),
+            "desiredState": desired_state,
+            "tags": tags,
+            "schedules": schedules,
+            "notes": notes,
+        });
+        self.pending_snapshots.borrow_mut().remove(name);
+        self.storage.archive_node(name, &detail)?;
+
+        if let Some(before) = state {
+            let mut after = before.clone();
+            after.peers.clear();
+            let (_, removed) = service_state.connection.update(&before, &after);
+            if !removed.is_empty() {
+                self.event_subscriber.on_event(Event::ConnectionChanged {
+                    added: Vec::new(),
+                    removed: removed.iter().filter_map(|addrs| service_state.socket_addrs_to_name(addrs)).collect(),
+                });
+            }
+            service_state.agent_query_result.remove(name);
+        }
+        service_state.maintenance.remove(name);
+        self.recovered.remove(name);
+
+        self.event_subscriber.on_event(Event::AgentRemoved {
+            name: name.clone(),
+        });
+        self.write_event(name, NodeEventKind::Archived, json!({}));
+        Ok(())
     }
 
-    fn socket_addr_to_name(&self, addr: &SocketAddr) -> Option<NodeName> {
-        let find = self
-            .state
-            .agent_query_result
-            .values()
-            .find(|agent| agent.address.map(|agent_address| agent_address == *addr).unwrap_or(false));
+    /// The maintenance is changed after all the tags are changed
+    fn edit_nodes(&self, edits: &[NodeEdit]) -> Result<(), DBError> {
+        self.storage.edit_tags(edits)?;
+        for edit in edits {
+            if let Some(maintenance) = edit.maintenance {
+                self.set_maintenance(&edit.node_name, maintenance);
+            }
+        }
+        Ok(())
+    }
 
-        find.map(|agent| agent.name.clone())
+    /// Renames the node in the storages and in the states.
+    /// The connected agent keeps reporting the old name, which is translated with the alias.
+    fn rename_agent(&mut self, from: &NodeName, to: &NodeName, resealed: &[NodeSecret]) -> Result<(), DBError> {
+        self.pending_snapshots.borrow_mut().remove(from);
+        self.storage.rename_node(from, to, resealed)?;
+        if let Err(err) = self.log_storage.rename(from, to) {
+            cerror!("Cannot rename the logs of {} to {} : {}", from, to, err);
+        }
+
+        {
+            let mut guard = self.state.write().expect("Should success write state");
+            let service_state = &mut *guard;
+            if let Some(mut state) = service_state.agent_query_result.remove(from) {
+                state.name = to.clone();
+                self.write_snapshot(&state);
+                service_state.agent_query_result.insert(to.clone(), state);
+            }
+            if service_state.maintenance.remove(from) {
+                service_state.maintenance.insert(to.clone());
+            }
+            let mut renamed_alias = false;
+            for name in service_state.aliases.values_mut().filter(|name| **name == *from) {
+                *name = to.clone();
+                renamed_alias = true;
+            }
+            if !renamed_alias {
+                service_state.aliases.insert(from.clone(), to.clone());
+            }
+            service_state.aliases.retain(|reported_name, name| *reported_name != *name);
+        }
+        if let Some(mut snapshot) = self.recovered.remove(from) {
+            snapshot.name = to.clone();
+            self.recovered.insert(to.clone(), snapshot);
+        }
+
+        self.event_subscriber.on_event(Event::AgentRenamed {
+            from: from.clone(),
+            to: to.clone(),
+        });
+        self.write_event(to, NodeEventKind::Renamed, json!({ "from": from, "to": to }));
+        Ok(())
+    }
+
+    fn write_schedule_run(&self, run: &ScheduleRun) -> Result<(), Box<error::Error>> {
+        self.storage.insert_schedule_run(run)?;
+        Ok(())
     }
 
-    fn get_agent(&self, name: &NodeName, callback: Sender<Option<AgentQueryResult>>) {
-        let agent_query_result = self.state.agent_query_result.get(name);
-        if let Err(err) = callback.send(agent_query_result.map(|state| state.clone())) {
-            cerror!("Cannot call calback get_agent, name: {}\nerr: {}", name, err);
+    fn set_maintenance(&self, node_name: &NodeName, maintenance: bool) {
+        let changed = {
+            let mut service_state = self.state.write().expect("Should success write state");
+            if maintenance {
+                service_state.maintenance.insert(node_name.clone())
+            } else {
+                service_state.maintenance.remove(node_name)
+            }
+        };
+        if !changed {
+            return
         }
+        let kind = if maintenance {
+            NodeEventKind::MaintenanceStarted
+        } else {
+            NodeEventKind::MaintenanceFinished
+        };
+        self.write_event(node_name, kind, json!({}));
     }
 
-    fn get_agents(&self, callback: Sender<Vec<AgentQueryResult>>) {
-        let states: Vec<AgentQueryResult> =
-            self.state.agent_query_result.values().into_iter().map(|state| state.clone()).collect();
-        if let Err(err) = callback.send(states) {
-            cerror!("Callback error {}", err);
+    fn set_sealing(&self, node_name: &NodeName, sealing: bool) {
+        let changed = {
+            let mut service_state = self.state.write().expect("Should success write state");
+            match service_state.agent_query_result.get_mut(node_name) {
+                Some(state) if state.sealing != Some(sealing) => {
+                    state.sealing = Some(sealing);
+                    self.write_snapshot(state);
+                    true
+                }
+                _ => false,
+            }
+        };
+        if !changed {
+            return
         }
+        let kind = if sealing {
+            NodeEventKind::SealingStarted
+        } else {
+            NodeEventKind::SealingStopped
+        };
+        self.write_event(node_name, kind, json!({}));
+    }
+
+    fn set_slo(&self, slo: &Slo) -> Result<(), Box<error::Error>> {
+        self.storage.upsert_slo(slo)?;
+        Ok(())
+    }
+
+    fn remove_slo(&self, name: &str) -> Result<(), Box<error::Error>> {
+        self.storage.remove_slo(name)?;
+        Ok(())
+    }
+
+    fn set_start_template(&self, template: &StartTemplate) -> Result<(), Box<error::Error>> {
+        self.storage.upsert_start_template(template)?;
+        Ok(())
+    }
+
+    fn remove_start_template(&self, name: &str) -> Result<(), Box<error::Error>> {
+        self.storage.remove_start_template(name)?;
+        Ok(())
+    }
+
+    fn set_log_rule(&mut self, rule: &LogRule) -> Result<(), Box<error::Error>> {
+        self.storage.upsert_log_rule(rule)?;
+        self.log_rules.retain(|current| current.name != rule.name);
+        self.log_rules.push(rule.clone());
+        Ok(())
     }
 
-    fn get_connections(&self, callback: Sender<Vec<rpc_type::Connection>>) {
-        let connections: Vec<Connection> = self.state.connection.get_all();
-        let rpc_connections =
-            connections.iter().filter_map(|connection| self.socket_addrs_to_name(connection)).collect();
-        if let Err(err) = callback.send(rpc_connections) {
-            cerror!("Callback error {}", err);
+    fn remove_log_rule(&mut self, name: &str) -> Result<(), Box<error::Error>> {
+        self.storage.remove_log_rule(name)?;
+        self.log_rules.retain(|rule| rule.name != name);
+        Ok(())
+    }
+
+    /// Samples the running nodes
+    fn record_metric_samples(&self) -> Result<(), Box<error::Error>> {
+        let now = chrono::Local::now();
+        let samples: Vec<MetricSample> = {
+            let state = self.state.read().expect("Should success read state");
+            state
+                .agent_query_result
+                .values()
+                .filter(|agent| agent.status == NodeStatus::Run)
+                .map(|agent| MetricSample {
+                    node_name: agent.name.clone(),
+                    time: now,
+                    best_block_number: agent.best_block_id.as_ref().map(|block_id| block_id.block_number),
+                    peer_count: agent.peers.len() as f64,
+                    samples: 1,
+                })
+                .collect()
+        };
+        if !samples.is_empty() {
+            self.storage.upsert_metric_samples(MetricResolution::Raw, &samples)?;
+        }
+        Ok(())
+    }
+
+    /// Rolls up the previous and the current periods, so the coarse resolutions have the latest samples too.
+    /// The minutes are rolled up first, so the last minute of the previous hour is in the hour.
+    fn roll_up_metric_samples(&self) -> Result<(), Box<error::Error>> {
+        let now = chrono::Local::now();
+        for (fine, coarse) in METRIC_ROLL_UPS.iter() {
+            let current = coarse.truncate(&now);
+            let previous = coarse.truncate(&(current - chrono::Duration::seconds(1)));
+            let samples = self.storage.get_metric_samples(*fine, None, &previous, &now)?;
+            let rolled_up = roll_up(&samples, *coarse);
+            if !rolled_up.is_empty() {
+                self.storage.upsert_metric_samples(*coarse, &rolled_up)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Raises an event for the first log which matches each rule, so a burst of the same error raises one
+    fn match_log_rules(&self, node_name: &NodeName, logs: &[StructuredLog]) {
+        for rule in &self.log_rules {
+            if let Some(log) = logs.iter().find(|log| rule.is_matched(log)) {
+                cwarn!("A log of {} matched the rule {} : {}", node_name, rule.name, log.message);
+                self.write_event(
+                    node_name,
+                    NodeEventKind::LogRuleMatched,
+                    json!({
+                        "rule": rule.name,
+                        "level": log.level,
+                        "target": log.target,
+                        "message": log.message,
+                    }),
+                );
+                self.event_subscriber.on_event(Event::LogRuleMatched {
+                    name: node_name.clone(),
+                    rule: rule.name.clone(),
+                    log: log.clone(),
+                });
+            }
         }
     }
 
+    fn record_slo_samples(&self) -> Result<(), Box<error::Error>> {
+        let now = chrono::Local::now();
+        let minute = now.with_second(0).and_then(|time| time.with_nanosecond(0)).unwrap_or(now);
+        for slo in self.storage.get_slos()? {
+            let good = self.is_slo_good(&slo)?;
+            self.storage.insert_slo_sample(&slo.name, &minute, good)?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Nothing is sampled while the hub is down, so the minutes between the last sample and the start are recorded
+     * as bad. The minutes before the window are not recorded because they don't affect the status.
+     */
+    fn record_slo_downtime(&self) -> Result<(), Box<error::Error>> {
+        let now = chrono::Local::now();
+        let current_minute = now.with_second(0).and_then(|time| time.with_nanosecond(0)).unwrap_or(now);
+        for slo in self.storage.get_slos()? {
+            let last_minute = match self.storage.get_last_slo_sample_minute(&slo.name)? {
+                Some(last_minute) => last_minute,
+                None => continue,
+            };
+            let window_start = current_minute - chrono::Duration::minutes(i64::from(slo.window_minutes));
+            let mut minute = cmp::max(last_minute + chrono::Duration::minutes(1), window_start);
+            let mut minutes = Vec::new();
+            while minute < current_minute {
+                minutes.push(minute);
+                minute = minute + chrono::Duration::minutes(1);
+            }
+            if !minutes.is_empty() {
+                cwarn!("SLO {} was not sampled for {} minutes while the hub was down", slo.name, minutes.len());
+                self.storage.insert_slo_samples(&slo.name, &minutes, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// An SLO is good when all the selected nodes are running. The nodes in maintenance are regarded as running.
+    fn is_slo_good(&self, slo: &Slo) -> Result<bool, Box<error::Error>> {
+        let node_names = if slo.tags.is_empty() {
+            None
+        } else {
+            Some(self.storage.get_node_names_by_tags(&slo.tags)?)
+        };
+
+        let service_state = self.state.read().expect("Should success read state");
+        let status = |name: &NodeName| {
+            if service_state.maintenance.contains(name) {
+                return NodeStatus::Run
+            }
+            service_state.agent_query_result.get(name).map(|agent| agent.status).unwrap_or(NodeStatus::Error)
+        };
+        let statuses: Vec<NodeStatus> = match node_names {
+            None => service_state.agent_query_result.keys().map(status).collect(),
+            Some(node_names) => node_names.iter().map(status).collect(),
+        };
+        Ok(!statuses.is_empty() && statuses.iter().all(|status| *status == NodeStatus::Run))
+    }
+
     fn save_start_option(
         &mut self,
         node_name: &NodeName,
         env: &String,
         args: &String,
+        binary_path: Option<String>,
     ) -> Result<(), Box<error::Error>> {
-        let before_extra = queries::agent_extra::get(&self.db_conn, node_name)?;
+        let before_extra = self.storage.get_agent_extra(node_name)?;
         let mut extra = before_extra.clone().unwrap_or(Default::default());
+        // The values of the secrets are given only to the agent
+        let secret_names: Vec<String> =
+            self.storage.get_secrets(node_name)?.into_iter().map(|secret| secret.name).collect();
 
-        extra.prev_env = env.to_string();
+        extra.prev_env = secrets::redact_env(env, &secret_names);
         extra.prev_args = args.to_string();
+        extra.binary_path = binary_path;
 
         let after_extra = extra.clone();
-        queries::agent_extra::upsert(&self.db_conn, node_name, &extra)?;
+        self.storage.upsert_agent_extra(node_name, &extra)?;
 
         self.event_subscriber.on_event(Event::AgentExtraUpdated {
             name: node_name.clone(),
@@ -260,43 +930,115 @@ impl Service {
         Ok(())
     }
 
-    fn get_agent_extra(
+    fn write_logs(
         &self,
         node_name: &NodeName,
-        callback: Sender<Option<AgentExtra>>,
+        logs: Vec<StructuredLog>,
+        backfilled: bool,
     ) -> Result<(), Box<error::Error>> {
-        let extra = queries::agent_extra::get(&self.db_conn, node_name)?;
-        if let Err(err) = callback.send(extra) {
-            cerror!("Callback error {}", err);
+        // The backfilled logs are old, so they don't raise the alerts
+        if !backfilled {
+            self.match_log_rules(node_name, &logs);
         }
-        Ok(())
+        self.log_storage.insert(node_name, logs, backfilled)
     }
 
-    fn get_logs(&self, params: LogQueryParams, callback: Sender<Vec<Log>>) -> Result<(), Box<error::Error>> {
-        let logs = queries::logs::search(&self.db_conn, params)?;
-        callback.send(logs)?;
+    fn add_tags(&self, node_name: &NodeName, tags: &[String]) -> Result<(), Box<error::Error>> {
+        self.storage.add_tags(node_name, tags)?;
+        self.event_subscriber.on_event(Event::TagsChanged {
+            name: node_name.clone(),
+        });
         Ok(())
     }
 
-    fn write_logs(&self, node_name: &NodeName, logs: Vec<StructuredLog>) -> Result<(), Box<error::Error>> {
-        queries::logs::insert(&self.db_conn, node_name, logs)?;
+    fn remove_tags(&self, node_name: &NodeName, tags: &[String]) -> Result<(), Box<error::Error>> {
+        self.storage.remove_tags(node_name, tags)?;
+        self.event_subscriber.on_event(Event::TagsChanged {
+            name: node_name.clone(),
+        });
         Ok(())
     }
 
-    fn get_log_targets(&self, callback: Sender<Vec<String>>) -> Result<(), Box<error::Error>> {
-        let targets = queries::logs::get_targets(&self.db_conn)?;
-        callback.send(targets)?;
+    fn write_audit(&self, entry: &AuditEntry) -> Result<(), Box<error::Error>> {
+        self.storage.insert_audit(entry)?;
         Ok(())
     }
 }
 
-impl ServiceSender {
-    pub fn new(sender: Sender<Message>) -> Self {
-        Self {
-            sender,
+/// A node seals by its config when it starts, so the hub knows it only after it sees the node start.
+/// The sealing is kept while the agent is disconnected, because the node may still run.
+fn sealing_after(before: &AgentQueryResult, after: &AgentQueryResult) -> Option<bool> {
+    match (before.status, after.status) {
+        (NodeStatus::Run, NodeStatus::Run)
+        | (NodeStatus::Error, NodeStatus::Run)
+        | (NodeStatus::Reconnecting, NodeStatus::Run)
+        | (NodeStatus::UFO, NodeStatus::Run) => before.sealing,
+        (_, NodeStatus::Run) => Some(true),
+        (_, NodeStatus::Error) | (_, NodeStatus::Reconnecting) | (_, NodeStatus::UFO) => before.sealing,
+        _ => None,
+    }
+}
+
+fn status_changed_event(before: NodeStatus, after: NodeStatus) -> Option<(NodeEventKind, Value)> {
+    if before == after {
+        return None
+    }
+    let detail = json!({
+        "from": before,
+        "to": after,
+    });
+    let kind = match (before, after) {
+        (NodeStatus::Updating, NodeStatus::Run) => NodeEventKind::UpdateApplied,
+        (_, NodeStatus::Run) => NodeEventKind::CodeChainStarted,
+        (_, NodeStatus::Stop) => NodeEventKind::CodeChainStopped,
+        (_, NodeStatus::Error) => NodeEventKind::CodeChainCrashed,
+        (_, NodeStatus::Updating) => NodeEventKind::UpdateStarted,
+        _ => return None,
+    };
+    Some((kind, detail))
+}
+
+/// Aggregates the samples, which are ordered by the node names and the times, into the periods of the resolution
+fn roll_up(samples: &[MetricSample], resolution: MetricResolution) -> Vec<MetricSample> {
+    let mut rolled_up: Vec<MetricSample> = Vec::new();
+    for sample in samples {
+        let time = resolution.truncate(&sample.time);
+        if let Some(last) = rolled_up.last_mut() {
+            if last.node_name == sample.node_name && last.time == time {
+                let samples = last.samples + sample.samples;
+                let peers = last.peer_count * f64::from(last.samples) + sample.peer_count * f64::from(sample.samples);
+                last.peer_count = peers / f64::from(samples);
+                last.best_block_number = last.best_block_number.max(sample.best_block_number);
+                last.samples = samples;
+                continue
+            }
+        }
+        rolled_up.push(MetricSample {
+            time,
+            ..sample.clone()
+        });
+    }
+    rolled_up
+}
+
+fn wait_injected_delay(injected_delay: &Mutex<Option<(Duration, Instant)>>) {
+    let delay = {
+        let mut injected_delay = injected_delay.lock().expect("Should success get injected_delay");
+        match *injected_delay {
+            Some((delay, until)) if Instant::now() < until => Some(delay),
+            Some(_) => {
+                *injected_delay = None;
+                None
+            }
+            None => None,
         }
+    };
+    if let Some(delay) = delay {
+        thread::sleep(delay);
     }
+}
 
+impl ServiceSender {
     pub fn initialize_agent_query_result(&self, agent_query_result: AgentQueryResult) -> Result<bool, DBError> {
         let (tx, rx) = channel();
         self.sender.send(Message::InitializeAgent(agent_query_result, tx)).expect("Should success update agent");
@@ -308,55 +1050,373 @@ impl ServiceSender {
         self.sender.send(Message::UpdateAgent(agent_query_result)).expect("Should success update agent");
     }
 
+    pub fn disconnect_agent(&self, agent_query_result: AgentQueryResult) {
+        self.sender.send(Message::DisconnectAgent(agent_query_result)).expect("Should success update agent");
+    }
+
     pub fn get_agent_query_result(&self, name: &str) -> Result<Option<AgentQueryResult>, DBError> {
-        let (tx, rx) = channel();
-        self.sender.send(Message::GetAgent(name.to_string(), tx)).expect("Should success send request");
-        let agent_query_result = rx.recv().map_err(|_| DBError::Timeout)?;
-        Ok(agent_query_result)
+        let state = self.state.read().expect("Should success read state");
+        Ok(state.agent_query_result.get(name).cloned())
     }
 
     pub fn get_agents_state(&self) -> Result<Vec<AgentQueryResult>, DBError> {
-        let (tx, rx) = channel();
-        self.sender.send(Message::GetAgents(tx)).expect("Should success send request");
-        let agents_state = rx.recv().map_err(|_| DBError::Timeout)?;
-        Ok(agents_state)
+        let state = self.state.read().expect("Should success read state");
+        Ok(state.agent_query_result.values().cloned().collect())
     }
 
     pub fn get_connections(&self) -> Result<Vec<rpc_type::Connection>, DBError> {
-        let (tx, rx) = channel();
-        self.sender.send(Message::GetConnections(tx)).expect("Should success send request");
-        let connections = rx.recv().map_err(|_| DBError::Timeout)?;
-        Ok(connections)
+        let state = self.state.read().expect("Should success read state");
+        let connections: Vec<Connection> = state.connection.get_all();
+        Ok(connections.iter().filter_map(|connection| state.socket_addrs_to_name(connection)).collect())
     }
 
-    pub fn save_start_option(&self, node_name: &NodeName, env: &str, args: &str) {
+    /// `binary_path` is None when the node runs the binary built from the commit
+    pub fn save_start_option(&self, node_name: &NodeName, env: &str, args: &str, binary_path: Option<String>) {
         self.sender
-            .send(Message::SaveStartOption(node_name.clone(), env.to_string(), args.to_string()))
+            .send(Message::SaveStartOption(node_name.clone(), env.to_string(), args.to_string(), binary_path))
             .expect("Should success send request");
     }
 
     pub fn get_agent_extra(&self, node_name: &NodeName) -> Result<Option<AgentExtra>, DBError> {
-        let (tx, rx) = channel();
-        self.sender.send(Message::GetAgentExtra(node_name.clone(), tx)).expect("Should success send request");
-        let agent_extra = rx.recv().map_err(|_| DBError::Timeout)?;
-        Ok(agent_extra)
+        self.storage().get_agent_extra(node_name)
     }
 
-    pub fn get_logs(&self, params: LogQueryParams) -> Result<Vec<Log>, DBError> {
-        let (tx, rx) = channel();
-        self.sender.send(Message::GetLogs(params, tx)).expect("Should success send request");
-        let logs = rx.recv().map_err(|_| DBError::Timeout)?;
-        Ok(logs)
+    pub fn get_logs(&self, mut params: LogQueryParams) -> Result<Vec<Log>, DBError> {
+        // The log storages report the errors as texts, so an invalid page is refused here
+        Paging::new(params.page, params.item_per_page)?;
+        if let Some(filter) = params.filter.as_mut() {
+            if !filter.tags.is_empty() {
+                let tagged_names = self.storage().get_node_names_by_tags(&filter.tags)?;
+                filter.node_names = if filter.node_names.is_empty() {
+                    tagged_names
+                } else {
+                    filter.node_names.iter().filter(|name| tagged_names.contains(name)).cloned().collect()
+                };
+                if filter.node_names.is_empty() {
+                    return Ok(Vec::new())
+                }
+            }
+        }
+        wait_injected_delay(&self.injected_delay);
+        Ok(self.log_storage.search(params)?)
     }
 
     pub fn write_logs(&self, node_name: &NodeName, logs: Vec<StructuredLog>) {
         self.sender.send(Message::WriteLogs(node_name.clone(), logs)).expect("Should success send request");
     }
 
+    pub fn write_backfilled_logs(&self, node_name: &NodeName, logs: Vec<StructuredLog>) {
+        self.sender.send(Message::WriteBackfilledLogs(node_name.clone(), logs)).expect("Should success send request");
+    }
+
+    pub fn get_log_context(&self, id: i64, before: i64, after: i64) -> Result<Option<LogContext>, DBError> {
+        wait_injected_delay(&self.injected_delay);
+        Ok(self.log_storage.get_context(id, before, after)?)
+    }
+
     pub fn get_log_targets(&self) -> Result<Vec<String>, DBError> {
+        wait_injected_delay(&self.injected_delay);
+        Ok(self.log_storage.get_targets()?)
+    }
+
+    pub fn get_tags(&self, node_name: &NodeName) -> Result<Vec<String>, DBError> {
+        self.storage().get_tags(node_name)
+    }
+
+    pub fn add_tags(&self, node_name: &NodeName, tags: Vec<String>) {
+        self.sender.send(Message::AddTags(node_name.clone(), tags)).expect("Should success send request");
+    }
+
+    pub fn remove_tags(&self, node_name: &NodeName, tags: Vec<String>) {
+        self.sender.send(Message::RemoveTags(node_name.clone(), tags)).expect("Should success send request");
+    }
+
+    pub fn get_node_names_by_tags(&self, tags: Vec<String>) -> Result<Vec<NodeName>, DBError> {
+        self.storage().get_node_names_by_tags(&tags)
+    }
+
+    pub fn write_audit(&self, entry: AuditEntry) {
+        self.sender.send(Message::WriteAudit(entry)).expect("Should success send request");
+    }
+
+    pub fn write_event(&self, node_name: &NodeName, kind: NodeEventKind, detail: Value) {
+        self.sender.send(Message::WriteEvent(node_name.clone(), kind, detail)).expect("Should success send request");
+    }
+
+    pub fn get_audits(&self, params: AuditQueryParams) -> Result<Vec<Audit>, DBError> {
+        self.storage().search_audits(params)
+    }
+
+    pub fn get_events(&self, params: NodeEventQueryParams) -> Result<Vec<NodeEvent>, DBError> {
+        self.storage().search_events(params)
+    }
+
+    pub fn get_event_node_name(&self, id: i32) -> Result<Option<NodeName>, DBError> {
+        self.storage().get_event_node_name(id)
+    }
+
+    pub fn inject_query_delay(&self, delay: Duration, duration: Duration) -> Result<(), String> {
+        let until = Instant::now().checked_add(duration).ok_or_else(|| format!("{:?} is too long", duration))?;
+        cwarn!("DB queries are delayed {:?} for {:?}", delay, duration);
+        let mut injected_delay = self.injected_delay.lock().expect("Should success get injected_delay");
+        *injected_delay = Some((delay, until));
+        Ok(())
+    }
+
+    pub fn set_slo(&self, slo: Slo) {
+        self.sender.send(Message::SetSlo(slo)).expect("Should success send request");
+    }
+
+    pub fn remove_slo(&self, name: String) {
+        self.sender.send(Message::RemoveSlo(name)).expect("Should success send request");
+    }
+
+    pub fn record_slo_samples(&self) {
+        self.sender.send(Message::RecordSloSamples).expect("Should success send request");
+    }
+
+    pub fn record_slo_downtime(&self) {
+        self.sender.send(Message::RecordSloDowntime).expect("Should success send request");
+    }
+
+    pub fn set_start_template(&self, template: StartTemplate) {
+        self.sender.send(Message::SetStartTemplate(template)).expect("Should success send request");
+    }
+
+    pub fn remove_start_template(&self, name: String) {
+        self.sender.send(Message::RemoveStartTemplate(name)).expect("Should success send request");
+    }
+
+    pub fn get_start_templates(&self) -> Result<Vec<StartTemplate>, DBError> {
+        self.storage().get_start_templates()
+    }
+
+    pub fn set_log_rule(&self, rule: LogRule) {
+        self.sender.send(Message::SetLogRule(rule)).expect("Should success send request");
+    }
+
+    pub fn remove_log_rule(&self, name: String) {
+        self.sender.send(Message::RemoveLogRule(name)).expect("Should success send request");
+    }
+
+    pub fn get_log_rules(&self) -> Result<Vec<LogRule>, DBError> {
+        self.storage().get_log_rules()
+    }
+
+    pub fn set_desired_state(&self, state: DesiredState) {
+        self.sender.send(Message::SetDesiredState(state)).expect("Should success send request");
+    }
+
+    pub fn remove_desired_state(&self, node_name: &NodeName) {
+        self.sender.send(Message::RemoveDesiredState(node_name.clone())).expect("Should success send request");
+    }
+
+    pub fn get_desired_states(&self) -> Result<Vec<DesiredState>, DBError> {
+        self.storage().get_desired_states()
+    }
+
+    pub fn set_secret(&self, secret: NodeSecret) {
+        self.sender.send(Message::SetSecret(secret)).expect("Should success send request");
+    }
+
+    pub fn remove_secret(&self, node_name: &NodeName, name: &str) {
+        self.sender
+            .send(Message::RemoveSecret(node_name.clone(), name.to_string()))
+            .expect("Should success send request");
+    }
+
+    pub fn get_secrets(&self, node_name: &NodeName) -> Result<Vec<NodeSecret>, DBError> {
+        self.storage().get_secrets(node_name)
+    }
+
+    pub fn get_secret_names(&self, node_name: &NodeName) -> Result<Vec<String>, DBError> {
+        Ok(self.get_secrets(node_name)?.into_iter().map(|secret| secret.name).collect())
+    }
+
+    pub fn record_metric_samples(&self) {
+        self.sender.send(Message::RecordMetricSamples).expect("Should success send request");
+    }
+
+    pub fn roll_up_metric_samples(&self) {
+        self.sender.send(Message::RollUpMetricSamples).expect("Should success send request");
+    }
+
+    pub fn remove_metric_samples(&self, resolution: MetricResolution, before: chrono::DateTime<chrono::Local>) {
+        self.sender.send(Message::RemoveMetricSamples(resolution, before)).expect("Should success send request");
+    }
+
+    pub fn get_metric_samples(
+        &self,
+        resolution: MetricResolution,
+        node_name: &NodeName,
+        from: &chrono::DateTime<chrono::Local>,
+        to: &chrono::DateTime<chrono::Local>,
+    ) -> Result<Vec<MetricSample>, DBError> {
+        self.storage().get_metric_samples(resolution, Some(node_name), from, to)
+    }
+
+    pub fn get_start_template(&self, name: &str) -> Result<Option<StartTemplate>, DBError> {
+        Ok(self.get_start_templates()?.into_iter().find(|template| template.name == name))
+    }
+
+    pub fn create_schedule(&self, entry: ScheduleEntry) -> Result<Schedule, DBError> {
+        let (tx, rx) = channel();
+        self.sender.send(Message::CreateSchedule(entry, tx)).expect("Should success send request");
+        rx.recv().map_err(|_| DBError::Timeout)?
+    }
+
+    /// Returns false if there is no schedule with the id.
+    pub fn remove_schedule(&self, id: i32) -> Result<bool, DBError> {
+        let (tx, rx) = channel();
+        self.sender.send(Message::RemoveSchedule(id, tx)).expect("Should success send request");
+        rx.recv().map_err(|_| DBError::Timeout)?
+    }
+
+    pub fn add_note(&self, entry: NoteEntry) -> Result<Note, DBError> {
+        let (tx, rx) = channel();
+        self.sender.send(Message::AddNote(entry, tx)).expect("Should success send request");
+        rx.recv().map_err(|_| DBError::Timeout)?
+    }
+
+    /// Returns false if there is no note with the id.
+    pub fn remove_note(&self, id: i32) -> Result<bool, DBError> {
         let (tx, rx) = channel();
-        self.sender.send(Message::GetLogTargets(tx)).expect("Should success");
-        let targets = rx.recv().map_err(|_| DBError::Timeout)?;
-        Ok(targets)
+        self.sender.send(Message::RemoveNote(id, tx)).expect("Should success send request");
+        rx.recv().map_err(|_| DBError::Timeout)?
+    }
+
+    pub fn get_note(&self, id: i32) -> Result<Option<Note>, DBError> {
+        self.storage().get_note(id)
+    }
+
+    pub fn get_node_notes(&self, node_name: &NodeName) -> Result<Vec<Note>, DBError> {
+        self.storage().get_node_notes(node_name)
+    }
+
+    pub fn get_event_notes(&self, event_ids: &[i32]) -> Result<Vec<Note>, DBError> {
+        self.storage().get_event_notes(event_ids)
+    }
+
+    pub fn get_schedules(&self) -> Result<Vec<Schedule>, DBError> {
+        self.storage().get_schedules()
+    }
+
+    pub fn write_schedule_run(&self, run: ScheduleRun) {
+        self.sender.send(Message::WriteScheduleRun(run)).expect("Should success send request");
+    }
+
+    pub fn get_schedule_runs(&self, params: ScheduleRunQueryParams) -> Result<Vec<ScheduleRun>, DBError> {
+        self.storage().search_schedule_runs(params)
+    }
+
+    pub fn set_maintenance(&self, node_name: &NodeName, maintenance: bool) {
+        self.sender.send(Message::SetMaintenance(node_name.clone(), maintenance)).expect("Should success send request");
+    }
+
+    pub fn archive_agent(&self, node_name: &NodeName) -> Result<(), DBError> {
+        let (tx, rx) = channel();
+        self.sender.send(Message::ArchiveAgent(node_name.clone(), tx)).expect("Should success send request");
+        rx.recv().map_err(|_| DBError::Timeout)?
+    }
+
+    pub fn get_archived_agents(&self) -> Result<Vec<ArchivedNode>, DBError> {
+        self.storage().get_archived_nodes()
+    }
+
+    /// `resealed` are the secrets of the node sealed for the new name
+    pub fn rename_agent(&self, from: &NodeName, to: &NodeName, resealed: Vec<NodeSecret>) -> Result<(), DBError> {
+        let (tx, rx) = channel();
+        let message = Message::RenameAgent(from.clone(), to.clone(), resealed, tx);
+        self.sender.send(message).expect("Should success send request");
+        rx.recv().map_err(|_| DBError::Timeout)?
+    }
+
+    /// Applies the edits of the nodes together. The tags are not changed at all if it fails.
+    pub fn edit_nodes(&self, edits: Vec<NodeEdit>) -> Result<(), DBError> {
+        let (tx, rx) = channel();
+        self.sender.send(Message::EditNodes(edits, tx)).expect("Should success send request");
+        rx.recv().map_err(|_| DBError::Timeout)?
+    }
+
+    /// Translates the name which an agent reports to the name in the hub.
+    pub fn resolve_name(&self, reported_name: &NodeName) -> NodeName {
+        let state = self.state.read().expect("Should success read state");
+        state.aliases.get(reported_name).cloned().unwrap_or_else(|| reported_name.clone())
+    }
+
+    pub fn is_in_maintenance(&self, node_name: &NodeName) -> bool {
+        let state = self.state.read().expect("Should success read state");
+        state.maintenance.contains(node_name)
+    }
+
+    pub fn set_sealing(&self, node_name: &NodeName, sealing: bool) {
+        self.sender.send(Message::SetSealing(node_name.clone(), sealing)).expect("Should success send request");
+    }
+
+    /// None if the node is not running or the hub doesn't know whether it seals
+    pub fn is_sealing(&self, node_name: &NodeName) -> Option<bool> {
+        let state = self.state.read().expect("Should success read state");
+        state
+            .agent_query_result
+            .get(node_name)
+            .filter(|agent| agent.status == NodeStatus::Run)
+            .and_then(|agent| agent.sealing)
+    }
+
+    pub fn flush(&self, timeout: Duration) -> Result<(), DBError> {
+        let (tx, rx) = channel();
+        self.sender.send(Message::Flush(tx)).expect("Should success send request");
+        rx.recv_timeout(timeout).map_err(|_| DBError::Timeout)
+    }
+
+    /// Fails if the service thread is stuck
+    pub fn ping(&self, timeout: Duration) -> Result<(), DBError> {
+        let (tx, rx) = channel();
+        self.sender.send(Message::Ping(tx)).expect("Should success send request");
+        rx.recv_timeout(timeout).map_err(|_| DBError::Timeout)
+    }
+
+    pub fn ping_storage(&self) -> Result<(), DBError> {
+        self.storage().ping()
+    }
+
+    pub fn get_slo_statuses(&self) -> Result<Vec<SloStatus>, DBError> {
+        let storage = self.storage();
+        let now = chrono::Local::now();
+        let burn_rate_since = now - chrono::Duration::minutes(SLO_BURN_RATE_WINDOW_MINUTES);
+        let mut statuses = Vec::new();
+        for slo in storage.get_slos()? {
+            let window_since = now - chrono::Duration::minutes(i64::from(slo.window_minutes));
+            let (good_minutes, total_minutes) = storage.count_slo_samples(&slo.name, &window_since)?;
+            let (recent_good, recent_total) = storage.count_slo_samples(&slo.name, &burn_rate_since)?;
+
+            let allowed_bad_ratio = 1.0 - slo.target;
+            let error_budget = allowed_bad_ratio * f64::from(slo.window_minutes);
+            let bad_minutes = (total_minutes - good_minutes) as f64;
+            let availability = if total_minutes > 0 {
+                Some(good_minutes as f64 / total_minutes as f64)
+            } else {
+                None
+            };
+            let burn_rate = if recent_total > 0 {
+                Some((recent_total - recent_good) as f64 / recent_total as f64 / allowed_bad_ratio)
+            } else {
+                None
+            };
+            statuses.push(SloStatus {
+                slo,
+                good_minutes,
+                total_minutes,
+                availability,
+                error_budget_remaining: 1.0 - bad_minutes / error_budget,
+                burn_rate,
+            });
+        }
+        Ok(statuses)
+    }
+
+    fn storage(&self) -> &Storage {
+        wait_injected_delay(&self.injected_delay);
+        &*self.storage
     }
 }
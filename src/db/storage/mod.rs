@@ -0,0 +1,185 @@
+mod pg;
+mod sqlite;
+
+use std::sync::Arc;
+
+use chrono;
+use serde_json::Value;
+
+use super::super::common_rpc_types::{NodeName, StructuredLog};
+use super::migrations::Migration;
+use super::types::{
+    AgentExtra, AgentSnapshot, ArchivedNode, Audit, AuditEntry, AuditQueryParams, DesiredState, Error as DBError, Log,
+    LogContext, LogQueryParams, LogRule, MetricResolution, MetricSample, NodeEdit, NodeEvent, NodeEventKind,
+    NodeEventQueryParams, NodeSecret, Note, NoteEntry, Schedule, ScheduleEntry, ScheduleRun, ScheduleRunQueryParams,
+    Slo, StartTemplate,
+};
+
+/// Stores the states of the hub which should survive restarts.
+pub trait Storage: Send + Sync {
+    /// Returns the versions of the applied migrations. Nothing is applied if the table for the versions doesn't exist.
+    fn applied_migrations(&self) -> Result<Vec<i32>, DBError>;
+    /// Creates the table for the versions if it doesn't exist
+    fn apply_migration(&self, migration: &Migration) -> Result<(), DBError>;
+    /// Runs `migrate` while no other hub migrates the same database
+    fn with_migration_lock(&self, migrate: &mut FnMut() -> Result<(), DBError>) -> Result<(), DBError>;
+    /// Checks the connection to the database
+    fn ping(&self) -> Result<(), DBError>;
+
+    fn get_agent_extra(&self, node_name: &NodeName) -> Result<Option<AgentExtra>, DBError>;
+    fn upsert_agent_extra(&self, node_name: &NodeName, agent_extra: &AgentExtra) -> Result<(), DBError>;
+
+    fn get_agent_snapshots(&self) -> Result<Vec<AgentSnapshot>, DBError>;
+    /// Writes the snapshots at once, so the reconnecting agents don't make a write for each
+    fn upsert_agent_snapshots(&self, snapshots: &[AgentSnapshot]) -> Result<(), DBError>;
+
+    /// Moves the snapshot, the start option, the tags and the schedules of the node to the archive.
+    /// The logs, the events and the audits are kept as they are.
+    fn archive_node(&self, node_name: &NodeName, detail: &Value) -> Result<(), DBError>;
+    fn get_archived_nodes(&self) -> Result<Vec<ArchivedNode>, DBError>;
+
+    /// Renames the start option, the snapshot, the logs, the tags, the events and the schedules of the node.
+    /// The audits are kept as they are. The secrets are replaced by `resealed` in the same transaction.
+    fn rename_node(&self, from: &NodeName, to: &NodeName, resealed: &[NodeSecret]) -> Result<(), DBError>;
+    /// Returns the pairs of the name which the agent reports and the name in the hub
+    fn get_node_aliases(&self) -> Result<Vec<(NodeName, NodeName)>, DBError>;
+
+    /// Logs are deduplicated by (name, timestamp, message), so inserting the same logs again is harmless.
+    fn insert_logs(&self, node_name: &NodeName, logs: Vec<StructuredLog>, backfilled: bool) -> Result<(), DBError>;
+    fn search_logs(&self, params: LogQueryParams) -> Result<Vec<Log>, DBError>;
+    /// None if the log doesn't exist
+    fn get_log_context(&self, id: i64, before: i64, after: i64) -> Result<Option<LogContext>, DBError>;
+    fn get_log_targets(&self) -> Result<Vec<String>, DBError>;
+
+    fn get_tags(&self, node_name: &NodeName) -> Result<Vec<String>, DBError>;
+    fn add_tags(&self, node_name: &NodeName, tags: &[String]) -> Result<(), DBError>;
+    fn remove_tags(&self, node_name: &NodeName, tags: &[String]) -> Result<(), DBError>;
+    fn get_node_names_by_tags(&self, tags: &[String]) -> Result<Vec<NodeName>, DBError>;
+    /// Changes the tags of all the nodes in a transaction
+    fn edit_tags(&self, edits: &[NodeEdit]) -> Result<(), DBError>;
+
+    fn insert_audit(&self, entry: &AuditEntry) -> Result<(), DBError>;
+    fn search_audits(&self, params: AuditQueryParams) -> Result<Vec<Audit>, DBError>;
+
+    fn insert_event(&self, node_name: &NodeName, kind: NodeEventKind, detail: &Value) -> Result<(), DBError>;
+    fn search_events(&self, params: NodeEventQueryParams) -> Result<Vec<NodeEvent>, DBError>;
+    /// None if there is no event with the id
+    fn get_event_node_name(&self, id: i32) -> Result<Option<NodeName>, DBError>;
+
+    fn upsert_slo(&self, slo: &Slo) -> Result<(), DBError>;
+    fn remove_slo(&self, name: &str) -> Result<(), DBError>;
+    fn get_slos(&self) -> Result<Vec<Slo>, DBError>;
+    fn insert_slo_sample(
+        &self,
+        name: &str,
+        minute: &chrono::DateTime<chrono::Local>,
+        good: bool,
+    ) -> Result<(), DBError>;
+    fn insert_slo_samples(
+        &self,
+        name: &str,
+        minutes: &[chrono::DateTime<chrono::Local>],
+        good: bool,
+    ) -> Result<(), DBError>;
+    fn get_last_slo_sample_minute(&self, name: &str) -> Result<Option<chrono::DateTime<chrono::Local>>, DBError>;
+    /// Returns the number of the good samples and the number of all samples since the given time.
+    fn count_slo_samples(&self, name: &str, since: &chrono::DateTime<chrono::Local>) -> Result<(i64, i64), DBError>;
+
+    fn upsert_start_template(&self, template: &StartTemplate) -> Result<(), DBError>;
+    fn remove_start_template(&self, name: &str) -> Result<(), DBError>;
+    fn get_start_templates(&self) -> Result<Vec<StartTemplate>, DBError>;
+
+    fn upsert_log_rule(&self, rule: &LogRule) -> Result<(), DBError>;
+    fn remove_log_rule(&self, name: &str) -> Result<(), DBError>;
+    fn get_log_rules(&self) -> Result<Vec<LogRule>, DBError>;
+
+    fn upsert_desired_state(&self, state: &DesiredState) -> Result<(), DBError>;
+    fn remove_desired_state(&self, node_name: &NodeName) -> Result<(), DBError>;
+    fn get_desired_states(&self) -> Result<Vec<DesiredState>, DBError>;
+
+    fn upsert_secret(&self, secret: &NodeSecret) -> Result<(), DBError>;
+    fn remove_secret(&self, node_name: &NodeName, name: &str) -> Result<(), DBError>;
+    fn get_secrets(&self, node_name: &NodeName) -> Result<Vec<NodeSecret>, DBError>;
+
+    fn insert_note(&self, entry: &NoteEntry) -> Result<Note, DBError>;
+    /// Returns false if there is no note with the id.
+    fn remove_note(&self, id: i32) -> Result<bool, DBError>;
+    fn get_note(&self, id: i32) -> Result<Option<Note>, DBError>;
+    /// The notes on the node and on its events, the newest first
+    fn get_node_notes(&self, node_name: &NodeName) -> Result<Vec<Note>, DBError>;
+    fn get_event_notes(&self, event_ids: &[i32]) -> Result<Vec<Note>, DBError>;
+
+    /// The rolled-up samples of the same period are replaced.
+    fn upsert_metric_samples(&self, resolution: MetricResolution, samples: &[MetricSample]) -> Result<(), DBError>;
+    /// Returns the samples in [from, to) ordered by the node names and the times.
+    fn get_metric_samples(
+        &self,
+        resolution: MetricResolution,
+        node_name: Option<&NodeName>,
+        from: &chrono::DateTime<chrono::Local>,
+        to: &chrono::DateTime<chrono::Local>,
+    ) -> Result<Vec<MetricSample>, DBError>;
+    fn remove_metric_samples(
+        &self,
+        resolution: MetricResolution,
+        before: &chrono::DateTime<chrono::Local>,
+    ) -> Result<(), DBError>;
+
+    fn insert_schedule(&self, entry: &ScheduleEntry) -> Result<Schedule, DBError>;
+    /// Returns false if there is no schedule with the id.
+    fn remove_schedule(&self, id: i32) -> Result<bool, DBError>;
+    fn get_schedules(&self) -> Result<Vec<Schedule>, DBError>;
+    fn insert_schedule_run(&self, run: &ScheduleRun) -> Result<(), DBError>;
+    fn search_schedule_runs(&self, params: ScheduleRunQueryParams) -> Result<Vec<ScheduleRun>, DBError>;
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum StorageOption {
+    #[serde(rename_all = "camelCase")]
+    Postgres {
+        user: String,
+        password: String,
+        /// The host and the port of the server
+        #[serde(default = "default_postgres_host")]
+        host: String,
+        /// The host and the port of a read replica. The log search, the audits, the events and the schedule history
+        /// are read from it, so the heavy queries don't slow down the writes and the dashboard
+        #[serde(default)]
+        replica_host: Option<String>,
+    },
+    /// Creates the database file if it doesn't exist. Good for trying the hub locally
+    #[serde(rename_all = "camelCase")]
+    Sqlite {
+        path: String,
+    },
+}
+
+impl Default for StorageOption {
+    fn default() -> Self {
+        StorageOption::Postgres {
+            user: "codechain-agent-hub".to_string(),
+            password: "preempt-entreat-bell-chanson".to_string(),
+            host: default_postgres_host(),
+            replica_host: None,
+        }
+    }
+}
+
+fn default_postgres_host() -> String {
+    "localhost".to_string()
+}
+
+pub fn create(option: StorageOption) -> Arc<Storage> {
+    match option {
+        StorageOption::Postgres {
+            user,
+            password,
+            host,
+            replica_host,
+        } => Arc::new(pg::PostgresStorage::new(&user, &password, &host, replica_host.as_ref().map(String::as_str))),
+        StorageOption::Sqlite {
+            path,
+        } => Arc::new(sqlite::SqliteStorage::open(&path)),
+    }
+}
@@ -0,0 +1,355 @@
+use std::thread;
+use std::time::Duration;
+
+use chrono;
+use postgres;
+use r2d2;
+use r2d2_postgres::{PostgresConnectionManager, TlsMode};
+use serde_json::Value;
+
+use super::super::super::common_rpc_types::{NodeName, StructuredLog};
+use super::super::migrations::Migration;
+use super::super::queries;
+use super::super::types::{
+    AgentExtra, AgentSnapshot, ArchivedNode, Audit, AuditEntry, AuditQueryParams, DesiredState, Error as DBError, Log,
+    LogContext, LogQueryParams, LogRule, MetricResolution, MetricSample, NodeEdit, NodeEvent, NodeEventKind,
+    NodeEventQueryParams, NodeSecret, Note, NoteEntry, Paging, Schedule, ScheduleEntry, ScheduleRun,
+    ScheduleRunQueryParams, Slo, StartTemplate,
+};
+use super::Storage;
+
+/// Writes of the db service and the reads of the other threads share the pool
+const DB_POOL_SIZE: u32 = 8;
+/// The reads don't wait long for the replica which is down, because the primary can answer them
+const REPLICA_CONNECTION_TIMEOUT: Duration = Duration::from_secs(1);
+/// The advisory lock of the migrations, next to the default lock of the leader election
+const MIGRATION_LOCK_ID: i64 = 0x6875_6201;
+/// How often a hub tries the migration lock while another hub migrates
+const MIGRATION_LOCK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct QueryTimeout;
+
+impl r2d2::CustomizeConnection<postgres::Connection, postgres::Error> for QueryTimeout {
+    fn on_acquire(&self, conn: &mut postgres::Connection) -> Result<(), postgres::Error> {
+        queries::config::set_query_timeout(conn)
+    }
+}
+
+pub struct PostgresStorage {
+    pool: r2d2::Pool<PostgresConnectionManager>,
+    /// The heavy reads go to the replica if it is configured
+    replica: Option<r2d2::Pool<PostgresConnectionManager>>,
+}
+
+impl PostgresStorage {
+    pub fn new(user: &str, password: &str, host: &str, replica_host: Option<&str>) -> Self {
+        let pool = r2d2::Pool::builder()
+            .max_size(DB_POOL_SIZE)
+            .connection_customizer(Box::new(QueryTimeout))
+            .build(connection_manager(user, password, host))
+            .unwrap();
+        // The hub starts without the replica, and the reads fall back to the primary until it is up
+        let replica = replica_host.map(|replica_host| {
+            r2d2::Pool::builder()
+                .max_size(DB_POOL_SIZE)
+                .connection_timeout(REPLICA_CONNECTION_TIMEOUT)
+                .connection_customizer(Box::new(QueryTimeout))
+                .build_unchecked(connection_manager(user, password, replica_host))
+        });
+        Self {
+            pool,
+            replica,
+        }
+    }
+
+    fn connection(&self) -> Result<r2d2::PooledConnection<PostgresConnectionManager>, DBError> {
+        Ok(self.pool.get()?)
+    }
+
+    /// For the queries which scan many rows and can see the writes a little late
+    fn read_connection(&self) -> Result<r2d2::PooledConnection<PostgresConnectionManager>, DBError> {
+        if let Some(replica) = &self.replica {
+            match replica.get() {
+                Ok(conn) => return Ok(conn),
+                Err(err) => cwarn!("Cannot connect to the read replica. Read from the primary : {}", err),
+            }
+        }
+        self.connection()
+    }
+}
+
+fn connection_manager(user: &str, password: &str, host: &str) -> PostgresConnectionManager {
+    let conn_uri = format!("postgres://{}:{}@{}", user, password, host);
+    PostgresConnectionManager::new(conn_uri, TlsMode::None).unwrap()
+}
+
+impl Storage for PostgresStorage {
+    fn applied_migrations(&self) -> Result<Vec<i32>, DBError> {
+        Ok(queries::migrations::get_applied(&*self.connection()?)?)
+    }
+
+    fn apply_migration(&self, migration: &Migration) -> Result<(), DBError> {
+        let conn = self.connection()?;
+        Ok(queries::migrations::apply(&*conn, migration.version, migration.name, migration.postgres)?)
+    }
+
+    fn with_migration_lock(&self, migrate: &mut FnMut() -> Result<(), DBError>) -> Result<(), DBError> {
+        // The lock is tried instead of waited, because the query timeout would cancel the wait
+        let conn = self.connection()?;
+        if !queries::leader::try_lock(&*conn, MIGRATION_LOCK_ID)? {
+            cinfo!("Another hub is migrating the database. Wait for it");
+            while !queries::leader::try_lock(&*conn, MIGRATION_LOCK_ID)? {
+                thread::sleep(MIGRATION_LOCK_INTERVAL);
+            }
+        }
+        // The migrations are applied with the other connections of the pool
+        let result = migrate();
+        queries::leader::unlock(&*conn, MIGRATION_LOCK_ID)?;
+        result
+    }
+
+    fn ping(&self) -> Result<(), DBError> {
+        Ok(queries::leader::ping(&*self.connection()?)?)
+    }
+
+    fn get_agent_extra(&self, node_name: &NodeName) -> Result<Option<AgentExtra>, DBError> {
+        Ok(queries::agent_extra::get(&*self.connection()?, node_name)?)
+    }
+
+    fn upsert_agent_extra(&self, node_name: &NodeName, agent_extra: &AgentExtra) -> Result<(), DBError> {
+        Ok(queries::agent_extra::upsert(&*self.connection()?, node_name, agent_extra)?)
+    }
+
+    fn get_agent_snapshots(&self) -> Result<Vec<AgentSnapshot>, DBError> {
+        Ok(queries::agent_snapshots::get_all(&*self.connection()?)?)
+    }
+
+    fn upsert_agent_snapshots(&self, snapshots: &[AgentSnapshot]) -> Result<(), DBError> {
+        Ok(queries::agent_snapshots::upsert(&*self.connection()?, snapshots)?)
+    }
+
+    fn archive_node(&self, node_name: &NodeName, detail: &Value) -> Result<(), DBError> {
+        Ok(queries::archive::archive(&*self.connection()?, node_name, detail)?)
+    }
+
+    fn get_archived_nodes(&self) -> Result<Vec<ArchivedNode>, DBError> {
+        Ok(queries::archive::get_all(&*self.connection()?)?)
+    }
+
+    fn rename_node(&self, from: &NodeName, to: &NodeName, resealed: &[NodeSecret]) -> Result<(), DBError> {
+        Ok(queries::renames::rename(&*self.connection()?, from, to, resealed)?)
+    }
+
+    fn get_node_aliases(&self) -> Result<Vec<(NodeName, NodeName)>, DBError> {
+        Ok(queries::renames::get_aliases(&*self.connection()?)?)
+    }
+
+    fn insert_logs(&self, node_name: &NodeName, logs: Vec<StructuredLog>, backfilled: bool) -> Result<(), DBError> {
+        Ok(queries::logs::insert(&*self.connection()?, node_name, logs, backfilled)?)
+    }
+
+    fn search_logs(&self, params: LogQueryParams) -> Result<Vec<Log>, DBError> {
+        let paging = Paging::new(params.page, params.item_per_page)?;
+        Ok(queries::logs::search(&*self.read_connection()?, params, paging)?)
+    }
+
+    fn get_log_context(&self, id: i64, before: i64, after: i64) -> Result<Option<LogContext>, DBError> {
+        Ok(queries::logs::get_context(&*self.read_connection()?, id, before, after)?)
+    }
+
+    fn get_log_targets(&self) -> Result<Vec<String>, DBError> {
+        Ok(queries::logs::get_targets(&*self.read_connection()?)?)
+    }
+
+    fn get_tags(&self, node_name: &NodeName) -> Result<Vec<String>, DBError> {
+        Ok(queries::tags::get(&*self.connection()?, node_name)?)
+    }
+
+    fn add_tags(&self, node_name: &NodeName, tags: &[String]) -> Result<(), DBError> {
+        Ok(queries::tags::add(&*self.connection()?, node_name, tags)?)
+    }
+
+    fn remove_tags(&self, node_name: &NodeName, tags: &[String]) -> Result<(), DBError> {
+        Ok(queries::tags::remove(&*self.connection()?, node_name, tags)?)
+    }
+
+    fn get_node_names_by_tags(&self, tags: &[String]) -> Result<Vec<NodeName>, DBError> {
+        Ok(queries::tags::get_node_names(&*self.connection()?, tags)?)
+    }
+
+    fn edit_tags(&self, edits: &[NodeEdit]) -> Result<(), DBError> {
+        Ok(queries::tags::edit(&*self.connection()?, edits)?)
+    }
+
+    fn insert_audit(&self, entry: &AuditEntry) -> Result<(), DBError> {
+        Ok(queries::audit::insert(&*self.connection()?, entry)?)
+    }
+
+    fn search_audits(&self, params: AuditQueryParams) -> Result<Vec<Audit>, DBError> {
+        let paging = Paging::new(params.page, params.item_per_page)?;
+        Ok(queries::audit::search(&*self.read_connection()?, params, paging)?)
+    }
+
+    fn insert_event(&self, node_name: &NodeName, kind: NodeEventKind, detail: &Value) -> Result<(), DBError> {
+        Ok(queries::events::insert(&*self.connection()?, node_name, kind, detail)?)
+    }
+
+    fn search_events(&self, params: NodeEventQueryParams) -> Result<Vec<NodeEvent>, DBError> {
+        let paging = Paging::new(params.page, params.item_per_page)?;
+        Ok(queries::events::search(&*self.read_connection()?, params, paging)?)
+    }
+
+    fn get_event_node_name(&self, id: i32) -> Result<Option<NodeName>, DBError> {
+        Ok(queries::events::get_node_name(&*self.connection()?, id)?)
+    }
+
+    fn upsert_slo(&self, slo: &Slo) -> Result<(), DBError> {
+        Ok(queries::slo::upsert(&*self.connection()?, slo)?)
+    }
+
+    fn remove_slo(&self, name: &str) -> Result<(), DBError> {
+        Ok(queries::slo::remove(&*self.connection()?, name)?)
+    }
+
+    fn get_slos(&self) -> Result<Vec<Slo>, DBError> {
+        Ok(queries::slo::get_all(&*self.connection()?)?)
+    }
+
+    fn insert_slo_sample(
+        &self,
+        name: &str,
+        minute: &chrono::DateTime<chrono::Local>,
+        good: bool,
+    ) -> Result<(), DBError> {
+        Ok(queries::slo::insert_sample(&*self.connection()?, name, minute, good)?)
+    }
+
+    fn insert_slo_samples(
+        &self,
+        name: &str,
+        minutes: &[chrono::DateTime<chrono::Local>],
+        good: bool,
+    ) -> Result<(), DBError> {
+        Ok(queries::slo::insert_samples(&*self.connection()?, name, minutes, good)?)
+    }
+
+    fn get_last_slo_sample_minute(&self, name: &str) -> Result<Option<chrono::DateTime<chrono::Local>>, DBError> {
+        Ok(queries::slo::get_last_sample_minute(&*self.connection()?, name)?)
+    }
+
+    fn count_slo_samples(&self, name: &str, since: &chrono::DateTime<chrono::Local>) -> Result<(i64, i64), DBError> {
+        Ok(queries::slo::count_samples(&*self.connection()?, name, since)?)
+    }
+
+    fn upsert_start_template(&self, template: &StartTemplate) -> Result<(), DBError> {
+        Ok(queries::start_templates::upsert(&*self.connection()?, template)?)
+    }
+
+    fn remove_start_template(&self, name: &str) -> Result<(), DBError> {
+        Ok(queries::start_templates::remove(&*self.connection()?, name)?)
+    }
+
+    fn get_start_templates(&self) -> Result<Vec<StartTemplate>, DBError> {
+        Ok(queries::start_templates::get_all(&*self.connection()?)?)
+    }
+
+    fn upsert_log_rule(&self, rule: &LogRule) -> Result<(), DBError> {
+        Ok(queries::log_rules::upsert(&*self.connection()?, rule)?)
+    }
+
+    fn remove_log_rule(&self, name: &str) -> Result<(), DBError> {
+        Ok(queries::log_rules::remove(&*self.connection()?, name)?)
+    }
+
+    fn get_log_rules(&self) -> Result<Vec<LogRule>, DBError> {
+        Ok(queries::log_rules::get_all(&*self.connection()?)?)
+    }
+
+    fn upsert_desired_state(&self, state: &DesiredState) -> Result<(), DBError> {
+        Ok(queries::desired_states::upsert(&*self.connection()?, state)?)
+    }
+
+    fn remove_desired_state(&self, node_name: &NodeName) -> Result<(), DBError> {
+        Ok(queries::desired_states::remove(&*self.connection()?, node_name)?)
+    }
+
+    fn get_desired_states(&self) -> Result<Vec<DesiredState>, DBError> {
+        Ok(queries::desired_states::get_all(&*self.connection()?)?)
+    }
+
+    fn upsert_secret(&self, secret: &NodeSecret) -> Result<(), DBError> {
+        Ok(queries::secrets::upsert(&*self.connection()?, secret)?)
+    }
+
+    fn remove_secret(&self, node_name: &NodeName, name: &str) -> Result<(), DBError> {
+        Ok(queries::secrets::remove(&*self.connection()?, node_name, name)?)
+    }
+
+    fn get_secrets(&self, node_name: &NodeName) -> Result<Vec<NodeSecret>, DBError> {
+        Ok(queries::secrets::get(&*self.connection()?, node_name)?)
+    }
+
+    fn insert_note(&self, entry: &NoteEntry) -> Result<Note, DBError> {
+        Ok(queries::notes::insert(&*self.connection()?, entry)?)
+    }
+
+    fn remove_note(&self, id: i32) -> Result<bool, DBError> {
+        Ok(queries::notes::remove(&*self.connection()?, id)?)
+    }
+
+    fn get_note(&self, id: i32) -> Result<Option<Note>, DBError> {
+        Ok(queries::notes::get(&*self.connection()?, id)?)
+    }
+
+    fn get_node_notes(&self, node_name: &NodeName) -> Result<Vec<Note>, DBError> {
+        Ok(queries::notes::get_by_node(&*self.connection()?, node_name)?)
+    }
+
+    fn get_event_notes(&self, event_ids: &[i32]) -> Result<Vec<Note>, DBError> {
+        Ok(queries::notes::get_by_events(&*self.read_connection()?, event_ids)?)
+    }
+
+    fn upsert_metric_samples(&self, resolution: MetricResolution, samples: &[MetricSample]) -> Result<(), DBError> {
+        Ok(queries::node_metrics::upsert(&*self.connection()?, resolution, samples)?)
+    }
+
+    fn get_metric_samples(
+        &self,
+        resolution: MetricResolution,
+        node_name: Option<&NodeName>,
+        from: &chrono::DateTime<chrono::Local>,
+        to: &chrono::DateTime<chrono::Local>,
+    ) -> Result<Vec<MetricSample>, DBError> {
+        Ok(queries::node_metrics::get(&*self.connection()?, resolution, node_name, from, to)?)
+    }
+
+    fn remove_metric_samples(
+        &self,
+        resolution: MetricResolution,
+        before: &chrono::DateTime<chrono::Local>,
+    ) -> Result<(), DBError> {
+        Ok(queries::node_metrics::remove_before(&*self.connection()?, resolution, before)?)
+    }
+
+    fn insert_schedule(&self, entry: &ScheduleEntry) -> Result<Schedule, DBError> {
+        Ok(queries::schedules::insert(&*self.connection()?, entry)?)
+    }
+
+    fn remove_schedule(&self, id: i32) -> Result<bool, DBError> {
+        Ok(queries::schedules::remove(&*self.connection()?, id)?)
+    }
+
+    fn get_schedules(&self) -> Result<Vec<Schedule>, DBError> {
+        Ok(queries::schedules::get_all(&*self.connection()?)?)
+    }
+
+    fn insert_schedule_run(&self, run: &ScheduleRun) -> Result<(), DBError> {
+        Ok(queries::schedules::insert_run(&*self.connection()?, run)?)
+    }
+
+    fn search_schedule_runs(&self, params: ScheduleRunQueryParams) -> Result<Vec<ScheduleRun>, DBError> {
+        let paging = Paging::new(params.page, params.item_per_page)?;
+        Ok(queries::schedules::search_runs(&*self.read_connection()?, params, paging)?)
+    }
+}
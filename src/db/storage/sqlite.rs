@@ -0,0 +1,1114 @@
+use std::sync::{Mutex, MutexGuard};
+
+use chrono;
+use rusqlite;
+use rusqlite::types::ToSql;
+use serde_json;
+use serde_json::Value;
+
+use super::super::super::common_rpc_types::{NodeName, StructuredLog};
+use super::super::migrations::Migration;
+use super::super::types::{
+    AgentExtra, AgentSnapshot, ArchivedNode, Audit, AuditEntry, AuditQueryParams, DesiredState, Error as DBError, Log,
+    LogContext, LogCursor, LogDuration, LogQueryParams, LogRule, MetricResolution, MetricSample, NodeEdit, NodeEvent,
+    NodeEventKind, NodeEventQueryParams, NodeSecret, Note, NoteEntry, OrderBy, Paging, Schedule, ScheduleEntry,
+    ScheduleRun, ScheduleRunQueryParams, Slo, StartTemplate,
+};
+use super::Storage;
+
+/// SQLite doesn't have a timestamp type. Timestamps are saved as UTC texts, so comparing texts compares times.
+fn to_text<Tz: chrono::TimeZone>(time: &chrono::DateTime<Tz>) -> String {
+    time.with_timezone(&chrono::Utc).format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string()
+}
+
+fn from_text(text: &str) -> Result<chrono::DateTime<chrono::Local>, DBError> {
+    let time = chrono::DateTime::parse_from_rfc3339(text)
+        .map_err(|err| DBError::Query(format!("Invalid timestamp {} : {}", text, err)))?;
+    Ok(time.with_timezone(&chrono::Local))
+}
+
+/// Builds the WHERE clause. SQLite doesn't have arrays, so `= ANY($1)` of Postgres becomes `IN (?, ?)`.
+struct Conditions {
+    conditions: Vec<String>,
+    parameters: Vec<Box<ToSql>>,
+}
+
+impl Conditions {
+    fn new() -> Self {
+        Self {
+            conditions: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, condition: &str, parameter: Box<ToSql>) {
+        self.conditions.push(condition.to_string());
+        self.parameters.push(parameter);
+    }
+
+    fn add_any<T: ToSql + 'static>(&mut self, column: &str, values: Vec<T>) {
+        if values.is_empty() {
+            return
+        }
+        let placeholders: Vec<&str> = values.iter().map(|_| "?").collect();
+        self.conditions.push(format!("{} IN ({})", column, placeholders.join(", ")));
+        for value in values {
+            self.parameters.push(Box::new(value));
+        }
+    }
+
+    fn add_time(&mut self, time: Option<LogDuration>) {
+        if let Some(time) = time {
+            if let Some(from) = time.from_time {
+                self.add("timestamp > ?", Box::new(to_text(&from)));
+            }
+            if let Some(to) = time.to_time {
+                self.add("timestamp < ?", Box::new(to_text(&to)));
+            }
+        }
+    }
+
+    /// The logs which have the same timestamp are ordered by the ids
+    fn add_after(&mut self, after: Option<LogCursor>) {
+        if let Some(after) = after {
+            let timestamp = to_text(&after.timestamp);
+            self.conditions.push("(timestamp > ? OR (timestamp = ? AND id > ?))".to_string());
+            self.parameters.push(Box::new(timestamp.clone()));
+            self.parameters.push(Box::new(timestamp));
+            self.parameters.push(Box::new(after.id));
+        }
+    }
+
+    fn where_clause(&self) -> String {
+        if self.conditions.is_empty() {
+            return "".to_string()
+        }
+        "WHERE ".to_string() + &self.conditions.join(" AND ")
+    }
+
+    fn parameters(&self) -> Vec<&ToSql> {
+        self.parameters.iter().map(|param| param.as_ref()).collect()
+    }
+}
+
+fn paging_clause(order_by: OrderBy, page: Option<i32>, item_per_page: Option<i32>) -> Result<String, DBError> {
+    let paging = Paging::new(page, item_per_page)?;
+    Ok(format!("ORDER BY timestamp {:?} LIMIT {} OFFSET {}", order_by, paging.limit, paging.offset))
+}
+
+/// A single connection is shared by all threads, so it is not for production.
+pub struct SqliteStorage {
+    conn: Mutex<rusqlite::Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Self {
+        let conn = rusqlite::Connection::open(path).unwrap();
+        cinfo!("SQLite database is opened at {}", path);
+        Self {
+            conn: Mutex::new(conn),
+        }
+    }
+
+    fn connection(&self) -> MutexGuard<rusqlite::Connection> {
+        self.conn.lock().expect("Should success lock the SQLite connection")
+    }
+
+    fn query_strings(&self, sql: &str, parameters: &[&ToSql]) -> Result<Vec<String>, DBError> {
+        let conn = self.connection();
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement.query_map(parameters, |row| row.get::<_, String>(0))?;
+        let mut result = Vec::new();
+        for row in rows {
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    fn query_logs(&self, sql: &str, parameters: &[&ToSql]) -> Result<Vec<Log>, DBError> {
+        let conn = self.connection();
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement.query_map(parameters, |row| -> Result<Log, DBError> {
+            Ok(Log {
+                id: row.get("id"),
+                node_name: row.get("name"),
+                level: row.get("level"),
+                target: row.get("target"),
+                timestamp: from_text(&row.get::<_, String>("timestamp"))?,
+                message: format!("{} {}", row.get::<_, String>("thread_name"), row.get::<_, String>("message")),
+                backfilled: row.get("backfilled"),
+            })
+        })?;
+        let mut logs = Vec::new();
+        for row in rows {
+            logs.push(row??);
+        }
+        Ok(logs)
+    }
+
+    fn query_notes(&self, sql: &str, parameters: &[&ToSql]) -> Result<Vec<Note>, DBError> {
+        let conn = self.connection();
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement.query_map(parameters, |row| -> Result<Note, DBError> {
+            Ok(Note {
+                id: row.get("id"),
+                node_name: row.get("name"),
+                event_id: row.get("event_id"),
+                author: row.get("author"),
+                text: row.get("text"),
+                created_at: from_text(&row.get::<_, String>("created_at"))?,
+            })
+        })?;
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row??);
+        }
+        Ok(notes)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn applied_migrations(&self) -> Result<Vec<i32>, DBError> {
+        ctrace!("Query applied migrations");
+
+        let conn = self.connection();
+        let created: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_migrations')",
+            &[],
+            |row| row.get(0),
+        )?;
+        if !created {
+            return Ok(Vec::new())
+        }
+        let mut statement = conn.prepare("SELECT version FROM schema_migrations ORDER BY version")?;
+        let rows = statement.query_map(&[], |row| row.get::<_, i32>("version"))?;
+        let mut versions = Vec::new();
+        for row in rows {
+            versions.push(row?);
+        }
+        Ok(versions)
+    }
+
+    fn apply_migration(&self, migration: &Migration) -> Result<(), DBError> {
+        let mut conn = self.connection();
+        let transaction = conn.transaction()?;
+        transaction.execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at TEXT NOT NULL
+            )",
+            &[],
+        )?;
+        transaction.execute_batch(migration.sqlite)?;
+        transaction.execute(
+            "INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, ?, ?)",
+            &[&migration.version, &migration.name, &to_text(&chrono::Local::now())],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn with_migration_lock(&self, migrate: &mut FnMut() -> Result<(), DBError>) -> Result<(), DBError> {
+        // The database file is opened by this hub only
+        migrate()
+    }
+
+    fn ping(&self) -> Result<(), DBError> {
+        self.connection().query_row("SELECT 1", &[], |_| ())?;
+        Ok(())
+    }
+
+    fn get_agent_extra(&self, node_name: &NodeName) -> Result<Option<AgentExtra>, DBError> {
+        ctrace!("Query agent extra by name {}", node_name);
+
+        let conn = self.connection();
+        let mut statement = conn.prepare("SELECT prev_env, prev_args, binary_path FROM agent_extra WHERE name=?")?;
+        let mut rows = statement.query_map(&[node_name], |row| AgentExtra {
+            prev_env: row.get("prev_env"),
+            prev_args: row.get("prev_args"),
+            binary_path: row.get("binary_path"),
+        })?;
+        match rows.next() {
+            Some(row) => Ok(Some(row?)),
+            None => Ok(None),
+        }
+    }
+
+    fn upsert_agent_extra(&self, node_name: &NodeName, agent_extra: &AgentExtra) -> Result<(), DBError> {
+        ctrace!("Upsert agent extra {:?}", agent_extra);
+
+        self.connection().execute(
+            "INSERT OR REPLACE INTO agent_extra (name, prev_env, prev_args, binary_path) VALUES (?, ?, ?, ?)",
+            &[node_name, &agent_extra.prev_env, &agent_extra.prev_args, &agent_extra.binary_path],
+        )?;
+        Ok(())
+    }
+
+    fn get_agent_snapshots(&self) -> Result<Vec<AgentSnapshot>, DBError> {
+        ctrace!("Query agent snapshots");
+
+        Ok(self
+            .query_strings("SELECT snapshot FROM agent_snapshots", &[])?
+            .iter()
+            .filter_map(|snapshot| {
+                let snapshot = serde_json::from_str(snapshot);
+                if let Err(err) = &snapshot {
+                    cwarn!("Invalid agent snapshot : {}", err);
+                }
+                snapshot.ok()
+            })
+            .collect())
+    }
+
+    fn upsert_agent_snapshots(&self, snapshots: &[AgentSnapshot]) -> Result<(), DBError> {
+        ctrace!("Upsert {} agent snapshots", snapshots.len());
+
+        let mut conn = self.connection();
+        let transaction = conn.transaction()?;
+        {
+            let mut statement = transaction
+                .prepare("INSERT OR REPLACE INTO agent_snapshots (name, snapshot, updated_at) VALUES (?, ?, ?)")?;
+            let now = to_text(&chrono::Local::now());
+            for snapshot in snapshots {
+                let serialized = serde_json::to_string(snapshot).expect("AgentSnapshot is serializable");
+                statement.execute(&[&snapshot.name, &serialized, &now])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn archive_node(&self, node_name: &NodeName, detail: &Value) -> Result<(), DBError> {
+        ctrace!("Archive node {}", node_name);
+
+        let mut conn = self.connection();
+        let transaction = conn.transaction()?;
+        transaction.execute(
+            "INSERT INTO archived_nodes (name, archived_at, detail) VALUES (?, ?, ?)",
+            &[node_name, &to_text(&chrono::Local::now()), &detail.to_string()],
+        )?;
+        transaction.execute("DELETE FROM agent_snapshots WHERE name=?", &[node_name])?;
+        transaction.execute("DELETE FROM agent_extra WHERE name=?", &[node_name])?;
+        transaction.execute("DELETE FROM desired_states WHERE name=?", &[node_name])?;
+        transaction.execute("DELETE FROM node_notes WHERE name=?", &[node_name])?;
+        transaction.execute("DELETE FROM node_secrets WHERE name=?", &[node_name])?;
+        transaction.execute("DELETE FROM node_tags WHERE name=?", &[node_name])?;
+        transaction.execute("DELETE FROM schedules WHERE node_name=?", &[node_name])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn get_archived_nodes(&self) -> Result<Vec<ArchivedNode>, DBError> {
+        ctrace!("Query archived nodes");
+
+        let conn = self.connection();
+        let mut statement = conn.prepare("SELECT * FROM archived_nodes ORDER BY archived_at DESC")?;
+        let rows = statement.query_map(&[], |row| -> Result<ArchivedNode, DBError> {
+            Ok(ArchivedNode {
+                id: row.get("id"),
+                name: row.get("name"),
+                archived_at: from_text(&row.get::<_, String>("archived_at"))?,
+                detail: serde_json::from_str(&row.get::<_, String>("detail")).unwrap_or_default(),
+            })
+        })?;
+        let mut archived = Vec::new();
+        for row in rows {
+            archived.push(row??);
+        }
+        Ok(archived)
+    }
+
+    fn rename_node(&self, from: &NodeName, to: &NodeName, resealed: &[NodeSecret]) -> Result<(), DBError> {
+        ctrace!("Rename node {} to {}", from, to);
+
+        let mut conn = self.connection();
+        let transaction = conn.transaction()?;
+        transaction.execute("UPDATE agent_extra SET name=? WHERE name=?", &[to, from])?;
+        transaction.execute("UPDATE agent_snapshots SET name=? WHERE name=?", &[to, from])?;
+        transaction.execute("UPDATE desired_states SET name=? WHERE name=?", &[to, from])?;
+        transaction.execute("UPDATE logs SET name=? WHERE name=?", &[to, from])?;
+        transaction.execute("UPDATE node_tags SET name=? WHERE name=?", &[to, from])?;
+        transaction.execute("UPDATE node_events SET name=? WHERE name=?", &[to, from])?;
+        transaction.execute("UPDATE node_metrics SET name=? WHERE name=?", &[to, from])?;
+        transaction.execute("UPDATE node_secrets SET name=? WHERE name=?", &[to, from])?;
+        for secret in resealed {
+            transaction.execute(
+                "UPDATE node_secrets SET sealed=? WHERE name=? AND secret_name=?",
+                &[&secret.sealed, to, &secret.name],
+            )?;
+        }
+        transaction.execute("UPDATE node_notes SET name=? WHERE name=?", &[to, from])?;
+        transaction.execute("UPDATE schedules SET node_name=? WHERE node_name=?", &[to, from])?;
+        transaction.execute("UPDATE schedule_runs SET node_name=? WHERE node_name=?", &[to, from])?;
+        let updated = transaction.execute("UPDATE node_aliases SET name=? WHERE name=?", &[to, from])?;
+        if updated == 0 {
+            transaction.execute("INSERT INTO node_aliases (reported_name, name) VALUES (?, ?)", &[from, to])?;
+        }
+        // Renamed back to the reported name
+        transaction.execute("DELETE FROM node_aliases WHERE reported_name=name", &[])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn get_node_aliases(&self) -> Result<Vec<(NodeName, NodeName)>, DBError> {
+        ctrace!("Query node aliases");
+
+        let conn = self.connection();
+        let mut statement = conn.prepare("SELECT reported_name, name FROM node_aliases")?;
+        let rows = statement.query_map(&[], |row| (row.get("reported_name"), row.get("name")))?;
+        let mut aliases = Vec::new();
+        for row in rows {
+            aliases.push(row?);
+        }
+        Ok(aliases)
+    }
+
+    fn insert_logs(&self, node_name: &NodeName, logs: Vec<StructuredLog>, backfilled: bool) -> Result<(), DBError> {
+        ctrace!("Add log {} : {:?}", node_name, logs);
+
+        let mut conn = self.connection();
+        let transaction = conn.transaction()?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT OR IGNORE INTO logs (name, level, target, message, timestamp, thread_name, backfilled) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )?;
+            for log in &logs {
+                let rfc3339with_nano_second = "%Y-%m-%dT%H:%M:%S.%f%z";
+                let datetime = chrono::DateTime::parse_from_str(&log.timestamp, rfc3339with_nano_second)
+                    .map_err(|err| DBError::Query(format!("Invalid timestamp {} : {}", log.timestamp, err)))?;
+                statement.execute(&[
+                    node_name,
+                    &log.level,
+                    &log.target,
+                    &log.message,
+                    &to_text(&datetime),
+                    &log.thread_name,
+                    &backfilled,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn search_logs(&self, params: LogQueryParams) -> Result<Vec<Log>, DBError> {
+        ctrace!("Search log with {:?}", params);
+
+        let mut conditions = Conditions::new();
+        if let Some(filter) = params.filter {
+            conditions.add_any("name", filter.node_names);
+            conditions.add_any("level", filter.levels.iter().map(|level| level.to_string().to_uppercase()).collect());
+            conditions.add_any("target", filter.targets);
+            if let Some(thread_name) = filter.thread_name {
+                conditions.add("thread_name = ?", Box::new(thread_name));
+            }
+        }
+        if let Some(search) = params.search {
+            if search != "" {
+                // LIKE of SQLite is case insensitive like ILIKE of Postgres
+                conditions.add("message LIKE ?", Box::new(format!("%{}%", search)));
+            }
+        }
+        conditions.add_time(params.time);
+        let paging = if params.after.is_some() {
+            format!("ORDER BY timestamp ASC, id ASC LIMIT {}", Paging::new(None, params.item_per_page)?.limit)
+        } else {
+            paging_clause(params.order_by.unwrap_or(OrderBy::ASC), params.page, params.item_per_page)?
+        };
+        conditions.add_after(params.after);
+
+        let query_string = format!("SELECT * FROM logs {} {}", conditions.where_clause(), paging);
+
+        self.query_logs(&query_string, &conditions.parameters())
+    }
+
+    /// The logs which have the same timestamp are ordered by the ids
+    fn get_log_context(&self, id: i64, before: i64, after: i64) -> Result<Option<LogContext>, DBError> {
+        ctrace!("Query the context of the log {}", id);
+
+        let log = match self.query_logs("SELECT * FROM logs WHERE id = ?", &[&id])?.pop() {
+            Some(log) => log,
+            None => return Ok(None),
+        };
+        let timestamp = to_text(&log.timestamp);
+        let mut before = self.query_logs(
+            "SELECT * FROM logs WHERE name = ? AND (timestamp < ? OR (timestamp = ? AND id < ?)) \
+             ORDER BY timestamp DESC, id DESC LIMIT ?",
+            &[&log.node_name, &timestamp, &timestamp, &log.id, &before],
+        )?;
+        before.reverse();
+        let after = self.query_logs(
+            "SELECT * FROM logs WHERE name = ? AND (timestamp > ? OR (timestamp = ? AND id > ?)) \
+             ORDER BY timestamp ASC, id ASC LIMIT ?",
+            &[&log.node_name, &timestamp, &timestamp, &log.id, &after],
+        )?;
+        Ok(Some(LogContext {
+            before,
+            log,
+            after,
+        }))
+    }
+
+    fn get_log_targets(&self) -> Result<Vec<String>, DBError> {
+        ctrace!("Query targets");
+
+        self.query_strings("SELECT DISTINCT target FROM logs ORDER BY target", &[])
+    }
+
+    fn get_tags(&self, node_name: &NodeName) -> Result<Vec<String>, DBError> {
+        ctrace!("Query tags by name {}", node_name);
+
+        self.query_strings("SELECT tag FROM node_tags WHERE name=? ORDER BY tag", &[node_name])
+    }
+
+    fn add_tags(&self, node_name: &NodeName, tags: &[String]) -> Result<(), DBError> {
+        ctrace!("Add tags {:?} to {}", tags, node_name);
+
+        let conn = self.connection();
+        for tag in tags {
+            conn.execute("INSERT OR IGNORE INTO node_tags (name, tag) VALUES (?, ?)", &[node_name, tag])?;
+        }
+        Ok(())
+    }
+
+    fn remove_tags(&self, node_name: &NodeName, tags: &[String]) -> Result<(), DBError> {
+        ctrace!("Remove tags {:?} from {}", tags, node_name);
+
+        let conn = self.connection();
+        for tag in tags {
+            conn.execute("DELETE FROM node_tags WHERE name=? AND tag=?", &[node_name, tag])?;
+        }
+        Ok(())
+    }
+
+    fn get_node_names_by_tags(&self, tags: &[String]) -> Result<Vec<NodeName>, DBError> {
+        ctrace!("Query node names by tags {:?}", tags);
+
+        if tags.is_empty() {
+            return Ok(Vec::new())
+        }
+        let mut conditions = Conditions::new();
+        conditions.add_any("tag", tags.to_vec());
+        let query_string = format!("SELECT DISTINCT name FROM node_tags {}", conditions.where_clause());
+        self.query_strings(&query_string, &conditions.parameters())
+    }
+
+    fn edit_tags(&self, edits: &[NodeEdit]) -> Result<(), DBError> {
+        ctrace!("Edit tags {:?}", edits);
+
+        let mut conn = self.connection();
+        let transaction = conn.transaction()?;
+        for edit in edits {
+            for tag in &edit.remove_tags {
+                transaction.execute("DELETE FROM node_tags WHERE name=? AND tag=?", &[&edit.node_name, tag])?;
+            }
+            for tag in &edit.add_tags {
+                transaction
+                    .execute("INSERT OR IGNORE INTO node_tags (name, tag) VALUES (?, ?)", &[&edit.node_name, tag])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn insert_audit(&self, entry: &AuditEntry) -> Result<(), DBError> {
+        ctrace!("Add audit {:?}", entry);
+
+        let arguments = entry.arguments.to_string();
+        self.connection().execute(
+            "INSERT INTO audit_logs (timestamp, caller, identity, method, node_name, arguments, sandbox) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            &[
+                &to_text(&chrono::Local::now()),
+                &entry.caller,
+                &entry.identity,
+                &entry.method,
+                &entry.node_name,
+                &arguments,
+                &entry.sandbox,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn search_audits(&self, params: AuditQueryParams) -> Result<Vec<Audit>, DBError> {
+        ctrace!("Search audit with {:?}", params);
+
+        let mut conditions = Conditions::new();
+        if let Some(filter) = params.filter {
+            conditions.add_any("node_name", filter.node_names);
+            conditions.add_any("method", filter.methods);
+            conditions.add_any("caller", filter.callers);
+        }
+        conditions.add_time(params.time);
+
+        let query_string = format!(
+            "SELECT * FROM audit_logs {} {}",
+            conditions.where_clause(),
+            paging_clause(params.order_by.unwrap_or(OrderBy::DESC), params.page, params.item_per_page)?
+        );
+
+        let conn = self.connection();
+        let mut statement = conn.prepare(&query_string)?;
+        let rows = statement.query_map(&conditions.parameters(), |row| -> Result<Audit, DBError> {
+            Ok(Audit {
+                id: row.get("id"),
+                timestamp: from_text(&row.get::<_, String>("timestamp"))?,
+                caller: row.get("caller"),
+                identity: row.get("identity"),
+                method: row.get("method"),
+                node_name: row.get("node_name"),
+                arguments: serde_json::from_str(&row.get::<_, String>("arguments")).unwrap_or_default(),
+                sandbox: row.get("sandbox"),
+            })
+        })?;
+        let mut audits = Vec::new();
+        for row in rows {
+            audits.push(row??);
+        }
+        Ok(audits)
+    }
+
+    fn insert_event(&self, node_name: &NodeName, kind: NodeEventKind, detail: &Value) -> Result<(), DBError> {
+        ctrace!("Add event {:?} of {}", kind, node_name);
+
+        self.connection().execute(
+            "INSERT INTO node_events (name, kind, detail, timestamp) VALUES (?, ?, ?, ?)",
+            &[node_name, &format!("{:?}", kind), &detail.to_string(), &to_text(&chrono::Local::now())],
+        )?;
+        Ok(())
+    }
+
+    fn search_events(&self, params: NodeEventQueryParams) -> Result<Vec<NodeEvent>, DBError> {
+        ctrace!("Search events with {:?}", params);
+
+        let mut conditions = Conditions::new();
+        conditions.add_any("name", params.node_names);
+        conditions.add_time(params.time);
+
+        let query_string = format!(
+            "SELECT * FROM node_events {} {}",
+            conditions.where_clause(),
+            paging_clause(params.order_by.unwrap_or(OrderBy::DESC), params.page, params.item_per_page)?
+        );
+
+        let conn = self.connection();
+        let mut statement = conn.prepare(&query_string)?;
+        let rows = statement.query_map(&conditions.parameters(), |row| -> Result<Option<NodeEvent>, DBError> {
+            let kind = serde_json::from_value(Value::String(row.get("kind")));
+            if let Err(err) = &kind {
+                cwarn!("Unknown event kind {}", err);
+            }
+            Ok(match kind.ok() {
+                Some(kind) => Some(NodeEvent {
+                    id: row.get("id"),
+                    node_name: row.get("name"),
+                    kind,
+                    detail: serde_json::from_str(&row.get::<_, String>("detail")).unwrap_or_default(),
+                    timestamp: from_text(&row.get::<_, String>("timestamp"))?,
+                }),
+                None => None,
+            })
+        })?;
+        let mut events = Vec::new();
+        for row in rows {
+            if let Some(event) = row?? {
+                events.push(event);
+            }
+        }
+        Ok(events)
+    }
+
+    fn get_event_node_name(&self, id: i32) -> Result<Option<NodeName>, DBError> {
+        ctrace!("Query the node of event {}", id);
+
+        Ok(self.query_strings("SELECT name FROM node_events WHERE id=?", &[&id])?.pop())
+    }
+
+    fn upsert_slo(&self, slo: &Slo) -> Result<(), DBError> {
+        ctrace!("Set SLO {:?}", slo);
+
+        let tags = serde_json::to_string(&slo.tags).expect("Strings are always serializable");
+        self.connection().execute(
+            "INSERT OR REPLACE INTO slos (name, tags, target, window_minutes, burn_rate_threshold) \
+             VALUES (?, ?, ?, ?, ?)",
+            &[&slo.name, &tags, &slo.target, &slo.window_minutes, &slo.burn_rate_threshold],
+        )?;
+        Ok(())
+    }
+
+    fn remove_slo(&self, name: &str) -> Result<(), DBError> {
+        ctrace!("Remove SLO {}", name);
+
+        let conn = self.connection();
+        conn.execute("DELETE FROM slos WHERE name=?", &[&name])?;
+        conn.execute("DELETE FROM slo_samples WHERE name=?", &[&name])?;
+        Ok(())
+    }
+
+    fn get_slos(&self) -> Result<Vec<Slo>, DBError> {
+        ctrace!("Query SLOs");
+
+        let conn = self.connection();
+        let mut statement = conn.prepare("SELECT * FROM slos ORDER BY name")?;
+        let rows = statement.query_map(&[], |row| Slo {
+            name: row.get("name"),
+            tags: serde_json::from_str(&row.get::<_, String>("tags")).unwrap_or_default(),
+            target: row.get("target"),
+            window_minutes: row.get("window_minutes"),
+            burn_rate_threshold: row.get("burn_rate_threshold"),
+        })?;
+        let mut slos = Vec::new();
+        for row in rows {
+            slos.push(row?);
+        }
+        Ok(slos)
+    }
+
+    fn insert_slo_sample(
+        &self,
+        name: &str,
+        minute: &chrono::DateTime<chrono::Local>,
+        good: bool,
+    ) -> Result<(), DBError> {
+        ctrace!("Add SLO sample of {} at {}", name, minute);
+
+        self.connection().execute(
+            "INSERT OR IGNORE INTO slo_samples (name, minute, good) VALUES (?, ?, ?)",
+            &[&name, &to_text(minute), &good],
+        )?;
+        Ok(())
+    }
+
+    fn insert_slo_samples(
+        &self,
+        name: &str,
+        minutes: &[chrono::DateTime<chrono::Local>],
+        good: bool,
+    ) -> Result<(), DBError> {
+        ctrace!("Add {} SLO samples of {}", minutes.len(), name);
+
+        let mut conn = self.connection();
+        let transaction = conn.transaction()?;
+        {
+            let mut statement =
+                transaction.prepare("INSERT OR IGNORE INTO slo_samples (name, minute, good) VALUES (?, ?, ?)")?;
+            for minute in minutes {
+                statement.execute(&[&name, &to_text(minute), &good])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn get_last_slo_sample_minute(&self, name: &str) -> Result<Option<chrono::DateTime<chrono::Local>>, DBError> {
+        let minute: Option<String> = self.connection().query_row(
+            "SELECT MAX(minute) AS minute FROM slo_samples WHERE name=?",
+            &[&name],
+            |row| row.get("minute"),
+        )?;
+        match minute {
+            Some(minute) => Ok(Some(from_text(&minute)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn count_slo_samples(&self, name: &str, since: &chrono::DateTime<chrono::Local>) -> Result<(i64, i64), DBError> {
+        let counts = self.connection().query_row(
+            "SELECT COALESCE(SUM(good), 0) AS good, COUNT(*) AS total FROM slo_samples WHERE name=? AND minute >= ?",
+            &[&name, &to_text(since)],
+            |row| (row.get("good"), row.get("total")),
+        )?;
+        Ok(counts)
+    }
+
+    fn upsert_start_template(&self, template: &StartTemplate) -> Result<(), DBError> {
+        ctrace!("Set start template {:?}", template);
+
+        self.connection().execute(
+            "INSERT OR REPLACE INTO start_templates (name, env, args, commit_hash) VALUES (?, ?, ?, ?)",
+            &[&template.name, &template.env, &template.args, &template.commit_hash],
+        )?;
+        Ok(())
+    }
+
+    fn remove_start_template(&self, name: &str) -> Result<(), DBError> {
+        ctrace!("Remove start template {}", name);
+
+        self.connection().execute("DELETE FROM start_templates WHERE name=?", &[&name])?;
+        Ok(())
+    }
+
+    fn get_start_templates(&self) -> Result<Vec<StartTemplate>, DBError> {
+        ctrace!("Query start templates");
+
+        let conn = self.connection();
+        let mut statement = conn.prepare("SELECT * FROM start_templates ORDER BY name")?;
+        let rows = statement.query_map(&[], |row| StartTemplate {
+            name: row.get("name"),
+            env: row.get("env"),
+            args: row.get("args"),
+            commit_hash: row.get("commit_hash"),
+        })?;
+        let mut templates = Vec::new();
+        for row in rows {
+            templates.push(row?);
+        }
+        Ok(templates)
+    }
+
+    fn upsert_log_rule(&self, rule: &LogRule) -> Result<(), DBError> {
+        ctrace!("Set log rule {:?}", rule);
+
+        let levels = serde_json::to_string(&rule.levels).expect("Strings are always serializable");
+        self.connection().execute(
+            "INSERT OR REPLACE INTO log_rules (name, pattern, levels) VALUES (?, ?, ?)",
+            &[&rule.name, &rule.pattern, &levels],
+        )?;
+        Ok(())
+    }
+
+    fn remove_log_rule(&self, name: &str) -> Result<(), DBError> {
+        ctrace!("Remove log rule {}", name);
+
+        self.connection().execute("DELETE FROM log_rules WHERE name=?", &[&name])?;
+        Ok(())
+    }
+
+    fn get_log_rules(&self) -> Result<Vec<LogRule>, DBError> {
+        ctrace!("Query log rules");
+
+        let conn = self.connection();
+        let mut statement = conn.prepare("SELECT * FROM log_rules ORDER BY name")?;
+        let rows = statement.query_map(&[], |row| LogRule {
+            name: row.get("name"),
+            pattern: row.get("pattern"),
+            levels: serde_json::from_str(&row.get::<_, String>("levels")).unwrap_or_default(),
+        })?;
+        let mut rules = Vec::new();
+        for row in rows {
+            rules.push(row?);
+        }
+        Ok(rules)
+    }
+
+    fn upsert_desired_state(&self, state: &DesiredState) -> Result<(), DBError> {
+        ctrace!("Set desired state {:?}", state);
+
+        self.connection().execute(
+            "INSERT OR REPLACE INTO desired_states (name, status, env, args, commit_hash) VALUES (?, ?, ?, ?, ?)",
+            &[&state.node_name, &state.status.as_str(), &state.env, &state.args, &state.commit_hash],
+        )?;
+        Ok(())
+    }
+
+    fn remove_desired_state(&self, node_name: &NodeName) -> Result<(), DBError> {
+        ctrace!("Remove desired state of {}", node_name);
+
+        self.connection().execute("DELETE FROM desired_states WHERE name=?", &[node_name])?;
+        Ok(())
+    }
+
+    fn get_desired_states(&self) -> Result<Vec<DesiredState>, DBError> {
+        ctrace!("Query desired states");
+
+        let conn = self.connection();
+        let mut statement = conn.prepare("SELECT * FROM desired_states ORDER BY name")?;
+        let rows = statement.query_map(&[], |row| {
+            let status = serde_json::from_value(Value::String(row.get("status")));
+            if let Err(err) = &status {
+                cwarn!("Unknown desired status {}", err);
+            }
+            status.ok().map(|status| DesiredState {
+                node_name: row.get("name"),
+                status,
+                env: row.get("env"),
+                args: row.get("args"),
+                commit_hash: row.get("commit_hash"),
+            })
+        })?;
+        let mut states = Vec::new();
+        for row in rows {
+            if let Some(state) = row? {
+                states.push(state);
+            }
+        }
+        Ok(states)
+    }
+
+    fn upsert_secret(&self, secret: &NodeSecret) -> Result<(), DBError> {
+        ctrace!("Set secret {} of {}", secret.name, secret.node_name);
+
+        self.connection().execute(
+            "INSERT OR REPLACE INTO node_secrets (name, secret_name, sealed) VALUES (?, ?, ?)",
+            &[&secret.node_name, &secret.name, &secret.sealed],
+        )?;
+        Ok(())
+    }
+
+    fn remove_secret(&self, node_name: &NodeName, name: &str) -> Result<(), DBError> {
+        ctrace!("Remove secret {} of {}", name, node_name);
+
+        self.connection().execute("DELETE FROM node_secrets WHERE name=? AND secret_name=?", &[node_name, &name])?;
+        Ok(())
+    }
+
+    fn get_secrets(&self, node_name: &NodeName) -> Result<Vec<NodeSecret>, DBError> {
+        ctrace!("Query secrets of {}", node_name);
+
+        let conn = self.connection();
+        let mut statement = conn.prepare("SELECT * FROM node_secrets WHERE name=? ORDER BY secret_name")?;
+        let rows = statement.query_map(&[node_name], |row| NodeSecret {
+            node_name: row.get("name"),
+            name: row.get("secret_name"),
+            sealed: row.get("sealed"),
+        })?;
+        let mut secrets = Vec::new();
+        for row in rows {
+            secrets.push(row?);
+        }
+        Ok(secrets)
+    }
+
+    fn insert_note(&self, entry: &NoteEntry) -> Result<Note, DBError> {
+        ctrace!("Add note {:?}", entry);
+
+        let created_at = chrono::Local::now();
+        let conn = self.connection();
+        conn.execute(
+            "INSERT INTO node_notes (name, event_id, author, text, created_at) VALUES (?, ?, ?, ?, ?)",
+            &[&entry.node_name, &entry.event_id, &entry.author, &entry.text, &to_text(&created_at)],
+        )?;
+        Ok(Note {
+            id: conn.last_insert_rowid() as i32,
+            node_name: entry.node_name.clone(),
+            event_id: entry.event_id,
+            author: entry.author.clone(),
+            text: entry.text.clone(),
+            created_at,
+        })
+    }
+
+    fn remove_note(&self, id: i32) -> Result<bool, DBError> {
+        ctrace!("Remove note {}", id);
+
+        let removed = self.connection().execute("DELETE FROM node_notes WHERE id=?", &[&id])?;
+        Ok(removed > 0)
+    }
+
+    fn get_note(&self, id: i32) -> Result<Option<Note>, DBError> {
+        ctrace!("Query note {}", id);
+
+        Ok(self.query_notes("SELECT * FROM node_notes WHERE id=?", &[&id])?.pop())
+    }
+
+    fn get_node_notes(&self, node_name: &NodeName) -> Result<Vec<Note>, DBError> {
+        ctrace!("Query notes of {}", node_name);
+
+        self.query_notes("SELECT * FROM node_notes WHERE name=? ORDER BY created_at DESC", &[node_name])
+    }
+
+    fn get_event_notes(&self, event_ids: &[i32]) -> Result<Vec<Note>, DBError> {
+        ctrace!("Query notes of events {:?}", event_ids);
+
+        let mut conditions = Conditions::new();
+        conditions.add_any("event_id", event_ids.to_vec());
+        if conditions.is_empty() {
+            return Ok(Vec::new())
+        }
+        let query_string = format!("SELECT * FROM node_notes {} ORDER BY created_at DESC", conditions.where_clause());
+        self.query_notes(&query_string, &conditions.parameters())
+    }
+
+    fn upsert_metric_samples(&self, resolution: MetricResolution, samples: &[MetricSample]) -> Result<(), DBError> {
+        ctrace!("Upsert {} {} metric samples", samples.len(), resolution.as_str());
+
+        let mut conn = self.connection();
+        let transaction = conn.transaction()?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT OR REPLACE INTO node_metrics (name, resolution, time, best_block_number, peer_count, samples) \
+                 VALUES (?, ?, ?, ?, ?, ?)",
+            )?;
+            for sample in samples {
+                statement.execute(&[
+                    &sample.node_name,
+                    &resolution.as_str(),
+                    &to_text(&sample.time),
+                    &sample.best_block_number,
+                    &sample.peer_count,
+                    &sample.samples,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn get_metric_samples(
+        &self,
+        resolution: MetricResolution,
+        node_name: Option<&NodeName>,
+        from: &chrono::DateTime<chrono::Local>,
+        to: &chrono::DateTime<chrono::Local>,
+    ) -> Result<Vec<MetricSample>, DBError> {
+        ctrace!("Query {} metric samples of {:?} from {} to {}", resolution.as_str(), node_name, from, to);
+
+        let mut conditions = Conditions::new();
+        conditions.add("resolution=?", Box::new(resolution.as_str().to_string()));
+        conditions.add("time >= ?", Box::new(to_text(from)));
+        conditions.add("time < ?", Box::new(to_text(to)));
+        if let Some(node_name) = node_name {
+            conditions.add("name=?", Box::new(node_name.clone()));
+        }
+        let conn = self.connection();
+        let mut statement =
+            conn.prepare(&format!("SELECT * FROM node_metrics {} ORDER BY name, time", conditions.where_clause()))?;
+        let rows = statement.query_map(&conditions.parameters(), |row| -> Result<MetricSample, DBError> {
+            Ok(MetricSample {
+                node_name: row.get("name"),
+                time: from_text(&row.get::<_, String>("time"))?,
+                best_block_number: row.get("best_block_number"),
+                peer_count: row.get("peer_count"),
+                samples: row.get("samples"),
+            })
+        })?;
+        let mut samples = Vec::new();
+        for row in rows {
+            samples.push(row??);
+        }
+        Ok(samples)
+    }
+
+    fn remove_metric_samples(
+        &self,
+        resolution: MetricResolution,
+        before: &chrono::DateTime<chrono::Local>,
+    ) -> Result<(), DBError> {
+        ctrace!("Remove {} metric samples before {}", resolution.as_str(), before);
+
+        self.connection().execute(
+            "DELETE FROM node_metrics WHERE resolution=? AND time < ?",
+            &[&resolution.as_str(), &to_text(before)],
+        )?;
+        Ok(())
+    }
+
+    fn insert_schedule(&self, entry: &ScheduleEntry) -> Result<Schedule, DBError> {
+        ctrace!("Add schedule {:?}", entry);
+
+        let created_at = chrono::Local::now();
+        let conn = self.connection();
+        conn.execute(
+            "INSERT INTO schedules (kind, cron, node_name, created_at) VALUES (?, ?, ?, ?)",
+            &[&entry.kind.as_str(), &entry.cron, &entry.node_name, &to_text(&created_at)],
+        )?;
+        Ok(Schedule {
+            id: conn.last_insert_rowid() as i32,
+            kind: entry.kind,
+            cron: entry.cron.clone(),
+            node_name: entry.node_name.clone(),
+            created_at,
+        })
+    }
+
+    fn remove_schedule(&self, id: i32) -> Result<bool, DBError> {
+        ctrace!("Remove schedule {}", id);
+
+        let removed = self.connection().execute("DELETE FROM schedules WHERE id=?", &[&id])?;
+        Ok(removed > 0)
+    }
+
+    fn get_schedules(&self) -> Result<Vec<Schedule>, DBError> {
+        ctrace!("Query schedules");
+
+        let conn = self.connection();
+        let mut statement = conn.prepare("SELECT * FROM schedules ORDER BY id")?;
+        let rows = statement.query_map(&[], |row| -> Result<Option<Schedule>, DBError> {
+            let kind = serde_json::from_value(Value::String(row.get("kind")));
+            if let Err(err) = &kind {
+                cwarn!("Unknown schedule kind {}", err);
+            }
+            Ok(match kind.ok() {
+                Some(kind) => Some(Schedule {
+                    id: row.get("id"),
+                    kind,
+                    cron: row.get("cron"),
+                    node_name: row.get("node_name"),
+                    created_at: from_text(&row.get::<_, String>("created_at"))?,
+                }),
+                None => None,
+            })
+        })?;
+        let mut schedules = Vec::new();
+        for row in rows {
+            if let Some(schedule) = row?? {
+                schedules.push(schedule);
+            }
+        }
+        Ok(schedules)
+    }
+
+    fn insert_schedule_run(&self, run: &ScheduleRun) -> Result<(), DBError> {
+        ctrace!("Add schedule run {:?}", run);
+
+        self.connection().execute(
+            "INSERT INTO schedule_runs (schedule_id, node_name, started_at, finished_at, result, detail) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+            &[
+                &run.schedule_id,
+                &run.node_name,
+                &to_text(&run.started_at),
+                &to_text(&run.finished_at),
+                &run.result.as_str(),
+                &run.detail,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn search_schedule_runs(&self, params: ScheduleRunQueryParams) -> Result<Vec<ScheduleRun>, DBError> {
+        ctrace!("Search schedule runs with {:?}", params);
+
+        let mut conditions = Conditions::new();
+        if let Some(schedule_id) = params.schedule_id {
+            conditions.add("schedule_id = ?", Box::new(schedule_id));
+        }
+        let paging = Paging::new(params.page, params.item_per_page)?;
+        let query_string = format!(
+            "SELECT * FROM schedule_runs {} ORDER BY started_at DESC LIMIT {} OFFSET {}",
+            conditions.where_clause(),
+            paging.limit,
+            paging.offset
+        );
+
+        let conn = self.connection();
+        let mut statement = conn.prepare(&query_string)?;
+        let rows = statement.query_map(&conditions.parameters(), |row| -> Result<Option<ScheduleRun>, DBError> {
+            let result = serde_json::from_value(Value::String(row.get("result")));
+            if let Err(err) = &result {
+                cwarn!("Unknown schedule run result {}", err);
+            }
+            Ok(match result.ok() {
+                Some(result) => Some(ScheduleRun {
+                    schedule_id: row.get("schedule_id"),
+                    node_name: row.get("node_name"),
+                    started_at: from_text(&row.get::<_, String>("started_at"))?,
+                    finished_at: from_text(&row.get::<_, String>("finished_at"))?,
+                    result,
+                    detail: row.get("detail"),
+                }),
+                None => None,
+            })
+        })?;
+        let mut runs = Vec::new();
+        for row in rows {
+            if let Some(run) = row?? {
+                runs.push(run);
+            }
+        }
+        Ok(runs)
+    }
+}
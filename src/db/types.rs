@@ -1,11 +1,17 @@
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashSet;
+use std::error;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::net::SocketAddr;
 
+use chrono;
+use chrono::TimeZone;
+use serde_json::Value;
+
 use super::super::common_rpc_types::{
-    BlackList, BlockId, HardwareInfo, NodeName, NodeStatus, NodeVersion, PendingParcel, WhiteList,
+    AgentVersion, BlackList, BlockId, HardwareInfo, NodeClock, NodeName, NodeStatus, NodeVersion, PendingParcel,
+    StructuredLog, WhiteList,
 };
 
 
@@ -21,12 +27,68 @@ pub struct AgentQueryResult {
     pub whitelist: Option<WhiteList>,
     pub blacklist: Option<BlackList>,
     pub hardware: Option<HardwareInfo>,
+    pub agent_version: Option<AgentVersion>,
+    /// None until the agent reports its system time
+    pub clock: Option<NodeClock>,
+    /// Whether the node seals the blocks, which the agents don't report.
+    /// True after the hub sees the node start, false after node_stopSealing, and None if the hub doesn't know it.
+    pub sealing: Option<bool>,
+}
+
+impl AgentQueryResult {
+    pub fn is_clock_skewed(&self) -> bool {
+        self.clock.as_ref().map_or(false, |clock| clock.skewed)
+    }
+}
+
+/// The last state reported by an agent, which is kept across the restarts of the hub.
+/// The best block is left out because it changes on every block of a running node.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSnapshot {
+    pub name: NodeName,
+    pub status: NodeStatus,
+    pub address: Option<SocketAddr>,
+    pub version_hash: Option<String>,
+    /// None in the snapshots written before the sealing was kept
+    #[serde(default)]
+    pub sealing: Option<bool>,
+}
+
+impl<'a> From<&'a AgentQueryResult> for AgentSnapshot {
+    fn from(state: &'a AgentQueryResult) -> Self {
+        Self {
+            name: state.name.clone(),
+            status: state.status,
+            address: state.address,
+            version_hash: state.version.as_ref().map(|version| version.hash.clone()),
+            sealing: state.sealing,
+        }
+    }
+}
+
+impl From<AgentSnapshot> for AgentQueryResult {
+    fn from(snapshot: AgentSnapshot) -> Self {
+        Self {
+            name: snapshot.name,
+            status: snapshot.status,
+            address: snapshot.address,
+            version: snapshot.version_hash.map(|hash| NodeVersion {
+                version: String::new(),
+                hash,
+            }),
+            sealing: snapshot.sealing,
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(PartialEq, Clone, Debug, Default)]
 pub struct AgentExtra {
     pub prev_env: String,
     pub prev_args: String,
+    /// Set when the node runs a pre-installed binary instead of the one built from the commit
+    pub binary_path: Option<String>,
 }
 
 /**
@@ -119,7 +181,7 @@ impl Connections {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct LogQueryParams {
     pub filter: Option<LogFilter>,
@@ -128,18 +190,33 @@ pub struct LogQueryParams {
     pub page: Option<i32>,
     pub item_per_page: Option<i32>,
     pub order_by: Option<OrderBy>,
+    /// Reads the logs after the cursor in the ascending order instead of the page. It is not exposed to the API.
+    #[serde(skip)]
+    pub after: Option<LogCursor>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// The position of the last log read, for paging through the logs without an offset
+#[derive(Debug, Clone)]
+pub struct LogCursor {
+    pub timestamp: chrono::DateTime<chrono::Local>,
+    /// Used by the storages whose ids are serial
+    pub id: i64,
+    /// The number of the logs at the timestamp which are already read. Used by the storages whose ids are hashes.
+    pub read_at_timestamp: i64,
+}
+
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct LogFilter {
     pub node_names: Vec<String>,
     pub levels: Vec<LogLevel>,
     pub targets: Vec<String>,
     pub thread_name: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum LogLevel {
     Error,
@@ -155,31 +232,504 @@ impl fmt::Display for LogLevel {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct LogDuration {
     pub from_time: Option<chrono::DateTime<chrono::Local>>,
     pub to_time: Option<chrono::DateTime<chrono::Local>>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, JsonSchema)]
 pub enum OrderBy {
     ASC,
     DESC,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Log {
-    pub id: i32,
+    /// Serial in the database. The hash of the log in the other log storages.
+    pub id: i64,
     pub node_name: String,
     pub level: String,
     pub target: String,
     pub timestamp: chrono::DateTime<chrono::Local>,
     pub message: String,
+    /// True if the agent buffered the log while the hub was unreachable and sent it later
+    pub backfilled: bool,
+}
+
+/// A log and the logs of the same node around it, in the order of the time
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LogContext {
+    pub before: Vec<Log>,
+    pub log: Log,
+    pub after: Vec<Log>,
 }
 
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub caller: Option<String>,
+    /// The name of the token of the caller
+    pub identity: Option<String>,
+    pub method: String,
+    pub node_name: Option<NodeName>,
+    pub arguments: Value,
+    /// The request was validated but not executed
+    pub sandbox: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Audit {
+    pub id: i32,
+    pub timestamp: chrono::DateTime<chrono::Local>,
+    pub caller: Option<String>,
+    pub identity: Option<String>,
+    pub method: String,
+    pub node_name: Option<NodeName>,
+    pub arguments: Value,
+    pub sandbox: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditQueryParams {
+    pub filter: Option<AuditFilter>,
+    pub time: Option<LogDuration>,
+    pub page: Option<i32>,
+    pub item_per_page: Option<i32>,
+    pub order_by: Option<OrderBy>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditFilter {
+    #[serde(default)]
+    pub node_names: Vec<String>,
+    #[serde(default)]
+    pub methods: Vec<String>,
+    #[serde(default)]
+    pub callers: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+pub enum NodeEventKind {
+    AgentConnected,
+    AgentDisconnected,
+    CodeChainStarted,
+    CodeChainStopped,
+    CodeChainCrashed,
+    UpdateStarted,
+    UpdateApplied,
+    /// The version hash of CodeChain is changed
+    VersionChanged,
+    MaintenanceStarted,
+    MaintenanceFinished,
+    /// The node is removed by node_remove
+    Archived,
+    /// The node is renamed by node_rename
+    Renamed,
+    /// The state reported by the reconnected agent differs from the state before the hub restarted
+    ChangedWhileHubDown,
+    /// The release tracking found a release of CodeChain which the node doesn't run
+    UpdateAvailable,
+    /// A log of the node matched a log rule
+    LogRuleMatched,
+    /// The reconciliation started or stopped the node to match its desired state
+    DesiredStateCorrected,
+    /// The running node has another start option or commit than its desired state
+    DesiredStateDrifted,
+    /// The node reported a block which another node didn't at the same height
+    ForkDetected,
+    /// The chaos testing killed, paused or isolated the node
+    ChaosInjected,
+    /// The clock of the host drifted beyond `max_clock_skew_ms`
+    ClockSkewed,
+    /// The clock of the host is back within `max_clock_skew_ms`
+    ClockSynchronized,
+    /// node_startSealing resumed the block production of the node
+    SealingStarted,
+    /// node_stopSealing took the node out of the block production without stopping it
+    SealingStopped,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeEvent {
+    pub id: i32,
+    pub node_name: NodeName,
+    pub kind: NodeEventKind,
+    pub detail: Value,
+    pub timestamp: chrono::DateTime<chrono::Local>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeEventQueryParams {
+    #[serde(default)]
+    pub node_names: Vec<NodeName>,
+    pub time: Option<LogDuration>,
+    pub page: Option<i32>,
+    pub item_per_page: Option<i32>,
+    pub order_by: Option<OrderBy>,
+}
+
+/// The number of the items of a page when it is not given
+const DEFAULT_ITEM_PER_PAGE: i32 = 100;
+/// A larger item per page is clamped to this
+const MAX_ITEM_PER_PAGE: i32 = 1000;
+
+/// The LIMIT and the OFFSET of a page
+#[derive(Debug, Clone, Copy)]
+pub struct Paging {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Paging {
+    /// The pages start from 1, and the item per page is clamped between 1 and `MAX_ITEM_PER_PAGE`
+    pub fn new(page: Option<i32>, item_per_page: Option<i32>) -> Result<Self, Error> {
+        let page = page.unwrap_or(1);
+        if page < 1 {
+            return Err(Error::InvalidParams(format!("The page starts from 1, but {} is given", page)))
+        }
+        let limit = i64::from(item_per_page.unwrap_or(DEFAULT_ITEM_PER_PAGE).max(1).min(MAX_ITEM_PER_PAGE));
+        let offset = (i64::from(page) - 1)
+            .checked_mul(limit)
+            .ok_or_else(|| Error::InvalidParams(format!("The page {} is too far", page)))?;
+        Ok(Self {
+            limit,
+            offset,
+        })
+    }
+}
+
+/// The message of r2d2::Error without the last error of the connections
+const R2D2_TIMEOUT: &str = "timed out waiting for connection";
+
 #[derive(Debug, Clone)]
 pub enum Error {
     Timeout,
+    Query(String),
+    /// The query is refused before it reaches the storage
+    InvalidParams(String),
+}
+
+impl From<postgres::Error> for Error {
+    fn from(err: postgres::Error) -> Self {
+        Error::Query(format!("{}", err))
+    }
+}
+
+impl From<Box<error::Error>> for Error {
+    fn from(err: Box<error::Error>) -> Self {
+        Error::Query(format!("{}", err))
+    }
+}
+
+/// r2d2 gives up waiting for a connection when the pool is busy or when the database refuses the connections.
+/// It has the last error of the connections only in the latter case, so only the former is a timeout.
+impl From<r2d2::Error> for Error {
+    fn from(err: r2d2::Error) -> Self {
+        let message = err.to_string();
+        if message == R2D2_TIMEOUT {
+            Error::Timeout
+        } else {
+            Error::Query(message)
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Query(format!("{}", err))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Timeout => write!(f, "DB timeout"),
+            Error::Query(err) => write!(f, "{}", err),
+            Error::InvalidParams(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// An objective that all nodes selected by the tags are running.
+/// The ratio of the good minutes in the window should be higher than the target.
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Slo {
+    pub name: String,
+    /// Selects all nodes when it is empty
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// e.g. 0.999
+    pub target: f64,
+    pub window_minutes: i32,
+    pub burn_rate_threshold: f64,
+}
+
+/// A named start option which node_start can start a node with.
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StartTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub env: String,
+    #[serde(default)]
+    pub args: String,
+    /// The node is updated to the commit if it runs another one
+    #[serde(default)]
+    pub commit_hash: Option<String>,
+}
+
+/// Raises a LogRuleMatched event when a log of a node contains the pattern.
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LogRule {
+    pub name: String,
+    /// Matched case-insensitively as a part of the message
+    pub pattern: String,
+    /// e.g. ["error", "warn"]. Matches the logs of every level when it is empty
+    #[serde(default)]
+    pub levels: Vec<String>,
+}
+
+impl LogRule {
+    pub fn is_matched(&self, log: &StructuredLog) -> bool {
+        let is_level_matched =
+            self.levels.is_empty() || self.levels.iter().any(|level| level.eq_ignore_ascii_case(&log.level));
+        is_level_matched && log.message.to_lowercase().contains(&self.pattern.to_lowercase())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum DesiredStatus {
+    Running,
+    Stopped,
+}
+
+impl DesiredStatus {
+    /// The same as the serialized value
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DesiredStatus::Running => "running",
+            DesiredStatus::Stopped => "stopped",
+        }
+    }
+}
+
+/// The state which the reconciliation keeps a node in
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DesiredState {
+    pub node_name: NodeName,
+    pub status: DesiredStatus,
+    /// The start option of the running node
+    pub env: String,
+    pub args: String,
+    /// The running node is expected to be built from this commit
+    pub commit_hash: Option<String>,
+}
+
+/// An env variable of a node which is encrypted in the database and never shown
+#[derive(Debug, Clone)]
+pub struct NodeSecret {
+    pub node_name: NodeName,
+    pub name: String,
+    /// The value encrypted with the secrets key
+    pub sealed: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct NoteEntry {
+    pub node_name: NodeName,
+    /// The note is about this event of the node when it is set
+    pub event_id: Option<i32>,
+    pub author: Option<String>,
+    pub text: String,
+}
+
+/// A free-text note of an operator on a node or on an event of the node, e.g. "disk replaced"
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Note {
+    pub id: i32,
+    pub node_name: NodeName,
+    pub event_id: Option<i32>,
+    /// The name of the token of the operator who wrote the note. None when no token is configured
+    pub author: Option<String>,
+    pub text: String,
+    pub created_at: chrono::DateTime<chrono::Local>,
+}
+
+/// The raw samples are rolled up into the minutes, and the minutes into the hours
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum MetricResolution {
+    Raw,
+    Minute,
+    Hour,
+}
+
+impl MetricResolution {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MetricResolution::Raw => "raw",
+            MetricResolution::Minute => "minute",
+            MetricResolution::Hour => "hour",
+        }
+    }
+
+    /// The start of the period which the time belongs to. The periods are aligned in UTC.
+    pub fn truncate(self, time: &chrono::DateTime<chrono::Local>) -> chrono::DateTime<chrono::Local> {
+        let seconds = match self {
+            MetricResolution::Raw => return *time,
+            MetricResolution::Minute => 60,
+            MetricResolution::Hour => 60 * 60,
+        };
+        let timestamp = time.timestamp() - time.timestamp().rem_euclid(seconds);
+        chrono::Local.timestamp(timestamp, 0)
+    }
+}
+
+/// A sample of a node, or the aggregate of the samples in a minute or an hour
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricSample {
+    pub node_name: NodeName,
+    /// The start of the period when the sample is rolled up
+    pub time: chrono::DateTime<chrono::Local>,
+    /// The highest in the period
+    pub best_block_number: Option<i64>,
+    /// The average in the period
+    pub peer_count: f64,
+    /// The number of the raw samples in the period
+    pub samples: i32,
+}
+
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricQueryParams {
+    pub from_time: Option<chrono::DateTime<chrono::Local>>,
+    pub to_time: Option<chrono::DateTime<chrono::Local>>,
+    /// Chosen by the length of the range when it is not given
+    pub resolution: Option<MetricResolution>,
+}
+
+/// The changes of a node by a bulk edit
+#[derive(Debug, Clone)]
+pub struct NodeEdit {
+    pub node_name: NodeName,
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    /// Unchanged if it is None
+    pub maintenance: Option<bool>,
+}
+
+/// A removed node. `detail` keeps the last state, the start option, the tags and the schedules of the node.
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedNode {
+    pub id: i32,
+    pub name: NodeName,
+    pub archived_at: chrono::DateTime<chrono::Local>,
+    pub detail: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ScheduleKind {
+    Restart,
+    /// Restores a recent snapshot on the node and verifies that the node syncs from it
+    VerifySnapshot,
+}
+
+impl ScheduleKind {
+    /// The same as the serialized value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleKind::Restart => "restart",
+            ScheduleKind::VerifySnapshot => "verifySnapshot",
+        }
+    }
+}
+
+/// Runs an operation on a node whenever the cron expression matches.
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Schedule {
+    pub id: i32,
+    pub kind: ScheduleKind,
+    pub cron: String,
+    pub node_name: NodeName,
+    pub created_at: chrono::DateTime<chrono::Local>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScheduleEntry {
+    pub kind: ScheduleKind,
+    pub cron: String,
+    pub node_name: NodeName,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ScheduleRunResult {
+    Succeeded,
+    Failed,
+    /// The run is refused by a guard, e.g. the quorum guard
+    Skipped,
+}
+
+impl ScheduleRunResult {
+    /// The same as the serialized value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleRunResult::Succeeded => "succeeded",
+            ScheduleRunResult::Failed => "failed",
+            ScheduleRunResult::Skipped => "skipped",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleRun {
+    pub schedule_id: i32,
+    pub node_name: NodeName,
+    pub started_at: chrono::DateTime<chrono::Local>,
+    pub finished_at: chrono::DateTime<chrono::Local>,
+    pub result: ScheduleRunResult,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleRunQueryParams {
+    /// Selects the runs of all schedules when it is None
+    pub schedule_id: Option<i32>,
+    pub page: Option<i32>,
+    pub item_per_page: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SloStatus {
+    pub slo: Slo,
+    pub good_minutes: i64,
+    pub total_minutes: i64,
+    /// None when there is no sample
+    pub availability: Option<f64>,
+    /// The ratio of the remaining error budget, becomes negative when the SLO is violated
+    pub error_budget_remaining: f64,
+    /// How fast the error budget is consumed in the last hour. 1.0 consumes the budget exactly in the window
+    pub burn_rate: Option<f64>,
 }
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use base64;
+use chrono;
+use sha2::{Digest, Sha256};
+
+use super::agent::{AgentSender, BinaryChunkRequest, BinaryInstallRequest, SendAgentRPC};
+use super::common_rpc_types::{CommitHash, NodeName, ShellUpdateCodeChainRequest};
+use super::frontend;
+use super::job::Jobs;
+use super::jsonrpc;
+
+/// The transfer progress is notified at most once in this interval
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DistributionOption {
+    /// Builds or downloads the binary of the commit $COMMIT_HASH to the path $OUTPUT, e.g. a script running cargo build
+    pub build_command: String,
+    /// The built binaries are kept in the subdirectories named by their commits
+    #[serde(default = "default_dir")]
+    pub dir: PathBuf,
+    /// The size of a chunk in bytes before it is base64 encoded
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: usize,
+}
+
+fn default_dir() -> PathBuf {
+    PathBuf::from("artifacts")
+}
+
+fn default_chunk_size() -> usize {
+    256 * 1024
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, JsonSchema)]
+pub enum UpdatePhase {
+    Building,
+    Transferring,
+    Installing,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateProgress {
+    /// The job of the update
+    pub job_id: u64,
+    pub node_name: NodeName,
+    pub commit_hash: CommitHash,
+    pub phase: UpdatePhase,
+    pub transferred_bytes: u64,
+    /// 0 until the binary is built
+    pub total_bytes: u64,
+    pub error: Option<String>,
+    pub updated_at: chrono::DateTime<chrono::Local>,
+}
+
+struct Artifact {
+    path: PathBuf,
+    size: u64,
+    sha256: String,
+}
+
+/// Builds the binary of a commit once on the hub, and transfers it to the agents which have the binaryInstall
+/// capability instead of making each of them build CodeChain.
+pub struct Distributor {
+    option: DistributionOption,
+    frontend_service: frontend::ServiceSender,
+    jobs: Arc<Jobs>,
+    /// Builds one binary at a time, so a commit is built once even if many nodes are updated to it at once
+    build_lock: Mutex<()>,
+    /// The latest update of each node
+    progress: RwLock<HashMap<NodeName, UpdateProgress>>,
+}
+
+impl Distributor {
+    pub fn new(option: DistributionOption, frontend_service: frontend::ServiceSender, jobs: Arc<Jobs>) -> Self {
+        Self {
+            option,
+            frontend_service,
+            jobs,
+            build_lock: Mutex::new(()),
+            progress: Default::default(),
+        }
+    }
+
+    /// Sorted by the node names
+    pub fn progress(&self) -> Vec<UpdateProgress> {
+        let progress = self.progress.read().expect("Should success read the update progress");
+        let mut progress: Vec<UpdateProgress> = progress.values().cloned().collect();
+        progress.sort_by(|a, b| a.node_name.cmp(&b.node_name));
+        progress
+    }
+
+    /// Updates the node in another thread, reporting the phases to the job. Fails if the node is being updated already.
+    pub fn start(
+        distributor: &Arc<Self>,
+        job_id: u64,
+        name: NodeName,
+        agent: AgentSender,
+        req: ShellUpdateCodeChainRequest,
+    ) -> Result<(), String> {
+        validate_commit_hash(&req.commit_hash)?;
+        let progress = UpdateProgress {
+            job_id,
+            node_name: name.clone(),
+            commit_hash: req.commit_hash.clone(),
+            phase: UpdatePhase::Building,
+            transferred_bytes: 0,
+            total_bytes: 0,
+            error: None,
+            updated_at: chrono::Local::now(),
+        };
+        {
+            let mut nodes = distributor.progress.write().expect("Should success write the update progress");
+            if let Some(running) = nodes.get(&name) {
+                if running.phase != UpdatePhase::Done && running.phase != UpdatePhase::Failed {
+                    return Err(format!("{} is being updated to {}", name, running.commit_hash))
+                }
+            }
+            nodes.insert(name.clone(), progress.clone());
+        }
+        distributor.notify(&progress);
+        distributor.jobs.set_phase(job_id, "building");
+
+        let distributor = Arc::clone(distributor);
+        thread::Builder::new()
+            .name(format!("distribute {}", name))
+            .spawn(move || {
+                let result = distributor.distribute(job_id, &name, &agent, req);
+                if let Err(err) = &result {
+                    cwarn!("Cannot update {} with the built binary : {}", name, err);
+                    distributor.update(&name, true, |progress| {
+                        progress.phase = UpdatePhase::Failed;
+                        progress.error = Some(err.clone());
+                    });
+                }
+                distributor.jobs.finish(job_id, result);
+            })
+            .expect("Should success running distribution thread");
+        Ok(())
+    }
+
+    fn distribute(
+        &self,
+        job_id: u64,
+        name: &NodeName,
+        agent: &AgentSender,
+        req: ShellUpdateCodeChainRequest,
+    ) -> Result<(), String> {
+        let cancellation = self.jobs.cancellation(job_id);
+        let artifact = self.artifact(&req.commit_hash)?;
+        if cancellation.is_cancelled() {
+            return Err("Cancelled".to_string())
+        }
+        self.jobs.set_phase(job_id, "transferring");
+        self.update(name, true, |progress| {
+            progress.phase = UpdatePhase::Transferring;
+            progress.total_bytes = artifact.size;
+        });
+
+        let mut file = File::open(&artifact.path).map_err(|err| format!("Cannot open the binary : {}", err))?;
+        let mut buffer = vec![0; self.option.chunk_size];
+        let mut offset = 0;
+        let mut last_notified = Instant::now();
+        loop {
+            let read = file.read(&mut buffer).map_err(|err| format!("Cannot read the binary : {}", err))?;
+            if read == 0 {
+                break
+            }
+            if cancellation.is_cancelled() {
+                return Err("Cancelled".to_string())
+            }
+            let chunk = BinaryChunkRequest {
+                commit_hash: req.commit_hash.clone(),
+                total_size: artifact.size,
+                offset,
+                data: base64::encode(&buffer[..read]),
+            };
+            agent.binary_upload_chunk(chunk, Some(&cancellation)).map_err(|err| err.to_string())?;
+            offset += read as u64;
+            let notify = last_notified.elapsed() >= NOTIFY_INTERVAL;
+            if notify {
+                last_notified = Instant::now();
+            }
+            self.update(name, notify, |progress| progress.transferred_bytes = offset);
+        }
+
+        self.update(name, true, |progress| progress.phase = UpdatePhase::Installing);
+        self.jobs.set_phase(job_id, "installing");
+        let install = BinaryInstallRequest {
+            commit_hash: req.commit_hash,
+            sha256: artifact.sha256,
+            env: req.env,
+            args: req.args,
+        };
+        agent.binary_install(install, Some(&cancellation)).map_err(|err| err.to_string())?;
+        self.update(name, true, |progress| progress.phase = UpdatePhase::Done);
+        Ok(())
+    }
+
+    /// Runs the build command unless the binary of the commit is built already.
+    /// The checksum is written next to the binary, which is moved to its place last.
+    fn artifact(&self, commit_hash: &str) -> Result<Artifact, String> {
+        let _guard = self.build_lock.lock().expect("Should success lock the build");
+        let dir = self.option.dir.join(commit_hash);
+        let path = dir.join("codechain");
+        let checksum_path = dir.join("codechain.sha256");
+        if !path.exists() {
+            fs::create_dir_all(&dir).map_err(|err| format!("Cannot create {} : {}", dir.display(), err))?;
+            let output = dir.join("codechain.partial");
+            cinfo!("Build CodeChain {}", commit_hash);
+            let result = Command::new("sh")
+                .arg("-c")
+                .arg(&self.option.build_command)
+                .env("COMMIT_HASH", commit_hash)
+                .env("OUTPUT", &output)
+                .output()
+                .map_err(|err| format!("Cannot run the build command : {}", err))?;
+            if !result.status.success() {
+                return Err(format!("The build command failed : {}", String::from_utf8_lossy(&result.stderr)))
+            }
+            let sha256 = file_sha256(&output).map_err(|err| format!("Cannot read the built binary : {}", err))?;
+            fs::write(&checksum_path, &sha256).map_err(|err| format!("Cannot write the checksum : {}", err))?;
+            fs::rename(&output, &path).map_err(|err| format!("Cannot move the built binary : {}", err))?;
+        }
+        let size = fs::metadata(&path).map_err(|err| format!("Cannot read the binary : {}", err))?.len();
+        let sha256 = fs::read_to_string(&checksum_path).map_err(|err| format!("Cannot read the checksum : {}", err))?;
+        Ok(Artifact {
+            path,
+            size,
+            sha256: sha256.trim().to_string(),
+        })
+    }
+
+    fn update<F>(&self, name: &NodeName, notify: bool, f: F)
+    where
+        F: FnOnce(&mut UpdateProgress), {
+        let progress = {
+            let mut nodes = self.progress.write().expect("Should success write the update progress");
+            let progress = match nodes.get_mut(name) {
+                Some(progress) => progress,
+                None => return,
+            };
+            f(progress);
+            progress.updated_at = chrono::Local::now();
+            progress.clone()
+        };
+        if notify {
+            self.notify(&progress);
+        }
+    }
+
+    fn notify(&self, progress: &UpdateProgress) {
+        let message = jsonrpc::serialize_notification("node_updateProgress", progress);
+        self.frontend_service
+            .send(frontend::Message::SendNodeEvent(vec![progress.node_name.clone()], message))
+            .expect("Should success send event");
+    }
+}
+
+/// The commit hash is a part of the path of the binary
+fn validate_commit_hash(commit_hash: &str) -> Result<(), String> {
+    if commit_hash.is_empty() || !commit_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid commit hash {:?}", commit_hash))
+    }
+    Ok(())
+}
+
+fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break
+        }
+        hasher.input(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.result()))
+}
@@ -1,6 +1,8 @@
+use chrono;
 use serde_json;
 use serde_json::Value;
 
+use super::common_rpc_types::NodeName;
 use super::db;
 use super::frontend;
 use super::jsonrpc;
@@ -15,6 +17,20 @@ impl EventPropagator {
             frontend_service,
         }
     }
+
+    /// The frontends of the other namespaces don't receive the events of the nodes
+    fn send_node_event(&self, node_names: Vec<NodeName>, message: String) {
+        self.frontend_service
+            .send(frontend::Message::SendNodeEvent(node_names, message))
+            .expect("Should success send event");
+    }
+
+    /// The frontend service caches the namespaces of the nodes
+    fn invalidate_namespaces(&self, node_names: Vec<NodeName>) {
+        self.frontend_service
+            .send(frontend::Message::InvalidateNamespaces(node_names))
+            .expect("Should success send event");
+    }
 }
 
 impl db::EventSubscriber for EventPropagator {
@@ -27,6 +43,7 @@ impl db::EventSubscriber for EventPropagator {
                 let mut diff = json!({
                     "name": after.name,
                 });
+                let clock_alert = clock_alert(before.as_ref(), &after);
 
                 if before.is_none() {
                     diff["address"] = serde_json::to_value(after.address).unwrap();
@@ -38,6 +55,8 @@ impl db::EventSubscriber for EventPropagator {
                     diff["whitelist"] = serde_json::to_value(after.whitelist).unwrap();
                     diff["blacklist"] = serde_json::to_value(after.blacklist).unwrap();
                     diff["hardware"] = serde_json::to_value(after.hardware).unwrap();
+                    diff["agentVersion"] = serde_json::to_value(after.agent_version).unwrap();
+                    diff["clock"] = serde_json::to_value(after.clock).unwrap();
                 } else {
                     let before = before.unwrap();
                     if before == after {
@@ -71,6 +90,12 @@ impl db::EventSubscriber for EventPropagator {
                     if before.hardware != after.hardware {
                         diff["hardware"] = serde_json::to_value(after.hardware).unwrap();
                     }
+                    if before.agent_version != after.agent_version {
+                        diff["agentVersion"] = serde_json::to_value(after.agent_version).unwrap();
+                    }
+                    if before.clock != after.clock {
+                        diff["clock"] = serde_json::to_value(after.clock).unwrap();
+                    }
                 }
 
                 let message = jsonrpc::serialize_notification(
@@ -80,42 +105,69 @@ impl db::EventSubscriber for EventPropagator {
                     }),
                 );
 
-                self.frontend_service.send(frontend::Message::SendEvent(message)).expect("Should success send event");
+                self.send_node_event(vec![after.name.clone()], message);
                 let message = jsonrpc::serialize_notification("node_updated", diff);
-                self.frontend_service.send(frontend::Message::SendEvent(message)).expect("Should success send event");
+                self.send_node_event(vec![after.name.clone()], message);
+                if let Some((method, params)) = clock_alert {
+                    let message = jsonrpc::serialize_notification(method, params);
+                    self.send_node_event(vec![after.name.clone()], message);
+                }
             }
             db::Event::ConnectionChanged {
                 added,
                 removed,
             } => {
-                let collection_added: Vec<Value> = added
-                    .iter()
-                    .map(|(first, second)| {
-                        json!({
-                            "nodeA": first,
-                            "nodeB": second,
-                        })
-                    })
-                    .collect();
-                let collection_removed: Vec<Value> = removed
-                    .iter()
-                    .map(|(first, second)| {
+                // Each connection is sent apart, so the frontends receive only the connections in their namespaces
+                let changes = added.iter().map(|connection| (connection, true));
+                let changes = changes.chain(removed.iter().map(|connection| (connection, false)));
+                for ((first, second), is_added) in changes {
+                    let connection = vec![json!({
+                        "nodeA": first,
+                        "nodeB": second,
+                    })];
+                    let (collection_added, collection_removed) = if is_added {
+                        (connection, Vec::new())
+                    } else {
+                        (Vec::new(), connection)
+                    };
+                    let message = jsonrpc::serialize_notification(
+                        "dashboard_updated",
                         json!({
-                            "nodeA": first,
-                            "nodeB": second,
-                        })
-                    })
-                    .collect();
+                            "connectionsAdded": collection_added,
+                            "connectionsRemoved": collection_removed,
+                        }),
+                    );
+                    self.send_node_event(vec![first.clone(), second.clone()], message);
+                }
+            }
+            db::Event::AgentRemoved {
+                name,
+            } => {
                 let message = jsonrpc::serialize_notification(
                     "dashboard_updated",
                     json!({
-                        "connectionsAdded": collection_added,
-                        "connectionsRemoved": collection_removed,
+                        "nodesRemoved": [name],
                     }),
                 );
-
-                self.frontend_service.send(frontend::Message::SendEvent(message)).expect("Should success send event");
+                self.send_node_event(vec![name.clone()], message);
+                self.invalidate_namespaces(vec![name]);
+            }
+            db::Event::AgentRenamed {
+                from,
+                to,
+            } => {
+                self.invalidate_namespaces(vec![from.clone(), to.clone()]);
+                let message = jsonrpc::serialize_notification(
+                    "dashboard_updated",
+                    json!({
+                        "nodesRenamed": [{ "from": from, "to": to }],
+                    }),
+                );
+                self.send_node_event(vec![to.clone()], message);
             }
+            db::Event::TagsChanged {
+                name,
+            } => self.invalidate_namespaces(vec![name]),
             db::Event::AgentExtraUpdated {
                 name,
                 before,
@@ -129,6 +181,7 @@ impl db::EventSubscriber for EventPropagator {
                     diff["startOption"] = json!({
                         "env": after.prev_env,
                         "args": after.prev_args,
+                        "binaryPath": after.binary_path,
                     });
                 } else {
                     let before = before.unwrap();
@@ -136,17 +189,86 @@ impl db::EventSubscriber for EventPropagator {
                         return
                     }
 
-                    if before.prev_env != after.prev_env || before.prev_args != after.prev_args {
+                    if before.prev_env != after.prev_env
+                        || before.prev_args != after.prev_args
+                        || before.binary_path != after.binary_path
+                    {
                         diff["startOption"] = json!({
                             "env": after.prev_env,
                             "args": after.prev_args,
+                            "binaryPath": after.binary_path,
                         });
                     }
                 }
 
                 let message = jsonrpc::serialize_notification("node_updated", diff);
-                self.frontend_service.send(frontend::Message::SendEvent(message)).expect("Should success send event");
+                self.send_node_event(vec![name.clone()], message);
+            }
+            db::Event::AgentConnected {
+                state,
+            } => {
+                let message = jsonrpc::serialize_notification(
+                    "event_agentConnected",
+                    json!({
+                        "name": state.name,
+                        "timestamp": chrono::Local::now(),
+                        "status": state.status,
+                        "bestBlockId": state.best_block_id,
+                    }),
+                );
+                self.send_node_event(vec![state.name.clone()], message);
+            }
+            db::Event::AgentDisconnected {
+                last,
+                reason,
+            } => {
+                let message = jsonrpc::serialize_notification(
+                    "event_agentDisconnected",
+                    json!({
+                        "name": last.name,
+                        "timestamp": chrono::Local::now(),
+                        "lastStatus": last.status,
+                        "bestBlockId": last.best_block_id,
+                        "reason": reason,
+                    }),
+                );
+                self.send_node_event(vec![last.name.clone()], message);
+            }
+            db::Event::LogRuleMatched {
+                name,
+                rule,
+                log,
+            } => {
+                let message = jsonrpc::serialize_notification(
+                    "log_ruleMatched",
+                    json!({
+                        "name": name,
+                        "rule": rule,
+                        "level": log.level,
+                        "target": log.target,
+                        "message": log.message,
+                        "timestamp": log.timestamp,
+                    }),
+                );
+                self.send_node_event(vec![name.clone()], message);
             }
         }
     }
 }
+
+/// alert_clockSkew when the clock of the host drifts beyond the threshold, and alert_clockSkewResolved when it is back
+fn clock_alert(before: Option<&db::AgentQueryResult>, after: &db::AgentQueryResult) -> Option<(&'static str, Value)> {
+    let was_skewed = before.map_or(false, |before| before.is_clock_skewed());
+    let method = match (was_skewed, after.is_clock_skewed()) {
+        (false, true) => "alert_clockSkew",
+        (true, false) => "alert_clockSkewResolved",
+        _ => return None,
+    };
+    Some((
+        method,
+        json!({
+            "nodeName": after.name,
+            "clock": after.clock,
+        }),
+    ))
+}
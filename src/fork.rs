@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono;
+use cprimitives::H256;
+
+use super::common_rpc_types::{NodeName, NodeStatus};
+use super::db;
+use super::frontend;
+use super::frontend::namespace::namespace_of;
+use super::jsonrpc;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// The block hashes of this number of the latest heights are compared
+const TRACKED_HEIGHTS: i64 = 1000;
+/// The fork is regarded as resolved when no diverging height is found in this period
+const RESOLVE_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ForkBranch {
+    block_hash: H256,
+    node_names: Vec<NodeName>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ForkIncident {
+    severity: &'static str,
+    /// The namespace of the forked nodes. The nodes in different namespaces run different networks.
+    namespace: Option<String>,
+    /// The first height where the nodes reported different blocks
+    block_number: i64,
+    branches: Vec<ForkBranch>,
+    /// The last height where the nodes reported different blocks
+    last_block_number: i64,
+    started_at: chrono::DateTime<chrono::Local>,
+}
+
+#[derive(Default)]
+struct Forks {
+    /// The nodes which reported each block at each height
+    heights: BTreeMap<i64, HashMap<H256, BTreeSet<NodeName>>>,
+    incident: Option<(ForkIncident, Instant)>,
+}
+
+impl Forks {
+    /// Returns true if the node moved to another block at the height, which is not reported by the other nodes.
+    /// The node is removed from the blocks it reported at the same or higher heights, because it switched the branch.
+    fn report(&mut self, name: &NodeName, block_number: i64, block_hash: H256) -> bool {
+        let is_reported = self
+            .heights
+            .get(&block_number)
+            .and_then(|blocks| blocks.get(&block_hash))
+            .map_or(false, |names| names.contains(name));
+        if is_reported {
+            return false
+        }
+        for blocks in self.heights.range_mut(block_number..).map(|(_, blocks)| blocks) {
+            for names in blocks.values_mut() {
+                names.remove(name);
+            }
+            blocks.retain(|_, names| !names.is_empty());
+        }
+        let blocks = self.heights.entry(block_number).or_default();
+        blocks.entry(block_hash).or_default().insert(name.clone());
+        blocks.len() > 1
+    }
+}
+
+/// Alerts when the nodes of a namespace report different blocks at the same height.
+/// The nodes rarely report the same height at the same moment, so the blocks of the recent heights are remembered.
+pub fn run_thread(db_service: db::ServiceSender, frontend_service: frontend::ServiceSender) {
+    thread::Builder::new()
+        .name("fork".to_string())
+        .spawn(move || {
+            let mut forks = HashMap::new();
+            loop {
+                thread::sleep(CHECK_INTERVAL);
+                if let Err(err) = check(&db_service, &frontend_service, &mut forks) {
+                    cerror!("Cannot check the forks : {:?}", err);
+                }
+            }
+        })
+        .expect("Should success running fork thread");
+}
+
+fn check(
+    db_service: &db::ServiceSender,
+    frontend_service: &frontend::ServiceSender,
+    forks: &mut HashMap<Option<String>, Forks>,
+) -> Result<(), db::Error> {
+    let mut diverged: HashMap<Option<String>, BTreeSet<i64>> = HashMap::new();
+    for state in db_service.get_agents_state()? {
+        let best_block_id = match (state.status, state.best_block_id) {
+            (NodeStatus::Run, Some(best_block_id)) => best_block_id,
+            _ => continue,
+        };
+        let namespace = namespace_of(db_service, &state.name)?;
+        let network = forks.entry(namespace.clone()).or_default();
+        if network.report(&state.name, best_block_id.block_number, best_block_id.hash) {
+            diverged.entry(namespace).or_default().insert(best_block_id.block_number);
+        }
+    }
+
+    for (namespace, network) in forks.iter_mut() {
+        if let Some(highest) = network.heights.keys().next_back().cloned() {
+            network.heights = network.heights.split_off(&(highest - TRACKED_HEIGHTS));
+        }
+        let diverged = diverged.remove(namespace).unwrap_or_default();
+        check_network(db_service, frontend_service, namespace, network, diverged);
+    }
+    Ok(())
+}
+
+fn check_network(
+    db_service: &db::ServiceSender,
+    frontend_service: &frontend::ServiceSender,
+    namespace: &Option<String>,
+    forks: &mut Forks,
+    diverged: BTreeSet<i64>,
+) {
+    for block_number in diverged {
+        match &mut forks.incident {
+            Some((incident, last_seen)) => {
+                incident.last_block_number = incident.last_block_number.max(block_number);
+                *last_seen = Instant::now();
+            }
+            None => {
+                let branches = forks.heights[&block_number]
+                    .iter()
+                    .map(|(block_hash, names)| ForkBranch {
+                        block_hash: *block_hash,
+                        node_names: names.iter().cloned().collect(),
+                    })
+                    .collect();
+                let incident = ForkIncident {
+                    severity: "critical",
+                    namespace: namespace.clone(),
+                    block_number,
+                    branches,
+                    last_block_number: block_number,
+                    started_at: chrono::Local::now(),
+                };
+                cerror!("The nodes in {:?} forked at block {} : {:?}", namespace, block_number, incident.branches);
+                for name in incident.branches.iter().flat_map(|branch| branch.node_names.iter()) {
+                    db_service.write_event(name, db::NodeEventKind::ForkDetected, json!(incident));
+                }
+                send(frontend_service, "alert_fork", &incident);
+                forks.incident = Some((incident, Instant::now()));
+            }
+        }
+    }
+
+    let is_resolved = forks.incident.as_ref().map_or(false, |(_, last_seen)| last_seen.elapsed() >= RESOLVE_WINDOW);
+    if is_resolved {
+        let (incident, _) = forks.incident.take().expect("Checked");
+        cinfo!("The fork in {:?} at block {} is resolved", namespace, incident.block_number);
+        send(frontend_service, "alert_forkResolved", &incident);
+    }
+}
+
+/// The alert is sent to the frontends which see all of the forked nodes
+fn send(frontend_service: &frontend::ServiceSender, method: &str, incident: &ForkIncident) {
+    let node_names = incident.branches.iter().flat_map(|branch| branch.node_names.iter().cloned()).collect();
+    let message = jsonrpc::serialize_notification(method, incident);
+    frontend_service.send(frontend::Message::SendNodeEvent(node_names, message)).expect("Should success send event");
+}
@@ -1,110 +1,989 @@
-use super::super::agent::SendAgentRPC;
-use super::super::common_rpc_types::{CommitHash, NodeName, ShellStartCodeChainRequest, ShellUpdateCodeChainRequest};
-use super::super::router::Router;
-use super::super::rpc::{response, RPCError, RPCResponse};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use chrono;
+use serde::Serialize;
+use serde_json;
+use serde_json::Value;
+
+use super::super::agent::{
+    protocol, AgentSender, ChaosBlockPeersRequest, ChaosPauseRequest, CodeChainRPC, SendAgentRPC,
+};
+use super::super::build_info::BuildInfo;
+use super::super::common_rpc_types::{
+    AgentCapability, CommitHash, NodeName, NodeStatus, ShellStartCodeChainRequest, ShellUpdateCodeChainRequest,
+};
+use super::super::db;
+use super::super::distribution::{Distributor, UpdateProgress};
+use super::super::job::{Job, JobStatus};
+use super::super::outage;
+use super::super::router::{MethodDescription, Router};
+use super::super::rpc::{response, RPCError, RPCResponse, RPCResult};
+use super::super::secrets;
+use super::super::session::Session;
+use super::api_changes;
+use super::impact;
+use super::namespace;
+use super::report;
+use super::service::Message;
+use super::schedule;
+use super::start_policy;
+use super::template;
 use super::types::{
-    Context, DashboardGetNetworkResponse, DashboardNode, LogGetRequest, LogGetResponse, LogGetTargetsResponse,
-    NodeConnection, NodeGetInfoResponse,
+    ApiChangesResponse, AuditGetRequest, AuditGetResponse, ChangeReport, Context, DashboardGetNetworkDiffResponse,
+    DashboardGetNetworkResponse, DashboardNode, DesiredStateRequest, ErrorDigest, EventGetRequest, EventGetResponse,
+    Fault, HistoryGetRequest, HistoryGetResponse, HubStats, LogGetRequest, LogGetResponse, LogGetTargetsResponse,
+    LogLevelChange, NetworkApplyAction, NetworkApplyConnectionResult, NetworkApplyNodeResult, NetworkApplyResponse,
+    NetworkScenario, NodeBulkEditResponse, NodeBulkResult, NodeChannelMetrics, NodeConnection, NodeEditPatch,
+    NodeEditResult, NodeFindMatch, NodeFindResult, NodeGetInfoResponse, NodeSelector, NodeSetLogLevelResponse,
+    NodeStopImpact, NodeUpdateResponse, PendingAction, ScenarioNode,
 };
 
+const RECENT_EVENTS_IN_NODE_INFO: i32 = 10;
+/// The number of the distinct error messages in node_getInfo
+const RECENT_ERRORS_IN_NODE_INFO: usize = 10;
+/// The number of the latest error logs which are digested
+const ERROR_LOGS_TO_DIGEST: i32 = 200;
+/// The owner of a node is the tag like "owner:alice"
+const OWNER_TAG_PREFIX: &str = "owner:";
+/// The number of the logs on each side of log_getContext when it is omitted
+const DEFAULT_LOG_CONTEXT: i64 = 20;
+const MAX_LOG_CONTEXT: i64 = 500;
+/// The longest pause or partition of the chaos testing
+const MAX_CHAOS_DURATION_SECS: u64 = 60 * 60;
+/// The notes are short remarks, so a longer text is refused instead of being stored
+const MAX_NOTE_LENGTH: usize = 2000;
+/// The longest ban of the address of a killed session
+const MAX_BAN_SECS: u64 = 24 * 60 * 60;
+
 pub fn add_routing(router: &mut Router<Context>) {
-    router.add_route("ping", Box::new(ping as fn(Context) -> RPCResponse<String>));
+    router.add_route("ping", &[], Box::new(ping as fn(Context) -> RPCResponse<String>));
+    router.add_route("rpc_methods", &[], Box::new(rpc_methods as fn(Context) -> RPCResponse<Vec<String>>));
+    router.add_route(
+        "rpc_describe",
+        &["method"],
+        Box::new(rpc_describe as fn(Context, (String,)) -> RPCResponse<MethodDescription>),
+    );
+    router.add_route(
+        "rpc_getApiChanges",
+        &["sinceVersion"],
+        Box::new(rpc_get_api_changes as fn(Context, (String,)) -> RPCResponse<ApiChangesResponse>),
+    );
+    router.add_route(
+        "admin_getBuildInfo",
+        &[],
+        Box::new(admin_get_build_info as fn(Context) -> RPCResponse<BuildInfo>),
+    );
     router.add_route(
         "node_getInfo",
+        &["name"],
         Box::new(node_get_info as fn(Context, (String,)) -> RPCResponse<NodeGetInfoResponse>),
     );
     router.add_route(
         "dashboard_getNetwork",
+        &[],
         Box::new(dashboard_get_network as fn(Context) -> RPCResponse<DashboardGetNetworkResponse>),
     );
+    router.add_route(
+        "dashboard_getNetworkDiff",
+        &["sinceSeq", "sinceEpoch"],
+        Box::new(
+            dashboard_get_network_diff
+                as fn(Context, (u64, Option<u64>)) -> RPCResponse<DashboardGetNetworkDiffResponse>,
+        ),
+    );
     router.add_route(
         "node_start",
-        Box::new(node_start as fn(Context, (String, ShellStartCodeChainRequest)) -> RPCResponse<()>),
+        &["name", "req", "template"],
+        Box::new(
+            node_start as fn(Context, (String, Option<ShellStartCodeChainRequest>, Option<String>)) -> RPCResponse<()>,
+        ),
+    );
+    router.add_route("node_stop", &["name"], Box::new(node_stop as fn(Context, (String,)) -> RPCResponse<()>));
+    router.add_route(
+        "node_getStopImpact",
+        &["name"],
+        Box::new(node_get_stop_impact as fn(Context, (NodeName,)) -> RPCResponse<NodeStopImpact>),
+    );
+    router.add_route(
+        "node_update",
+        &["name", "commitHash"],
+        Box::new(node_update as fn(Context, (NodeName, CommitHash)) -> RPCResponse<NodeUpdateResponse>),
     );
-    router.add_route("node_stop", Box::new(node_stop as fn(Context, (String,)) -> RPCResponse<()>));
-    router.add_route("node_update", Box::new(node_update as fn(Context, (NodeName, CommitHash)) -> RPCResponse<()>));
     router.add_route(
         "shell_getCodeChainLog",
+        &["name"],
         Box::new(shell_get_codechain_log as fn(Context, (String,)) -> RPCResponse<String>),
     );
-    router.add_route("log_getTargets", Box::new(log_get_targets as fn(Context) -> RPCResponse<LogGetTargetsResponse>));
-    router.add_route("log_get", Box::new(log_get as fn(Context, (LogGetRequest,)) -> RPCResponse<LogGetResponse>));
+    router.add_route(
+        "log_getTargets",
+        &[],
+        Box::new(log_get_targets as fn(Context) -> RPCResponse<LogGetTargetsResponse>),
+    );
+    router.add_route(
+        "log_get",
+        &["req"],
+        Box::new(log_get as fn(Context, (LogGetRequest,)) -> RPCResponse<LogGetResponse>),
+    );
+    router.add_route(
+        "log_getContext",
+        &["logId", "before", "after"],
+        Box::new(log_get_context as fn(Context, (i64, Option<i64>, Option<i64>)) -> RPCResponse<db::LogContext>),
+    );
+    router.add_route(
+        "log_setRule",
+        &["rule"],
+        Box::new(log_set_rule as fn(Context, (db::LogRule,)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "log_removeRule",
+        &["name"],
+        Box::new(log_remove_rule as fn(Context, (String,)) -> RPCResponse<()>),
+    );
+    router.add_route("log_getRules", &[], Box::new(log_get_rules as fn(Context) -> RPCResponse<Vec<db::LogRule>>));
+    router.add_route(
+        "network_connect",
+        &["from", "to"],
+        Box::new(network_connect as fn(Context, (NodeName, NodeName)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "network_disconnect",
+        &["from", "to"],
+        Box::new(network_disconnect as fn(Context, (NodeName, NodeName)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "node_find",
+        &["query"],
+        Box::new(node_find as fn(Context, (String,)) -> RPCResponse<Vec<NodeFindResult>>),
+    );
+    router.add_route(
+        "node_addTags",
+        &["name", "tags"],
+        Box::new(node_add_tags as fn(Context, (NodeName, Vec<String>)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "node_removeTags",
+        &["name", "tags"],
+        Box::new(node_remove_tags as fn(Context, (NodeName, Vec<String>)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "dashboard_getNetworkByTags",
+        &["tags"],
+        Box::new(
+            dashboard_get_network_by_tags as fn(Context, (Vec<String>,)) -> RPCResponse<DashboardGetNetworkResponse>,
+        ),
+    );
+    router.add_route(
+        "node_startByTags",
+        &["tags"],
+        Box::new(node_start_by_tags as fn(Context, (Vec<String>,)) -> RPCResponse<Vec<NodeBulkResult>>),
+    );
+    router.add_route(
+        "node_stopByTags",
+        &["tags", "force"],
+        Box::new(node_stop_by_tags as fn(Context, (Vec<String>, Option<bool>)) -> RPCResponse<Vec<NodeBulkResult>>),
+    );
+    router.add_route(
+        "node_bulkEdit",
+        &["selector", "patch", "preview"],
+        Box::new(
+            node_bulk_edit
+                as fn(Context, (NodeSelector, NodeEditPatch, Option<bool>)) -> RPCResponse<NodeBulkEditResponse>,
+        ),
+    );
+    router.add_route(
+        "node_getEvents",
+        &["name", "req"],
+        Box::new(node_get_events as fn(Context, (NodeName, EventGetRequest)) -> RPCResponse<EventGetResponse>),
+    );
+    router.add_route(
+        "node_setDesiredState",
+        &["name", "req"],
+        Box::new(node_set_desired_state as fn(Context, (NodeName, DesiredStateRequest)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "node_removeDesiredState",
+        &["name"],
+        Box::new(node_remove_desired_state as fn(Context, (NodeName,)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "node_getDesiredStates",
+        &[],
+        Box::new(node_get_desired_states as fn(Context) -> RPCResponse<Vec<db::DesiredState>>),
+    );
+    router.add_route(
+        "node_setSecret",
+        &["name", "secretName", "value"],
+        Box::new(node_set_secret as fn(Context, (NodeName, String, String)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "node_removeSecret",
+        &["name", "secretName"],
+        Box::new(node_remove_secret as fn(Context, (NodeName, String)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "node_getSecrets",
+        &["name"],
+        Box::new(node_get_secrets as fn(Context, (NodeName,)) -> RPCResponse<Vec<String>>),
+    );
+    router.add_route(
+        "node_getHistory",
+        &["name", "req"],
+        Box::new(node_get_history as fn(Context, (NodeName, HistoryGetRequest)) -> RPCResponse<HistoryGetResponse>),
+    );
+    router.add_route(
+        "dashboard_getEvents",
+        &["req"],
+        Box::new(dashboard_get_events as fn(Context, (EventGetRequest,)) -> RPCResponse<EventGetResponse>),
+    );
+    router.add_route(
+        "audit_get",
+        &["req"],
+        Box::new(audit_get as fn(Context, (AuditGetRequest,)) -> RPCResponse<AuditGetResponse>),
+    );
+    router.add_route("network_export", &[], Box::new(network_export as fn(Context) -> RPCResponse<NetworkScenario>));
+    router.add_route(
+        "node_getChannelMetrics",
+        &["name"],
+        Box::new(node_get_channel_metrics as fn(Context, (NodeName,)) -> RPCResponse<NodeChannelMetrics>),
+    );
+    router.add_route(
+        "debug_injectFault",
+        &["fault"],
+        Box::new(debug_inject_fault as fn(Context, (Fault,)) -> RPCResponse<()>),
+    );
+    router.add_route("chaos_kill", &["name"], Box::new(chaos_kill as fn(Context, (NodeName,)) -> RPCResponse<()>));
+    router.add_route(
+        "chaos_pause",
+        &["name", "durationSecs"],
+        Box::new(chaos_pause as fn(Context, (NodeName, u64)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "chaos_partition",
+        &["groupA", "groupB", "durationSecs"],
+        Box::new(
+            chaos_partition as fn(Context, (Vec<NodeName>, Vec<NodeName>, u64)) -> RPCResponse<Vec<NodeBulkResult>>,
+        ),
+    );
+    router.add_route("slo_set", &["slo"], Box::new(slo_set as fn(Context, (db::Slo,)) -> RPCResponse<()>));
+    router.add_route("slo_remove", &["name"], Box::new(slo_remove as fn(Context, (String,)) -> RPCResponse<()>));
+    router.add_route(
+        "template_set",
+        &["template"],
+        Box::new(template_set as fn(Context, (db::StartTemplate,)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "template_remove",
+        &["name"],
+        Box::new(template_remove as fn(Context, (String,)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "template_list",
+        &[],
+        Box::new(template_list as fn(Context) -> RPCResponse<Vec<db::StartTemplate>>),
+    );
+    router.add_route(
+        "slo_getStatus",
+        &[],
+        Box::new(slo_get_status as fn(Context) -> RPCResponse<Vec<db::SloStatus>>),
+    );
+    router.add_route(
+        "node_remove",
+        &["name", "force"],
+        Box::new(node_remove as fn(Context, (NodeName, Option<bool>)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "node_listArchived",
+        &[],
+        Box::new(node_list_archived as fn(Context) -> RPCResponse<Vec<db::ArchivedNode>>),
+    );
+    router.add_route(
+        "node_rename",
+        &["name", "newName"],
+        Box::new(node_rename as fn(Context, (NodeName, NodeName)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "report_changes",
+        &["since"],
+        Box::new(report_changes as fn(Context, (chrono::DateTime<chrono::Local>,)) -> RPCResponse<ChangeReport>),
+    );
+    router.add_route(
+        "schedule_create",
+        &["kind", "cron", "node"],
+        Box::new(schedule_create as fn(Context, (db::ScheduleKind, String, NodeName)) -> RPCResponse<db::Schedule>),
+    );
+    router.add_route("schedule_remove", &["id"], Box::new(schedule_remove as fn(Context, (i32,)) -> RPCResponse<()>));
+    router.add_route("schedule_list", &[], Box::new(schedule_list as fn(Context) -> RPCResponse<Vec<db::Schedule>>));
+    router.add_route(
+        "schedule_getHistory",
+        &["req"],
+        Box::new(
+            schedule_get_history as fn(Context, (db::ScheduleRunQueryParams,)) -> RPCResponse<Vec<db::ScheduleRun>>,
+        ),
+    );
+    router.add_route(
+        "network_apply",
+        &["scenario", "force"],
+        Box::new(network_apply as fn(Context, (NetworkScenario, Option<bool>)) -> RPCResponse<NetworkApplyResponse>),
+    );
+    router.add_route(
+        "node_setLogLevel",
+        &["name", "filter"],
+        Box::new(node_set_log_level as fn(Context, (NodeName, String)) -> RPCResponse<NodeSetLogLevelResponse>),
+    );
+    router.add_route(
+        "node_startSealing",
+        &["name"],
+        Box::new(node_start_sealing as fn(Context, (NodeName,)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "node_stopSealing",
+        &["name", "force"],
+        Box::new(node_stop_sealing as fn(Context, (NodeName, Option<bool>)) -> RPCResponse<()>),
+    );
+    router.add_route(
+        "note_add",
+        &["nodeName", "text", "eventId"],
+        Box::new(note_add as fn(Context, (NodeName, String, Option<i32>)) -> RPCResponse<db::Note>),
+    );
+    router.add_route("note_remove", &["id"], Box::new(note_remove as fn(Context, (i32,)) -> RPCResponse<()>));
+    router.add_route("action_list", &[], Box::new(action_list as fn(Context) -> RPCResponse<Vec<PendingAction>>));
+    router.add_route("action_approve", &["id"], Box::new(action_approve as fn(Context, (u64,)) -> RPCResponse<Value>));
+    router.add_route("action_reject", &["id"], Box::new(action_reject as fn(Context, (u64,)) -> RPCResponse<()>));
+    router.add_route(
+        "update_getProgress",
+        &[],
+        Box::new(update_get_progress as fn(Context) -> RPCResponse<Vec<UpdateProgress>>),
+    );
+    router.add_route("job_get", &["id"], Box::new(job_get as fn(Context, (u64,)) -> RPCResponse<Job>));
+    router.add_route(
+        "job_list",
+        &["nodeName"],
+        Box::new(job_list as fn(Context, (Option<NodeName>,)) -> RPCResponse<Vec<Job>>),
+    );
+    router.add_route("job_cancel", &["id"], Box::new(job_cancel as fn(Context, (u64,)) -> RPCResponse<Job>));
+    router.add_route("hub_getStats", &[], Box::new(hub_get_stats as fn(Context) -> RPCResponse<HubStats>));
+    router.add_route("hub_listSessions", &[], Box::new(hub_list_sessions as fn(Context) -> RPCResponse<Vec<Session>>));
+    router.add_route(
+        "hub_killSession",
+        &["id", "banSecs"],
+        Box::new(hub_kill_session as fn(Context, (u64, Option<u64>)) -> RPCResponse<()>),
+    );
+
+    // The methods which scan the database or call all the agents consume more of the rate limit
+    router.set_weight("log_get", 10.0);
+    router.set_weight("log_getContext", 5.0);
+    router.set_weight("log_getTargets", 5.0);
+    router.set_weight("audit_get", 5.0);
+    router.set_weight("dashboard_getEvents", 5.0);
+    router.set_weight("schedule_getHistory", 5.0);
+    router.set_weight("report_changes", 20.0);
+    router.set_weight("network_export", 5.0);
+    router.set_weight("node_getStopImpact", 5.0);
 }
 
 fn ping(_: Context) -> RPCResponse<String> {
     response("pong".to_string())
 }
 
+fn rpc_methods(context: Context) -> RPCResponse<Vec<String>> {
+    response(context.methods.iter().map(|description| description.method.clone()).collect())
+}
+
+fn rpc_describe(context: Context, args: (String,)) -> RPCResponse<MethodDescription> {
+    let (method,) = args;
+    let description = context
+        .methods
+        .iter()
+        .find(|description| description.method == method)
+        .ok_or_else(|| RPCError::NotFound(method.clone()))?;
+    response(description.clone())
+}
+
+fn rpc_get_api_changes(context: Context, args: (String,)) -> RPCResponse<ApiChangesResponse> {
+    let (since_version,) = args;
+    let changes = api_changes::changes_since(&since_version).map_err(RPCError::InvalidParams)?;
+    response(ApiChangesResponse {
+        current_version: context.build_info.version.clone(),
+        changes,
+    })
+}
+
+fn admin_get_build_info(context: Context) -> RPCResponse<BuildInfo> {
+    response((*context.build_info).clone())
+}
+
+fn hub_get_stats(context: Context) -> RPCResponse<HubStats> {
+    namespace::require_admin(&context, "hub_getStats")?;
+    response(HubStats {
+        methods: context.rpc_metrics.stats(),
+    })
+}
+
+fn hub_list_sessions(context: Context) -> RPCResponse<Vec<Session>> {
+    namespace::require_admin(&context, "hub_listSessions")?;
+    let (tx, rx) = channel();
+    context.frontend_service.send(Message::GetSubscribers(tx)).expect("Should success send GetSubscribers");
+    let subscribers = rx.recv().map_err(|err| RPCError::Internal(err.to_string()))?;
+    let agent_service = &context.agent_service;
+    response(context.sessions.list(|connection| agent_service.get_agent_name(connection), &subscribers))
+}
+
+/// The killed agent reconnects by itself, so its address can be banned for a while with `banSecs`
+fn hub_kill_session(context: Context, args: (u64, Option<u64>)) -> RPCResponse<()> {
+    let (id, ban_secs) = args;
+    namespace::require_admin(&context, "hub_killSession")?;
+    if ban_secs.map_or(false, |ban_secs| ban_secs > MAX_BAN_SECS) {
+        return Err(RPCError::InvalidParams(format!("The ban should be at most {} seconds", MAX_BAN_SECS)))
+    }
+    audit(&context, "hub_killSession", None, json!({ "id": id, "banSecs": ban_secs }));
+    let is_killed = if context.sandbox {
+        context.sessions.contains(id)
+    } else {
+        let ban = ban_secs.map(Duration::from_secs);
+        context.sessions.kill(id, "Killed by hub_killSession", ban).map_err(RPCError::Internal)?
+    };
+    if !is_killed {
+        return Err(RPCError::NotFound(format!("Session {}", id)))
+    }
+    response(())
+}
+
 fn dashboard_get_network(context: Context) -> RPCResponse<DashboardGetNetworkResponse> {
     let agents_state = context.db_service.get_agents_state()?;
     let connections = context.db_service.get_connections()?;
-    let dashboard_nodes = agents_state.iter().map(|agent| DashboardNode::from_db_state(agent)).collect();
+    let dashboard_nodes: Vec<DashboardNode> =
+        agents_state.iter().map(|agent| DashboardNode::from_db_state(agent)).collect();
+    let seq = context.network_history.record(&dashboard_nodes, &connections);
+    let visible = namespace::visible_nodes(&context)?;
     response(DashboardGetNetworkResponse {
-        nodes: dashboard_nodes,
-        connections: connections.iter().map(|connection| NodeConnection::from_connection(connection)).collect(),
+        epoch: context.network_history.epoch(),
+        seq,
+        nodes: dashboard_nodes.into_iter().filter(|node| visible.contains(node.name())).collect(),
+        connections: connections
+            .iter()
+            .filter(|(node_a, node_b)| visible.contains(node_a) && visible.contains(node_b))
+            .map(|connection| NodeConnection::from_connection(connection))
+            .collect(),
     })
 }
 
+/// Returns the nodes and the connections changed since the network of sinceSeq in sinceEpoch
+fn dashboard_get_network_diff(
+    context: Context,
+    args: (u64, Option<u64>),
+) -> RPCResponse<DashboardGetNetworkDiffResponse> {
+    let (since_seq, since_epoch) = args;
+    let agents_state = context.db_service.get_agents_state()?;
+    let connections = context.db_service.get_connections()?;
+    let dashboard_nodes = agents_state.iter().map(|agent| DashboardNode::from_db_state(agent)).collect();
+    let mut diff = context.network_history.diff(since_epoch, since_seq, dashboard_nodes, connections);
+    let visible = namespace::visible_nodes(&context)?;
+    diff.nodes_added.retain(|node| visible.contains(node.name()));
+    diff.nodes_changed.retain(|node| visible.contains(node.name()));
+    diff.nodes_removed.retain(|name| visible.contains(name));
+    let is_visible =
+        |connection: &NodeConnection| visible.contains(&connection.node_a) && visible.contains(&connection.node_b);
+    diff.connections_added.retain(&is_visible);
+    diff.connections_removed.retain(&is_visible);
+    response(diff)
+}
+
 fn node_get_info(context: Context, args: (String,)) -> RPCResponse<NodeGetInfoResponse> {
     let (name,) = args;
-    let agent_query_result = context.db_service.get_agent_query_result(&name)?.ok_or(RPCError::AgentNotFound)?;
+    namespace::check_node(&context, &name)?;
+    let agent_query_result =
+        context.db_service.get_agent_query_result(&name)?.ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
     let extra = context.db_service.get_agent_extra(&name)?;
-    response(NodeGetInfoResponse::from_db_state(&agent_query_result, &extra))
+    let tags = context.db_service.get_tags(&name)?;
+    let events = context.db_service.get_events(db::NodeEventQueryParams {
+        node_names: vec![name.clone()],
+        item_per_page: Some(RECENT_EVENTS_IN_NODE_INFO),
+        ..Default::default()
+    })?;
+    let mut info = NodeGetInfoResponse::from_db_state(&agent_query_result, &extra, tags, events);
+    info.notes = context.db_service.get_node_notes(&name)?;
+    info.recent_errors = recent_errors(&context, &name)?;
+    info.sealing = context.db_service.is_sealing(&name);
+    info.update_available = context.releases.update_for(info.version.as_ref().map(|version| version.hash.as_str()));
+    response(info)
+}
+
+/// Digests the latest error logs of the node into the distinct messages, the most recent first
+fn recent_errors(context: &Context, name: &NodeName) -> RPCResult<Vec<ErrorDigest>> {
+    let logs = context.db_service.get_logs(db::LogQueryParams {
+        filter: Some(db::LogFilter {
+            node_names: vec![name.clone()],
+            levels: vec![db::LogLevel::Error],
+            targets: Vec::new(),
+            thread_name: None,
+            tags: Vec::new(),
+        }),
+        search: None,
+        time: None,
+        page: Some(1),
+        item_per_page: Some(ERROR_LOGS_TO_DIGEST),
+        order_by: Some(db::OrderBy::DESC),
+        after: None,
+    })?;
+
+    let mut digests: Vec<ErrorDigest> = Vec::new();
+    for log in logs {
+        if let Some(digest) =
+            digests.iter_mut().find(|digest| digest.target == log.target && digest.message == log.message)
+        {
+            digest.count += 1;
+            continue
+        }
+        if digests.len() < RECENT_ERRORS_IN_NODE_INFO {
+            digests.push(ErrorDigest {
+                target: log.target,
+                message: log.message,
+                count: 1,
+                last_seen: log.timestamp,
+            });
+        }
+    }
+    Ok(digests)
+}
+
+fn node_get_events(context: Context, args: (NodeName, EventGetRequest)) -> RPCResponse<EventGetResponse> {
+    let (name, mut req) = args;
+    namespace::check_node(&context, &name)?;
+    req.node_names = vec![name];
+    let events = context.db_service.get_events(req)?;
+    event_get_response(&context, events)
+}
+
+fn event_get_response(context: &Context, events: Vec<db::NodeEvent>) -> RPCResponse<EventGetResponse> {
+    let event_ids: Vec<i32> = events.iter().map(|event| event.id).collect();
+    let notes = context.db_service.get_event_notes(&event_ids)?;
+    response(EventGetResponse {
+        events,
+        notes,
+    })
+}
+
+/// The reconciliation starts or stops the node until it matches the desired state
+fn node_set_desired_state(context: Context, args: (NodeName, DesiredStateRequest)) -> RPCResponse<()> {
+    let (name, mut req) = args;
+    namespace::check_node(&context, &name)?;
+    let secret_names = context.db_service.get_secret_names(&name)?;
+    req.env = req.env.map(|env| secrets::redact_env(&env, &secret_names));
+    audit(&context, "node_setDesiredState", Some(&name), &req);
+
+    if context.db_service.get_agent_query_result(&name)?.is_none() {
+        return Err(RPCError::AgentNotFound(name))
+    }
+    let extra = context.db_service.get_agent_extra(&name)?.unwrap_or_default();
+    let env = req.env.unwrap_or(extra.prev_env);
+    let args = req.args.unwrap_or(extra.prev_args);
+    if req.status == db::DesiredStatus::Running {
+        render_start_request(&context, &name, &env, &args, extra.binary_path)?;
+    }
+    if !context.sandbox {
+        context.db_service.set_desired_state(db::DesiredState {
+            node_name: name,
+            status: req.status,
+            env,
+            args,
+            commit_hash: req.commit_hash,
+        });
+    }
+    response(())
+}
+
+fn node_remove_desired_state(context: Context, args: (NodeName,)) -> RPCResponse<()> {
+    let (name,) = args;
+    namespace::check_node(&context, &name)?;
+    audit(&context, "node_removeDesiredState", Some(&name), ());
+
+    if !context.sandbox {
+        context.db_service.remove_desired_state(&name);
+    }
+    response(())
 }
 
-fn node_start(context: Context, args: (NodeName, ShellStartCodeChainRequest)) -> RPCResponse<()> {
+fn node_get_desired_states(context: Context) -> RPCResponse<Vec<db::DesiredState>> {
+    let visible = namespace::visible_nodes(&context)?;
+    let mut states = context.db_service.get_desired_states()?;
+    states.retain(|state| visible.contains(&state.node_name));
+    response(states)
+}
+
+/// The value is encrypted with the secrets key, and only the agent receives it in the env of the start commands
+fn node_set_secret(context: Context, args: (NodeName, String, String)) -> RPCResponse<()> {
+    let (name, secret_name, value) = args;
+    namespace::check_node(&context, &name)?;
+    let option = context.config.secrets.as_ref().ok_or(RPCError::FeatureDisabled("Secrets"))?;
+    // The value is never audited
+    audit(&context, "node_setSecret", Some(&name), &secret_name);
+
+    if secret_name.is_empty() || secret_name.contains(|c: char| c == '=' || c.is_whitespace()) {
+        return Err(RPCError::InvalidParams(format!("Invalid secret name \"{}\"", secret_name)))
+    }
+    if value.contains(char::is_whitespace) {
+        return Err(RPCError::InvalidParams("The value of an env variable can't have a space".to_string()))
+    }
+    if context.db_service.get_agent_query_result(&name)?.is_none() {
+        return Err(RPCError::AgentNotFound(name))
+    }
+    // The secret is a part of the env of the start command, so the start policy applies to it
+    let variable = format!("{}={}", secret_name, value);
+    start_policy::check(&context.config.frontend.start_policy, &variable, "", None)
+        .map_err(|rejection| RPCError::StartRequestRejected(name.clone(), rejection))?;
+    let sealed = option.encrypt(&name, &secret_name, &value).map_err(RPCError::Internal)?;
+    if !context.sandbox {
+        context.db_service.set_secret(db::NodeSecret {
+            node_name: name,
+            name: secret_name,
+            sealed,
+        });
+    }
+    response(())
+}
+
+fn node_remove_secret(context: Context, args: (NodeName, String)) -> RPCResponse<()> {
+    let (name, secret_name) = args;
+    namespace::check_node(&context, &name)?;
+    audit(&context, "node_removeSecret", Some(&name), &secret_name);
+
+    if !context.sandbox {
+        context.db_service.remove_secret(&name, &secret_name);
+    }
+    response(())
+}
+
+/// Only the names of the secrets are returned
+fn node_get_secrets(context: Context, args: (NodeName,)) -> RPCResponse<Vec<String>> {
+    let (name,) = args;
+    namespace::check_node(&context, &name)?;
+    response(context.db_service.get_secret_names(&name)?)
+}
+
+/// The env variables of the secrets of the node, which override the variables of the same names
+fn secret_env(context: &Context, name: &NodeName) -> RPCResult<String> {
+    let secrets = context.db_service.get_secrets(name)?;
+    if secrets.is_empty() {
+        return Ok(String::new())
+    }
+    let option = context.config.secrets.as_ref().ok_or(RPCError::FeatureDisabled("Secrets"))?;
+    let variables = secrets
+        .iter()
+        .map(|secret| {
+            let value = option
+                .decrypt(name, &secret.name, &secret.sealed)
+                .map_err(|err| RPCError::Internal(format!("Cannot decrypt the secret {} : {}", secret.name, err)))?;
+            Ok(format!("{}={}", secret.name, value))
+        })
+        .collect::<RPCResult<Vec<String>>>()?;
+    Ok(variables.join(" "))
+}
+
+fn node_get_history(context: Context, args: (NodeName, HistoryGetRequest)) -> RPCResponse<HistoryGetResponse> {
     let (name, req) = args;
+    namespace::check_node(&context, &name)?;
+    let to = req.to_time.unwrap_or_else(chrono::Local::now);
+    let from = req.from_time.unwrap_or_else(|| to - chrono::Duration::days(1));
+    if from >= to {
+        return Err(RPCError::InvalidParams("fromTime should be before toTime".to_string()))
+    }
+    let resolution = req.resolution.unwrap_or_else(|| context.config.metrics_history.resolution_for(&from, &to));
+    let samples = context.db_service.get_metric_samples(resolution, &name, &from, &to)?;
+    response(HistoryGetResponse {
+        resolution,
+        samples,
+    })
+}
+
+fn dashboard_get_events(context: Context, args: (EventGetRequest,)) -> RPCResponse<EventGetResponse> {
+    let (mut req,) = args;
+    if !namespace::visible_nodes(&context)?.restrict(&mut req.node_names) {
+        return response(EventGetResponse {
+            events: Vec::new(),
+            notes: Vec::new(),
+        })
+    }
+    let events = context.db_service.get_events(req)?;
+    event_get_response(&context, events)
+}
+
+/// With a start template, the env and the args of `req` override the variables and the flags of the template.
+/// The node is updated instead if the template has a commit which the node doesn't run.
+fn node_start(
+    context: Context,
+    args: (NodeName, Option<ShellStartCodeChainRequest>, Option<String>),
+) -> RPCResponse<()> {
+    let (name, req, template_name) = args;
+    namespace::check_node(&context, &name)?;
+    let secret_names = context.db_service.get_secret_names(&name)?;
+    let audited_req = req.clone().map(|req| ShellStartCodeChainRequest {
+        env: secrets::redact_env(&req.env, &secret_names),
+        ..req
+    });
+    audit(&context, "node_start", Some(&name), (&audited_req, &template_name));
 
     let agent = context.agent_service.get_agent(name.clone());
     if agent.is_none() {
-        return Err(RPCError::AgentNotFound)
+        return Err(RPCError::AgentNotFound(name))
     }
     let agent = agent.expect("Already checked");
-    agent.shell_start_codechain(req.clone())?;
+    let (env, args, req_binary_path, commit_hash) = match (req, template_name) {
+        (Some(req), None) => (req.env, req.args, req.binary_path, None),
+        (req, Some(template_name)) => {
+            let start_template = context
+                .db_service
+                .get_start_template(&template_name)?
+                .ok_or_else(|| RPCError::NotFound(format!("Start template {}", template_name)))?;
+            let (env, args, binary_path) =
+                req.map(|req| (req.env, req.args, req.binary_path)).unwrap_or_default();
+            (
+                template::merge_env(&start_template.env, &env),
+                template::merge_args(&start_template.args, &args),
+                binary_path,
+                start_template.commit_hash,
+            )
+        }
+        (None, None) => return Err(RPCError::InvalidParams("Either req or template is required".to_string())),
+    };
+    // The node keeps running the pre-installed binary if binaryPath is omitted
+    let binary_path = match req_binary_path {
+        Some(binary_path) => Some(binary_path),
+        None => context.db_service.get_agent_extra(&name)?.and_then(|extra| extra.binary_path),
+    };
+    // The pre-installed binary is not built from the commit
+    let current_hash =
+        context.db_service.get_agent_query_result(&name)?.and_then(|state| state.version).map(|version| version.hash);
+    let commit_hash =
+        commit_hash.filter(|commit_hash| binary_path.is_none() && Some(commit_hash) != current_hash.as_ref());
+    let start_request = render_start_request(&context, &name, &env, &args, binary_path.clone())?;
+    if context.sandbox {
+        return response(())
+    }
+    match commit_hash {
+        Some(commit_hash) => {
+            start_update(&context, "node_start", &name, agent, ShellUpdateCodeChainRequest {
+                env: start_request.env,
+                args: start_request.args,
+                commit_hash,
+                job_id: None,
+            })?;
+        }
+        None => agent.shell_start_codechain(start_request)?,
+    }
 
-    context.db_service.save_start_option(&name, &req.env, &req.args);
+    context.db_service.save_start_option(&name, &env, &args, binary_path);
 
     response(())
 }
 
 fn node_stop(context: Context, args: (String,)) -> RPCResponse<()> {
+    namespace::check_node(&context, &args.0)?;
+    require_approval(&context, "node_stop", &args)?;
     let (name,) = args;
+    audit(&context, "node_stop", Some(&name), ());
 
-    let agent = context.agent_service.get_agent(name);
+    let agent = context.agent_service.get_agent(name.clone());
     if agent.is_none() {
-        return Err(RPCError::AgentNotFound)
+        return Err(RPCError::AgentNotFound(name))
     }
     let agent = agent.expect("Already checked");
+    if context.sandbox {
+        return response(())
+    }
+    stop_desired_state(&context, &name)?;
     agent.shell_stop_codechain()?;
 
     response(())
 }
 
-fn node_update(context: Context, args: (NodeName, CommitHash)) -> RPCResponse<()> {
+/// The reconciliation would start the stopped node again, so the desired state of the node becomes stopped
+fn stop_desired_state(context: &Context, name: &NodeName) -> RPCResult<()> {
+    let running = context
+        .db_service
+        .get_desired_states()?
+        .into_iter()
+        .find(|state| state.node_name == *name && state.status == db::DesiredStatus::Running);
+    if let Some(mut state) = running {
+        cinfo!("The desired state of {} becomes stopped because it is stopped manually", name);
+        state.status = db::DesiredStatus::Stopped;
+        context.db_service.set_desired_state(state);
+    }
+    Ok(())
+}
+
+fn node_get_stop_impact(context: Context, args: (NodeName,)) -> RPCResponse<NodeStopImpact> {
+    let (name,) = args;
+    namespace::check_node(&context, &name)?;
+    response(impact::stop_impact(&context, &name)?)
+}
+
+fn node_update(context: Context, args: (NodeName, CommitHash)) -> RPCResponse<NodeUpdateResponse> {
+    namespace::check_node(&context, &args.0)?;
+    require_approval(&context, "node_update", &args)?;
     let (name, commit_hash) = args;
+    audit(&context, "node_update", Some(&name), &commit_hash);
 
     let agent = context.agent_service.get_agent(name.clone());
     if agent.is_none() {
-        return Err(RPCError::AgentNotFound)
+        return Err(RPCError::AgentNotFound(name))
     }
     let agent = agent.expect("Already checked");
 
-    let extra = context.db_service.get_agent_extra(&name)?;
-    agent.shell_update_codechain(ShellUpdateCodeChainRequest {
-        env: extra.as_ref().map(|extra| extra.prev_env.clone()).unwrap_or("".to_string()),
-        args: extra.as_ref().map(|extra| extra.prev_args.clone()).unwrap_or("".to_string()),
+    if let Some(faults) = &context.faults {
+        if faults.take_update_failure(&name) {
+            return Err(RPCError::Internal(format!("Update of {} failed by the injected fault", name)))
+        }
+    }
+
+    let extra = context.db_service.get_agent_extra(&name)?.unwrap_or_default();
+    let ShellStartCodeChainRequest {
+        env,
+        args,
+        ..
+    } = render_start_request(&context, &name, &extra.prev_env, &extra.prev_args, None)?;
+    if context.sandbox {
+        return response(NodeUpdateResponse {
+            job_id: 0,
+        })
+    }
+    let job_id = start_update(&context, "node_update", &name, agent, ShellUpdateCodeChainRequest {
+        env,
+        args,
         commit_hash,
+        job_id: None,
     })?;
+    // The node runs the binary built from the commit after the update
+    if extra.binary_path.is_some() {
+        context.db_service.save_start_option(&name, &extra.prev_env, &extra.prev_args, None);
+    }
+
+    response(NodeUpdateResponse {
+        job_id,
+    })
+}
+
+/// Starts the job which updates the node. The agents which can install the binary built by the hub don't build
+/// CodeChain themselves. The job is finished when the agent accepts the update, unless the agent reports the progress.
+pub fn start_update(
+    context: &Context,
+    method: &str,
+    name: &NodeName,
+    agent: AgentSender,
+    mut req: ShellUpdateCodeChainRequest,
+) -> RPCResult<u64> {
+    let job_id = context.jobs.create(method, name);
+    let result = match &context.distributor {
+        Some(distributor) if agent.has(AgentCapability::BinaryInstall) => {
+            Distributor::start(distributor, job_id, name.clone(), agent, req).map_err(RPCError::InvalidParams)
+        }
+        _ => {
+            let reports_progress = agent.has(AgentCapability::JobProgress);
+            if reports_progress {
+                req.job_id = Some(job_id);
+            }
+            let cancellation = context.jobs.cancellation(job_id);
+            agent.shell_update_codechain(req, Some(&cancellation)).map(|()| {
+                if !reports_progress {
+                    context.jobs.finish(job_id, Ok(()));
+                }
+            })
+        }
+    };
+    if let Err(err) = &result {
+        context.jobs.finish(job_id, Err(err.to_string()));
+    }
+    result.map(|()| job_id)
+}
+
+/// Tries the RPC of the node first, and restarts the node with the new RUST_LOG if the node doesn't support it.
+/// The level changed by the RPC is reset when the node restarts, but the restart saves it in the start option.
+fn node_set_log_level(context: Context, args: (NodeName, String)) -> RPCResponse<NodeSetLogLevelResponse> {
+    let (name, filter) = args;
+    namespace::check_node(&context, &name)?;
+    audit(&context, "node_setLogLevel", Some(&name), &filter);
+
+    if filter.is_empty() || filter.contains(char::is_whitespace) {
+        return Err(RPCError::InvalidParams(format!("Invalid log filter {:?}", filter)))
+    }
+    let (agent, state) = get_running_agent(&context, &name)?;
+    if context.sandbox {
+        return response(NodeSetLogLevelResponse {
+            changed_by: LogLevelChange::Rpc,
+        })
+    }
+    match CodeChainRPC::new(agent.clone()).set_log_level(state.status, &filter) {
+        Ok(()) => {
+            return response(NodeSetLogLevelResponse {
+                changed_by: LogLevelChange::Rpc,
+            })
+        }
+        Err(err) => cinfo!("Restart {} to change the log level, because the RPC failed : {}", name, err),
+    }
+
+    let extra = context.db_service.get_agent_extra(&name)?.unwrap_or_default();
+    let env = with_rust_log(&extra.prev_env, &filter);
+    let start_request = render_start_request(&context, &name, &env, &extra.prev_args, extra.binary_path.clone())?;
+    agent.shell_stop_codechain()?;
+    agent.shell_start_codechain(start_request)?;
+    context.db_service.save_start_option(&name, &env, &extra.prev_args, extra.binary_path);
+
+    response(NodeSetLogLevelResponse {
+        changed_by: LogLevelChange::Restart,
+    })
+}
+
+fn node_start_sealing(context: Context, args: (NodeName,)) -> RPCResponse<()> {
+    namespace::check_node(&context, &args.0)?;
+    require_approval(&context, "node_startSealing", &args)?;
+    let (name,) = args;
+    audit(&context, "node_startSealing", Some(&name), ());
+    set_sealing(&context, &name, true)
+}
+
+/// Takes the node out of the block production without stopping it, e.g. for a maintenance of a validator.
+/// The node seals again when it restarts.
+fn node_stop_sealing(context: Context, args: (NodeName, Option<bool>)) -> RPCResponse<()> {
+    namespace::check_node(&context, &args.0)?;
+    require_approval(&context, "node_stopSealing", &args)?;
+    let (name, force) = args;
+    audit(&context, "node_stopSealing", Some(&name), force);
+    impact::check_quorum_guard(&context, "node_stopSealing", &[name.clone()], force.unwrap_or(false))?;
+    set_sealing(&context, &name, false)
+}
 
+fn set_sealing(context: &Context, name: &NodeName, sealing: bool) -> RPCResponse<()> {
+    let (agent, state) = get_running_agent(context, name)?;
+    if context.sandbox {
+        return response(())
+    }
+    let codechain_rpc = CodeChainRPC::new(agent);
+    let result = if sealing {
+        codechain_rpc.start_sealing(state.status)
+    } else {
+        codechain_rpc.stop_sealing(state.status)
+    };
+    result.map_err(RPCError::Internal)?;
+    context.db_service.set_sealing(name, sealing);
     response(())
 }
 
+/// Replaces RUST_LOG of the space-separated env, or adds it
+fn with_rust_log(env: &str, filter: &str) -> String {
+    let mut variables: Vec<String> =
+        env.split_whitespace().filter(|variable| !variable.starts_with("RUST_LOG=")).map(str::to_string).collect();
+    variables.push(format!("RUST_LOG={}", filter));
+    variables.join(" ")
+}
+
 fn shell_get_codechain_log(context: Context, args: (String,)) -> RPCResponse<String> {
     let (name,) = args;
+    namespace::check_node(&context, &name)?;
 
-    let agent = context.agent_service.get_agent(name);
+    let agent = context.agent_service.get_agent(name.clone());
     if agent.is_none() {
-        return Err(RPCError::AgentNotFound)
+        return Err(RPCError::AgentNotFound(name))
     }
     let agent = agent.expect("Already checked");
     let result = agent.shell_get_codechain_log()?;
@@ -120,9 +999,1247 @@ fn log_get_targets(context: Context) -> RPCResponse<LogGetTargetsResponse> {
 }
 
 fn log_get(context: Context, args: (LogGetRequest,)) -> RPCResponse<LogGetResponse> {
-    let (req,) = args;
+    let (mut req,) = args;
+    if !namespace::restrict_logs(&context, &mut req)? {
+        return response(LogGetResponse {
+            logs: Vec::new(),
+        })
+    }
     let logs = context.db_service.get_logs(req)?;
     response(LogGetResponse {
         logs,
     })
 }
+
+/// The logs of the same node around the log, whatever the filters of the search which found it
+fn log_get_context(context: Context, args: (i64, Option<i64>, Option<i64>)) -> RPCResponse<db::LogContext> {
+    let (log_id, before, after) = args;
+    let count = |count: Option<i64>| -> RPCResult<i64> {
+        let count = count.unwrap_or(DEFAULT_LOG_CONTEXT);
+        if count < 0 || count > MAX_LOG_CONTEXT {
+            return Err(RPCError::InvalidParams(format!("The number of the logs should be 0 to {}", MAX_LOG_CONTEXT)))
+        }
+        Ok(count)
+    };
+    let (before, after) = (count(before)?, count(after)?);
+    let not_found = || RPCError::NotFound(format!("Log {}", log_id));
+    let log_context = context.db_service.get_log_context(log_id, before, after)?.ok_or_else(not_found)?;
+    if !namespace::visible_nodes(&context)?.contains(&log_context.log.node_name) {
+        return Err(not_found())
+    }
+    response(log_context)
+}
+
+fn log_set_rule(context: Context, args: (db::LogRule,)) -> RPCResponse<()> {
+    let (rule,) = args;
+    namespace::require_admin(&context, "log_setRule")?;
+    audit(&context, "log_setRule", None, &rule);
+
+    if rule.name.is_empty() {
+        return Err(RPCError::InvalidParams("The name of the log rule is empty".to_string()))
+    }
+    if rule.pattern.is_empty() {
+        return Err(RPCError::InvalidParams("The pattern of the log rule is empty".to_string()))
+    }
+    if !context.sandbox {
+        context.db_service.set_log_rule(rule);
+    }
+    response(())
+}
+
+fn log_remove_rule(context: Context, args: (String,)) -> RPCResponse<()> {
+    let (name,) = args;
+    namespace::require_admin(&context, "log_removeRule")?;
+    audit(&context, "log_removeRule", None, &name);
+
+    if !context.sandbox {
+        context.db_service.remove_log_rule(name);
+    }
+    response(())
+}
+
+fn log_get_rules(context: Context) -> RPCResponse<Vec<db::LogRule>> {
+    response(context.db_service.get_log_rules()?)
+}
+
+fn network_connect(context: Context, args: (NodeName, NodeName)) -> RPCResponse<()> {
+    let (name_a, name_b) = args;
+    namespace::check_node(&context, &name_a)?;
+    namespace::check_node(&context, &name_b)?;
+    audit(&context, "network_connect", Some(&name_a), &name_b);
+    connect_nodes(&context, &name_a, &name_b)?;
+    response(())
+}
+
+fn network_disconnect(context: Context, args: (NodeName, NodeName)) -> RPCResponse<()> {
+    let (name_a, name_b) = args;
+    namespace::check_node(&context, &name_a)?;
+    namespace::check_node(&context, &name_b)?;
+    audit(&context, "network_disconnect", Some(&name_a), &name_b);
+    disconnect_nodes(&context, &name_a, &name_b)?;
+    response(())
+}
+
+/// Finds the managed nodes related to a name, a node key, an IP address or a socket address.
+/// When the address belongs to an unmanaged peer, the managed nodes connected to the peer are returned.
+/// The node keys are asked to the running nodes only when the query is not an address.
+fn node_find(context: Context, args: (String,)) -> RPCResponse<Vec<NodeFindResult>> {
+    let (query,) = args;
+    let query = query.trim();
+    let visible = namespace::visible_nodes(&context)?;
+    let agents_state: Vec<db::AgentQueryResult> =
+        context.db_service.get_agents_state()?.into_iter().filter(|state| visible.contains(&state.name)).collect();
+
+    let is_address = query.parse::<SocketAddr>().is_ok() || query.parse::<IpAddr>().is_ok();
+    let is_matched_address: Box<Fn(&SocketAddr) -> bool> = if let Ok(socket_addr) = query.parse::<SocketAddr>() {
+        Box::new(move |addr: &SocketAddr| *addr == socket_addr)
+    } else if let Ok(ip) = query.parse::<IpAddr>() {
+        Box::new(move |addr: &SocketAddr| addr.ip() == ip)
+    } else {
+        Box::new(|_: &SocketAddr| false)
+    };
+
+    let lowercase_query = query.to_lowercase();
+    let mut results = Vec::new();
+    for state in &agents_state {
+        if state.address.as_ref().map(|address| is_matched_address(address)).unwrap_or(false) {
+            results.push(NodeFindResult {
+                name: state.name.clone(),
+                address: state.address,
+                matched_by: NodeFindMatch::Address,
+                matched_peer: None,
+            });
+        } else if !lowercase_query.is_empty() && state.name.to_lowercase().contains(&lowercase_query) {
+            results.push(NodeFindResult {
+                name: state.name.clone(),
+                address: state.address,
+                matched_by: NodeFindMatch::Name,
+                matched_peer: None,
+            });
+        } else if !is_address && !lowercase_query.is_empty() && is_node_key(&context, state, &lowercase_query) {
+            results.push(NodeFindResult {
+                name: state.name.clone(),
+                address: state.address,
+                matched_by: NodeFindMatch::NodeKey,
+                matched_peer: None,
+            });
+        }
+    }
+
+    if results.iter().all(|result| result.matched_by != NodeFindMatch::Address) {
+        for state in &agents_state {
+            if let Some(peer) = state.peers.iter().find(|peer| is_matched_address(*peer)) {
+                results.push(NodeFindResult {
+                    name: state.name.clone(),
+                    address: state.address,
+                    matched_by: NodeFindMatch::Peer,
+                    matched_peer: Some(*peer),
+                });
+            }
+        }
+    }
+
+    response(results)
+}
+
+/// The node key is the coinbase, which the logs print as the author of the blocks
+fn is_node_key(context: &Context, state: &db::AgentQueryResult, lowercase_query: &str) -> bool {
+    if state.status != NodeStatus::Run {
+        return false
+    }
+    let agent = match context.agent_service.get_agent(state.name.clone()) {
+        Some(agent) => agent,
+        None => return false,
+    };
+    match CodeChainRPC::new(agent).get_coinbase(state.status) {
+        Ok(Some(coinbase)) => coinbase.to_lowercase() == lowercase_query,
+        Ok(None) => false,
+        Err(err) => {
+            cdebug!("Cannot get the coinbase of {} : {}", state.name, err);
+            false
+        }
+    }
+}
+
+fn node_add_tags(context: Context, args: (NodeName, Vec<String>)) -> RPCResponse<()> {
+    let (name, tags) = args;
+    namespace::check_node(&context, &name)?;
+    namespace::check_tags(&context, &tags)?;
+    audit(&context, "node_addTags", Some(&name), &tags);
+    if !context.sandbox {
+        context.db_service.add_tags(&name, tags);
+    }
+    response(())
+}
+
+fn node_remove_tags(context: Context, args: (NodeName, Vec<String>)) -> RPCResponse<()> {
+    let (name, tags) = args;
+    namespace::check_node(&context, &name)?;
+    namespace::check_tags(&context, &tags)?;
+    audit(&context, "node_removeTags", Some(&name), &tags);
+    if !context.sandbox {
+        context.db_service.remove_tags(&name, tags);
+    }
+    response(())
+}
+
+fn dashboard_get_network_by_tags(context: Context, args: (Vec<String>,)) -> RPCResponse<DashboardGetNetworkResponse> {
+    let (tags,) = args;
+    let visible = namespace::visible_nodes(&context)?;
+    let mut names = context.db_service.get_node_names_by_tags(tags)?;
+    names.retain(|name| visible.contains(name));
+    let agents_state = context.db_service.get_agents_state()?;
+    let connections = context.db_service.get_connections()?;
+    let dashboard_nodes: Vec<DashboardNode> =
+        agents_state.iter().map(|agent| DashboardNode::from_db_state(agent)).collect();
+    let seq = context.network_history.record(&dashboard_nodes, &connections);
+    response(DashboardGetNetworkResponse {
+        epoch: context.network_history.epoch(),
+        seq,
+        nodes: dashboard_nodes.into_iter().filter(|node| names.contains(node.name())).collect(),
+        connections: connections
+            .iter()
+            .filter(|(node_a, node_b)| names.contains(node_a) && names.contains(node_b))
+            .map(|connection| NodeConnection::from_connection(connection))
+            .collect(),
+    })
+}
+
+/// Starts every tagged node with its previous start option.
+fn node_start_by_tags(context: Context, args: (Vec<String>,)) -> RPCResponse<Vec<NodeBulkResult>> {
+    require_approval(&context, "node_startByTags", &args)?;
+    let (tags,) = args;
+    audit(&context, "node_startByTags", None, &tags);
+    let visible = namespace::visible_nodes(&context)?;
+    let mut names = context.db_service.get_node_names_by_tags(tags)?;
+    names.retain(|name| visible.contains(name));
+    response(run_bulk(names, |name| {
+        let agent =
+            context.agent_service.get_agent(name.clone()).ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+        let extra = context.db_service.get_agent_extra(name)?.unwrap_or_default();
+        let start_request = render_start_request(&context, name, &extra.prev_env, &extra.prev_args, extra.binary_path)?;
+        if context.sandbox {
+            return Ok(())
+        }
+        agent.shell_start_codechain(start_request)?;
+        Ok(())
+    }))
+}
+
+/// Refused by the quorum guard when it stops too many validators, unless `force` is set by an allowed caller.
+fn node_stop_by_tags(context: Context, args: (Vec<String>, Option<bool>)) -> RPCResponse<Vec<NodeBulkResult>> {
+    require_approval(&context, "node_stopByTags", &args)?;
+    let (tags, force) = args;
+    audit(&context, "node_stopByTags", None, (&tags, force));
+    let visible = namespace::visible_nodes(&context)?;
+    let mut names = context.db_service.get_node_names_by_tags(tags)?;
+    names.retain(|name| visible.contains(name));
+    impact::check_quorum_guard(&context, "node_stopByTags", &names, force.unwrap_or(false))?;
+    response(run_bulk(names, |name| {
+        let agent =
+            context.agent_service.get_agent(name.clone()).ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+        if context.sandbox {
+            return Ok(())
+        }
+        stop_desired_state(&context, name)?;
+        agent.shell_stop_codechain()
+    }))
+}
+
+/// Resolves the variables of the saved start option with what the hub knows at this moment.
+/// `binary_path` is None when the node runs the binary built from the commit.
+pub fn render_start_request(
+    context: &Context,
+    name: &NodeName,
+    env: &str,
+    args: &str,
+    binary_path: Option<String>,
+) -> RPCResult<ShellStartCodeChainRequest> {
+    let agents = context.db_service.get_agents_state()?;
+    if binary_path.is_some() {
+        let protocol_version = agents
+            .iter()
+            .find(|agent| agent.name == *name)
+            .and_then(|agent| agent.agent_version.as_ref())
+            .map(|agent_version| agent_version.protocol_version)
+            .unwrap_or_default();
+        if protocol_version < protocol::BINARY_PATH_PROTOCOL_VERSION {
+            return Err(RPCError::InvalidParams(format!("The agent of {} can't run a pre-installed binary", name)))
+        }
+    }
+    // The bootnodes are the nodes of the same network
+    let network = namespace::namespace_of(&context.db_service, name)?;
+    let mut network_agents = Vec::new();
+    for agent in agents {
+        if namespace::namespace_of(&context.db_service, &agent.name)? == network {
+            network_agents.push(agent);
+        }
+    }
+    let render = |template: &str| template::render(template, name, &network_agents).map_err(RPCError::InvalidParams);
+    let (env, args) = (render(env)?, render(args)?);
+    let env = template::merge_env(&env, &secret_env(context, name)?);
+    start_policy::check(&context.config.frontend.start_policy, &env, &args, binary_path.as_ref().map(String::as_str))
+        .map_err(|rejection| RPCError::StartRequestRejected(name.clone(), rejection))?;
+    Ok(ShellStartCodeChainRequest {
+        env,
+        args,
+        binary_path,
+    })
+}
+
+/// Applies the patch to all the selected nodes together, or to none of them if it fails.
+/// Nothing is changed if `preview` is set.
+fn node_bulk_edit(
+    context: Context,
+    args: (NodeSelector, NodeEditPatch, Option<bool>),
+) -> RPCResponse<NodeBulkEditResponse> {
+    namespace::check_tags(&context, &args.1.add_tags)?;
+    namespace::check_tags(&context, &args.1.remove_tags)?;
+    if args.1.namespace.is_some() {
+        namespace::require_admin(&context, "Changing the namespace")?;
+    }
+    if args.2 != Some(true) {
+        require_approval(&context, "node_bulkEdit", &args)?;
+    }
+    let (selector, patch, preview) = args;
+    let preview = preview.unwrap_or(false);
+    if !preview {
+        audit(&context, "node_bulkEdit", None, (&selector, &patch));
+    }
+    let mut nodes = Vec::new();
+    let mut edits = Vec::new();
+    for name in select_nodes(&context, &selector)? {
+        let tags_before = context.db_service.get_tags(&name)?;
+        let tags_after = patch_tags(&tags_before, &patch);
+        let maintenance_before = context.db_service.is_in_maintenance(&name);
+        edits.push(db::NodeEdit {
+            node_name: name.clone(),
+            add_tags: tags_after.iter().filter(|tag| !tags_before.contains(tag)).cloned().collect(),
+            remove_tags: tags_before.iter().filter(|tag| !tags_after.contains(tag)).cloned().collect(),
+            maintenance: patch.maintenance,
+        });
+        nodes.push(NodeEditResult {
+            name,
+            tags_before,
+            tags_after,
+            maintenance_before,
+            maintenance_after: patch.maintenance.unwrap_or(maintenance_before),
+        });
+    }
+    if !preview && !context.sandbox {
+        context.db_service.edit_nodes(edits)?;
+    }
+    response(NodeBulkEditResponse {
+        preview,
+        nodes,
+    })
+}
+
+/// Fails if a node given by its name doesn't exist
+fn select_nodes(context: &Context, selector: &NodeSelector) -> RPCResult<Vec<NodeName>> {
+    if selector.names.is_empty() && selector.tags.is_empty() {
+        return Err(RPCError::InvalidParams("The selector should have names or tags".to_string()))
+    }
+    let visible = namespace::visible_nodes(context)?;
+    let mut names = Vec::new();
+    for name in &selector.names {
+        if context.db_service.get_agent_query_result(name)?.is_none() || !visible.contains(name) {
+            return Err(RPCError::AgentNotFound(name.clone()))
+        }
+        names.push(name.clone());
+    }
+    for name in context.db_service.get_node_names_by_tags(selector.tags.clone())? {
+        if !names.contains(&name) && visible.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+fn patch_tags(tags: &[String], patch: &NodeEditPatch) -> Vec<String> {
+    let mut added = patch.add_tags.clone();
+    let mut replaced_prefixes = Vec::new();
+    if let Some(owner) = &patch.owner {
+        added.push(format!("{}{}", OWNER_TAG_PREFIX, owner));
+        replaced_prefixes.push(OWNER_TAG_PREFIX);
+    }
+    if let Some(region) = &patch.region {
+        added.push(format!("{}{}", outage::REGION_TAG_PREFIX, region));
+        replaced_prefixes.push(outage::REGION_TAG_PREFIX);
+    }
+    if let Some(namespace_name) = &patch.namespace {
+        added.push(format!("{}{}", namespace::NAMESPACE_TAG_PREFIX, namespace_name));
+        replaced_prefixes.push(namespace::NAMESPACE_TAG_PREFIX);
+    }
+
+    let mut patched: Vec<String> = tags
+        .iter()
+        .filter(|tag| !patch.remove_tags.contains(tag))
+        .filter(|tag| !replaced_prefixes.iter().any(|prefix| tag.starts_with(prefix)))
+        .cloned()
+        .collect();
+    for tag in added {
+        if !patched.contains(&tag) {
+            patched.push(tag);
+        }
+    }
+    patched.sort();
+    patched
+}
+
+fn run_bulk<F>(names: Vec<NodeName>, f: F) -> Vec<NodeBulkResult>
+where
+    F: Fn(&NodeName) -> RPCResult<()>, {
+    names
+        .into_iter()
+        .map(|name| {
+            let error = f(&name).err().map(|err| err.to_string());
+            NodeBulkResult {
+                name,
+                error,
+            }
+        })
+        .collect()
+}
+
+/// The callers in a namespace see only the audits of their nodes
+fn audit_get(context: Context, args: (AuditGetRequest,)) -> RPCResponse<AuditGetResponse> {
+    let (mut req,) = args;
+    let visible = namespace::visible_nodes(&context)?;
+    let filter = req.filter.get_or_insert_with(Default::default);
+    if !visible.restrict(&mut filter.node_names) {
+        return response(AuditGetResponse {
+            audits: Vec::new(),
+        })
+    }
+    let audits = context.db_service.get_audits(req)?;
+    response(AuditGetResponse {
+        audits,
+    })
+}
+
+fn debug_inject_fault(context: Context, args: (Fault,)) -> RPCResponse<()> {
+    let (fault,) = args;
+    namespace::require_admin(&context, "debug_injectFault")?;
+    let faults = context.faults.clone().ok_or(RPCError::FeatureDisabled("Fault injection"))?;
+    if let Fault::DelayDbQueries {
+        delay_ms,
+        duration_ms,
+    } = fault
+    {
+        let max_ms = MAX_CHAOS_DURATION_SECS * 1000;
+        if delay_ms > max_ms || duration_ms > max_ms {
+            return Err(RPCError::InvalidParams(format!("The delay and the duration should be at most {} ms", max_ms)))
+        }
+    }
+    audit(&context, "debug_injectFault", None, &fault);
+    if context.sandbox {
+        return response(())
+    }
+    cwarn!("Fault injected {:?}", fault);
+
+    match fault {
+        Fault::DropAgentConnection {
+            name,
+        } => {
+            let agent = context.agent_service.get_agent(name.clone()).ok_or(RPCError::AgentNotFound(name))?;
+            agent.drop_connection().map_err(RPCError::Internal)?;
+        }
+        Fault::DelayDbQueries {
+            delay_ms,
+            duration_ms,
+        } => {
+            let (delay, duration) = (Duration::from_millis(delay_ms), Duration::from_millis(duration_ms));
+            context.db_service.inject_query_delay(delay, duration).map_err(RPCError::InvalidParams)?;
+        }
+        Fault::FailNextUpdate {
+            name,
+        } => {
+            faults.fail_next_update(name);
+        }
+    }
+    response(())
+}
+
+/// The chaos commands need the fault injection enabled, and only the admins run them
+fn check_chaos(context: &Context, method: &str, duration_secs: Option<u64>) -> RPCResult<()> {
+    namespace::require_admin(context, method)?;
+    context.faults.as_ref().ok_or(RPCError::FeatureDisabled("Fault injection"))?;
+    if let Some(duration_secs) = duration_secs {
+        if duration_secs == 0 || duration_secs > MAX_CHAOS_DURATION_SECS {
+            return Err(RPCError::InvalidParams(format!(
+                "The duration should be between 1 and {} seconds",
+                MAX_CHAOS_DURATION_SECS
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn chaos_kill(context: Context, args: (NodeName,)) -> RPCResponse<()> {
+    let (name,) = args;
+    check_chaos(&context, "chaos_kill", None)?;
+    audit(&context, "chaos_kill", Some(&name), ());
+    let agent = context.agent_service.get_agent(name.clone()).ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+    if context.sandbox {
+        return response(())
+    }
+    cwarn!("Chaos: killing {}", name);
+    // The desired state is kept, because the recovery by the reconciliation is a part of the experiment
+    let desired_states = context.db_service.get_desired_states()?;
+    if desired_states.iter().any(|state| state.node_name == name && state.status == db::DesiredStatus::Running) {
+        cwarn!("Chaos: {} should be running, so the reconciliation will start it again", name);
+    }
+    agent.chaos_kill()?;
+    context.db_service.write_event(&name, db::NodeEventKind::ChaosInjected, json!({ "action": "kill" }));
+    response(())
+}
+
+fn chaos_pause(context: Context, args: (NodeName, u64)) -> RPCResponse<()> {
+    let (name, duration_secs) = args;
+    check_chaos(&context, "chaos_pause", Some(duration_secs))?;
+    audit(&context, "chaos_pause", Some(&name), duration_secs);
+    let agent = context.agent_service.get_agent(name.clone()).ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+    if context.sandbox {
+        return response(())
+    }
+    cwarn!("Chaos: pausing {} for {} seconds", name, duration_secs);
+    agent.chaos_pause(ChaosPauseRequest {
+        duration_secs,
+    })?;
+    let detail = json!({ "action": "pause", "durationSecs": duration_secs });
+    context.db_service.write_event(&name, db::NodeEventKind::ChaosInjected, detail);
+    response(())
+}
+
+/// Each node of a group blocks the nodes of the other group, so the nodes in the same group still see each other
+fn chaos_partition(
+    context: Context,
+    args: (Vec<NodeName>, Vec<NodeName>, u64),
+) -> RPCResponse<Vec<NodeBulkResult>> {
+    let (group_a, group_b, duration_secs) = args;
+    check_chaos(&context, "chaos_partition", Some(duration_secs))?;
+    audit(&context, "chaos_partition", None, (&group_a, &group_b, duration_secs));
+    if group_a.is_empty() || group_b.is_empty() {
+        return Err(RPCError::InvalidParams("The groups should not be empty".to_string()))
+    }
+    if let Some(name) = group_a.iter().find(|name| group_b.contains(name)) {
+        return Err(RPCError::InvalidParams(format!("{} is in both groups", name)))
+    }
+    let agents = context.db_service.get_agents_state()?;
+    let addresses_of = |group: &[NodeName]| -> RPCResult<Vec<SocketAddr>> {
+        group
+            .iter()
+            .map(|name| {
+                agents
+                    .iter()
+                    .find(|agent| agent.name == *name)
+                    .ok_or_else(|| RPCError::AgentNotFound(name.clone()))?
+                    .address
+                    .ok_or_else(|| RPCError::InvalidParams(format!("The address of {} is unknown", name)))
+            })
+            .collect()
+    };
+    let (addresses_a, addresses_b) = (addresses_of(&group_a)?, addresses_of(&group_b)?);
+    cwarn!("Chaos: partitioning {:?} and {:?} for {} seconds", group_a, group_b, duration_secs);
+
+    let mut results = Vec::new();
+    for (group, addresses) in &[(&group_a, &addresses_b), (&group_b, &addresses_a)] {
+        results.extend(run_bulk(group.to_vec(), |name| {
+            let agent =
+                context.agent_service.get_agent(name.clone()).ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+            if context.sandbox {
+                return Ok(())
+            }
+            agent.chaos_block_peers(ChaosBlockPeersRequest {
+                addresses: addresses.to_vec(),
+                duration_secs,
+            })?;
+            let detail = json!({ "action": "partition", "blocked": addresses, "durationSecs": duration_secs });
+            context.db_service.write_event(name, db::NodeEventKind::ChaosInjected, detail);
+            Ok(())
+        }));
+    }
+    response(results)
+}
+
+fn slo_set(context: Context, args: (db::Slo,)) -> RPCResponse<()> {
+    let (slo,) = args;
+    namespace::require_admin(&context, "slo_set")?;
+    audit(&context, "slo_set", None, &slo);
+
+    if !(slo.target > 0.0 && slo.target < 1.0) {
+        return Err(RPCError::InvalidParams("The target should be between 0 and 1".to_string()))
+    }
+    if slo.window_minutes <= 0 {
+        return Err(RPCError::InvalidParams("The window should be positive".to_string()))
+    }
+    if !context.sandbox {
+        context.db_service.set_slo(slo);
+    }
+    response(())
+}
+
+fn slo_remove(context: Context, args: (String,)) -> RPCResponse<()> {
+    let (name,) = args;
+    namespace::require_admin(&context, "slo_remove")?;
+    audit(&context, "slo_remove", None, &name);
+
+    if !context.sandbox {
+        context.db_service.remove_slo(name);
+    }
+    response(())
+}
+
+fn template_set(context: Context, args: (db::StartTemplate,)) -> RPCResponse<()> {
+    let (start_template,) = args;
+    namespace::require_admin(&context, "template_set")?;
+    audit(&context, "template_set", None, &start_template);
+
+    if start_template.name.is_empty() {
+        return Err(RPCError::InvalidParams("The name of the start template is empty".to_string()))
+    }
+    if !context.sandbox {
+        context.db_service.set_start_template(start_template);
+    }
+    response(())
+}
+
+fn template_remove(context: Context, args: (String,)) -> RPCResponse<()> {
+    let (name,) = args;
+    namespace::require_admin(&context, "template_remove")?;
+    audit(&context, "template_remove", None, &name);
+
+    if !context.sandbox {
+        context.db_service.remove_start_template(name);
+    }
+    response(())
+}
+
+fn template_list(context: Context) -> RPCResponse<Vec<db::StartTemplate>> {
+    response(context.db_service.get_start_templates()?)
+}
+
+fn slo_get_status(context: Context) -> RPCResponse<Vec<db::SloStatus>> {
+    namespace::require_admin(&context, "slo_getStatus")?;
+    response(context.db_service.get_slo_statuses()?)
+}
+
+/// Refuses to remove the node while its agent is connected, unless `force` is set.
+/// A forced removal closes the agent connection, but the node appears again if the agent reconnects.
+fn node_remove(context: Context, args: (NodeName, Option<bool>)) -> RPCResponse<()> {
+    let (name, force) = args;
+    namespace::check_node(&context, &name)?;
+    audit(&context, "node_remove", Some(&name), force);
+
+    let is_known = context.db_service.get_agent_query_result(&name)?.is_some()
+        || context.db_service.get_agent_extra(&name)?.is_some();
+    if !is_known {
+        return Err(RPCError::AgentNotFound(name))
+    }
+    if let Some(agent) = context.agent_service.get_agent(name.clone()) {
+        if !force.unwrap_or(false) {
+            return Err(RPCError::InvalidParams(format!("The agent of {} is still connected", name)))
+        }
+        if context.sandbox {
+            return response(())
+        }
+        agent.close("The node is removed").map_err(RPCError::Internal)?;
+    }
+    if !context.sandbox {
+        context.db_service.archive_agent(&name)?;
+    }
+    response(())
+}
+
+fn node_rename(context: Context, args: (NodeName, NodeName)) -> RPCResponse<()> {
+    let (name, new_name) = args;
+    namespace::check_node(&context, &name)?;
+    audit(&context, "node_rename", Some(&name), &new_name);
+
+    if new_name.is_empty() || new_name == name {
+        return Err(RPCError::InvalidParams(format!("Cannot rename {} to {:?}", name, new_name)))
+    }
+    let is_known = context.db_service.get_agent_query_result(&name)?.is_some()
+        || context.db_service.get_agent_extra(&name)?.is_some();
+    if !is_known {
+        return Err(RPCError::AgentNotFound(name))
+    }
+    // An agent which reports the new name is translated to another node
+    let resolved = context.db_service.resolve_name(&new_name);
+    let is_taken = context.db_service.get_agent_query_result(&new_name)?.is_some()
+        || context.db_service.get_agent_extra(&new_name)?.is_some()
+        || (resolved != new_name && resolved != name);
+    if is_taken {
+        return Err(RPCError::InvalidParams(format!("{} is already used", new_name)))
+    }
+    let resealed = reseal_secrets(&context, &name, &new_name)?;
+    if context.sandbox {
+        return response(())
+    }
+
+    context.db_service.rename_agent(&name, &new_name, resealed)?;
+    if let Some(agent) = context.agent_service.get_agent(name) {
+        agent.rename(&new_name);
+    }
+    response(())
+}
+
+/// The secrets are bound to the node name, so they are sealed again for the new name
+fn reseal_secrets(context: &Context, name: &NodeName, new_name: &NodeName) -> RPCResult<Vec<db::NodeSecret>> {
+    let secrets = context.db_service.get_secrets(name)?;
+    if secrets.is_empty() {
+        return Ok(Vec::new())
+    }
+    let option = context.config.secrets.as_ref().ok_or(RPCError::FeatureDisabled("Secrets"))?;
+    secrets
+        .into_iter()
+        .map(|secret| {
+            let sealed = option
+                .decrypt(name, &secret.name, &secret.sealed)
+                .and_then(|value| option.encrypt(new_name, &secret.name, &value))
+                .map_err(|err| RPCError::Internal(format!("Cannot seal the secret {} again : {}", secret.name, err)))?;
+            Ok(db::NodeSecret {
+                node_name: new_name.clone(),
+                name: secret.name,
+                sealed,
+            })
+        })
+        .collect()
+}
+
+fn node_list_archived(context: Context) -> RPCResponse<Vec<db::ArchivedNode>> {
+    namespace::require_admin(&context, "node_listArchived")?;
+    response(context.db_service.get_archived_agents()?)
+}
+
+fn report_changes(context: Context, args: (chrono::DateTime<chrono::Local>,)) -> RPCResponse<ChangeReport> {
+    let (since,) = args;
+    namespace::require_admin(&context, "report_changes")?;
+    response(report::changes(&context, since)?)
+}
+
+fn schedule_create(context: Context, args: (db::ScheduleKind, String, NodeName)) -> RPCResponse<db::Schedule> {
+    let (kind, cron, node_name) = args;
+    namespace::check_node(&context, &node_name)?;
+    audit(&context, "schedule_create", Some(&node_name), (kind, &cron));
+    schedule::parse_cron(&cron).map_err(RPCError::InvalidParams)?;
+    if context.db_service.get_agent_query_result(&node_name)?.is_none() {
+        return Err(RPCError::AgentNotFound(node_name))
+    }
+    if kind == db::ScheduleKind::VerifySnapshot {
+        if let Some(agent) = context.agent_service.get_agent(node_name.clone()) {
+            agent.require(AgentCapability::Snapshot)?;
+        }
+    }
+    // The schedule which isn't saved has no id
+    if context.sandbox {
+        return response(db::Schedule {
+            id: 0,
+            kind,
+            cron,
+            node_name,
+            created_at: chrono::Local::now(),
+        })
+    }
+    response(context.db_service.create_schedule(db::ScheduleEntry {
+        kind,
+        cron,
+        node_name,
+    })?)
+}
+
+fn schedule_remove(context: Context, args: (i32,)) -> RPCResponse<()> {
+    let (id,) = args;
+    if let Some(schedule) = context.db_service.get_schedules()?.into_iter().find(|schedule| schedule.id == id) {
+        namespace::check_node(&context, &schedule.node_name)
+            .map_err(|_| RPCError::NotFound(format!("Schedule {}", id)))?;
+    }
+    audit(&context, "schedule_remove", None, id);
+    let is_removed = if context.sandbox {
+        context.db_service.get_schedules()?.iter().any(|schedule| schedule.id == id)
+    } else {
+        context.db_service.remove_schedule(id)?
+    };
+    if !is_removed {
+        return Err(RPCError::NotFound(format!("Schedule {}", id)))
+    }
+    response(())
+}
+
+fn schedule_list(context: Context) -> RPCResponse<Vec<db::Schedule>> {
+    let visible = namespace::visible_nodes(&context)?;
+    let mut schedules = context.db_service.get_schedules()?;
+    schedules.retain(|schedule| visible.contains(&schedule.node_name));
+    response(schedules)
+}
+
+fn schedule_get_history(context: Context, args: (db::ScheduleRunQueryParams,)) -> RPCResponse<Vec<db::ScheduleRun>> {
+    let (req,) = args;
+    let visible = namespace::visible_nodes(&context)?;
+    let mut runs = context.db_service.get_schedule_runs(req)?;
+    runs.retain(|run| visible.contains(&run.node_name));
+    response(runs)
+}
+
+/// Attaches the note to the event when the event id is given, or to the node otherwise
+fn note_add(context: Context, args: (NodeName, String, Option<i32>)) -> RPCResponse<db::Note> {
+    let (node_name, text, event_id) = args;
+    namespace::check_node(&context, &node_name)?;
+    audit(&context, "note_add", Some(&node_name), (&text, event_id));
+    if text.trim().is_empty() {
+        return Err(RPCError::InvalidParams("The note is empty".to_string()))
+    }
+    if text.chars().count() > MAX_NOTE_LENGTH {
+        return Err(RPCError::InvalidParams(format!("The note is longer than {} characters", MAX_NOTE_LENGTH)))
+    }
+    if let Some(event_id) = event_id {
+        if context.db_service.get_event_node_name(event_id)?.as_ref() != Some(&node_name) {
+            return Err(RPCError::NotFound(format!("Event {}", event_id)))
+        }
+    }
+    // The note which isn't saved has no id
+    if context.sandbox {
+        return response(db::Note {
+            id: 0,
+            node_name,
+            event_id,
+            author: context.identity.clone(),
+            text,
+            created_at: chrono::Local::now(),
+        })
+    }
+    response(context.db_service.add_note(db::NoteEntry {
+        node_name,
+        event_id,
+        author: context.identity.clone(),
+        text,
+    })?)
+}
+
+fn note_remove(context: Context, args: (i32,)) -> RPCResponse<()> {
+    let (id,) = args;
+    let note = context.db_service.get_note(id)?.ok_or_else(|| RPCError::NotFound(format!("Note {}", id)))?;
+    namespace::check_node(&context, &note.node_name).map_err(|_| RPCError::NotFound(format!("Note {}", id)))?;
+    audit(&context, "note_remove", Some(&note.node_name), id);
+    if !context.sandbox && !context.db_service.remove_note(id)? {
+        return Err(RPCError::NotFound(format!("Note {}", id)))
+    }
+    response(())
+}
+
+fn network_export(context: Context) -> RPCResponse<NetworkScenario> {
+    let visible = namespace::visible_nodes(&context)?;
+    let mut agents_state = context.db_service.get_agents_state()?;
+    agents_state.retain(|state| visible.contains(&state.name));
+    let mut connections = context.db_service.get_connections()?;
+    connections.retain(|(node_a, node_b)| visible.contains(node_a) && visible.contains(node_b));
+
+    let mut nodes = Vec::new();
+    for state in agents_state {
+        let extra = context.db_service.get_agent_extra(&state.name)?;
+        let binary_path = extra.as_ref().and_then(|extra| extra.binary_path.clone());
+        // The commit of a pre-installed binary can't be built
+        let commit_hash = state
+            .version
+            .map(|version| version.hash)
+            .filter(|hash| !hash.is_empty() && binary_path.is_none());
+        nodes.push(ScenarioNode {
+            env: extra.as_ref().map(|extra| extra.prev_env.clone()).unwrap_or_default(),
+            args: extra.as_ref().map(|extra| extra.prev_args.clone()).unwrap_or_default(),
+            name: state.name,
+            commit_hash,
+            binary_path,
+        });
+    }
+
+    response(NetworkScenario {
+        nodes,
+        connections: connections.iter().map(|connection| NodeConnection::from_connection(connection)).collect(),
+        exported_by: Some((*context.build_info).clone()),
+    })
+}
+
+/// Brings the network to the state described by the scenario.
+/// Nodes which are started by this call are not running yet when the connections are wired,
+/// so calling network_apply again after they are up is the way to finish the wiring.
+/// Refused by the quorum guard when it restarts or updates too many validators,
+/// unless `force` is set by an allowed caller.
+fn network_apply(context: Context, args: (NetworkScenario, Option<bool>)) -> RPCResponse<NetworkApplyResponse> {
+    for node in &args.0.nodes {
+        namespace::check_node(&context, &node.name)?;
+    }
+    for connection in &args.0.connections {
+        namespace::check_node(&context, &connection.node_a)?;
+        namespace::check_node(&context, &connection.node_b)?;
+    }
+    require_approval(&context, "network_apply", &args)?;
+    let (scenario, force) = args;
+    audit(&context, "network_apply", None, (&scenario, force));
+
+    let mut restarting = Vec::new();
+    for node in &scenario.nodes {
+        if let Some(state) = context.db_service.get_agent_query_result(&node.name)? {
+            let extra = context.db_service.get_agent_extra(&node.name)?;
+            match scenario_node_action(&state, extra.as_ref(), node) {
+                NetworkApplyAction::Restarted | NetworkApplyAction::Updated => restarting.push(node.name.clone()),
+                _ => {}
+            }
+        }
+    }
+    impact::check_quorum_guard(&context, "network_apply", &restarting, force.unwrap_or(false))?;
+
+    let nodes = scenario
+        .nodes
+        .iter()
+        .map(|node| match apply_scenario_node(&context, node) {
+            Ok(action) => NetworkApplyNodeResult {
+                name: node.name.clone(),
+                action: Some(action),
+                error: None,
+            },
+            Err(err) => NetworkApplyNodeResult {
+                name: node.name.clone(),
+                action: None,
+                error: Some(err.to_string()),
+            },
+        })
+        .collect();
+
+    let is_same_connection = |connection: &NodeConnection, (a, b): &(NodeName, NodeName)| {
+        (connection.node_a == *a && connection.node_b == *b) || (connection.node_a == *b && connection.node_b == *a)
+    };
+    let current_connections = context.db_service.get_connections()?;
+
+    let mut connections = Vec::new();
+    for desired in &scenario.connections {
+        if current_connections.iter().any(|current| is_same_connection(desired, current)) {
+            continue
+        }
+        let result = connect_nodes(&context, &desired.node_a, &desired.node_b);
+        connections.push(connection_apply_result(
+            &desired.node_a,
+            &desired.node_b,
+            NetworkApplyAction::Connected,
+            result,
+        ));
+    }
+
+    let in_scenario = |name: &NodeName| scenario.nodes.iter().any(|node| node.name == *name);
+    for current in &current_connections {
+        let (a, b) = current;
+        if !in_scenario(a) || !in_scenario(b) {
+            continue
+        }
+        if scenario.connections.iter().any(|desired| is_same_connection(desired, current)) {
+            continue
+        }
+        let result = disconnect_nodes(&context, a, b);
+        connections.push(connection_apply_result(a, b, NetworkApplyAction::Disconnected, result));
+    }
+
+    response(NetworkApplyResponse {
+        nodes,
+        connections,
+    })
+}
+
+fn apply_scenario_node(context: &Context, node: &ScenarioNode) -> RPCResult<NetworkApplyAction> {
+    let agent =
+        context.agent_service.get_agent(node.name.clone()).ok_or_else(|| RPCError::AgentNotFound(node.name.clone()))?;
+    let state = context
+        .db_service
+        .get_agent_query_result(&node.name)?
+        .ok_or_else(|| RPCError::AgentNotFound(node.name.clone()))?;
+    let extra = context.db_service.get_agent_extra(&node.name)?;
+
+    let action = scenario_node_action(&state, extra.as_ref(), node);
+    if action == NetworkApplyAction::Unchanged {
+        return Ok(action)
+    }
+    let start_request = render_start_request(context, &node.name, &node.env, &node.args, node.binary_path.clone())?;
+    if context.sandbox {
+        return Ok(action)
+    }
+    match action {
+        NetworkApplyAction::Updated => {
+            start_update(context, "network_apply", &node.name, agent, ShellUpdateCodeChainRequest {
+                env: start_request.env,
+                args: start_request.args,
+                commit_hash: node.commit_hash.clone().expect("Updated only when the commit hash is given"),
+                job_id: None,
+            })?;
+        }
+        NetworkApplyAction::Started => {
+            agent.shell_start_codechain(start_request)?;
+        }
+        NetworkApplyAction::Restarted => {
+            agent.shell_stop_codechain()?;
+            agent.shell_start_codechain(start_request)?;
+        }
+        _ => unreachable!(),
+    }
+
+    context.db_service.save_start_option(&node.name, &node.env, &node.args, node.binary_path.clone());
+    Ok(action)
+}
+
+/// Decides what network_apply does to bring the node to the scenario.
+fn scenario_node_action(
+    state: &db::AgentQueryResult,
+    extra: Option<&db::AgentExtra>,
+    node: &ScenarioNode,
+) -> NetworkApplyAction {
+    let current_hash = state.version.as_ref().map(|version| version.hash.clone());
+    let is_option_changed = extra
+        .map(|extra| {
+            extra.prev_env != node.env || extra.prev_args != node.args || extra.binary_path != node.binary_path
+        })
+        .unwrap_or(true);
+
+    match &node.commit_hash {
+        // The node with a pre-installed binary is not built from the commit
+        Some(commit_hash) if node.binary_path.is_none() && Some(commit_hash) != current_hash.as_ref() => {
+            NetworkApplyAction::Updated
+        }
+        _ if state.status != NodeStatus::Run => NetworkApplyAction::Started,
+        _ if is_option_changed => NetworkApplyAction::Restarted,
+        _ => NetworkApplyAction::Unchanged,
+    }
+}
+
+fn connection_apply_result(
+    node_a: &NodeName,
+    node_b: &NodeName,
+    action: NetworkApplyAction,
+    result: RPCResult<()>,
+) -> NetworkApplyConnectionResult {
+    let (action, error) = match result {
+        Ok(()) => (Some(action), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+    NetworkApplyConnectionResult {
+        node_a: node_a.clone(),
+        node_b: node_b.clone(),
+        action,
+        error,
+    }
+}
+
+fn connect_nodes(context: &Context, name_a: &NodeName, name_b: &NodeName) -> RPCResult<()> {
+    let (agent_a, state_a) = get_running_agent(context, name_a)?;
+    let (agent_b, state_b) = get_running_agent(context, name_b)?;
+    let address_a = get_node_address(&state_a)?;
+    let address_b = get_node_address(&state_b)?;
+    if context.sandbox {
+        return Ok(())
+    }
+
+    allow_peer(agent_a.clone(), &state_a, &address_b)?;
+    allow_peer(agent_b, &state_b, &address_a)?;
+
+    CodeChainRPC::new(agent_a).net_connect(state_a.status, &address_b).map_err(RPCError::Internal)
+}
+
+fn disconnect_nodes(context: &Context, name_a: &NodeName, name_b: &NodeName) -> RPCResult<()> {
+    let (agent_a, state_a) = get_running_agent(context, name_a)?;
+    let state_b =
+        context.db_service.get_agent_query_result(name_b)?.ok_or_else(|| RPCError::AgentNotFound(name_b.clone()))?;
+    let address_b = get_node_address(&state_b)?;
+    if context.sandbox {
+        return Ok(())
+    }
+
+    CodeChainRPC::new(agent_a).net_disconnect(state_a.status, &address_b).map_err(RPCError::Internal)
+}
+
+fn node_get_channel_metrics(context: Context, args: (NodeName,)) -> RPCResponse<NodeChannelMetrics> {
+    let (name,) = args;
+    namespace::check_node(&context, &name)?;
+    let agent = context.agent_service.get_agent(name.clone()).ok_or(RPCError::AgentNotFound(name))?;
+    response(NodeChannelMetrics {
+        channel: agent.channel_metrics(),
+        log_inbox: agent.log_inbox_metrics(),
+    })
+}
+
+fn get_running_agent(context: &Context, name: &NodeName) -> RPCResult<(AgentSender, db::AgentQueryResult)> {
+    let agent = context.agent_service.get_agent(name.clone()).ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+    let state =
+        context.db_service.get_agent_query_result(name)?.ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+    if state.status != NodeStatus::Run {
+        return Err(RPCError::CodeChainNotRunning(name.clone()))
+    }
+    Ok((agent, state))
+}
+
+fn get_node_address(state: &db::AgentQueryResult) -> RPCResult<SocketAddr> {
+    state.address.ok_or_else(|| RPCError::Internal(format!("The address of {} is unknown", state.name)))
+}
+
+/// Adds the peer to the node's whitelist when the whitelist is enabled, otherwise the node refuses the connection.
+fn allow_peer(agent: AgentSender, state: &db::AgentQueryResult, peer: &SocketAddr) -> RPCResult<()> {
+    let whitelist = match &state.whitelist {
+        Some(whitelist) if whitelist.enabled => whitelist,
+        _ => return Ok(()),
+    };
+    if whitelist.list.iter().any(|(ip, _)| *ip == peer.ip()) {
+        return Ok(())
+    }
+    CodeChainRPC::new(agent)
+        .add_to_whitelist(state.status, &peer.ip(), "Added by the agent hub")
+        .map_err(RPCError::Internal)
+}
+
+/// Records a mutating request before it is executed.
+/// Queues the request instead of running it if the approvals are required.
+/// The approved request runs without the approval queue, so it doesn't wait again.
+fn require_approval<T>(context: &Context, method: &str, args: &T) -> RPCResult<()>
+where
+    T: Serialize, {
+    let approvals = match &context.approvals {
+        Some(approvals) if !context.sandbox => approvals,
+        _ => return Ok(()),
+    };
+    let identity = approval_identity(context)?;
+    let id = approvals.add(method, serde_json::to_value(args)?, identity.clone(), context.namespace.clone());
+    audit(context, "action_request", None, (id, method, args));
+    cinfo!("{} from {} is waiting for the approval as the action {}", method, identity, id);
+    Err(RPCError::ApprovalRequired(id))
+}
+
+fn action_list(context: Context) -> RPCResponse<Vec<PendingAction>> {
+    let approvals = context.approvals.as_ref().ok_or(RPCError::FeatureDisabled("Approval"))?;
+    let mut actions = approvals.list(approval_timeout(&context));
+    actions.retain(|action| is_action_visible(&context, action));
+    response(actions)
+}
+
+fn update_get_progress(context: Context) -> RPCResponse<Vec<UpdateProgress>> {
+    let distributor = context.distributor.as_ref().ok_or(RPCError::FeatureDisabled("Binary distribution"))?;
+    let visible = namespace::visible_nodes(&context)?;
+    let mut progress = distributor.progress();
+    progress.retain(|progress| visible.contains(&progress.node_name));
+    response(progress)
+}
+
+fn job_get(context: Context, args: (u64,)) -> RPCResponse<Job> {
+    let (id,) = args;
+    let job = context.jobs.get(id).ok_or_else(|| RPCError::NotFound(format!("Job {}", id)))?;
+    namespace::check_node(&context, &job.node_name).map_err(|_| RPCError::NotFound(format!("Job {}", id)))?;
+    response(job)
+}
+
+/// The newest first. The finished jobs are kept until the hub restarts
+fn job_list(context: Context, args: (Option<NodeName>,)) -> RPCResponse<Vec<Job>> {
+    let (node_name,) = args;
+    let visible = namespace::visible_nodes(&context)?;
+    let mut jobs = context.jobs.list(node_name.as_ref());
+    jobs.retain(|job| visible.contains(&job.node_name));
+    response(jobs)
+}
+
+/// Stops waiting for the agent, and asks the agent to stop the job if it reports the progress
+fn job_cancel(context: Context, args: (u64,)) -> RPCResponse<Job> {
+    let (id,) = args;
+    let job = context.jobs.get(id).ok_or_else(|| RPCError::NotFound(format!("Job {}", id)))?;
+    namespace::check_node(&context, &job.node_name).map_err(|_| RPCError::NotFound(format!("Job {}", id)))?;
+    audit(&context, "job_cancel", Some(&job.node_name), &id);
+    if job.status != JobStatus::Running {
+        return Err(RPCError::InvalidParams(format!("Job {} is {:?} already", id, job.status)))
+    }
+    if context.sandbox {
+        return response(job)
+    }
+    if let Some(agent) = context.agent_service.get_agent(job.node_name.clone()) {
+        if agent.has(AgentCapability::JobProgress) {
+            agent.cancel_job(id);
+        }
+    }
+    context.jobs.cancel(id).map_err(RPCError::InvalidParams)?;
+    response(context.jobs.get(id).ok_or_else(|| RPCError::NotFound(format!("Job {}", id)))?)
+}
+
+/// Runs the pending action and returns its result. The operator who requested it can't approve it.
+fn action_approve(context: Context, args: (u64,)) -> RPCResponse<Value> {
+    let (id,) = args;
+    let approvals = context.approvals.clone().ok_or(RPCError::FeatureDisabled("Approval"))?;
+    let action = approvals
+        .list(approval_timeout(&context))
+        .into_iter()
+        .find(|action| action.id == id && is_action_visible(&context, action))
+        .ok_or_else(|| RPCError::NotFound(format!("Action {}", id)))?;
+    if action.requested_by == approval_identity(&context)? {
+        return Err(RPCError::InvalidParams("The action should be approved by another operator".to_string()))
+    }
+    audit(&context, "action_approve", None, &action);
+    if !context.sandbox {
+        approvals.take(id).ok_or_else(|| RPCError::NotFound(format!("Action {}", id)))?;
+    }
+
+    // The action runs in the namespace of the operator who requested it
+    let context = Context {
+        approvals: None,
+        namespace: action.namespace.clone(),
+        ..context
+    };
+    let arguments = action.arguments;
+    let result = match action.method.as_str() {
+        "node_stop" => serde_json::to_value(node_stop(context, serde_json::from_value(arguments)?)?)?,
+        "node_update" => serde_json::to_value(node_update(context, serde_json::from_value(arguments)?)?)?,
+        "node_startByTags" => serde_json::to_value(node_start_by_tags(context, serde_json::from_value(arguments)?)?)?,
+        "node_stopByTags" => serde_json::to_value(node_stop_by_tags(context, serde_json::from_value(arguments)?)?)?,
+        "node_bulkEdit" => serde_json::to_value(node_bulk_edit(context, serde_json::from_value(arguments)?)?)?,
+        "network_apply" => serde_json::to_value(network_apply(context, serde_json::from_value(arguments)?)?)?,
+        method => return Err(RPCError::Internal(format!("{} can't be approved", method))),
+    };
+    response(result)
+}
+
+fn action_reject(context: Context, args: (u64,)) -> RPCResponse<()> {
+    let (id,) = args;
+    let approvals = context.approvals.clone().ok_or(RPCError::FeatureDisabled("Approval"))?;
+    let actions = approvals.list(approval_timeout(&context));
+    if !actions.iter().any(|action| action.id == id && is_action_visible(&context, action)) {
+        return Err(RPCError::NotFound(format!("Action {}", id)))
+    }
+    audit(&context, "action_reject", None, id);
+    if context.sandbox {
+        return response(())
+    }
+    approvals.take(id).ok_or_else(|| RPCError::NotFound(format!("Action {}", id)))?;
+    response(())
+}
+
+/// The admins see every action, and the others see the actions requested in their namespace
+fn is_action_visible(context: &Context, action: &PendingAction) -> bool {
+    context.namespace.is_none() || context.namespace == action.namespace
+}
+
+/// The operators are told by the names of their tokens, because a caller address can be shared or changed
+fn approval_identity(context: &Context) -> RPCResult<String> {
+    context
+        .identity
+        .clone()
+        .ok_or_else(|| RPCError::PermissionDenied("The approvals need the api_tokens with the names".to_string()))
+}
+
+fn approval_timeout(context: &Context) -> chrono::Duration {
+    chrono::Duration::minutes(context.config.frontend.approval_timeout_minutes)
+}
+
+fn audit<T>(context: &Context, method: &str, node_name: Option<&NodeName>, arguments: T)
+where
+    T: Serialize, {
+    if context.sandbox {
+        cinfo!("{} from {:?} is validated but not executed in the sandbox", method, context.caller);
+    }
+    context.db_service.write_audit(db::AuditEntry {
+        caller: context.caller.clone(),
+        identity: context.identity.clone(),
+        method: method.to_string(),
+        node_name: node_name.cloned(),
+        arguments: serde_json::to_value(arguments).unwrap_or_default(),
+        sandbox: context.sandbox,
+    });
+}
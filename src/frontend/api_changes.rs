@@ -0,0 +1,381 @@
+/**
+ * The changes of the frontend API between the versions of the hub.
+ * Add an entry whenever a method or a response field is added, deprecated, changed or removed,
+ * and bump the version in Cargo.toml when the change is released.
+ */
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ApiChangeKind {
+    MethodAdded,
+    MethodDeprecated,
+    MethodRemoved,
+    ParamAdded,
+    FieldAdded,
+    FieldChanged,
+    FieldRemoved,
+    ErrorChanged,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiChange {
+    pub version: &'static str,
+    pub kind: ApiChangeKind,
+    /// "*" when the change affects all methods
+    pub method: &'static str,
+    /// The path of the param or the response field, e.g. "logs[].backfilled"
+    pub field: Option<&'static str>,
+    pub description: &'static str,
+}
+
+const fn method_added(version: &'static str, method: &'static str, description: &'static str) -> ApiChange {
+    ApiChange {
+        version,
+        kind: ApiChangeKind::MethodAdded,
+        method,
+        field: None,
+        description,
+    }
+}
+
+const API_CHANGES: &[ApiChange] = &[
+    method_added("0.2.0", "network_connect", "Connects two nodes"),
+    method_added("0.2.0", "network_disconnect", "Disconnects two nodes"),
+    method_added("0.2.0", "network_export", "Exports the network as a scenario"),
+    method_added("0.2.0", "network_apply", "Brings the network to the state described by a scenario"),
+    method_added("0.2.0", "node_find", "Finds the managed nodes by names, node keys and addresses"),
+    method_added("0.2.0", "node_addTags", "Adds tags to a node"),
+    method_added("0.2.0", "node_removeTags", "Removes tags from a node"),
+    method_added("0.2.0", "node_startByTags", "Starts the nodes which have the tags"),
+    method_added("0.2.0", "node_stopByTags", "Stops the nodes which have the tags"),
+    method_added("0.2.0", "dashboard_getNetworkByTags", "Returns the network of the nodes which have the tags"),
+    method_added("0.2.0", "admin_getBuildInfo", "Returns the version and the build of the hub"),
+    method_added("0.2.0", "audit_get", "Returns the audit log of the mutating methods"),
+    method_added("0.2.0", "node_getEvents", "Returns the lifecycle events of nodes"),
+    method_added("0.2.0", "dashboard_getEvents", "Returns the lifecycle events of all nodes"),
+    method_added("0.2.0", "node_getChannelMetrics", "Returns the metrics of the channel to the agent"),
+    method_added("0.2.0", "rpc_methods", "Lists the methods"),
+    method_added("0.2.0", "rpc_describe", "Describes the params and the result of a method"),
+    method_added("0.2.0", "debug_injectFault", "Injects a fault when the fault injection is enabled"),
+    method_added("0.2.0", "slo_set", "Adds or updates an SLO"),
+    method_added("0.2.0", "slo_remove", "Removes an SLO"),
+    method_added("0.2.0", "slo_getStatus", "Returns the availability and the error budget of the SLOs"),
+    method_added("0.2.0", "rpc_getApiChanges", "Returns the changes of the API since a version"),
+    method_added("0.2.0", "schedule_create", "Schedules periodic restarts of a node with a cron expression"),
+    method_added("0.2.0", "schedule_remove", "Removes a schedule"),
+    method_added("0.2.0", "schedule_list", "Lists the schedules"),
+    method_added("0.2.0", "schedule_getHistory", "Returns the runs of the schedules"),
+    method_added("0.2.0", "node_remove", "Removes a decommissioned node and archives its data"),
+    method_added("0.2.0", "node_listArchived", "Lists the removed nodes"),
+    method_added("0.2.0", "node_rename", "Renames a node"),
+    method_added("0.2.0", "report_changes", "Summarizes the changes of the fleet since a time"),
+    method_added("0.2.0", "node_getStopImpact", "Estimates what happens to the consensus when the node goes down"),
+    method_added("0.2.0", "node_bulkEdit", "Changes the tags, the owner, the region and the maintenance of nodes"),
+    method_added("0.2.0", "node_setLogLevel", "Changes RUST_LOG of a running node through its RPC or a restart"),
+    method_added("0.2.0", "template_set", "Creates or replaces a named start template of env, args and commit hash"),
+    method_added("0.2.0", "template_remove", "Removes a start template"),
+    method_added("0.2.0", "template_list", "Lists the start templates"),
+    method_added("0.2.0", "action_list", "Lists the destructive requests which wait for approvals"),
+    method_added("0.2.0", "action_approve", "Runs a pending action requested by another operator"),
+    method_added("0.2.0", "action_reject", "Drops a pending action"),
+    method_added("0.2.0", "update_getProgress", "Returns the phase and the transferred bytes of the binary updates"),
+    method_added("0.2.0", "job_get", "Returns the status, the phase and the output of a long-running operation"),
+    method_added("0.2.0", "job_list", "Lists the long-running operations, the newest first"),
+    method_added("0.2.0", "job_cancel", "Stops waiting for the agent and asks the agent to stop the job"),
+    method_added("0.2.0", "dashboard_getNetworkDiff", "Returns the nodes and the connections changed since a seq"),
+    method_added("0.2.0", "log_setRule", "Creates or replaces a pattern which raises log_ruleMatched"),
+    method_added("0.2.0", "log_removeRule", "Removes a log rule"),
+    method_added("0.2.0", "log_getRules", "Lists the log rules"),
+    method_added("0.2.0", "log_getContext", "Returns the logs of the same node before and after a log"),
+    method_added("0.2.0", "node_setDesiredState", "Keeps a node running with a start option or stopped"),
+    method_added("0.2.0", "node_removeDesiredState", "Stops reconciling a node"),
+    method_added("0.2.0", "node_getDesiredStates", "Lists the desired states of the nodes"),
+    method_added("0.2.0", "node_setSecret", "Sets an env variable of a node which is encrypted and never shown"),
+    method_added("0.2.0", "node_removeSecret", "Removes a secret env variable of a node"),
+    method_added("0.2.0", "node_getSecrets", "Lists the names of the secret env variables of a node"),
+    method_added("0.2.0", "node_startSealing", "Resumes the block production of a running node"),
+    method_added("0.2.0", "node_stopSealing", "Takes a running node out of the block production without stopping it"),
+    method_added("0.2.0", "note_add", "Attaches a note of the operator to a node or one of its events"),
+    method_added("0.2.0", "note_remove", "Removes a note"),
+    method_added("0.2.0", "node_getHistory", "Returns the block numbers and the peer counts of a node in a resolution"),
+    method_added("0.2.0", "chaos_kill", "SIGKILLs CodeChain of a node when the fault injection is enabled"),
+    method_added("0.2.0", "chaos_pause", "SIGSTOPs CodeChain of a node for the duration"),
+    method_added("0.2.0", "chaos_partition", "Blocks the traffic between two groups of nodes for the duration"),
+    method_added("0.2.0", "hub_getStats", "Returns the call counts, the errors and the latencies of the RPC methods"),
+    method_added("0.2.0", "hub_listSessions", "Returns the connected websockets of the frontends and the agents"),
+    method_added("0.2.0", "hub_killSession", "Closes a connected websocket and optionally bans its address"),
+    method_added("0.2.0", "hub_authenticate", "Authenticates a websocket with its first message"),
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldChanged,
+        method: "node_getInfo",
+        field: Some("events"),
+        description: "Events are objects with id, nodeName, kind, detail and timestamp instead of strings",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "node_getInfo",
+        field: Some("tags"),
+        description: "The tags of the node",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "log_get",
+        field: Some("logs[].backfilled"),
+        description: "True if the agent buffered the log while the hub was unreachable",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ParamAdded,
+        method: "log_get",
+        field: Some("filter.tags"),
+        description: "Selects the logs of the nodes which have the tags",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldChanged,
+        method: "dashboard_getNetwork",
+        field: Some("nodes[].status"),
+        description: "Reconnecting is added for the nodes whose agents haven't reconnected since the hub restarted",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ParamAdded,
+        method: "node_stopByTags",
+        field: Some("force"),
+        description: "Bypasses the quorum guard when the caller is allowed to",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ParamAdded,
+        method: "network_apply",
+        field: Some("force"),
+        description: "Bypasses the quorum guard when the caller is allowed to",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "node_getInfo",
+        field: Some("agentVersion"),
+        description: "The protocol version and the binary version of the agent",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "node_getInfo",
+        field: Some("recentErrors"),
+        description: "The distinct messages of the latest error logs with the counts and the last seen times",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "node_getChannelMetrics",
+        field: Some("logInbox"),
+        description: "The queued and the dropped logs which the agent pushed",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldChanged,
+        method: "schedule_create",
+        field: Some("kind"),
+        description: "verifySnapshot restores a recent snapshot on the node and verifies that it syncs",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ParamAdded,
+        method: "node_start",
+        field: Some("req.binaryPath"),
+        description: "Starts the pre-installed binary instead of the one built from the commit",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ParamAdded,
+        method: "node_start",
+        field: Some("template"),
+        description: "Starts the node with a start template, and req overrides its variables and flags",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "node_getInfo",
+        field: Some("startOption.binaryPath"),
+        description: "The pre-installed binary which the node runs",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "node_getInfo",
+        field: Some("agentVersion.capabilities"),
+        description: "The optional features of the agent, or null if the agent doesn't advertise them",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "audit_get",
+        field: Some("audits[].sandbox"),
+        description: "True if the request was sent in the sandbox and not executed",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "audit_get",
+        field: Some("audits[].identity"),
+        description: "The name of the API token of the caller, or null when no token is configured",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldChanged,
+        method: "node_update",
+        field: Some("jobId"),
+        description: "Returns the job which tracks the update instead of null",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "node_getInfo",
+        field: Some("updateAvailable"),
+        description: "The latest release of CodeChain when the node runs another commit",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "dashboard_getNetwork",
+        field: Some("seq"),
+        description: "The number of the network to get the diff since it by dashboard_getNetworkDiff",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "dashboard_getNetwork",
+        field: Some("epoch"),
+        description: "The run of the hub which numbered the seq, to pass as sinceEpoch of dashboard_getNetworkDiff",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ParamAdded,
+        method: "node_bulkEdit",
+        field: Some("patch.namespace"),
+        description: "Moves the nodes to the namespace. Only the admins can change it",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "action_list",
+        field: Some("namespace"),
+        description: "The namespace of the operator who requested the action",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "node_getInfo",
+        field: Some("clock"),
+        description: "The offsets of the host clock from the hub and the NTP servers",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "dashboard_getNetwork",
+        field: Some("nodes[].clockSkewed"),
+        description: "True if the host clock drifted beyond max_clock_skew_ms",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "node_getInfo",
+        field: Some("sealing"),
+        description: "False after node_stopSealing, or null if the node is not running or it is unknown",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "node_getInfo",
+        field: Some("notes"),
+        description: "The notes on the node and its events",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "node_getEvents",
+        field: Some("notes"),
+        description: "The notes on the returned events",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::FieldAdded,
+        method: "dashboard_getEvents",
+        field: Some("notes"),
+        description: "The notes on the returned events",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ErrorChanged,
+        method: "*",
+        field: None,
+        description: "A method which a namespaced token isn't allowed to call fails with the error -15",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ErrorChanged,
+        method: "*",
+        field: None,
+        description: "A request cancelled by job_cancel fails with the error -14",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ErrorChanged,
+        method: "*",
+        field: None,
+        description: "An agent which doesn't respond in time fails with the error -13 with timeoutMs instead of -3",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ErrorChanged,
+        method: "*",
+        field: None,
+        description: "A destructive request waiting for an approval fails with the error -12 with actionId",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ErrorChanged,
+        method: "node_start",
+        field: None,
+        description: "A start option rejected by the start policy fails with the error -11 with part, value and reason",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ErrorChanged,
+        method: "*",
+        field: None,
+        description: "A request which needs a capability which the agent doesn't have fails with the error -10",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ErrorChanged,
+        method: "*",
+        field: None,
+        description: "A client which sends too many requests gets the error -9 with retryAfterMs",
+    },
+    ApiChange {
+        version: "0.2.0",
+        kind: ApiChangeKind::ErrorChanged,
+        method: "*",
+        field: None,
+        description: "Errors have stable codes and the data with nodeName, agentError and reason",
+    },
+];
+
+/// Returns the changes after the given version.
+pub fn changes_since(since_version: &str) -> Result<Vec<ApiChange>, String> {
+    let since = parse_version(since_version).ok_or_else(|| format!("Invalid version {}", since_version))?;
+    Ok(API_CHANGES
+        .iter()
+        .filter(|change| parse_version(change.version).expect("Versions in API_CHANGES are valid") > since)
+        .cloned()
+        .collect())
+}
+
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    version.trim_start_matches('v').split('.').map(|number| number.parse().ok()).collect()
+}
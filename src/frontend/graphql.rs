@@ -0,0 +1,356 @@
+use std::sync::{Arc, Mutex};
+
+use chrono;
+use juniper;
+use juniper::{EmptyMutation, FieldResult, RootNode};
+use serde::Serialize;
+use serde_json;
+
+use super::super::common_rpc_types::NodeName;
+use super::super::db;
+use super::super::rate_limit::RateLimiter;
+use super::namespace;
+use super::types::Context;
+
+/// The number of the logs or the events of a field when the limit is omitted
+const DEFAULT_LIMIT: i32 = 100;
+const MAX_LIMIT: i32 = 1000;
+/// A query stops reading the storage when its fields cost more than this
+const MAX_COST: f64 = 1000.0;
+
+pub type Schema = RootNode<'static, Query, EmptyMutation<QueryContext>>;
+
+/**
+ * The context of a query, which counts the cost of the fields as juniper resolves them.
+ * `nodes`, `node`, `connections`, `tags` and `startOption` cost 1, `history` costs 5,
+ * and `logs` and `events` cost 1 plus their limit divided by 100.
+ * Each cost is taken from the rate limit before the field reads the storage.
+ */
+pub struct QueryContext {
+    pub context: Context,
+    rate_limiter: Arc<RateLimiter>,
+    cost: Mutex<f64>,
+}
+
+impl juniper::Context for QueryContext {}
+
+impl QueryContext {
+    pub fn new(context: Context, rate_limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            context,
+            rate_limiter,
+            cost: Mutex::new(0.0),
+        }
+    }
+
+    pub fn cost(&self) -> f64 {
+        *self.cost.lock().expect("Should success get cost")
+    }
+
+    fn charge(&self, weight: f64) -> FieldResult<&Context> {
+        let mut cost = self.cost.lock().expect("Should success get cost");
+        if *cost + weight > MAX_COST {
+            return Err(format!("The query costs more than {}", MAX_COST).into())
+        }
+        if let Err(retry_after) = self.rate_limiter.acquire(weight) {
+            let retry_after_ms = retry_after.as_secs() * 1000 + u64::from(retry_after.subsec_millis());
+            return Err(format!("Rate limited. Retry after {} ms", retry_after_ms).into())
+        }
+        *cost += weight;
+        Ok(&self.context)
+    }
+}
+
+pub fn schema() -> Schema {
+    Schema::new(Query, EmptyMutation::new())
+}
+
+pub struct Query;
+
+graphql_object!(Query: QueryContext |&self| {
+    description: "The nodes, the connections, the logs and the events which the caller can see"
+
+    field nodes(&executor, names: Option<Vec<String>>) -> FieldResult<Vec<Node>> {
+        let context = executor.context().charge(1.0)?;
+        let visible = namespace::visible_nodes(context)?;
+        Ok(context
+            .db_service
+            .get_agents_state()?
+            .into_iter()
+            .filter(|state| visible.contains(&state.name))
+            .filter(|state| names.as_ref().map_or(true, |names| names.contains(&state.name)))
+            .map(|state| Node {
+                state,
+            })
+            .collect())
+    }
+
+    field node(&executor, name: String) -> FieldResult<Option<Node>> {
+        let context = executor.context().charge(1.0)?;
+        if !namespace::visible_nodes(context)?.contains(&name) {
+            return Ok(None)
+        }
+        Ok(context.db_service.get_agent_query_result(&name)?.map(|state| Node {
+            state,
+        }))
+    }
+
+    field connections(&executor) -> FieldResult<Vec<Connection>> {
+        let context = executor.context().charge(1.0)?;
+        let visible = namespace::visible_nodes(context)?;
+        Ok(context
+            .db_service
+            .get_connections()?
+            .into_iter()
+            .filter(|(node_a, node_b)| visible.contains(node_a) && visible.contains(node_b))
+            .map(|(node_a, node_b)| Connection {
+                node_a,
+                node_b,
+            })
+            .collect())
+    }
+
+    field logs(
+        &executor,
+        node_names: Option<Vec<String>>,
+        levels: Option<Vec<String>>,
+        search: Option<String>,
+        limit: Option<i32>
+    ) -> FieldResult<Vec<Log>> {
+        get_logs(executor.context(), node_names.unwrap_or_default(), levels, search, limit)
+    }
+
+    field events(&executor, limit: Option<i32>) -> FieldResult<Vec<Event>> {
+        get_events(executor.context(), Vec::new(), limit)
+    }
+});
+
+pub struct Node {
+    state: db::AgentQueryResult,
+}
+
+graphql_object!(Node: QueryContext |&self| {
+    description: "A node which an agent manages. The fields which read the database are resolved only when requested"
+
+    field name() -> &str {
+        &self.state.name
+    }
+
+    field status() -> FieldResult<String> {
+        serialized_str(&self.state.status)
+    }
+
+    field address() -> Option<String> {
+        self.state.address.map(|address| address.to_string())
+    }
+
+    field version() -> Option<&str> {
+        self.state.version.as_ref().map(|version| version.version.as_str())
+    }
+
+    field commit_hash() -> Option<&str> {
+        self.state.version.as_ref().map(|version| version.hash.as_str())
+    }
+
+    field best_block_number() -> Option<i32> {
+        self.state.best_block_id.map(|best_block_id| best_block_id.block_number as i32)
+    }
+
+    field best_block_hash() -> FieldResult<Option<String>> {
+        match &self.state.best_block_id {
+            Some(best_block_id) => Ok(Some(serialized_str(&best_block_id.hash)?)),
+            None => Ok(None),
+        }
+    }
+
+    field peer_count() -> i32 {
+        self.state.peers.len() as i32
+    }
+
+    field peers() -> Vec<String> {
+        self.state.peers.iter().map(|peer| peer.to_string()).collect()
+    }
+
+    field tags(&executor) -> FieldResult<Vec<String>> {
+        Ok(executor.context().charge(1.0)?.db_service.get_tags(&self.state.name)?)
+    }
+
+    field start_option(&executor) -> FieldResult<Option<StartOption>> {
+        let extra = executor.context().charge(1.0)?.db_service.get_agent_extra(&self.state.name)?;
+        Ok(extra.map(|extra| StartOption {
+            env: extra.prev_env,
+            args: extra.prev_args,
+            binary_path: extra.binary_path,
+        }))
+    }
+
+    field logs(
+        &executor,
+        levels: Option<Vec<String>>,
+        search: Option<String>,
+        limit: Option<i32>
+    ) -> FieldResult<Vec<Log>> {
+        get_logs(executor.context(), vec![self.state.name.clone()], levels, search, limit)
+    }
+
+    field events(&executor, limit: Option<i32>) -> FieldResult<Vec<Event>> {
+        get_events(executor.context(), vec![self.state.name.clone()], limit)
+    }
+
+    field history(&executor, from_time: Option<String>, to_time: Option<String>) -> FieldResult<Vec<MetricSample>> {
+        let context = executor.context().charge(5.0)?;
+        let to = match to_time {
+            Some(to_time) => parse_time(&to_time)?,
+            None => chrono::Local::now(),
+        };
+        let from = match from_time {
+            Some(from_time) => parse_time(&from_time)?,
+            None => to - chrono::Duration::days(1),
+        };
+        let resolution = context.config.metrics_history.resolution_for(&from, &to);
+        let samples = context.db_service.get_metric_samples(resolution, &self.state.name, &from, &to)?;
+        Ok(samples
+            .into_iter()
+            .map(|sample| MetricSample {
+                time: sample.time.to_rfc3339(),
+                best_block_number: sample.best_block_number.map(|number| number as i32),
+                peer_count: sample.peer_count,
+            })
+            .collect())
+    }
+});
+
+#[derive(GraphQLObject)]
+pub struct Connection {
+    node_a: NodeName,
+    node_b: NodeName,
+}
+
+#[derive(GraphQLObject)]
+pub struct StartOption {
+    env: String,
+    args: String,
+    binary_path: Option<String>,
+}
+
+#[derive(GraphQLObject)]
+pub struct Log {
+    /// A Float because the hashed ids of ClickHouse and Elasticsearch don't fit in an Int. They are below 2^53.
+    id: f64,
+    node_name: NodeName,
+    level: String,
+    target: String,
+    timestamp: String,
+    message: String,
+    backfilled: bool,
+}
+
+#[derive(GraphQLObject)]
+pub struct Event {
+    id: i32,
+    node_name: NodeName,
+    kind: String,
+    /// The JSON text of the detail
+    detail: String,
+    timestamp: String,
+}
+
+#[derive(GraphQLObject)]
+pub struct MetricSample {
+    time: String,
+    best_block_number: Option<i32>,
+    peer_count: f64,
+}
+
+/// The latest logs first
+fn get_logs(
+    context: &QueryContext,
+    node_names: Vec<NodeName>,
+    levels: Option<Vec<String>>,
+    search: Option<String>,
+    limit: Option<i32>,
+) -> FieldResult<Vec<Log>> {
+    let context = context.charge(limit_cost(limit))?;
+    let levels = levels
+        .unwrap_or_default()
+        .into_iter()
+        .map(|level| serde_json::from_value(serde_json::Value::String(level)))
+        .collect::<Result<Vec<db::LogLevel>, _>>()?;
+    let mut params = db::LogQueryParams {
+        filter: Some(db::LogFilter {
+            node_names,
+            levels,
+            ..Default::default()
+        }),
+        search,
+        time: None,
+        page: Some(1),
+        item_per_page: Some(limit_of(limit)),
+        order_by: Some(db::OrderBy::DESC),
+        after: None,
+    };
+    if !namespace::restrict_logs(context, &mut params)? {
+        return Ok(Vec::new())
+    }
+    Ok(context
+        .db_service
+        .get_logs(params)?
+        .into_iter()
+        .map(|log| Log {
+            id: log.id as f64,
+            node_name: log.node_name,
+            level: log.level,
+            target: log.target,
+            timestamp: log.timestamp.to_rfc3339(),
+            message: log.message,
+            backfilled: log.backfilled,
+        })
+        .collect())
+}
+
+/// The latest events first
+fn get_events(context: &QueryContext, mut node_names: Vec<NodeName>, limit: Option<i32>) -> FieldResult<Vec<Event>> {
+    let context = context.charge(limit_cost(limit))?;
+    if !namespace::visible_nodes(context)?.restrict(&mut node_names) {
+        return Ok(Vec::new())
+    }
+    let events = context.db_service.get_events(db::NodeEventQueryParams {
+        node_names,
+        time: None,
+        page: Some(1),
+        item_per_page: Some(limit_of(limit)),
+        order_by: Some(db::OrderBy::DESC),
+    })?;
+    events
+        .into_iter()
+        .map(|event| {
+            Ok(Event {
+                id: event.id,
+                node_name: event.node_name,
+                kind: serialized_str(&event.kind)?,
+                detail: event.detail.to_string(),
+                timestamp: event.timestamp.to_rfc3339(),
+            })
+        })
+        .collect()
+}
+
+fn limit_of(limit: Option<i32>) -> i32 {
+    limit.unwrap_or(DEFAULT_LIMIT).max(1).min(MAX_LIMIT)
+}
+
+fn limit_cost(limit: Option<i32>) -> f64 {
+    1.0 + f64::from(limit_of(limit)) / f64::from(DEFAULT_LIMIT)
+}
+
+fn parse_time(time: &str) -> FieldResult<chrono::DateTime<chrono::Local>> {
+    Ok(chrono::DateTime::parse_from_rfc3339(time)?.with_timezone(&chrono::Local))
+}
+
+/// The same string as the JSON-RPC methods return
+fn serialized_str<T: Serialize>(value: &T) -> FieldResult<String> {
+    match serde_json::to_value(value)? {
+        serde_json::Value::String(value) => Ok(value),
+        value => Ok(value.to_string()),
+    }
+}
@@ -1,38 +1,195 @@
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::net::SocketAddr;
 use std::rc::Rc;
+use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use ws;
-use ws::{CloseCode, Error as WSError, Handler, Handshake, Result, Sender};
+use ws::util::{Timeout, Token};
+use ws::{CloseCode, Error as WSError, Frame, Handler, Handshake, Result, Sender};
 
 use super::super::jsonrpc;
-use super::super::router::Router;
+use super::super::rate_limit::RateLimiter;
+use super::super::router::{Error as RouterError, Router};
+use super::super::rpc::RPCError;
+use super::super::session::SessionRole;
+use super::super::shutdown::SHUTTING_DOWN_REASON;
+use super::super::web::query_string;
+use super::namespace;
 use super::types::Context;
 
+const PING: Token = Token(1);
+const EXPIRE: Token = Token(2);
+
+/// The first message of a websocket which didn't send the Authorization header, e.g. from a browser
+const AUTHENTICATE_METHOD: &str = "hub_authenticate";
+
+#[derive(Clone, Copy, Debug)]
+pub struct ConnectionOption {
+    pub ping_interval: Duration,
+    /// The connection is closed when nothing is received in this duration, including the pongs
+    pub idle_timeout: Duration,
+    /// The size of a message in bytes
+    pub max_message_size: usize,
+}
+
 pub struct WebSocketHandler {
     pub out: Sender,
     pub count: Rc<Cell<u32>>,
     pub context: Context,
     pub router: Arc<Router<Context>>,
+    pub rate_limiter: RateLimiter,
     pub frontend_service: super::ServiceSender,
+    pub shutting_down: Arc<AtomicBool>,
+    pub connection_option: ConnectionOption,
+    pub expire_timeout: Option<Timeout>,
+    /// None until the connection is authenticated
+    pub session_id: Option<u64>,
+    /// Some until the first message authenticates the connection
+    pub unauthenticated_peer: Option<SocketAddr>,
 }
 
-impl Handler for WebSocketHandler {
-    fn on_open(&mut self, _: Handshake) -> Result<()> {
+impl WebSocketHandler {
+    fn reset_expire_timeout(&mut self) -> Result<()> {
+        if let Some(timeout) = self.expire_timeout.take() {
+            self.out.cancel(timeout)?;
+        }
+        self.out.timeout(as_millis(self.connection_option.idle_timeout), EXPIRE)
+    }
+
+    /// The authenticated connection receives the events and is counted
+    fn admit(&mut self, peer_addr: SocketAddr) -> Result<()> {
         self.frontend_service
-            .send(super::Message::AddWS(self.out.clone()))
+            .send(super::Message::AddWS(self.out.clone(), self.context.namespace.clone()))
             .expect("Should success adding ws to frontend_service");
         // We have a new connection, so we increment the connection counter
-        Ok(self.count.set(self.count.get() + 1))
+        self.count.set(self.count.get() + 1);
+        self.session_id = Some(self.context.sessions.open(
+            SessionRole::Frontend,
+            Some(peer_addr),
+            self.context.identity.clone(),
+            self.out.clone(),
+        ));
+        self.out.timeout(as_millis(self.connection_option.ping_interval), PING)?;
+        self.reset_expire_timeout()
+    }
+
+    /// The first message should be a call of hub_authenticate with the params like {"token": "secret"}
+    fn authenticate_by_message(&mut self, peer_addr: SocketAddr, msg: ws::Message) -> Result<()> {
+        let text = match msg {
+            ws::Message::Text(ref text) if text.len() <= self.connection_option.max_message_size => text.clone(),
+            _ => return self.out.close_with_reason(CloseCode::Policy, "The first message should authenticate"),
+        };
+        let authenticated = RefCell::new(None);
+        let response = {
+            let tokens = &self.context.config.frontend.api_tokens;
+            jsonrpc::handle(
+                |method, params| {
+                    if method != AUTHENTICATE_METHOD {
+                        let err = format!("The first message should be {}", AUTHENTICATE_METHOD);
+                        return Err(RouterError::RPC(RPCError::PermissionDenied(err)))
+                    }
+                    let token = params["token"].as_str().or_else(|| params[0].as_str());
+                    let token = namespace::authenticate(tokens, token)
+                        .map_err(|err| RouterError::RPC(RPCError::PermissionDenied(err)))?;
+                    *authenticated.borrow_mut() = Some(token.cloned());
+                    Ok(Some(json!(true)))
+                },
+                text,
+            )
+        };
+        if let Some(response) = response {
+            self.out.send(ws::Message::Text(response))?;
+        }
+        match authenticated.into_inner() {
+            Some(token) => {
+                self.context.namespace = token.as_ref().and_then(|token| token.namespace.clone());
+                self.context.identity = token.map(|token| token.name);
+                self.admit(peer_addr)
+            }
+            None => {
+                cwarn!("Refuse the connection of {:?} which didn't authenticate", self.context.caller);
+                self.out.close_with_reason(CloseCode::Policy, "The token is invalid")
+            }
+        }
+    }
+}
+
+impl Handler for WebSocketHandler {
+    fn on_open(&mut self, handshake: Handshake) -> Result<()> {
+        // The peer address, not X-Forwarded-For, which the client can forge. An unknown address is refused.
+        // ws tells the peer address only after the upgrade, so the refused connection is closed with the policy code.
+        // The check comes first, so the refused connection is neither counted nor sent the events.
+        let peer_addr = match handshake.peer_addr {
+            Some(peer_addr) if self.context.config.listen.frontend_allowlist.allows(&peer_addr.ip()) => peer_addr,
+            peer_addr => {
+                cwarn!("Refuse the connection of {:?} which is not in the allowlist", peer_addr);
+                return self.out.close_with_reason(CloseCode::Policy, "The address is not allowed")
+            }
+        };
+        if self.context.sessions.is_banned(&peer_addr.ip()) {
+            cwarn!("Refuse the connection of {} which is banned", peer_addr);
+            return self.out.close_with_reason(CloseCode::Policy, "The address is banned")
+        }
+        let forwarded_for = handshake.request.header("X-Forwarded-For").and_then(|value| str::from_utf8(value).ok());
+        self.context.caller = Some(self.context.config.frontend.caller(peer_addr, forwarded_for));
+        self.context.peer_addr = Some(peer_addr);
+        // The connection to e.g. ws://hub:3012/?sandbox=true is in the sandbox
+        let query = handshake.request.resource().splitn(2, '?').nth(1).unwrap_or_default();
+        let query = query_string::parse(query);
+        self.context.sandbox = query.iter().any(|(key, value)| key == "sandbox" && value == "true");
+
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return self.out.close_with_reason(CloseCode::Away, SHUTTING_DOWN_REASON)
+        }
+        // The token is never in the URL, which the proxies and the browsers record
+        let token = handshake
+            .request
+            .header("Authorization")
+            .and_then(|value| str::from_utf8(value).ok())
+            .map(|value| value.trim_start_matches("Bearer "));
+        if token.is_none() && !self.context.config.frontend.api_tokens.is_empty() {
+            // A browser can't set the header of a websocket, so it sends the token with the first message
+            self.unauthenticated_peer = Some(peer_addr);
+            return self.reset_expire_timeout()
+        }
+        match namespace::authenticate(&self.context.config.frontend.api_tokens, token) {
+            Ok(token) => {
+                self.context.namespace = token.and_then(|token| token.namespace.clone());
+                self.context.identity = token.map(|token| token.name.clone());
+            }
+            Err(err) => {
+                cwarn!("Refuse the connection of {:?} : {}", self.context.caller, err);
+                return self.out.close_with_reason(CloseCode::Policy, err)
+            }
+        }
+        // The refused connections don't receive the events and are not counted
+        self.admit(peer_addr)
     }
 
     fn on_message(&mut self, msg: ws::Message) -> Result<()> {
+        if let Some(peer_addr) = self.unauthenticated_peer.take() {
+            return self.authenticate_by_message(peer_addr, msg)
+        }
+        // The refused connection is closing
+        if self.session_id.is_none() {
+            return Ok(())
+        }
         // Tell the user the current count
         ctrace!("The number of live connections is {}", self.count.get());
 
+        if msg.len() > self.connection_option.max_message_size {
+            cwarn!("{:?} sent a message of {} bytes", self.context.caller, msg.len());
+            let reason = format!("A message should be smaller than {} bytes", self.connection_option.max_message_size);
+            return self.out.close_with_reason(CloseCode::Size, reason)
+        }
+
         let response: Option<String> = match msg {
             ws::Message::Text(text) => {
-                jsonrpc::handle(|method, arg| self.router.run(self.context.clone(), &method, arg), text)
+                let (router, rate_limiter, context) = (&self.router, &self.rate_limiter, &self.context);
+                jsonrpc::handle(|method, arg| router.run_limited(rate_limiter, context.clone(), &method, arg), text)
             }
             _ => Some(jsonrpc::invalid_format()),
         };
@@ -44,6 +201,34 @@ impl Handler for WebSocketHandler {
         }
     }
 
+    fn on_frame(&mut self, frame: Frame) -> Result<Option<Frame>> {
+        self.reset_expire_timeout()?;
+        Ok(Some(frame))
+    }
+
+    fn on_timeout(&mut self, event: Token) -> Result<()> {
+        match event {
+            PING => {
+                self.out.ping(Vec::new())?;
+                self.out.timeout(as_millis(self.connection_option.ping_interval), PING)
+            }
+            EXPIRE => {
+                cinfo!("Close the idle connection of {:?}", self.context.caller);
+                self.out.close_with_reason(CloseCode::Away, "The connection is idle")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn on_new_timeout(&mut self, event: Token, timeout: Timeout) -> Result<()> {
+        if event == EXPIRE {
+            if let Some(previous) = self.expire_timeout.replace(timeout) {
+                self.out.cancel(previous)?;
+            }
+        }
+        Ok(())
+    }
+
     fn on_close(&mut self, code: CloseCode, reason: &str) {
         match code {
             CloseCode::Normal => cinfo!("The client is done with the connection."),
@@ -51,9 +236,15 @@ impl Handler for WebSocketHandler {
             CloseCode::Abnormal => cinfo!("Closing handshake failed! Unable to obtain closing status from client."),
             _ => cinfo!("The client encountered an error: {}", reason),
         }
+        let session_id = match self.session_id {
+            Some(session_id) => session_id,
+            // The refused connection was not added
+            None => return,
+        };
         self.frontend_service
             .send(super::Message::RemoveWS(self.out.clone()))
             .expect("Should success remove ws from frontend_service");
+        self.context.sessions.close(session_id);
 
         // The connection is going down, so we need to decrement the count
         self.count.set(self.count.get() - 1)
@@ -63,3 +254,7 @@ impl Handler for WebSocketHandler {
         cerror!("The server encountered an error: {:?}", err);
     }
 }
+
+fn as_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
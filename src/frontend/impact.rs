@@ -0,0 +1,163 @@
+use super::super::agent::{AgentSender, CodeChainRPC};
+use super::super::common_rpc_types::{NodeName, NodeStatus};
+use super::super::db;
+use super::super::rpc::{RPCError, RPCResult};
+use super::types::{Context, NodeStopImpact};
+
+/// Nodes with this tag are the validators of the consensus
+pub const VALIDATOR_TAG: &str = "validator";
+
+/// A proposal within this number of blocks is soon
+const PROPOSING_SOON_BLOCKS: i64 = 3;
+
+/// Estimates what happens when the node goes down by node_stop, node_update or a restart.
+pub fn stop_impact(context: &Context, name: &NodeName) -> RPCResult<NodeStopImpact> {
+    let state =
+        context.db_service.get_agent_query_result(name)?.ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+    let validators = context.db_service.get_node_names_by_tags(vec![VALIDATOR_TAG.to_string()])?;
+    let is_validator = validators.contains(name);
+    let is_running_validator = is_validator && state.status == NodeStatus::Run;
+
+    let running_validators = context
+        .db_service
+        .get_agents_state()?
+        .iter()
+        .filter(|agent| agent.status == NodeStatus::Run && validators.contains(&agent.name))
+        .filter(|agent| context.db_service.is_sealing(&agent.name) == Some(true))
+        .count();
+    // A validator whose sealing is unknown is not counted as running
+    let is_counted = is_running_validator && context.db_service.is_sealing(name) == Some(true);
+    let running_validators_after_stop = if is_counted {
+        running_validators - 1
+    } else {
+        running_validators
+    };
+    let quorum = quorum(validators.len());
+    let safety_margin = running_validators_after_stop as i64 - quorum as i64;
+
+    let next_proposal_in_blocks = match context.agent_service.get_agent(name.clone()) {
+        Some(agent) if is_running_validator => {
+            estimate_next_proposal(agent, &state, validators.len()).unwrap_or_else(|err| {
+                cwarn!("Cannot estimate the next proposal of {} : {}", name, err);
+                None
+            })
+        }
+        _ => None,
+    };
+    let proposing_soon = next_proposal_in_blocks.map(|blocks| blocks <= PROPOSING_SOON_BLOCKS).unwrap_or(false);
+
+    let mut warnings = Vec::new();
+    if proposing_soon {
+        warnings.push(format!("{} proposes a block in {} blocks", name, next_proposal_in_blocks.unwrap_or_default()));
+    }
+    if is_running_validator && safety_margin < 0 {
+        warnings.push(format!(
+            "Only {} of {} validators will be running, which is below the quorum {}. The consensus will halt",
+            running_validators_after_stop,
+            validators.len(),
+            quorum
+        ));
+    } else if is_running_validator && safety_margin == 0 {
+        warnings.push(format!("The consensus will halt if one more validator goes down while {} is stopped", name));
+    }
+
+    Ok(NodeStopImpact {
+        name: name.clone(),
+        status: state.status,
+        is_validator,
+        validators: validators.len(),
+        running_validators,
+        running_validators_after_stop,
+        quorum,
+        violates_quorum: safety_margin < 0,
+        safety_margin,
+        next_proposal_in_blocks,
+        proposing_soon,
+        warnings,
+    })
+}
+
+/// Refuses a batch operation which takes more than the allowed fraction of the validators offline at once.
+/// `names` are the nodes which the operation stops or restarts.
+/// The validators which don't seal are regarded as offline.
+/// The callers in the override list can bypass the guard with `force`.
+pub fn check_quorum_guard(context: &Context, method: &str, names: &[NodeName], force: bool) -> RPCResult<()> {
+    let validators = context.db_service.get_node_names_by_tags(vec![VALIDATOR_TAG.to_string()])?;
+    if validators.is_empty() {
+        return Ok(())
+    }
+    let running_validators: Vec<NodeName> = context
+        .db_service
+        .get_agents_state()?
+        .into_iter()
+        .filter(|agent| agent.status == NodeStatus::Run && validators.contains(&agent.name))
+        .filter(|agent| context.db_service.is_sealing(&agent.name) == Some(true))
+        .map(|agent| agent.name)
+        .collect();
+    let stopping = names.iter().filter(|name| running_validators.contains(name)).count();
+    if stopping == 0 {
+        return Ok(())
+    }
+
+    let offline = validators.len() - running_validators.len() + stopping;
+    let quorum_guard = &context.config.frontend.quorum_guard;
+    let max_offline = (validators.len() as f64 * quorum_guard.max_offline_fraction).floor() as usize;
+    if offline <= max_offline {
+        return Ok(())
+    }
+
+    let reason = format!(
+        "{} takes {} of {} validators offline, but at most {} can be offline at once",
+        method,
+        offline,
+        validators.len(),
+        max_offline
+    );
+    if !force {
+        return Err(RPCError::QuorumGuard(reason))
+    }
+    // Not the caller, which can come from the X-Forwarded-For header of the client
+    match context.peer_addr.map(|addr| addr.ip()) {
+        Some(ip) if quorum_guard.override_callers.contains(&ip) => {
+            cwarn!("{} overrides the quorum guard : {}", ip, reason);
+            Ok(())
+        }
+        _ => Err(RPCError::QuorumGuard(format!("{}. The caller is not allowed to override the guard", reason))),
+    }
+}
+
+/// Tendermint needs the votes of more than 2/3 of the validators
+fn quorum(validators: usize) -> usize {
+    if validators == 0 {
+        return 0
+    }
+    validators * 2 / 3 + 1
+}
+
+/// Assumes that the proposer rotates through the validators,
+/// so the node proposes again after all the other validators proposed once.
+/// Returns None if the node didn't propose in the last round.
+fn estimate_next_proposal(
+    agent: AgentSender,
+    state: &db::AgentQueryResult,
+    validators: usize,
+) -> Result<Option<i64>, String> {
+    let codechain_rpc = CodeChainRPC::new(agent);
+    let coinbase = match codechain_rpc.get_coinbase(state.status)? {
+        Some(coinbase) => coinbase,
+        None => return Ok(None),
+    };
+    let best_block_number = match state.best_block_id {
+        Some(best_block_id) => best_block_id.block_number,
+        None => return Ok(None),
+    };
+
+    let round = validators as i64;
+    let first = (best_block_number - round + 1).max(0);
+    for block_number in (first..=best_block_number).rev() {
+        if codechain_rpc.get_block_author(state.status, block_number)?.as_ref() == Some(&coinbase) {
+            return Ok(Some(block_number + round - best_block_number))
+        }
+    }
+    Ok(None)
+}
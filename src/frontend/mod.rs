@@ -1,9 +1,21 @@
 pub mod api;
+mod api_changes;
+pub mod graphql;
+mod impact;
+pub mod namespace;
+mod network_history;
+pub mod reconcile;
 pub mod handler;
+mod report;
+pub mod schedule;
 pub mod service;
+mod start_policy;
+mod template;
 pub mod types;
 
 pub use self::api::add_routing;
-pub use self::handler::WebSocketHandler;
+pub use self::handler::{ConnectionOption, WebSocketHandler};
+pub use self::namespace::ApiTokenOption;
 pub use self::service::{Message, Service, ServiceSender};
+pub use self::start_policy::{StartPolicyOption, StartRequestRejection};
 pub use self::types::*;
@@ -0,0 +1,116 @@
+use super::super::common_rpc_types::NodeName;
+use super::super::db;
+use super::super::rpc::{RPCError, RPCResult};
+use super::types::Context;
+
+/// The nodes with the tag like "namespace:team-a" belong to the namespace team-a
+pub const NAMESPACE_TAG_PREFIX: &str = "namespace:";
+
+/// The namespaces of a node joined by commas, which is None for the nodes without a namespace.
+/// The nodes in different namespaces run different networks
+pub fn namespace_of(db_service: &db::ServiceSender, name: &NodeName) -> Result<Option<String>, db::Error> {
+    let namespaces: Vec<String> = db_service
+        .get_tags(name)?
+        .into_iter()
+        .filter(|tag| tag.starts_with(NAMESPACE_TAG_PREFIX))
+        .map(|tag| tag[NAMESPACE_TAG_PREFIX.len()..].to_string())
+        .collect();
+    if namespaces.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(namespaces.join(",")))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiTokenOption {
+    pub token: String,
+    /// The operator who uses the token, e.g. "alice". The approvals and the notes are attributed to it
+    pub name: String,
+    /// The admins, who see every namespace, have no namespace
+    #[serde(default)]
+    pub namespace: Option<String>,
+}
+
+/// Returns the option of the token. Every client is an anonymous admin when no token is configured.
+pub fn authenticate<'a>(
+    tokens: &'a [ApiTokenOption],
+    token: Option<&str>,
+) -> Result<Option<&'a ApiTokenOption>, String> {
+    if tokens.is_empty() {
+        return Ok(None)
+    }
+    let token = token.ok_or_else(|| "The token is required".to_string())?;
+    tokens.iter().find(|option| option.token == token).map(Some).ok_or_else(|| "The token is invalid".to_string())
+}
+
+/// The nodes which the caller can see. None for the admins.
+pub struct VisibleNodes(Option<Vec<NodeName>>);
+
+impl VisibleNodes {
+    pub fn contains(&self, name: &NodeName) -> bool {
+        self.0.as_ref().map_or(true, |names| names.contains(name))
+    }
+
+    /// Narrows the node names of a query, where no name selects every node.
+    /// Returns false if no visible node is selected.
+    pub fn restrict(&self, node_names: &mut Vec<NodeName>) -> bool {
+        let names = match &self.0 {
+            Some(names) => names,
+            None => return true,
+        };
+        if node_names.is_empty() {
+            *node_names = names.clone();
+        } else {
+            node_names.retain(|name| names.contains(name));
+        }
+        !node_names.is_empty()
+    }
+}
+
+pub fn visible_nodes(context: &Context) -> RPCResult<VisibleNodes> {
+    match &context.namespace {
+        Some(namespace) => {
+            let tag = format!("{}{}", NAMESPACE_TAG_PREFIX, namespace);
+            Ok(VisibleNodes(Some(context.db_service.get_node_names_by_tags(vec![tag])?)))
+        }
+        None => Ok(VisibleNodes(None)),
+    }
+}
+
+/// Narrows the logs of a query to the visible nodes. Returns false if no visible node is selected.
+pub fn restrict_logs(context: &Context, params: &mut db::LogQueryParams) -> RPCResult<bool> {
+    let filter = params.filter.get_or_insert_with(Default::default);
+    Ok(visible_nodes(context)?.restrict(&mut filter.node_names))
+}
+
+/// The node in another namespace looks like it doesn't exist
+pub fn check_node(context: &Context, name: &NodeName) -> RPCResult<()> {
+    let namespace = match &context.namespace {
+        Some(namespace) => namespace,
+        None => return Ok(()),
+    };
+    let tag = format!("{}{}", NAMESPACE_TAG_PREFIX, namespace);
+    if !context.db_service.get_tags(name)?.contains(&tag) {
+        return Err(RPCError::AgentNotFound(name.clone()))
+    }
+    Ok(())
+}
+
+/// For the methods which affect every namespace
+pub fn require_admin(context: &Context, method: &str) -> RPCResult<()> {
+    match &context.namespace {
+        Some(namespace) => {
+            Err(RPCError::PermissionDenied(format!("{} is not allowed in the namespace {}", method, namespace)))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Only the admins move the nodes between the namespaces
+pub fn check_tags(context: &Context, tags: &[String]) -> RPCResult<()> {
+    if tags.iter().any(|tag| tag.starts_with(NAMESPACE_TAG_PREFIX)) {
+        require_admin(context, "Changing the namespace tags")?;
+    }
+    Ok(())
+}
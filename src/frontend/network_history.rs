@@ -0,0 +1,126 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono;
+
+use super::super::common_rpc_types::{Connection, NodeName};
+use super::types::{DashboardGetNetworkDiffResponse, DashboardNode, NodeConnection};
+
+/// The diffs since the older snapshots are answered with the whole network
+const MAX_SNAPSHOTS: usize = 100;
+
+struct Snapshot {
+    seq: u64,
+    nodes: HashMap<NodeName, DashboardNode>,
+    connections: BTreeSet<Connection>,
+}
+
+/// The networks which are sent to the frontends, numbered by the seq which is increased only when the network changes.
+/// The snapshots are kept in memory, so the seq starts again from 1 when the hub restarts.
+/// The seqs of the previous run are told apart by the epoch.
+pub struct NetworkHistory {
+    /// The time when the hub started in milliseconds
+    epoch: u64,
+    /// The oldest first
+    snapshots: Mutex<VecDeque<Snapshot>>,
+}
+
+impl Default for NetworkHistory {
+    fn default() -> Self {
+        Self {
+            epoch: chrono::Utc::now().timestamp_millis() as u64,
+            snapshots: Default::default(),
+        }
+    }
+}
+
+impl NetworkHistory {
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns the seq of the network
+    pub fn record(&self, nodes: &[DashboardNode], connections: &[Connection]) -> u64 {
+        let mut snapshots = self.snapshots.lock().expect("Should success get snapshots");
+        record(&mut snapshots, nodes, connections)
+    }
+
+    /// Returns the whole network as added if the snapshot of since_seq is not kept or is of another epoch
+    pub fn diff(
+        &self,
+        since_epoch: Option<u64>,
+        since_seq: u64,
+        nodes: Vec<DashboardNode>,
+        connections: Vec<Connection>,
+    ) -> DashboardGetNetworkDiffResponse {
+        let mut snapshots = self.snapshots.lock().expect("Should success get snapshots");
+        let seq = record(&mut snapshots, &nodes, &connections);
+        let since = snapshots
+            .iter()
+            .find(|snapshot| snapshot.seq == since_seq)
+            .filter(|_| since_epoch == Some(self.epoch));
+        let since = match since {
+            Some(since) => since,
+            None => {
+                return DashboardGetNetworkDiffResponse {
+                    epoch: self.epoch,
+                    seq,
+                    full: true,
+                    nodes_added: nodes,
+                    nodes_changed: Vec::new(),
+                    nodes_removed: Vec::new(),
+                    connections_added: connections.iter().map(NodeConnection::from_connection).collect(),
+                    connections_removed: Vec::new(),
+                }
+            }
+        };
+
+        let mut nodes_added = Vec::new();
+        let mut nodes_changed = Vec::new();
+        for node in nodes.iter() {
+            match since.nodes.get(node.name()) {
+                None => nodes_added.push(node.clone()),
+                Some(before) if before != node => nodes_changed.push(node.clone()),
+                Some(_) => {}
+            }
+        }
+        let names: BTreeSet<&NodeName> = nodes.iter().map(|node| node.name()).collect();
+        let mut nodes_removed: Vec<NodeName> =
+            since.nodes.keys().filter(|name| !names.contains(name)).cloned().collect();
+        nodes_removed.sort();
+        let connections: BTreeSet<Connection> = connections.into_iter().collect();
+        let connections_added = connections.difference(&since.connections);
+        let connections_removed = since.connections.difference(&connections);
+        DashboardGetNetworkDiffResponse {
+            epoch: self.epoch,
+            seq,
+            full: false,
+            nodes_added,
+            nodes_changed,
+            nodes_removed,
+            connections_added: connections_added.map(NodeConnection::from_connection).collect(),
+            connections_removed: connections_removed.map(NodeConnection::from_connection).collect(),
+        }
+    }
+}
+
+fn record(snapshots: &mut VecDeque<Snapshot>, nodes: &[DashboardNode], connections: &[Connection]) -> u64 {
+    let nodes: HashMap<NodeName, DashboardNode> =
+        nodes.iter().map(|node| (node.name().clone(), node.clone())).collect();
+    let connections: BTreeSet<Connection> = connections.iter().cloned().collect();
+    if let Some(last) = snapshots.back() {
+        if last.nodes == nodes && last.connections == connections {
+            return last.seq
+        }
+    }
+    let seq = snapshots.back().map_or(1, |last| last.seq + 1);
+    snapshots.push_back(Snapshot {
+        seq,
+        nodes,
+        connections,
+    });
+    if snapshots.len() > MAX_SNAPSHOTS {
+        snapshots.pop_front();
+    }
+    seq
+}
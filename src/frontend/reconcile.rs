@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::super::agent::SendAgentRPC;
+use super::super::common_rpc_types::{NodeName, NodeStatus, ShellUpdateCodeChainRequest};
+use super::super::db::{AgentQueryResult, DesiredState, DesiredStatus, NodeEventKind};
+use super::super::jsonrpc;
+use super::super::rpc::{RPCError, RPCResult};
+use super::api;
+use super::impact;
+use super::service::{Message, ServiceSender};
+use super::types::Context;
+
+const CHECK_INTERVAL_SECONDS: u64 = 30;
+/// A corrected node is not corrected again in this period, so a node which can't start is not started in a loop
+const CORRECTION_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Compares the desired states with the reported states, and starts or stops the nodes which drifted.
+/// The start option and the commit of a running node are only warned, because correcting them restarts the node.
+pub fn run_thread(context: Context, frontend_service: ServiceSender) {
+    thread::Builder::new()
+        .name("reconcile".to_string())
+        .spawn(move || {
+            let mut corrected: HashMap<NodeName, Instant> = HashMap::new();
+            let mut drifts: HashMap<NodeName, Vec<&'static str>> = HashMap::new();
+            loop {
+                thread::sleep(Duration::new(CHECK_INTERVAL_SECONDS, 0));
+                let states = match context.db_service.get_desired_states() {
+                    Ok(states) => states,
+                    Err(err) => {
+                        cerror!("Cannot get desired states : {:?}", err);
+                        continue
+                    }
+                };
+                corrected.retain(|_, at| at.elapsed() < CORRECTION_BACKOFF);
+                drifts.retain(|name, _| states.iter().any(|state| state.node_name == *name));
+                for state in &states {
+                    let name = &state.node_name;
+                    if corrected.contains_key(name) || context.db_service.is_in_maintenance(name) {
+                        continue
+                    }
+                    let reported = match context.db_service.get_agent_query_result(name) {
+                        Ok(Some(reported)) => reported,
+                        Ok(None) => continue,
+                        Err(err) => {
+                            cerror!("Cannot get the state of {} : {:?}", name, err);
+                            continue
+                        }
+                    };
+                    if needs_correction(state, &reported) {
+                        corrected.insert(name.clone(), Instant::now());
+                        correct(&context, state);
+                    } else if state.status == DesiredStatus::Running && reported.status == NodeStatus::Run {
+                        check_drift(&context, &frontend_service, state, &reported, &mut drifts);
+                    }
+                }
+            }
+        })
+        .expect("Should success running reconcile thread");
+}
+
+/// The nodes which are starting, updating or not connected are left until they settle
+fn needs_correction(state: &DesiredState, reported: &AgentQueryResult) -> bool {
+    match state.status {
+        DesiredStatus::Running => reported.status == NodeStatus::Stop || reported.status == NodeStatus::Error,
+        DesiredStatus::Stopped => reported.status == NodeStatus::Run,
+    }
+}
+
+fn correct(context: &Context, state: &DesiredState) {
+    let name = &state.node_name;
+    let (action, result) = match state.status {
+        DesiredStatus::Running => ("start", start(context, state)),
+        DesiredStatus::Stopped => ("stop", stop(context, name)),
+    };
+    match &result {
+        Ok(()) => cinfo!("The reconciliation {}s {} to match its desired state", action, name),
+        Err(err) => cwarn!("The reconciliation cannot {} {} : {}", action, name, err),
+    }
+    let error = result.err().map(|err| err.to_string());
+    context.db_service.write_event(
+        name,
+        NodeEventKind::DesiredStateCorrected,
+        json!({ "action": action, "status": state.status, "error": error }),
+    );
+}
+
+/// Starts the node with the desired start option. It is updated first if it is built from another commit.
+fn start(context: &Context, state: &DesiredState) -> RPCResult<()> {
+    let name = &state.node_name;
+    let agent = context.agent_service.get_agent(name.clone()).ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+    let binary_path = context.db_service.get_agent_extra(name)?.and_then(|extra| extra.binary_path);
+    let current_hash =
+        context.db_service.get_agent_query_result(name)?.and_then(|state| state.version).map(|version| version.hash);
+    let commit_hash = state
+        .commit_hash
+        .clone()
+        .filter(|commit_hash| binary_path.is_none() && Some(commit_hash) != current_hash.as_ref());
+    let start_request = api::render_start_request(context, name, &state.env, &state.args, binary_path.clone())?;
+    match commit_hash {
+        Some(commit_hash) => {
+            api::start_update(context, "reconcile", name, agent, ShellUpdateCodeChainRequest {
+                env: start_request.env,
+                args: start_request.args,
+                commit_hash,
+                job_id: None,
+            })?;
+        }
+        None => agent.shell_start_codechain(start_request)?,
+    }
+    context.db_service.save_start_option(name, &state.env, &state.args, binary_path);
+    Ok(())
+}
+
+/// Refused by the quorum guard like the other stops
+fn stop(context: &Context, name: &NodeName) -> RPCResult<()> {
+    impact::check_quorum_guard(context, "reconcile", &[name.clone()], false)?;
+    let agent = context.agent_service.get_agent(name.clone()).ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+    agent.shell_stop_codechain()
+}
+
+/// Warns once for each change of the drift
+fn check_drift(
+    context: &Context,
+    frontend_service: &ServiceSender,
+    state: &DesiredState,
+    reported: &AgentQueryResult,
+    drifts: &mut HashMap<NodeName, Vec<&'static str>>,
+) {
+    let name = &state.node_name;
+    let extra = match context.db_service.get_agent_extra(name) {
+        Ok(extra) => extra.unwrap_or_default(),
+        Err(err) => {
+            cerror!("Cannot get the start option of {} : {:?}", name, err);
+            return
+        }
+    };
+    let current_hash = reported.version.as_ref().map(|version| &version.hash);
+    let mut drift = Vec::new();
+    if extra.prev_env != state.env {
+        drift.push("env");
+    }
+    if extra.prev_args != state.args {
+        drift.push("args");
+    }
+    if state.commit_hash.is_some() && extra.binary_path.is_none() && state.commit_hash.as_ref() != current_hash {
+        drift.push("commitHash");
+    }
+    if drifts.get(name).map_or(drift.is_empty(), |before| *before == drift) {
+        return
+    }
+    if drift.is_empty() {
+        drifts.remove(name);
+        return
+    }
+
+    cwarn!("{} drifted from its desired state : {:?}", name, drift);
+    let detail = json!({
+        "fields": drift,
+        "desired": { "env": state.env, "args": state.args, "commitHash": state.commit_hash },
+        "actual": { "env": extra.prev_env, "args": extra.prev_args, "commitHash": current_hash },
+    });
+    context.db_service.write_event(name, NodeEventKind::DesiredStateDrifted, detail.clone());
+    let message = jsonrpc::serialize_notification("node_desiredStateDrifted", json!({ "name": name, "drift": detail }));
+    frontend_service.send(Message::SendNodeEvent(vec![name.clone()], message)).expect("Should success send event");
+    drifts.insert(name.clone(), drift);
+}
@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono;
+
+use super::super::common_rpc_types::NodeName;
+use super::super::db::{
+    Audit, AuditFilter, AuditQueryParams, LogDuration, NodeEvent, NodeEventKind, NodeEventQueryParams, OrderBy,
+};
+use super::super::rpc::RPCResult;
+use super::types::{ChangeReport, Context, Incident, VersionDrift};
+
+/// The audited methods which change the configuration of the hub
+const CONFIG_METHODS: &[&str] =
+    &["node_addTags", "node_removeTags", "node_rename", "slo_set", "slo_remove", "schedule_create", "schedule_remove"];
+
+const PAGE_SIZE: i32 = 1000;
+
+/// Summarizes the changes of the fleet since the given time.
+pub fn changes(context: &Context, since: chrono::DateTime<chrono::Local>) -> RPCResult<ChangeReport> {
+    let until = chrono::Local::now();
+    let window = LogDuration {
+        from_time: Some(since),
+        to_time: Some(until),
+    };
+
+    let mut updates_applied = Vec::new();
+    let mut nodes_added = Vec::new();
+    let mut nodes_removed = Vec::new();
+    // The flag is true if the incident is opened in the window
+    let mut incidents: Vec<(Incident, bool)> = Vec::new();
+    let mut versions_before: HashMap<NodeName, String> = HashMap::new();
+    let mut version_reverted = HashSet::new();
+    let mut seen = HashSet::new();
+
+    for event in search_events(context, window.clone())? {
+        if seen.insert(event.node_name.clone()) {
+            match last_event_before(context, &event.node_name, since)? {
+                None => nodes_added.push(event.node_name.clone()),
+                Some(ref last) if is_incident(last.kind) => incidents.push((incident(last), false)),
+                Some(_) => {}
+            }
+        }
+
+        match event.kind {
+            kind if is_incident(kind) => {
+                if find_open_incident(&mut incidents, &event.node_name, event.kind).is_none() {
+                    incidents.push((incident(&event), true));
+                }
+            }
+            NodeEventKind::CodeChainStarted | NodeEventKind::UpdateApplied | NodeEventKind::AgentConnected => {
+                let opening = if event.kind == NodeEventKind::AgentConnected {
+                    NodeEventKind::AgentDisconnected
+                } else {
+                    NodeEventKind::CodeChainCrashed
+                };
+                if let Some(open) = find_open_incident(&mut incidents, &event.node_name, opening) {
+                    open.closed_at = Some(event.timestamp);
+                }
+            }
+            NodeEventKind::Archived => nodes_removed.push(event.node_name.clone()),
+            NodeEventKind::VersionChanged => {
+                if version_reverted.insert(event.node_name.clone()) {
+                    if let Some(from) = event.detail["from"].as_str() {
+                        versions_before.insert(event.node_name.clone(), from.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+        if event.kind == NodeEventKind::UpdateApplied {
+            updates_applied.push(event);
+        }
+    }
+
+    let mut versions_after = BTreeMap::new();
+    for agent in context.db_service.get_agents_state()? {
+        if let Some(version) = agent.version {
+            *versions_after.entry(version.hash.clone()).or_insert(0) += 1;
+            if !version_reverted.contains(&agent.name) && !nodes_added.contains(&agent.name) {
+                versions_before.insert(agent.name, version.hash);
+            }
+        }
+    }
+    for archived in context.db_service.get_archived_agents()? {
+        if archived.archived_at < since || version_reverted.contains(&archived.name) {
+            continue
+        }
+        if let Some(hash) = archived.detail["lastState"]["versionHash"].as_str() {
+            versions_before.insert(archived.name, hash.to_string());
+        }
+    }
+    let mut versions_before_count = BTreeMap::new();
+    for hash in versions_before.into_iter().map(|(_, hash)| hash) {
+        *versions_before_count.entry(hash).or_insert(0) += 1;
+    }
+    let delta = versions_after.len() as i64 - versions_before_count.len() as i64;
+
+    let config_edits = search_config_edits(context, window)?;
+    let incidents_opened =
+        incidents.iter().filter(|(_, opened)| *opened).map(|(incident, _)| incident.clone()).collect();
+    let incidents_closed = incidents
+        .into_iter()
+        .map(|(incident, _)| incident)
+        .filter(|incident| incident.closed_at.is_some())
+        .collect();
+
+    Ok(ChangeReport {
+        since,
+        until,
+        updates_applied,
+        nodes_added,
+        nodes_removed,
+        config_edits,
+        incidents_opened,
+        incidents_closed,
+        version_drift: VersionDrift {
+            before: versions_before_count,
+            after: versions_after,
+            delta,
+        },
+    })
+}
+
+fn search_events(context: &Context, window: LogDuration) -> RPCResult<Vec<NodeEvent>> {
+    let mut events = Vec::new();
+    for page in 1.. {
+        let found = context.db_service.get_events(NodeEventQueryParams {
+            node_names: Vec::new(),
+            time: Some(window.clone()),
+            page: Some(page),
+            item_per_page: Some(PAGE_SIZE),
+            order_by: Some(OrderBy::ASC),
+        })?;
+        let is_last = found.len() < PAGE_SIZE as usize;
+        events.extend(found);
+        if is_last {
+            break
+        }
+    }
+    Ok(events)
+}
+
+fn search_config_edits(context: &Context, window: LogDuration) -> RPCResult<Vec<Audit>> {
+    let mut audits = Vec::new();
+    for page in 1.. {
+        let found = context.db_service.get_audits(AuditQueryParams {
+            filter: Some(AuditFilter {
+                node_names: Vec::new(),
+                methods: CONFIG_METHODS.iter().map(|method| method.to_string()).collect(),
+                callers: Vec::new(),
+            }),
+            time: Some(window.clone()),
+            page: Some(page),
+            item_per_page: Some(PAGE_SIZE),
+            order_by: Some(OrderBy::ASC),
+        })?;
+        let is_last = found.len() < PAGE_SIZE as usize;
+        audits.extend(found);
+        if is_last {
+            break
+        }
+    }
+    Ok(audits)
+}
+
+fn last_event_before(
+    context: &Context,
+    name: &NodeName,
+    before: chrono::DateTime<chrono::Local>,
+) -> RPCResult<Option<NodeEvent>> {
+    let events = context.db_service.get_events(NodeEventQueryParams {
+        node_names: vec![name.clone()],
+        time: Some(LogDuration {
+            from_time: None,
+            to_time: Some(before),
+        }),
+        page: Some(1),
+        item_per_page: Some(1),
+        order_by: Some(OrderBy::DESC),
+    })?;
+    Ok(events.into_iter().next())
+}
+
+fn is_incident(kind: NodeEventKind) -> bool {
+    kind == NodeEventKind::CodeChainCrashed || kind == NodeEventKind::AgentDisconnected
+}
+
+fn incident(event: &NodeEvent) -> Incident {
+    Incident {
+        node_name: event.node_name.clone(),
+        kind: event.kind,
+        opened_at: event.timestamp,
+        closed_at: None,
+    }
+}
+
+fn find_open_incident<'a>(
+    incidents: &'a mut Vec<(Incident, bool)>,
+    name: &NodeName,
+    kind: NodeEventKind,
+) -> Option<&'a mut Incident> {
+    incidents
+        .iter_mut()
+        .map(|(incident, _)| incident)
+        .find(|incident| incident.node_name == *name && incident.kind == kind && incident.closed_at.is_none())
+}
@@ -0,0 +1,202 @@
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono;
+use cron;
+use rand;
+
+use super::super::agent::SendAgentRPC;
+use super::super::common_rpc_types::{NodeName, NodeStatus};
+use super::super::db::{Schedule, ScheduleKind, ScheduleRun, ScheduleRunResult};
+use super::super::rpc::{RPCError, RPCResult};
+use super::api;
+use super::impact;
+use super::types::Context;
+
+const CHECK_INTERVAL_SECONDS: u64 = 30;
+/// The node stays in maintenance until it runs again or this timeout
+const RESTART_TIMEOUT: Duration = Duration::from_secs(120);
+/// A snapshot is picked randomly from this number of the newest snapshots
+const RECENT_SNAPSHOTS: usize = 5;
+/// The restored node is synced if it imports this number of blocks after the snapshot
+const SYNCED_BLOCKS: i64 = 10;
+const SYNC_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Accepts the standard 5 fields cron expressions, and the 6 or 7 fields expressions which start with seconds.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule, String> {
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+    cron::Schedule::from_str(&expression).map_err(|err| format!("Invalid cron expression {} : {:?}", expression, err))
+}
+
+/// Runs the schedules whose cron expressions matched since the last check.
+pub fn run_thread(context: Context) {
+    thread::Builder::new()
+        .name("schedule".to_string())
+        .spawn(move || {
+            let mut last_checked = chrono::Local::now();
+            loop {
+                thread::sleep(Duration::new(CHECK_INTERVAL_SECONDS, 0));
+                let now = chrono::Local::now();
+                match context.db_service.get_schedules() {
+                    Ok(schedules) => {
+                        for schedule in schedules.iter().filter(|schedule| is_due(schedule, &last_checked, &now)) {
+                            run(&context, schedule);
+                        }
+                    }
+                    Err(err) => cerror!("Cannot get schedules : {:?}", err),
+                }
+                last_checked = now;
+            }
+        })
+        .expect("Should success running schedule thread");
+}
+
+fn is_due(schedule: &Schedule, since: &chrono::DateTime<chrono::Local>, now: &chrono::DateTime<chrono::Local>) -> bool {
+    match parse_cron(&schedule.cron) {
+        Ok(cron) => cron.after(since).next().map(|next| next <= *now).unwrap_or(false),
+        Err(err) => {
+            cwarn!("Schedule {} is never run : {}", schedule.id, err);
+            false
+        }
+    }
+}
+
+fn run(context: &Context, schedule: &Schedule) {
+    cinfo!("Run schedule {} ({:?} {})", schedule.id, schedule.kind, schedule.node_name);
+    let started_at = chrono::Local::now();
+    let outcome = match schedule.kind {
+        ScheduleKind::Restart => restart(context, schedule).map(|()| None),
+        ScheduleKind::VerifySnapshot => verify_snapshot(context, schedule).map(Some),
+    };
+    let (result, detail) = match outcome {
+        Ok(detail) => (ScheduleRunResult::Succeeded, detail),
+        Err(err @ RPCError::QuorumGuard(_)) => (ScheduleRunResult::Skipped, Some(err.to_string())),
+        Err(err) => (ScheduleRunResult::Failed, Some(err.to_string())),
+    };
+    if let Some(detail) = &detail {
+        if result == ScheduleRunResult::Succeeded {
+            cinfo!("Schedule {} is {:?} : {}", schedule.id, result, detail);
+        } else {
+            cwarn!("Schedule {} is {:?} : {}", schedule.id, result, detail);
+        }
+    }
+    context.db_service.write_schedule_run(ScheduleRun {
+        schedule_id: schedule.id,
+        node_name: schedule.node_name.clone(),
+        started_at,
+        finished_at: chrono::Local::now(),
+        result,
+        detail,
+    });
+}
+
+/// Restarts the node with the previous start option. The node is in maintenance while it restarts,
+/// so the restart doesn't consume the error budgets of the SLOs.
+/// The restart is a job, so the frontends can follow and cancel it like the other long-running operations.
+fn restart(context: &Context, schedule: &Schedule) -> RPCResult<()> {
+    let name = &schedule.node_name;
+    impact::check_quorum_guard(context, "schedule", &[name.clone()], false)?;
+    let agent = context.agent_service.get_agent(name.clone()).ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+    let extra = context.db_service.get_agent_extra(name)?.unwrap_or_default();
+    let start_request =
+        api::render_start_request(context, name, &extra.prev_env, &extra.prev_args, extra.binary_path.clone())?;
+
+    let job_id = context.jobs.create("schedule_restart", name);
+    let cancellation = context.jobs.cancellation(job_id);
+    let check_cancelled = || {
+        if cancellation.is_cancelled() {
+            Err(RPCError::Cancelled(Some(name.clone())))
+        } else {
+            Ok(())
+        }
+    };
+    context.db_service.set_maintenance(name, true);
+    context.jobs.set_phase(job_id, "stopping");
+    let result = agent
+        .shell_stop_codechain()
+        .and_then(|()| check_cancelled())
+        .and_then(|()| {
+            context.jobs.set_phase(job_id, "starting");
+            agent.shell_start_codechain(start_request)
+        })
+        .and_then(|()| {
+            context.jobs.set_phase(job_id, "waiting");
+            wait_until_running(context, name, &check_cancelled)
+        });
+    context.db_service.set_maintenance(name, false);
+    context.jobs.finish(job_id, result.as_ref().map(|_| ()).map_err(|err| err.to_string()));
+    result
+}
+
+/// Restores a random recent snapshot on the scratch node, starts it and waits until it syncs.
+/// The snapshots are validated this way because a backup which is never restored may be broken unnoticed.
+fn verify_snapshot(context: &Context, schedule: &Schedule) -> RPCResult<String> {
+    let name = &schedule.node_name;
+    impact::check_quorum_guard(context, "schedule", &[name.clone()], false)?;
+    let agent = context.agent_service.get_agent(name.clone()).ok_or_else(|| RPCError::AgentNotFound(name.clone()))?;
+    let mut snapshots = agent.snapshot_list()?;
+    if snapshots.is_empty() {
+        return Err(RPCError::NotFound(format!("Snapshots for {}", name)))
+    }
+    snapshots.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    snapshots.truncate(RECENT_SNAPSHOTS);
+    let snapshot = snapshots.swap_remove(rand::random::<usize>() % snapshots.len());
+    let extra = context.db_service.get_agent_extra(name)?.unwrap_or_default();
+    let start_request =
+        api::render_start_request(context, name, &extra.prev_env, &extra.prev_args, extra.binary_path.clone())?;
+
+    cinfo!("Verify the snapshot {} of {} on {}", snapshot.id, snapshot.node_name, name);
+    context.db_service.set_maintenance(name, true);
+    let result = agent
+        .shell_stop_codechain()
+        .and_then(|()| agent.snapshot_restore(snapshot.id.clone()))
+        .and_then(|()| agent.shell_start_codechain(start_request))
+        .and_then(|()| wait_until_running(context, name, || Ok(())))
+        .and_then(|()| wait_until_synced(context, name, snapshot.block_number + SYNCED_BLOCKS));
+    context.db_service.set_maintenance(name, false);
+    match result {
+        Ok(best_block_number) => Ok(format!(
+            "The snapshot {} of {} at block {} is restored and synced to block {}",
+            snapshot.id, snapshot.node_name, snapshot.block_number, best_block_number
+        )),
+        Err(err) => Err(RPCError::Internal(format!(
+            "The snapshot {} of {} at block {} is not verified : {}",
+            snapshot.id, snapshot.node_name, snapshot.block_number, err
+        ))),
+    }
+}
+
+/// Returns the best block number when the node imports the block
+fn wait_until_synced(context: &Context, name: &NodeName, block_number: i64) -> RPCResult<i64> {
+    let started = Instant::now();
+    while started.elapsed() < SYNC_TIMEOUT {
+        thread::sleep(Duration::from_secs(1));
+        let state = context.db_service.get_agent_query_result(name)?;
+        let best_block_id = state.and_then(|state| state.best_block_id);
+        if let Some(best_block_id) = best_block_id.filter(|best_block_id| best_block_id.block_number >= block_number) {
+            return Ok(best_block_id.block_number)
+        }
+    }
+    Err(RPCError::Internal(format!("{} doesn't reach block {} in {:?}", name, block_number, SYNC_TIMEOUT)))
+}
+
+/// `check_cancelled` stops waiting when it fails
+fn wait_until_running<F>(context: &Context, name: &NodeName, check_cancelled: F) -> RPCResult<()>
+where
+    F: Fn() -> RPCResult<()>, {
+    let started = Instant::now();
+    while started.elapsed() < RESTART_TIMEOUT {
+        thread::sleep(Duration::from_secs(1));
+        check_cancelled()?;
+        let state = context.db_service.get_agent_query_result(name)?;
+        if state.map(|state| state.status == NodeStatus::Run).unwrap_or(false) {
+            return Ok(())
+        }
+    }
+    Err(RPCError::Internal(format!("{} is not running in {:?} after the restart", name, RESTART_TIMEOUT)))
+}
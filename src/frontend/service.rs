@@ -1,27 +1,67 @@
+use std::collections::{HashMap, VecDeque};
 use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 use std::vec::Vec;
 
 use ws;
 
+use super::super::common_rpc_types::NodeName;
+use super::super::db::Storage;
+use super::namespace::NAMESPACE_TAG_PREFIX;
+
 pub struct Service {
-    web_sockets: Vec<ws::Sender>,
+    /// The websockets with the namespaces of their tokens
+    web_sockets: Vec<(ws::Sender, Option<String>)>,
+    poll_sessions: HashMap<String, PollSession>,
+    /// Reads the namespaces of the nodes
+    storage: Arc<Storage>,
+    /// The namespaces of the nodes, which are read again when their tags change
+    namespaces: HashMap<NodeName, Vec<String>>,
+}
+
+/**
+ * PollSession keeps the events for a frontend which uses the HTTP long-poll transport instead of a websocket.
+ */
+struct PollSession {
+    /// The namespace of the token which created the session. None for the admins
+    namespace: Option<String>,
+    events: VecDeque<String>,
+    last_polled: Instant,
+    /// The long-poll which waits for the next events
+    waiter: Option<Sender<Option<Vec<String>>>>,
 }
 
+const MAX_EVENTS_PER_POLL_SESSION: usize = 1000;
+const POLL_SESSION_EXPIRATION: Duration = Duration::from_secs(60);
+
 pub type ServiceSender = Sender<Message>;
 
 pub enum Message {
-    AddWS(ws::Sender),
+    /// The websocket and the namespace of its token
+    AddWS(ws::Sender, Option<String>),
     RemoveWS(ws::Sender),
+    /// The event which every frontend receives
     SendEvent(String),
+    /// The event about the nodes, which the frontends receive only if their namespaces have all of the nodes
+    SendNodeEvent(Vec<NodeName>, String),
+    /// The session id and the namespace of its token
+    AddPollSession(String, Option<String>),
+    /// The callback receives None if the session doesn't exist. It waits for the next events if none are queued
+    TakePollEvents(String, Sender<Option<Vec<String>>>),
+    /// The callback receives the websockets which receive the events
+    GetSubscribers(Sender<Vec<ws::Sender>>),
+    /// The tags of the nodes are changed, so their namespaces are read again
+    InvalidateNamespaces(Vec<NodeName>),
 }
 
 impl Service {
-    pub fn run_thread() -> ServiceSender {
+    pub fn run_thread(storage: Arc<Storage>) -> ServiceSender {
         let (tx, rx) = channel();
         let service_sender = tx.clone();
 
-        let mut service = Service::new();
+        let mut service = Service::new(storage);
 
         thread::Builder::new()
             .name("frontend service".to_string())
@@ -29,14 +69,34 @@ impl Service {
                 for message in rx {
                     match message {
                         Message::SendEvent(jsonrpc_data) => {
-                            service.send_event(jsonrpc_data);
+                            service.send_event(&[], jsonrpc_data);
+                        }
+                        Message::SendNodeEvent(node_names, jsonrpc_data) => {
+                            service.send_event(&node_names, jsonrpc_data);
                         }
-                        Message::AddWS(web_socket) => {
-                            service.add_ws(web_socket);
+                        Message::AddWS(web_socket, namespace) => {
+                            service.add_ws(web_socket, namespace);
                         }
                         Message::RemoveWS(web_socket) => {
                             service.remove_ws(web_socket);
                         }
+                        Message::AddPollSession(session_id, namespace) => {
+                            service.add_poll_session(session_id, namespace);
+                        }
+                        Message::TakePollEvents(session_id, callback) => {
+                            service.take_poll_events(&session_id, callback);
+                        }
+                        Message::GetSubscribers(callback) => {
+                            let subscribers = service.web_sockets.iter().map(|(web_socket, _)| web_socket.clone());
+                            if callback.send(subscribers.collect()).is_err() {
+                                cwarn!("The subscribers are not received");
+                            }
+                        }
+                        Message::InvalidateNamespaces(node_names) => {
+                            for node_name in node_names {
+                                service.namespaces.remove(&node_name);
+                            }
+                        }
                     }
                 }
             })
@@ -48,28 +108,88 @@ impl Service {
 
 
 impl Service {
-    pub fn new() -> Self {
+    pub fn new(storage: Arc<Storage>) -> Self {
         Self {
             web_sockets: Vec::new(),
+            poll_sessions: HashMap::new(),
+            storage,
+            namespaces: HashMap::new(),
         }
     }
 
-    pub fn send_event(&mut self, data: String) {
-        for web_socket in &self.web_sockets {
+    /// The event of the nodes is sent only to the frontends whose namespaces have all of the nodes
+    pub fn send_event(&mut self, node_names: &[NodeName], data: String) {
+        // The tags are read only when a frontend has a namespace
+        let has_namespace = self.web_sockets.iter().any(|(_, namespace)| namespace.is_some())
+            || self.poll_sessions.values().any(|session| session.namespace.is_some());
+        let namespaces = if has_namespace {
+            self.namespaces_of(node_names)
+        } else {
+            Vec::new()
+        };
+        let is_visible = |namespace: &Option<String>| match namespace {
+            None => true,
+            Some(namespace) => namespaces.iter().all(|node_namespaces| node_namespaces.contains(namespace)),
+        };
+
+        for (web_socket, namespace) in &self.web_sockets {
+            if !is_visible(namespace) {
+                continue
+            }
             if let Err(err) = web_socket.send(data.clone()) {
                 cwarn!("Error when sending event to frontend {}", err);
             }
         }
+
+        self.remove_expired_poll_sessions();
+        for session in self.poll_sessions.values_mut().filter(|session| is_visible(&session.namespace)) {
+            // The waiter is gone if its long-poll timed out
+            if let Some(waiter) = session.waiter.take() {
+                if waiter.send(Some(vec![data.clone()])).is_ok() {
+                    continue
+                }
+            }
+            if session.events.len() >= MAX_EVENTS_PER_POLL_SESSION {
+                session.events.pop_front();
+            }
+            session.events.push_back(data.clone());
+        }
     }
 
-    pub fn add_ws(&mut self, web_socket: ws::Sender) {
-        debug_assert_eq!(false, self.web_sockets.contains(&web_socket));
-        self.web_sockets.push(web_socket);
+    /// The namespaces of each node. A node whose tags can't be read is in no namespace until they are read
+    fn namespaces_of(&mut self, node_names: &[NodeName]) -> Vec<Vec<String>> {
+        let mut result = Vec::with_capacity(node_names.len());
+        for node_name in node_names {
+            if let Some(namespaces) = self.namespaces.get(node_name) {
+                result.push(namespaces.clone());
+                continue
+            }
+            match self.storage.get_tags(node_name) {
+                Ok(tags) => {
+                    let namespaces: Vec<String> = tags
+                        .into_iter()
+                        .filter(|tag| tag.starts_with(NAMESPACE_TAG_PREFIX))
+                        .map(|tag| tag[NAMESPACE_TAG_PREFIX.len()..].to_string())
+                        .collect();
+                    self.namespaces.insert(node_name.clone(), namespaces.clone());
+                    result.push(namespaces);
+                }
+                Err(err) => {
+                    cwarn!("Cannot read the namespace of {} : {:?}", node_name, err);
+                    result.push(Vec::new());
+                }
+            }
+        }
+        result
+    }
+
+    pub fn add_ws(&mut self, web_socket: ws::Sender, namespace: Option<String>) {
+        debug_assert_eq!(false, self.web_sockets.iter().any(|(web_socket_iter, _)| *web_socket_iter == web_socket));
+        self.web_sockets.push((web_socket, namespace));
     }
 
     pub fn remove_ws(&mut self, web_socket: ws::Sender) {
-        debug_assert_eq!(true, self.web_sockets.contains(&web_socket));
-        let index = self.web_sockets.iter().position(|web_socket_iter| *web_socket_iter == web_socket);
+        let index = self.web_sockets.iter().position(|(web_socket_iter, _)| *web_socket_iter == web_socket);
         match index {
             None => cerror!("Cannot find websocket to delete, {:?}", web_socket.token()),
             Some(index) => {
@@ -77,4 +197,41 @@ impl Service {
             }
         }
     }
+
+    pub fn add_poll_session(&mut self, session_id: String, namespace: Option<String>) {
+        self.poll_sessions.insert(session_id, PollSession {
+            namespace,
+            events: VecDeque::new(),
+            last_polled: Instant::now(),
+            waiter: None,
+        });
+    }
+
+    pub fn take_poll_events(&mut self, session_id: &str, callback: Sender<Option<Vec<String>>>) {
+        self.remove_expired_poll_sessions();
+        let events = match self.poll_sessions.get_mut(session_id) {
+            Some(session) => {
+                session.last_polled = Instant::now();
+                if session.events.is_empty() {
+                    session.waiter = Some(callback);
+                    return
+                }
+                Some(session.events.drain(..).collect())
+            }
+            None => None,
+        };
+        if let Err(err) = callback.send(events) {
+            cerror!("Cannot send callback : {}", err);
+        }
+    }
+
+    fn remove_expired_poll_sessions(&mut self) {
+        self.poll_sessions.retain(|session_id, session| {
+            let is_alive = session.last_polled.elapsed() < POLL_SESSION_EXPIRATION;
+            if !is_alive {
+                cinfo!("Poll session {} is expired", session_id);
+            }
+            is_alive
+        });
+    }
 }
@@ -0,0 +1,109 @@
+use std::fmt;
+
+/// The agents run the start option in a shell, so these can't appear in it
+const SHELL_METACHARACTERS: &[char] =
+    &[';', '&', '|', '$', '`', '<', '>', '(', ')', '{', '}', '[', ']', '\\', '\'', '"', '*', '?', '!', '~', '\n', '\r'];
+
+/// Checked with the start options after the variables are resolved.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StartPolicyOption {
+    /// The flags which the args can have, e.g. "--port". All flags are allowed if it is empty
+    pub allowed_flags: Vec<String>,
+    pub denied_flags: Vec<String>,
+    /// The variables which the env can have, e.g. "RUST_LOG". All variables are allowed if it is empty
+    pub allowed_env: Vec<String>,
+    pub denied_env: Vec<String>,
+    /// The length of the env and the args in bytes
+    pub max_length: usize,
+}
+
+impl Default for StartPolicyOption {
+    fn default() -> Self {
+        Self {
+            allowed_flags: Vec::new(),
+            denied_flags: Vec::new(),
+            allowed_env: Vec::new(),
+            // They make the node load another code
+            denied_env: vec!["LD_PRELOAD".to_string(), "LD_LIBRARY_PATH".to_string()],
+            max_length: 4096,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartRequestRejection {
+    /// "env", "args" or "binaryPath"
+    pub part: &'static str,
+    /// The rejected variable, flag or character
+    pub value: String,
+    pub reason: String,
+}
+
+impl fmt::Display for StartRequestRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} : {}", self.value, self.part, self.reason)
+    }
+}
+
+pub fn check(
+    policy: &StartPolicyOption,
+    env: &str,
+    args: &str,
+    binary_path: Option<&str>,
+) -> Result<(), StartRequestRejection> {
+    check_text(policy, "env", env)?;
+    check_text(policy, "args", args)?;
+    if let Some(binary_path) = binary_path {
+        check_text(policy, "binaryPath", binary_path)?;
+    }
+
+    for variable in env.split_whitespace() {
+        let name = match variable.find('=') {
+            Some(index) => &variable[..index],
+            None => return Err(rejection("env", variable, "A variable should be NAME=value")),
+        };
+        check_name(&policy.allowed_env, &policy.denied_env, "env", name)?;
+    }
+    for flag in args.split_whitespace().filter(|arg| arg.starts_with('-')) {
+        // --port=3485 is the flag --port
+        let name = flag.split('=').next().unwrap_or_default();
+        check_name(&policy.allowed_flags, &policy.denied_flags, "args", name)?;
+    }
+    Ok(())
+}
+
+fn check_text(policy: &StartPolicyOption, part: &'static str, text: &str) -> Result<(), StartRequestRejection> {
+    if text.len() > policy.max_length {
+        let reason = format!("Longer than {} bytes", policy.max_length);
+        return Err(rejection(part, &format!("{} bytes", text.len()), &reason))
+    }
+    if let Some(metacharacter) = text.chars().find(|c| SHELL_METACHARACTERS.contains(c)) {
+        return Err(rejection(part, &format!("{:?}", metacharacter), "Shell metacharacters are not allowed"))
+    }
+    Ok(())
+}
+
+fn check_name(
+    allowed: &[String],
+    denied: &[String],
+    part: &'static str,
+    name: &str,
+) -> Result<(), StartRequestRejection> {
+    if denied.iter().any(|denied| denied == name) {
+        return Err(rejection(part, name, "Denied by the start policy"))
+    }
+    if !allowed.is_empty() && !allowed.iter().any(|allowed| allowed == name) {
+        return Err(rejection(part, name, "Not in the allowlist of the start policy"))
+    }
+    Ok(())
+}
+
+fn rejection(part: &'static str, value: &str, reason: &str) -> StartRequestRejection {
+    StartRequestRejection {
+        part,
+        value: value.to_string(),
+        reason: reason.to_string(),
+    }
+}
@@ -0,0 +1,97 @@
+use super::super::common_rpc_types::{NodeName, NodeStatus};
+use super::super::db::AgentQueryResult;
+
+/**
+ * Start options can have variables like `{{node.name}}`, which are resolved when the node starts.
+ * The saved start option keeps the variables, so the same option can be used for all nodes of a network.
+ *
+ * node.name: the name of the node
+ * network.bootnodes: the comma separated addresses of the other running nodes of the network
+ * host.public_ip: the IP address of the node's host
+ * host.port: the port of the node
+ *
+ * `agents` are the nodes of the network of the node, which are the nodes of the same namespace.
+ */
+pub fn render(template: &str, node_name: &NodeName, agents: &[AgentQueryResult]) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        let end = after_open.find("}}").ok_or_else(|| format!("Unclosed variable in \"{}\"", template))?;
+        let variable = after_open[..end].trim();
+        rendered.push_str(&resolve(variable, node_name, agents)?);
+        rest = &after_open[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+fn resolve(variable: &str, node_name: &NodeName, agents: &[AgentQueryResult]) -> Result<String, String> {
+    let address = || {
+        agents
+            .iter()
+            .find(|agent| &agent.name == node_name)
+            .and_then(|agent| agent.address)
+            .ok_or_else(|| format!("The address of {} is unknown", node_name))
+    };
+
+    match variable {
+        "node.name" => Ok(node_name.clone()),
+        "network.bootnodes" => {
+            let mut bootnodes: Vec<(&NodeName, String)> = agents
+                .iter()
+                .filter(|agent| &agent.name != node_name && agent.status == NodeStatus::Run)
+                .filter_map(|agent| agent.address.map(|address| (&agent.name, address.to_string())))
+                .collect();
+            bootnodes.sort();
+            Ok(bootnodes.into_iter().map(|(_, address)| address).collect::<Vec<_>>().join(","))
+        }
+        "host.public_ip" => Ok(address()?.ip().to_string()),
+        "host.port" => Ok(address()?.port().to_string()),
+        _ => Err(format!("Unknown variable {{{{{}}}}}", variable)),
+    }
+}
+
+/// Overrides the variables of the start template with the variables of the same names
+pub fn merge_env(template_env: &str, overrides: &str) -> String {
+    let name = |variable: &str| variable.split('=').next().unwrap_or_default().to_string();
+    let override_names: Vec<String> = overrides.split_whitespace().map(name).collect();
+    template_env
+        .split_whitespace()
+        .filter(|variable| !override_names.contains(&name(variable)))
+        .chain(overrides.split_whitespace())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Overrides the flags of the start template with the flags of the same names.
+/// A flag owns the values which follow it, e.g. `--port 3485` is replaced by `--port=4000` as a whole.
+pub fn merge_args(template_args: &str, overrides: &str) -> String {
+    let template_flags = split_flags(template_args);
+    let override_flags = split_flags(overrides);
+    let override_names: Vec<&str> = override_flags.iter().filter_map(|flag| flag_name(flag)).collect();
+    template_flags
+        .iter()
+        .filter(|flag| flag_name(flag).map_or(true, |name| !override_names.contains(&name)))
+        .chain(override_flags.iter())
+        .map(|flag| flag.join(" "))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Groups the args by the flags. The values before the first flag are a group without a flag
+fn split_flags(args: &str) -> Vec<Vec<&str>> {
+    let mut flags: Vec<Vec<&str>> = Vec::new();
+    for arg in args.split_whitespace() {
+        match flags.last_mut() {
+            Some(flag) if !arg.starts_with('-') => flag.push(arg),
+            _ => flags.push(vec![arg]),
+        }
+    }
+    flags
+}
+
+fn flag_name<'a>(flag: &[&'a str]) -> Option<&'a str> {
+    flag.first().filter(|arg| arg.starts_with('-')).map(|arg| arg.split('=').next().unwrap_or_default())
+}
@@ -1,21 +1,73 @@
-use std::net::SocketAddr;
+use std::collections::{BTreeMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use chrono;
+use serde_json::Value;
 
 use super::super::agent;
+use super::super::build_info::BuildInfo;
+use super::super::config::Config;
 use super::super::common_rpc_types;
 use super::super::common_rpc_types::{
-    BlackList, BlockId, HardwareInfo, HardwareUsage, NodeName, NodeStatus, NodeVersion, PendingParcel, WhiteList,
+    AgentVersion, BlackList, BlockId, CommitHash, HardwareInfo, HardwareUsage, NodeClock, NodeName, NodeStatus,
+    NodeVersion, PendingParcel, WhiteList,
 };
 use super::super::db;
+use super::super::distribution::Distributor;
+use super::super::job::Jobs;
+use super::super::jsonrpc;
+use super::super::release::{Release, ReleaseTracker};
+use super::super::router::MethodDescription;
+use super::super::rpc_metrics::{MethodStats, RpcMetrics};
+use super::super::session::Sessions;
+use super::api_changes::ApiChange;
+use super::network_history::NetworkHistory;
 
 #[derive(Clone)]
 pub struct Context {
     pub agent_service: agent::ServiceSender,
     pub db_service: db::ServiceSender,
+    /// Sends the events to the frontends
+    pub frontend_service: super::ServiceSender,
+    pub build_info: Arc<BuildInfo>,
+    pub methods: Arc<Vec<MethodDescription>>,
+    pub rpc_metrics: Arc<RpcMetrics>,
+    /// None when the fault injection is disabled
+    pub faults: Option<Arc<FaultState>>,
+    /// None when the destructive methods don't need approvals
+    pub approvals: Option<Arc<ApprovalQueue>>,
+    /// None when the hub doesn't build the binaries for the updates
+    pub distributor: Option<Arc<Distributor>>,
+    pub jobs: Arc<Jobs>,
+    /// The connected websockets of the frontends and the agents
+    pub sessions: Arc<Sessions>,
+    /// The remote address of the frontend which sends the request
+    pub caller: Option<String>,
+    /// The address of the TCP peer of the frontend, which the client can't forge unlike the caller
+    pub peer_addr: Option<SocketAddr>,
+    /// The namespace of the token of the caller. None for the admins, who see every namespace
+    pub namespace: Option<String>,
+    /// The name of the token of the caller. None when no token is configured
+    pub identity: Option<String>,
+    pub config: Arc<Config>,
+    /// Has no release when the release tracking is disabled
+    pub releases: Arc<ReleaseTracker>,
+    pub network_history: Arc<NetworkHistory>,
+    /// The mutating methods are validated and audited, but not executed
+    pub sandbox: bool,
 }
 
-pub type Event = String;
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuorumGuardOption {
+    /// Batch operations can't take more than this fraction of the validators offline at once
+    pub max_offline_fraction: f64,
+    /// The frontends on these addresses can bypass the guard with the force flag.
+    /// They are compared with the TCP peers, so `X-Forwarded-For` can't claim them.
+    pub override_callers: Vec<IpAddr>,
+}
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Clone, PartialEq, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 #[serde(untagged)]
 pub enum DashboardNode {
@@ -26,6 +78,8 @@ pub enum DashboardNode {
         version: Option<NodeVersion>,
         best_block_id: Option<BlockId>,
         name: NodeName,
+        /// The clock of the host drifted beyond `max_clock_skew_ms`
+        clock_skewed: bool,
     },
     #[serde(rename_all = "camelCase")]
     UFO {
@@ -43,11 +97,25 @@ impl DashboardNode {
             address: state.address,
             version: state.version.clone(),
             best_block_id: state.best_block_id.clone(),
+            clock_skewed: state.is_clock_skewed(),
+        }
+    }
+
+    pub fn name(&self) -> &NodeName {
+        match self {
+            DashboardNode::Normal {
+                name,
+                ..
+            } => name,
+            DashboardNode::UFO {
+                name,
+                ..
+            } => name,
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeConnection {
     pub node_a: String,
@@ -64,21 +132,41 @@ impl NodeConnection {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct DashboardGetNetworkResponse {
+    /// Changed when the hub restarts, and the seqs of different epochs are not comparable
+    pub epoch: u64,
+    /// Increased whenever the network changes, to get the diff by dashboard_getNetworkDiff
+    pub seq: u64,
     pub nodes: Vec<DashboardNode>,
     pub connections: Vec<NodeConnection>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DashboardGetNetworkDiffResponse {
+    pub epoch: u64,
+    pub seq: u64,
+    /// The snapshot of sinceSeq is too old or of another epoch, so every node and connection is added
+    pub full: bool,
+    pub nodes_added: Vec<DashboardNode>,
+    pub nodes_changed: Vec<DashboardNode>,
+    pub nodes_removed: Vec<NodeName>,
+    pub connections_added: Vec<NodeConnection>,
+    pub connections_removed: Vec<NodeConnection>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct StartOption {
     pub env: String,
     pub args: String,
+    /// None when the node runs the binary built from the commit
+    pub binary_path: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeGetInfoResponse {
     pub name: NodeName,
@@ -92,7 +180,39 @@ pub struct NodeGetInfoResponse {
     pub whitelist: Option<WhiteList>,
     pub blacklist: Option<BlackList>,
     pub hardware: Option<HardwareInfo>,
-    pub events: Vec<Event>,
+    pub events: Vec<db::NodeEvent>,
+    pub tags: Vec<String>,
+    pub agent_version: Option<AgentVersion>,
+    /// None until the agent reports the system time of its host
+    pub clock: Option<NodeClock>,
+    /// False after node_stopSealing until node_startSealing or a restart.
+    /// None if the node is not running or the hub hasn't seen it start since it began to keep the sealing
+    pub sealing: Option<bool>,
+    /// The notes of the operators on the node and its events, the newest first
+    pub notes: Vec<db::Note>,
+    pub recent_errors: Vec<ErrorDigest>,
+    /// The latest release when the node runs another commit. None when the release tracking is disabled
+    pub update_available: Option<Release>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeChannelMetrics {
+    #[serde(flatten)]
+    pub channel: jsonrpc::ChannelMetrics,
+    /// The logs which the agent pushed
+    pub log_inbox: agent::LogInboxMetrics,
+}
+
+/// The error logs which have the same message
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorDigest {
+    pub target: String,
+    pub message: String,
+    /// The number of the occurrences in the latest error logs
+    pub count: usize,
+    pub last_seen: chrono::DateTime<chrono::Local>,
 }
 
 impl NodeGetInfoResponse {
@@ -133,11 +253,23 @@ impl NodeGetInfoResponse {
                     percentage_used: 0.6,
                 },
             }),
-            events: vec!["Network connected".to_string(), "Block received".to_string()],
+            events: Vec::new(),
+            tags: Vec::new(),
+            agent_version: None,
+            clock: None,
+            sealing: None,
+            notes: Vec::new(),
+            recent_errors: Vec::new(),
+            update_available: None,
         }
     }
 
-    pub fn from_db_state(state: &db::AgentQueryResult, extra: &Option<db::AgentExtra>) -> Self {
+    pub fn from_db_state(
+        state: &db::AgentQueryResult,
+        extra: &Option<db::AgentExtra>,
+        tags: Vec<String>,
+        events: Vec<db::NodeEvent>,
+    ) -> Self {
         let mut dummy = Self::dummy();
         dummy.address = state.address;
         dummy.status = state.status;
@@ -151,13 +283,18 @@ impl NodeGetInfoResponse {
         dummy.start_option = extra.as_ref().map(|extra| StartOption {
             env: extra.prev_env.clone(),
             args: extra.prev_args.clone(),
+            binary_path: extra.binary_path.clone(),
         });
         dummy.hardware = state.hardware.clone();
+        dummy.agent_version = state.agent_version.clone();
+        dummy.clock = state.clock.clone();
+        dummy.tags = tags;
+        dummy.events = events;
         dummy
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct LogGetTargetsResponse {
     pub targets: Vec<String>,
@@ -165,8 +302,366 @@ pub struct LogGetTargetsResponse {
 
 pub type LogGetRequest = db::LogQueryParams;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct LogGetResponse {
     pub logs: Vec<db::Log>,
 }
+
+/**
+ * NetworkScenario describes a whole test network declaratively.
+ * It is produced by network_export and consumed by network_apply.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkScenario {
+    pub nodes: Vec<ScenarioNode>,
+    pub connections: Vec<NodeConnection>,
+    #[serde(default)]
+    pub exported_by: Option<BuildInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioNode {
+    pub name: NodeName,
+    pub env: String,
+    pub args: String,
+    pub commit_hash: Option<CommitHash>,
+    /// Runs the pre-installed binary instead of building the commit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum NetworkApplyAction {
+    Unchanged,
+    Started,
+    Restarted,
+    Updated,
+    Connected,
+    Disconnected,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkApplyNodeResult {
+    pub name: NodeName,
+    pub action: Option<NetworkApplyAction>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkApplyConnectionResult {
+    pub node_a: NodeName,
+    pub node_b: NodeName,
+    pub action: Option<NetworkApplyAction>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkApplyResponse {
+    pub nodes: Vec<NetworkApplyNodeResult>,
+    pub connections: Vec<NetworkApplyConnectionResult>,
+}
+
+#[derive(Debug, Serialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum NodeFindMatch {
+    Name,
+    Address,
+    Peer,
+    /// The coinbase of the node, which signs its blocks
+    NodeKey,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeFindResult {
+    pub name: NodeName,
+    pub address: Option<SocketAddr>,
+    pub matched_by: NodeFindMatch,
+    pub matched_peer: Option<SocketAddr>,
+}
+
+pub type EventGetRequest = db::NodeEventQueryParams;
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EventGetResponse {
+    pub events: Vec<db::NodeEvent>,
+    /// The notes attached to the events, the newest first
+    pub notes: Vec<db::Note>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DesiredStateRequest {
+    pub status: db::DesiredStatus,
+    /// The saved start option is used when they are omitted
+    #[serde(default)]
+    pub env: Option<String>,
+    #[serde(default)]
+    pub args: Option<String>,
+    #[serde(default)]
+    pub commit_hash: Option<CommitHash>,
+}
+
+pub type HistoryGetRequest = db::MetricQueryParams;
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryGetResponse {
+    pub resolution: db::MetricResolution,
+    pub samples: Vec<db::MetricSample>,
+}
+
+pub type AuditGetRequest = db::AuditQueryParams;
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditGetResponse {
+    pub audits: Vec<db::Audit>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeBulkResult {
+    pub name: NodeName,
+    pub error: Option<String>,
+}
+
+/// Selects the nodes which have any of the names or any of the tags
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeSelector {
+    #[serde(default)]
+    pub names: Vec<NodeName>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// The changes which node_bulkEdit applies to each selected node.
+/// The owner, the region and the namespace are the tags like "owner:alice", "region:us-east" and "namespace:team-a",
+/// and they replace the previous ones.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NodeEditPatch {
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    pub owner: Option<String>,
+    pub region: Option<String>,
+    /// Only the admins can change the namespace
+    pub namespace: Option<String>,
+    pub maintenance: Option<bool>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeEditResult {
+    pub name: NodeName,
+    pub tags_before: Vec<String>,
+    pub tags_after: Vec<String>,
+    pub maintenance_before: bool,
+    pub maintenance_after: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeBulkEditResponse {
+    /// Nothing is changed in the preview
+    pub preview: bool,
+    pub nodes: Vec<NodeEditResult>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum LogLevelChange {
+    /// The running node changed the level through its RPC
+    Rpc,
+    /// The node doesn't support the RPC, so it was restarted with the new RUST_LOG
+    Restart,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeSetLogLevelResponse {
+    pub changed_by: LogLevelChange,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeUpdateResponse {
+    /// The job which tracks the update. 0 in the sandbox
+    pub job_id: u64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HubStats {
+    /// The frontend RPC methods which have been called since the hub started
+    pub methods: Vec<MethodStats>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Fault {
+    #[serde(rename_all = "camelCase")]
+    DropAgentConnection {
+        name: NodeName,
+    },
+    #[serde(rename_all = "camelCase")]
+    DelayDbQueries {
+        delay_ms: u64,
+        duration_ms: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    FailNextUpdate {
+        name: NodeName,
+    },
+}
+
+#[derive(Default)]
+pub struct FaultState {
+    update_failures: Mutex<HashSet<NodeName>>,
+}
+
+impl FaultState {
+    pub fn fail_next_update(&self, name: NodeName) {
+        let mut update_failures = self.update_failures.lock().expect("Should success get update_failures");
+        update_failures.insert(name);
+    }
+
+    /// Returns true only once after fail_next_update is called.
+    pub fn take_update_failure(&self, name: &NodeName) -> bool {
+        let mut update_failures = self.update_failures.lock().expect("Should success get update_failures");
+        update_failures.remove(name)
+    }
+}
+
+/// A destructive request which waits for the approval of another operator
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingAction {
+    pub id: u64,
+    pub method: String,
+    /// The positional arguments of the method
+    pub arguments: Value,
+    /// The name of the token of the operator who requested it
+    pub requested_by: String,
+    /// The namespace of the operator who requested it
+    pub namespace: Option<String>,
+    pub requested_at: chrono::DateTime<chrono::Local>,
+}
+
+/// The pending actions are kept in memory, so they are dropped when the hub restarts.
+#[derive(Default)]
+pub struct ApprovalQueue {
+    /// The next id and the pending actions
+    actions: Mutex<(u64, Vec<PendingAction>)>,
+}
+
+impl ApprovalQueue {
+    pub fn add(
+        &self,
+        method: &str,
+        arguments: Value,
+        requested_by: String,
+        namespace: Option<String>,
+    ) -> u64 {
+        let mut actions = self.actions.lock().expect("Should success get actions");
+        actions.0 += 1;
+        let id = actions.0;
+        actions.1.push(PendingAction {
+            id,
+            method: method.to_string(),
+            arguments,
+            requested_by,
+            namespace,
+            requested_at: chrono::Local::now(),
+        });
+        id
+    }
+
+    /// Removes the expired actions too
+    pub fn list(&self, expire_after: chrono::Duration) -> Vec<PendingAction> {
+        let mut actions = self.actions.lock().expect("Should success get actions");
+        let expired_at = chrono::Local::now() - expire_after;
+        actions.1.retain(|action| action.requested_at > expired_at);
+        actions.1.clone()
+    }
+
+    pub fn take(&self, id: u64) -> Option<PendingAction> {
+        let mut actions = self.actions.lock().expect("Should success get actions");
+        let index = actions.1.iter().position(|action| action.id == id)?;
+        Some(actions.1.remove(index))
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiChangesResponse {
+    pub current_version: String,
+    pub changes: Vec<ApiChange>,
+}
+
+/// What happens when the node goes down. Validators are the nodes which have the "validator" tag.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeStopImpact {
+    pub name: NodeName,
+    pub status: NodeStatus,
+    pub is_validator: bool,
+    pub validators: usize,
+    pub running_validators: usize,
+    pub running_validators_after_stop: usize,
+    pub quorum: usize,
+    pub violates_quorum: bool,
+    /// The number of validators which can go down more without halting the consensus
+    pub safety_margin: i64,
+    /// None if it is not a running validator or cannot be estimated
+    pub next_proposal_in_blocks: Option<i64>,
+    pub proposing_soon: bool,
+    pub warnings: Vec<String>,
+}
+
+/// The changes of the fleet in a window, which are summarized from the events and the audit log.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeReport {
+    pub since: chrono::DateTime<chrono::Local>,
+    pub until: chrono::DateTime<chrono::Local>,
+    pub updates_applied: Vec<db::NodeEvent>,
+    pub nodes_added: Vec<NodeName>,
+    pub nodes_removed: Vec<NodeName>,
+    pub config_edits: Vec<db::Audit>,
+    pub incidents_opened: Vec<Incident>,
+    pub incidents_closed: Vec<Incident>,
+    pub version_drift: VersionDrift,
+}
+
+/// A crash of CodeChain or a disconnection of the agent, which lasts until the node recovers
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Incident {
+    pub node_name: NodeName,
+    /// CodeChainCrashed or AgentDisconnected
+    pub kind: db::NodeEventKind,
+    pub opened_at: chrono::DateTime<chrono::Local>,
+    /// None if the incident is still open
+    pub closed_at: Option<chrono::DateTime<chrono::Local>>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionDrift {
+    /// The number of nodes by the version hash at the beginning of the window
+    pub before: BTreeMap<String, usize>,
+    /// The number of nodes by the version hash now
+    pub after: BTreeMap<String, usize>,
+    /// The change of the number of the distinct versions
+    pub delta: i64,
+}
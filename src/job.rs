@@ -0,0 +1,220 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono;
+
+use super::common_rpc_types::NodeName;
+use super::frontend;
+use super::jsonrpc;
+use super::jsonrpc::Cancellation;
+
+/// The oldest finished jobs are dropped over this number
+const MAX_FINISHED_JOBS: usize = 1000;
+/// Only the last lines of the output are kept
+const MAX_OUTPUT_LINES: usize = 100;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub enum JobStatus {
+    Running,
+    Done,
+    Failed,
+    /// Cancelled by job_cancel
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub id: u64,
+    /// The RPC which started the job, e.g. "node_update"
+    pub method: String,
+    pub node_name: NodeName,
+    pub status: JobStatus,
+    /// The step which the job is in, e.g. "cloning", "building" or "restarting"
+    pub phase: Option<String>,
+    /// The last lines of the output of the agent, e.g. the build errors
+    pub output: Vec<String>,
+    pub error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Local>,
+    pub updated_at: chrono::DateTime<chrono::Local>,
+}
+
+/// The params of job_progress, which the agents with the jobProgress capability send while they run a job
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgress {
+    pub job_id: u64,
+    #[serde(default)]
+    pub phase: Option<String>,
+    /// The new lines of the output since the last progress
+    #[serde(default)]
+    pub output: Vec<String>,
+    /// Done or Failed when the job is finished
+    #[serde(default)]
+    pub status: Option<JobStatus>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// The long-running operations which the frontends can follow with job_get, job_list and job_updated.
+/// The jobs are kept in memory, so they are lost when the hub restarts.
+pub struct Jobs {
+    /// The last id and the jobs, the oldest first
+    jobs: Mutex<(u64, VecDeque<Job>)>,
+    /// The cancellations of the running jobs
+    cancellations: Mutex<HashMap<u64, Cancellation>>,
+    frontend_service: frontend::ServiceSender,
+}
+
+impl Jobs {
+    pub fn new(frontend_service: frontend::ServiceSender) -> Self {
+        Self {
+            jobs: Default::default(),
+            cancellations: Default::default(),
+            frontend_service,
+        }
+    }
+
+    pub fn create(&self, method: &str, node_name: &NodeName) -> u64 {
+        let now = chrono::Local::now();
+        let job = {
+            let mut jobs = self.jobs.lock().expect("Should success get jobs");
+            jobs.0 += 1;
+            let job = Job {
+                id: jobs.0,
+                method: method.to_string(),
+                node_name: node_name.clone(),
+                status: JobStatus::Running,
+                phase: None,
+                output: Vec::new(),
+                error: None,
+                created_at: now,
+                updated_at: now,
+            };
+            jobs.1.push_back(job.clone());
+            self.cancellations.lock().expect("Should success get cancellations").insert(job.id, Default::default());
+            let finished = jobs.1.iter().filter(|job| job.status != JobStatus::Running).count();
+            if finished > MAX_FINISHED_JOBS {
+                if let Some(index) = jobs.1.iter().position(|job| job.status != JobStatus::Running) {
+                    jobs.1.remove(index);
+                }
+            }
+            job
+        };
+        self.notify(&job);
+        job.id
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        let jobs = self.jobs.lock().expect("Should success get jobs");
+        jobs.1.iter().find(|job| job.id == id).cloned()
+    }
+
+    /// The newest first
+    pub fn list(&self, node_name: Option<&NodeName>) -> Vec<Job> {
+        let jobs = self.jobs.lock().expect("Should success get jobs");
+        jobs.1.iter().rev().filter(|job| node_name.map_or(true, |name| job.node_name == *name)).cloned().collect()
+    }
+
+    /// The calls made with it stop waiting when the job is cancelled. It is already cancelled if the job is finished.
+    pub fn cancellation(&self, id: u64) -> Cancellation {
+        let cancellations = self.cancellations.lock().expect("Should success get cancellations");
+        cancellations.get(&id).cloned().unwrap_or_else(|| {
+            let cancellation = Cancellation::default();
+            cancellation.cancel();
+            cancellation
+        })
+    }
+
+    /// Stops waiting for the agent. Fails if the job is not running.
+    pub fn cancel(&self, id: u64) -> Result<(), String> {
+        let job = self.get(id).ok_or_else(|| format!("Job {} is not found", id))?;
+        if job.status != JobStatus::Running {
+            return Err(format!("Job {} is {:?} already", id, job.status))
+        }
+        let cancellation = self.cancellation(id);
+        self.update(id, |job| job.status = JobStatus::Cancelled);
+        cancellation.cancel();
+        Ok(())
+    }
+
+    pub fn set_phase(&self, id: u64, phase: &str) {
+        self.update(id, |job| job.phase = Some(phase.to_string()));
+    }
+
+    pub fn finish(&self, id: u64, result: Result<(), String>) {
+        self.update(id, |job| match result {
+            Ok(()) => job.status = JobStatus::Done,
+            Err(err) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(err);
+            }
+        });
+    }
+
+    /// `node_name` is the node of the agent which sent the progress. An agent can't change the jobs of the other nodes
+    pub fn on_progress(&self, node_name: Option<&NodeName>, progress: JobProgress) {
+        let JobProgress {
+            job_id,
+            phase,
+            output,
+            status,
+            error,
+        } = progress;
+        let job = match self.get(job_id) {
+            Some(job) => job,
+            None => {
+                cwarn!("An agent sent the progress of the unknown job {}", job_id);
+                return
+            }
+        };
+        if Some(&job.node_name) != node_name {
+            cwarn!("The agent of {:?} sent the progress of the job {} of {}", node_name, job_id, job.node_name);
+            return
+        }
+        self.update(job_id, |job| {
+            if phase.is_some() {
+                job.phase = phase;
+            }
+            job.output.extend(output);
+            let overflow = job.output.len().saturating_sub(MAX_OUTPUT_LINES);
+            job.output.drain(..overflow);
+            if let Some(status) = status {
+                job.status = status;
+            }
+            if error.is_some() {
+                job.error = error;
+            }
+        });
+    }
+
+    /// The finished job doesn't change
+    fn update<F>(&self, id: u64, f: F)
+    where
+        F: FnOnce(&mut Job), {
+        let job = {
+            let mut jobs = self.jobs.lock().expect("Should success get jobs");
+            let job = match jobs.1.iter_mut().find(|job| job.id == id) {
+                Some(job) => job,
+                None => return,
+            };
+            if job.status != JobStatus::Running {
+                return
+            }
+            f(job);
+            job.updated_at = chrono::Local::now();
+            job.clone()
+        };
+        if job.status != JobStatus::Running {
+            self.cancellations.lock().expect("Should success get cancellations").remove(&id);
+        }
+        self.notify(&job);
+    }
+
+    fn notify(&self, job: &Job) {
+        let message = jsonrpc::serialize_notification("job_updated", job);
+        self.frontend_service
+            .send(frontend::Message::SendNodeEvent(vec![job.node_name.clone()], message))
+            .expect("Should success send event");
+    }
+}
@@ -1,17 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
 use std::fmt;
 use std::option::Option;
 use std::result::Result::{Err, Ok};
 use std::sync::mpsc::{channel, RecvError, RecvTimeoutError, Sender};
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::sync::PoisonError;
-use std::time::Duration;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use jsonrpc_core::types::{
-    Call, Error as JSONRPCError, ErrorCode, Failure, Id, MethodCall, Notification, Output, Params, Response, Success,
-    Version,
+    Call, Error as JSONRPCError, ErrorCode, Failure, Id, MethodCall, Notification, Output, Params, Request, Response,
+    Success, Version,
 };
 use rand;
 use serde::de::DeserializeOwned;
@@ -20,11 +20,12 @@ use serde_json;
 use serde_json::{Error as SerdeError, Value};
 
 use super::router::Error as RouterError;
+use super::secrets;
 use super::ws::{Error as WSError, Message, Sender as WSSender};
 
 pub fn handle<F>(router: F, text: String) -> Option<String>
 where
-    F: FnOnce(String, Value) -> Result<Option<Value>, RouterError>, {
+    F: Fn(String, Value) -> Result<Option<Value>, RouterError>, {
     let deserialized = serde_json::from_str(&text);
     let response: Option<Response> = match deserialized {
         Err(_) => Some(
@@ -35,63 +36,73 @@ where
             }
             .into(),
         ),
-        Ok(Call::Invalid(id)) => Some(
+        Ok(Request::Single(call)) => handle_call(&router, call).map(Response::Single),
+        Ok(Request::Batch(ref calls)) if calls.is_empty() => Some(
             Failure {
                 jsonrpc: None,
-                id,
-                error: JSONRPCError::new(ErrorCode::ParseError),
+                id: Id::Null,
+                error: JSONRPCError::new(ErrorCode::InvalidRequest),
             }
             .into(),
         ),
-        Ok(Call::MethodCall(MethodCall {
+        Ok(Request::Batch(calls)) => {
+            let outputs: Vec<Output> = calls.into_iter().filter_map(|call| handle_call(&router, call)).collect();
+            // A batch which only contains notifications has no response
+            if outputs.is_empty() {
+                None
+            } else {
+                Some(Response::Batch(outputs))
+            }
+        }
+    };
+    response.map(|response| serde_json::to_string(&response).expect("Should success serialize"))
+}
+
+fn handle_call<F>(router: &F, call: Call) -> Option<Output>
+where
+    F: Fn(String, Value) -> Result<Option<Value>, RouterError>, {
+    match call {
+        Call::Invalid(id) => Some(Output::Failure(Failure {
+            jsonrpc: None,
+            id,
+            error: JSONRPCError::new(ErrorCode::ParseError),
+        })),
+        Call::MethodCall(MethodCall {
             id,
             method,
             params,
             ..
-        })) => {
+        }) => {
             let value_params = serde_json::to_value(params).expect("Change to value always success");
             match router(method, value_params) {
-                Ok(Some(value)) => Some(
-                    Success {
-                        jsonrpc: None,
-                        result: value,
-                        id,
-                    }
-                    .into(),
-                ),
+                Ok(Some(value)) => Some(Output::Success(Success {
+                    jsonrpc: None,
+                    result: value,
+                    id,
+                })),
                 Ok(None) => {
                     let mut error = JSONRPCError::new(ErrorCode::InternalError);
                     error.data = Some(serde_json::Value::String("API returns no value".to_string()));
-                    Some(
-                        Failure {
-                            jsonrpc: None,
-                            id,
-                            error,
-                        }
-                        .into(),
-                    )
-                }
-                Err(RouterError::MethodNotFound) => Some(
-                    Failure {
-                        jsonrpc: None,
-                        id,
-                        error: JSONRPCError::new(ErrorCode::MethodNotFound),
-                    }
-                    .into(),
-                ),
-                Err(RouterError::RPC(err)) => Some(
-                    Failure {
+                    Some(Output::Failure(Failure {
                         jsonrpc: None,
                         id,
-                        error: err.to_jsonrpc_error(),
-                    }
-                    .into(),
-                ),
+                        error,
+                    }))
+                }
+                Err(RouterError::MethodNotFound) => Some(Output::Failure(Failure {
+                    jsonrpc: None,
+                    id,
+                    error: JSONRPCError::new(ErrorCode::MethodNotFound),
+                })),
+                Err(RouterError::RPC(err)) => Some(Output::Failure(Failure {
+                    jsonrpc: None,
+                    id,
+                    error: err.to_jsonrpc_error(),
+                })),
             }
         }
-        Ok(Call::Notification(_)) => None,
-    };
-    response.map(|response| serde_json::to_string(&response).expect("Should success serialize"))
+        Call::Notification(_) => None,
+    }
 }
 
 pub fn invalid_format() -> String {
@@ -103,17 +114,144 @@ pub fn invalid_format() -> String {
     .expect("Should success serialize")
 }
 
+/// The lane of an outgoing request.
+/// Control requests are sent at once, while the telemetry requests wait for the earlier ones to be answered.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Priority {
+    Control,
+    Telemetry,
+}
+
+#[derive(Clone, Default, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LaneMetrics {
+    pub queued: usize,
+    pub sent: u64,
+    pub total_wait_ms: u64,
+    pub max_wait_ms: u64,
+}
+
+impl LaneMetrics {
+    fn record_sent(&mut self, queued_at: Instant) {
+        let wait = queued_at.elapsed();
+        let wait_ms = wait.as_secs() * 1000 + u64::from(wait.subsec_millis());
+        self.sent += 1;
+        self.total_wait_ms += wait_ms;
+        if wait_ms > self.max_wait_ms {
+            self.max_wait_ms = wait_ms;
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelMetrics {
+    pub control: LaneMetrics,
+    pub telemetry: LaneMetrics,
+}
+
+/// The telemetry requests which the agent answers at once. The others wait in the telemetry lane,
+/// so the control requests don't wait behind them in the agent.
+const MAX_TELEMETRY_IN_FLIGHT: usize = 4;
+
+#[derive(Default)]
+struct Lanes {
+    /// The ids, the queued times and the telemetry requests which wait for a slot
+    telemetry: VecDeque<(u64, Instant, String)>,
+    /// The ids of the telemetry requests which are sent and not answered yet
+    in_flight: Vec<u64>,
+    metrics: ChannelMetrics,
+    closed: bool,
+}
+
+struct Outbox {
+    lanes: Mutex<Lanes>,
+    ws_sender: WSSender,
+}
+
+impl Outbox {
+    /// The control requests and the notifications are sent at once.
+    /// The telemetry requests are sent when fewer than MAX_TELEMETRY_IN_FLIGHT are waiting for their responses.
+    fn push(&self, priority: Priority, id: Option<u64>, message: String) {
+        let mut lanes = self.lanes.lock().expect("Should success get lanes");
+        if lanes.closed {
+            return
+        }
+        match (priority, id) {
+            (Priority::Telemetry, Some(id)) => {
+                lanes.telemetry.push_back((id, Instant::now(), message));
+                lanes.metrics.telemetry.queued += 1;
+                self.send_telemetry(&mut lanes);
+            }
+            (Priority::Telemetry, None) => {
+                lanes.metrics.telemetry.record_sent(Instant::now());
+                self.send(message);
+            }
+            (Priority::Control, _) => {
+                lanes.metrics.control.record_sent(Instant::now());
+                self.send(message);
+            }
+        }
+    }
+
+    /// Frees the slot of the request when it is answered, timed out or cancelled
+    fn finish(&self, id: u64) {
+        let mut lanes = self.lanes.lock().expect("Should success get lanes");
+        if let Some(index) = lanes.in_flight.iter().position(|in_flight| *in_flight == id) {
+            lanes.in_flight.remove(index);
+        } else if let Some(index) = lanes.telemetry.iter().position(|(queued, ..)| *queued == id) {
+            lanes.telemetry.remove(index);
+            lanes.metrics.telemetry.queued -= 1;
+        }
+        self.send_telemetry(&mut lanes);
+    }
+
+    fn send_telemetry(&self, lanes: &mut Lanes) {
+        while lanes.in_flight.len() < MAX_TELEMETRY_IN_FLIGHT && !lanes.closed {
+            let (id, queued_at, message) = match lanes.telemetry.pop_front() {
+                Some(queued) => queued,
+                None => return,
+            };
+            lanes.metrics.telemetry.queued -= 1;
+            lanes.metrics.telemetry.record_sent(queued_at);
+            lanes.in_flight.push(id);
+            self.send(message);
+        }
+    }
+
+    fn send(&self, message: String) {
+        if let Err(err) = self.ws_sender.send(Message::Text(message)) {
+            cerror!("Cannot send JSONRPC request {}", err);
+        }
+    }
+
+    fn close(&self) {
+        let mut lanes = self.lanes.lock().expect("Should success get lanes");
+        lanes.closed = true;
+        lanes.telemetry.clear();
+        lanes.in_flight.clear();
+        lanes.metrics.telemetry.queued = 0;
+    }
+}
+
 #[derive(Clone)]
 pub struct Context {
     pub ws_sender: WSSender,
     pub ws_callback: Arc<Mutex<HashMap<u64, Sender<String>>>>,
+    outbox: Arc<Outbox>,
 }
 
 impl Context {
     pub fn new(sender: WSSender) -> Self {
+        let outbox = Arc::new(Outbox {
+            lanes: Mutex::new(Default::default()),
+            ws_sender: sender.clone(),
+        });
+
         Self {
             ws_sender: sender,
             ws_callback: Arc::new(Mutex::new(HashMap::new())),
+            outbox,
         }
     }
 
@@ -126,15 +264,74 @@ impl Context {
         let mut ws_callback = self.ws_callback.lock().unwrap();
         ws_callback.remove(&id);
     }
+
+    pub fn channel_metrics(&self) -> ChannelMetrics {
+        let lanes = self.outbox.lanes.lock().expect("Should success get lanes");
+        lanes.metrics.clone()
+    }
+
+    /// Sends a notification to the agent in the lane of the priority.
+    pub fn notify(&self, priority: Priority, notification: String) {
+        ctrace!("send JSONRPC notification {}", notification);
+        self.outbox.push(priority, None, notification);
+    }
+
+    /// Drops the queued requests.
+    pub fn close(&self) {
+        self.outbox.close();
+    }
 }
 
+/// The agent RPCs which have no timeout of their own wait for the response in this duration
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub enum CallError {
     InternalWS(WSError),
     InternalRecv(RecvError),
     InternalSerde(SerdeError),
     InternalSync(String),
     Response(JSONRPCError),
-    Timeout(RecvTimeoutError),
+    /// The agent didn't respond in the duration
+    Timeout(Duration),
+    /// The caller stopped waiting for the response by Cancellation::cancel
+    Cancelled,
+}
+
+/// Stops waiting for the responses of the calls made with it. The calls made after it is cancelled fail at once.
+#[derive(Clone, Default)]
+pub struct Cancellation {
+    /// Whether it is cancelled, and the contexts and the ids of the calls which are waiting
+    state: Arc<Mutex<(bool, Vec<(Context, u64)>)>>,
+}
+
+impl Cancellation {
+    pub fn cancel(&self) {
+        let mut state = self.state.lock().expect("Should success get cancellation");
+        state.0 = true;
+        // The waiting calls are disconnected when their callbacks are dropped
+        for (context, id) in state.1.drain(..) {
+            context.remove_callback(id);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.lock().expect("Should success get cancellation").0
+    }
+
+    /// Returns false if it is cancelled already
+    fn register(&self, context: &Context, id: u64) -> bool {
+        let mut state = self.state.lock().expect("Should success get cancellation");
+        if state.0 {
+            return false
+        }
+        state.1.push((context.clone(), id));
+        true
+    }
+
+    fn unregister(&self, id: u64) {
+        let mut state = self.state.lock().expect("Should success get cancellation");
+        state.1.retain(|(_, call_id)| *call_id != id);
+    }
 }
 
 impl From<WSError> for CallError {
@@ -167,26 +364,15 @@ impl From<JSONRPCError> for CallError {
     }
 }
 
-impl From<RecvTimeoutError> for CallError {
-    fn from(error: RecvTimeoutError) -> Self {
-        CallError::Timeout(error)
-    }
-}
-
-pub fn call_no_arg<Res>(context: Context, method: &str) -> Result<Res, CallError>
-where
-    Res: DeserializeOwned, {
-    call_one_arg(context, method, Value::Null)
-}
-
-pub fn call_one_arg<Arg, Res>(context: Context, method: &str, arg: Arg) -> Result<Res, CallError>
-where
-    Arg: Serialize,
-    Res: DeserializeOwned, {
-    call_many_args(context, method, vec![arg])
-}
-
-pub fn call_many_args<Arg, Res>(context: Context, method: &str, args: Arg) -> Result<Res, CallError>
+/// Waits for the response in the timeout, or until the cancellation is cancelled.
+pub fn call_with_timeout<Arg, Res>(
+    context: Context,
+    priority: Priority,
+    method: &str,
+    args: Arg,
+    timeout: Duration,
+    cancellation: Option<&Cancellation>,
+) -> Result<Res, CallError>
 where
     Arg: Serialize,
     Res: DeserializeOwned, {
@@ -201,11 +387,25 @@ where
     };
     let serialized_request = serde_json::to_string(&request)?;
     context.add_callback(id, tx);
-    ctrace!("send JSONRPC {}", serialized_request);
-    context.ws_sender.send(Message::Text(serialized_request))?;
-    let receive_result = rx.recv_timeout(Duration::new(10, 0));
+    if let Some(cancellation) = cancellation {
+        if !cancellation.register(&context, id) {
+            context.remove_callback(id);
+            return Err(CallError::Cancelled)
+        }
+    }
+    ctrace!("send JSONRPC {}", redact_env(&request));
+    context.outbox.push(priority, Some(id), serialized_request);
+    let receive_result = rx.recv_timeout(timeout);
+    context.outbox.finish(id);
     context.remove_callback(id);
-    let received_string = receive_result?;
+    if let Some(cancellation) = cancellation {
+        cancellation.unregister(id);
+    }
+    let received_string = match receive_result {
+        Ok(received_string) => received_string,
+        Err(RecvTimeoutError::Timeout) => return Err(CallError::Timeout(timeout)),
+        Err(RecvTimeoutError::Disconnected) => return Err(CallError::Cancelled),
+    };
     ctrace!("Receive JSONRPC {}", received_string);
 
     let res = serde_json::from_str(&received_string)?;
@@ -219,6 +419,20 @@ where
     }
 }
 
+/// The env of the start commands has the values of the secrets, so they are not logged
+fn redact_env(request: &MethodCall) -> String {
+    let mut request = request.clone();
+    if let Some(Params::Array(params)) = request.params.as_mut() {
+        for param in params.iter_mut() {
+            let redacted = param.get("env").and_then(Value::as_str).map(secrets::redact_all_env);
+            if let Some(redacted) = redacted {
+                param["env"] = Value::String(redacted);
+            }
+        }
+    }
+    serde_json::to_string(&request).unwrap_or_default()
+}
+
 pub fn serialize_notification<Arg>(method: &str, arg: Arg) -> String
 where
     Arg: Serialize, {
@@ -232,6 +446,19 @@ where
     serde_json::to_string(&noti).expect("Should success serialize")
 }
 
+#[derive(Deserialize)]
+struct Incoming {
+    method: Option<String>,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Returns the method and the params if the message is a notification from the agent, not a response.
+pub fn parse_notification(text: &str) -> Option<(String, Value)> {
+    let incoming: Incoming = serde_json::from_str(text).ok()?;
+    incoming.method.map(|method| (method, incoming.params))
+}
+
 // Called on websocket thread
 pub fn on_receive(context: Context, text: String) {
     match on_receive_internal(context, text) {
@@ -272,7 +499,8 @@ impl fmt::Display for CallError {
             CallError::InternalSerde(err) => write!(f, "Call Internal Error {}", err),
             CallError::InternalSync(err) => write!(f, "Call Internal Error {}", err),
             CallError::Response(err) => write!(f, "JSONRPC error {:?}", err),
-            CallError::Timeout(err) => write!(f, "Timeout {}", err),
+            CallError::Timeout(timeout) => write!(f, "No response in {:?}", timeout),
+            CallError::Cancelled => write!(f, "Cancelled"),
         }
     }
 }
@@ -1,5 +1,16 @@
+#[cfg(feature = "mock-agent")]
+extern crate chrono;
 extern crate log;
+#[cfg(feature = "mock-agent")]
+#[macro_use]
+extern crate serde_json;
+#[cfg(feature = "mock-agent")]
+extern crate ws;
 
 #[macro_use]
 pub mod logger;
 pub use logger::init as logger_init;
+
+/// The mock agent and the helpers for the integration tests
+#[cfg(feature = "mock-agent")]
+pub mod test_support;
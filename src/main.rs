@@ -1,172 +1,321 @@
 #[macro_use]
 extern crate log;
 
+extern crate aes_gcm;
+extern crate base64;
 extern crate chrono;
 extern crate codechain_rpc as crpc;
+extern crate cron;
+extern crate ctrlc;
+extern crate hyper;
 extern crate iron;
 extern crate jsonrpc_core;
+#[macro_use]
+extern crate juniper;
 extern crate postgres;
 extern crate primitives as cprimitives;
+extern crate r2d2;
+extern crate r2d2_postgres;
 extern crate rand;
+extern crate rusqlite;
+#[macro_use]
+extern crate schemars;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
+extern crate sha2;
+extern crate toml;
 extern crate ws;
 
 #[macro_use]
 mod logger;
 mod agent;
+mod allowlist;
+mod build_info;
 mod common_rpc_types;
+mod config;
 mod db;
+mod distribution;
 mod event_propagator;
+mod fork;
 mod frontend;
+mod job;
 mod jsonrpc;
+mod metrics_history;
+mod outage;
+mod rate_limit;
+mod release;
 mod router;
 mod rpc;
+mod rpc_metrics;
+mod secrets;
+mod session;
+mod shutdown;
+mod slo;
 mod util;
+mod web;
 
 use std::cell::Cell;
-use std::fmt;
+use std::env;
+use std::net::SocketAddr;
+use std::process;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::thread;
 
 use iron::prelude::*;
-use iron::status;
-use ws::listen;
+use ws::WebSocket;
 
-use self::agent::SendAgentRPC;
+use self::build_info::BuildInfo;
+use self::config::Config;
 use self::event_propagator::EventPropagator;
 use self::logger::init as logger_init;
+use self::rate_limit::RateLimiter;
 use self::router::Router;
 
 fn main() {
     logger_init().expect("Logger should be initialized");
 
-    // FIXME: move to config
-    let db_user = "codechain-agent-hub";
-    let db_password = "preempt-entreat-bell-chanson";
+    let args: Vec<String> = env::args().collect();
+    // Applies the migrations and exits
+    let migrate_only = args.iter().any(|arg| arg == "--migrate-only");
+    // Exits with 1 if there are pending migrations
+    let check = args.iter().any(|arg| arg == "--check");
+
+    let config_path = args.iter().position(|arg| arg == "--config").and_then(|index| args.get(index + 1));
+    let config = match Config::load(config_path.map(String::as_str)) {
+        Ok(config) => Arc::new(config),
+        Err(err) => {
+            cerror!("{}", err);
+            process::exit(1);
+        }
+    };
 
-    let frontend_service_sender = frontend::Service::run_thread();
+    let storage = db::create_storage(config.storage.clone());
+    if check {
+        check_migrations(&*storage);
+    }
+    // The web listener answers the health checks while this hub waits as the standby
+    let standby_handler = web::StandbyHandler::default();
+    let webserver_join = if migrate_only {
+        None
+    } else {
+        Some(run_webserver(config.listen.web, standby_handler.clone()))
+    };
+    // Only the leader migrates the database and serves
+    if let (Some(option), false) = (&config.leader_election, migrate_only) {
+        db::leader::become_leader(&config.storage, option);
+    }
+    let schema_version = match db::migrations::migrate(&*storage) {
+        Ok(schema_version) => schema_version,
+        Err(err) => {
+            cerror!("Cannot migrate the database : {}", err);
+            process::exit(1);
+        }
+    };
+    if migrate_only {
+        cinfo!("The database is migrated to version {}", schema_version);
+        return
+    }
+
+    let mut build_info = BuildInfo::new();
+    build_info.schema_version = Some(schema_version);
+    build_info.config_hash = config.hash.clone();
+    let build_info = Arc::new(build_info);
+    build_info.print_banner();
+
+    let frontend_service_sender = frontend::Service::run_thread(Arc::clone(&storage));
     let event_propagater = Box::new(EventPropagator::new(frontend_service_sender.clone()));
     let db_service_sender = db::Service::run_thread(db::ServiceNewArg {
         event_subscriber: event_propagater,
-        db_user: db_user.to_string(),
-        db_password: db_password.to_string(),
+        storage,
+        log_storage: config.log_storage.clone(),
     });
-    let agent_service_sender = agent::Service::run_thread(db_service_sender.clone());
-    let agent_service_for_frontend = agent_service_sender.clone();
-    let web_handler = WebHandler::new(agent_service_sender.clone());
+    let agent_service_sender = agent::Service::run_thread(
+        db_service_sender.clone(),
+        config.agent.workers,
+        config.agent.update_interval(),
+        config.agent.command_timeouts.clone(),
+        config.agent.max_clock_skew_ms,
+    );
+    slo::run_thread(db_service_sender.clone(), frontend_service_sender.clone());
+    metrics_history::run_thread(db_service_sender.clone(), config.metrics_history.clone());
+    outage::run_thread(db_service_sender.clone(), frontend_service_sender.clone());
+    fork::run_thread(db_service_sender.clone(), frontend_service_sender.clone());
+    let jobs = Arc::new(job::Jobs::new(frontend_service_sender.clone()));
+    let sessions = Arc::new(session::Sessions::default());
+    let releases = match &config.release_tracking {
+        Some(option) => release::run_thread(option.clone(), db_service_sender.clone(), frontend_service_sender.clone()),
+        None => Default::default(),
+    };
+
+    let mut frontend_router = Arc::new(Router::new());
+    frontend::add_routing(Arc::get_mut(&mut frontend_router).unwrap());
+    Arc::get_mut(&mut frontend_router).unwrap().set_rate_limit(config.frontend.rate_limit);
+    let frontend_context = frontend::Context {
+        agent_service: agent_service_sender.clone(),
+        db_service: db_service_sender.clone(),
+        frontend_service: frontend_service_sender.clone(),
+        build_info,
+        methods: Arc::new(frontend_router.describe()),
+        rpc_metrics: frontend_router.metrics(),
+        faults: if config.frontend.fault_injection {
+            Some(Default::default())
+        } else {
+            None
+        },
+        approvals: if config.frontend.approval_required {
+            Some(Default::default())
+        } else {
+            None
+        },
+        distributor: config.distribution.as_ref().map(|option| {
+            Arc::new(distribution::Distributor::new(option.clone(), frontend_service_sender.clone(), Arc::clone(&jobs)))
+        }),
+        jobs: Arc::clone(&jobs),
+        sessions: Arc::clone(&sessions),
+        caller: None,
+        peer_addr: None,
+        namespace: None,
+        identity: None,
+        config: Arc::clone(&config),
+        releases,
+        network_history: Default::default(),
+        sandbox: false,
+    };
 
+    frontend::schedule::run_thread(frontend_context.clone());
+    frontend::reconcile::run_thread(frontend_context.clone(), frontend_service_sender.clone());
+
+    let shutting_down = Arc::new(AtomicBool::new(false));
+    let listeners = Arc::new(web::ListenerStatus::default());
+
+    let web_handler = web::WebHandler::new(web::WebHandlerNewArg {
+        agent_service_sender: agent_service_sender.clone(),
+        db_service_sender: db_service_sender.clone(),
+        frontend_service_sender: frontend_service_sender.clone(),
+        frontend_context: frontend_context.clone(),
+        frontend_router: frontend_router.clone(),
+        public_metrics: config.public_metrics.clone(),
+        rest_gateway: config.frontend.rest_gateway,
+        graphql: config.frontend.graphql,
+        listeners: Arc::clone(&listeners),
+        shutting_down: Arc::clone(&shutting_down),
+    });
+
+    let (frontend_listener_tx, frontend_listener_rx) = channel();
+    let frontend_shutting_down = Arc::clone(&shutting_down);
+    let frontend_connection = config.frontend.connection_option();
+    let frontend_address = config.listen.frontend;
+    let frontend_listeners = Arc::clone(&listeners);
     let frontend_join = thread::Builder::new()
         .name("frontend listen".to_string())
         .spawn(move || {
             let count = Rc::new(Cell::new(0));
-            let mut frontend_router = Arc::new(Router::new());
-            frontend::add_routing(Arc::get_mut(&mut frontend_router).unwrap());
-            let frontend_context = frontend::Context {
-                agent_service: agent_service_for_frontend,
-                db_service: db_service_sender.clone(),
+            let settings = ws::Settings {
+                max_fragment_size: frontend_connection.max_message_size,
+                ..Default::default()
             };
-            listen("0.0.0.0:3012", move |out| frontend::WebSocketHandler {
-                out,
-                count: count.clone(),
-                context: frontend_context.clone(),
-                router: frontend_router.clone(),
-                frontend_service: frontend_service_sender.clone(),
-            })
-            .unwrap();
+            let web_socket = ws::Builder::new()
+                .with_settings(settings)
+                .build(move |out| frontend::WebSocketHandler {
+                    out,
+                    count: count.clone(),
+                    context: frontend_context.clone(),
+                    router: frontend_router.clone(),
+                    rate_limiter: frontend_router.rate_limiter(),
+                    frontend_service: frontend_service_sender.clone(),
+                    shutting_down: Arc::clone(&frontend_shutting_down),
+                    connection_option: frontend_connection,
+                    expire_timeout: None,
+                    session_id: None,
+                    unauthenticated_peer: None,
+                })
+                .unwrap();
+            frontend_listener_tx.send(web_socket.broadcaster()).expect("Should success send the frontend listener");
+            let web_socket = web_socket.bind(frontend_address).unwrap();
+            frontend_listeners.frontend.store(true, Ordering::SeqCst);
+            web_socket.run().unwrap();
+            frontend_listeners.frontend.store(false, Ordering::SeqCst);
         })
         .expect("Should success listening frontend");
 
+    let (agent_listener_tx, agent_listener_rx) = channel();
+    let agent_shutting_down = Arc::clone(&shutting_down);
+    let agent_service = agent_service_sender.clone();
+    let agent_accept_limiter = Arc::new(RateLimiter::new(Some(config.agent.accept_rate)));
+    let agent_address = config.listen.agent;
+    let agent_allowlist = Arc::new(config.listen.agent_allowlist.clone());
+    let agent_listeners = Arc::clone(&listeners);
     let agent_join = thread::Builder::new()
         .name("agent listen".to_string())
         .spawn(move || {
             let count = Rc::new(Cell::new(0));
-            listen("0.0.0.0:4012", |out| {
-                agent::WebSocketHandler::new(out, count.clone(), agent_service_sender.clone())
+            let web_socket = WebSocket::new(|out| {
+                agent::WebSocketHandler::new(
+                    out,
+                    count.clone(),
+                    agent_service.clone(),
+                    Arc::clone(&agent_shutting_down),
+                    Arc::clone(&agent_accept_limiter),
+                    Arc::clone(&agent_allowlist),
+                    Arc::clone(&jobs),
+                    Arc::clone(&sessions),
+                )
             })
             .unwrap();
+            agent_listener_tx.send(web_socket.broadcaster()).expect("Should success send the agent listener");
+            let web_socket = web_socket.bind(agent_address).unwrap();
+            agent_listeners.agent.store(true, Ordering::SeqCst);
+            web_socket.run().unwrap();
+            agent_listeners.agent.store(false, Ordering::SeqCst);
         })
         .expect("Should success listening agent");
 
-    let webserver_join = thread::Builder::new()
-        .name("webserver".to_string())
-        .spawn(move || {
-            let _server = Iron::new(web_handler).http("0.0.0.0:5012").unwrap();
-            cinfo!("Webserver listening on 5012");
-        })
-        .expect("Should success open webserver");
+    shutdown::run_thread(shutdown::ShutdownNewArg {
+        shutting_down,
+        agent_listener: agent_listener_rx.recv().expect("Should success receive the agent listener"),
+        frontend_listener: frontend_listener_rx.recv().expect("Should success receive the frontend listener"),
+        listener_threads: vec![frontend_join, agent_join],
+        agent_service: agent_service_sender,
+        db_service: db_service_sender,
+    });
 
-    frontend_join.join().expect("Join frotend listner");
-    agent_join.join().expect("Join agent listner");
-    webserver_join.join().expect("Join webserver");
-}
+    standby_handler.set(web_handler);
 
-struct WebHandler {
-    agent_service_sender: Mutex<agent::ServiceSender>,
+    // The shutdown thread joins the listeners and exits the process
+    webserver_join.expect("The webserver runs unless it only migrates").join().expect("Join webserver");
 }
 
-impl WebHandler {
-    fn new(agent_service_sender: agent::ServiceSender) -> Self {
-        Self {
-            agent_service_sender: Mutex::new(agent_service_sender),
-        }
-    }
+fn run_webserver(address: SocketAddr, handler: web::StandbyHandler) -> thread::JoinHandle<()> {
+    thread::Builder::new()
+        .name("webserver".to_string())
+        .spawn(move || {
+            let _server = Iron::new(handler).http(address).unwrap();
+            cinfo!("Webserver listening on {}", address);
+        })
+        .expect("Should success open webserver")
 }
 
-impl iron::Handler for WebHandler {
-    fn handle(&self, req: &mut iron::Request) -> IronResult<iron::Response> {
-        let paths = req.url.path();
-        if paths.len() != 2 {
-            cwarn!("Invalid web request {}", req.url);
-            return Ok(Response::with(status::NotFound))
+fn check_migrations(storage: &db::Storage) -> ! {
+    match db::migrations::pending(storage) {
+        Ok(ref pending) if pending.is_empty() => {
+            cinfo!("The database is up to date (version {})", db::migrations::latest_version());
+            process::exit(0);
         }
-
-        if paths.get(0).expect("Already checked") != &"log" {
-            cwarn!("Invalid web request {}", req.url);
-            return Ok(Response::with(status::NotFound))
+        Ok(pending) => {
+            for migration in pending {
+                cinfo!("Pending migration {} {}", migration.version, migration.name);
+            }
+            process::exit(1);
         }
-
-        let node_name = *paths.get(1).expect("Already checked");
-        ctrace!("Get log for agent-{}", node_name);
-
-        let agent = self
-            .agent_service_sender
-            .lock()
-            .expect("Should success get lock")
-            .get_agent(node_name.to_string())
-            .ok_or_else(|| iron::IronError::new(WebError::new("Not Found"), status::NotFound))?;
-
-        let log =
-            agent.shell_get_codechain_log().map_err(|err| iron::IronError::new(err, status::InternalServerError))?;
-
-        use iron::mime;
-        let content_type = "text/plain".parse::<mime::Mime>().unwrap();
-        Ok(Response::with((content_type, status::Ok, log)))
-    }
-}
-
-#[derive(Debug)]
-struct WebError {
-    value: String,
-}
-
-impl WebError {
-    fn new(s: &str) -> Self {
-        WebError {
-            value: s.to_string(),
+        Err(err) => {
+            cerror!("Cannot check the migrations : {}", err);
+            process::exit(1);
         }
     }
 }
-
-impl fmt::Display for WebError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
-    }
-}
-
-impl std::error::Error for WebError {}
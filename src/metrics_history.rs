@@ -0,0 +1,84 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono;
+
+use super::db;
+use super::db::MetricResolution;
+
+/// The samples are rolled up and expired in this interval
+const ROLL_UP_INTERVAL: Duration = Duration::from_secs(60);
+/// node_getHistory chooses the finest resolution which returns at most this number of samples
+const MAX_HISTORY_POINTS: i64 = 1000;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MetricsHistoryOption {
+    pub sample_interval_secs: u64,
+    pub raw_retention_hours: i64,
+    pub minute_retention_days: i64,
+    pub hour_retention_days: i64,
+}
+
+impl Default for MetricsHistoryOption {
+    fn default() -> Self {
+        Self {
+            sample_interval_secs: 10,
+            raw_retention_hours: 6,
+            minute_retention_days: 7,
+            hour_retention_days: 365,
+        }
+    }
+}
+
+impl MetricsHistoryOption {
+    pub fn retention(&self, resolution: MetricResolution) -> chrono::Duration {
+        match resolution {
+            MetricResolution::Raw => chrono::Duration::hours(self.raw_retention_hours),
+            MetricResolution::Minute => chrono::Duration::days(self.minute_retention_days),
+            MetricResolution::Hour => chrono::Duration::days(self.hour_retention_days),
+        }
+    }
+
+    /// The finest resolution which is still kept at the start of the range and doesn't return too many samples
+    pub fn resolution_for(
+        &self,
+        from: &chrono::DateTime<chrono::Local>,
+        to: &chrono::DateTime<chrono::Local>,
+    ) -> MetricResolution {
+        let range = (*to - *from).num_seconds();
+        let since = chrono::Local::now() - *from;
+        let candidates = [
+            (MetricResolution::Raw, self.sample_interval_secs.max(1) as i64),
+            (MetricResolution::Minute, 60),
+        ];
+        candidates
+            .iter()
+            .find(|(resolution, period)| range / period <= MAX_HISTORY_POINTS && since <= self.retention(*resolution))
+            .map_or(MetricResolution::Hour, |(resolution, _)| *resolution)
+    }
+}
+
+/// Samples the block numbers and the peer counts of the running nodes, rolls them up into the minutes and the hours,
+/// and removes the samples older than their retentions.
+pub fn run_thread(db_service: db::ServiceSender, option: MetricsHistoryOption) {
+    thread::Builder::new()
+        .name("metrics history".to_string())
+        .spawn(move || {
+            let mut last_rolled_up = Instant::now();
+            loop {
+                thread::sleep(Duration::from_secs(option.sample_interval_secs.max(1)));
+                db_service.record_metric_samples();
+                if last_rolled_up.elapsed() < ROLL_UP_INTERVAL {
+                    continue
+                }
+                last_rolled_up = Instant::now();
+                db_service.roll_up_metric_samples();
+                let now = chrono::Local::now();
+                for resolution in &[MetricResolution::Raw, MetricResolution::Minute, MetricResolution::Hour] {
+                    db_service.remove_metric_samples(*resolution, now - option.retention(*resolution));
+                }
+            }
+        })
+        .expect("Should success running metrics history thread");
+}
@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono;
+use serde::Serialize;
+
+use super::common_rpc_types::{NodeName, NodeStatus};
+use super::db;
+use super::frontend;
+use super::jsonrpc;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// The nodes which go offline in this window are correlated before they are alerted
+const CORRELATION_WINDOW: Duration = Duration::from_secs(30);
+/// A region is in an outage when this number of nodes in it go offline in the window
+const REGIONAL_OUTAGE_MIN_NODES: usize = 3;
+/// The region of a node is the tag like "region:us-east"
+pub const REGION_TAG_PREFIX: &str = "region:";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NodeAlert {
+    node_name: NodeName,
+    status: NodeStatus,
+    region: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct RegionalOutage {
+    region: String,
+    node_names: Vec<NodeName>,
+    started_at: chrono::DateTime<chrono::Local>,
+}
+
+#[derive(Default)]
+struct Alerts {
+    /// The offline nodes which are not alerted yet
+    pending: HashMap<NodeName, Instant>,
+    /// The nodes which are alerted one by one
+    alerted: HashSet<NodeName>,
+    outages: HashMap<String, RegionalOutage>,
+}
+
+/// Alerts the offline nodes. When many nodes in a region go offline together,
+/// they are alerted once as a regional outage instead of one by one.
+pub fn run_thread(db_service: db::ServiceSender, frontend_service: frontend::ServiceSender) {
+    thread::Builder::new()
+        .name("outage".to_string())
+        .spawn(move || {
+            let mut alerts = Alerts::default();
+            loop {
+                thread::sleep(CHECK_INTERVAL);
+                if let Err(err) = check(&db_service, &frontend_service, &mut alerts) {
+                    cerror!("Cannot check the outages : {:?}", err);
+                }
+            }
+        })
+        .expect("Should success running outage thread");
+}
+
+fn check(
+    db_service: &db::ServiceSender,
+    frontend_service: &frontend::ServiceSender,
+    alerts: &mut Alerts,
+) -> Result<(), db::Error> {
+    let now = Instant::now();
+    let states = db_service.get_agents_state()?;
+    // The stopped nodes, the nodes in maintenance and the agents which didn't reconnect after the hub restarted
+    // are not offline
+    let offline: HashMap<NodeName, NodeStatus> = states
+        .into_iter()
+        .filter(|state| state.status == NodeStatus::Error && !db_service.is_in_maintenance(&state.name))
+        .map(|state| (state.name, state.status))
+        .collect();
+
+    alerts.pending.retain(|name, _| offline.contains_key(name));
+    for name in alerts.alerted.iter().filter(|name| !offline.contains_key(*name)) {
+        cinfo!("{} is recovered", name);
+        send(frontend_service, vec![name.clone()], "alert_nodeRecovered", &json!({ "nodeName": name }));
+    }
+    alerts.alerted.retain(|name| offline.contains_key(name));
+    let mut recovered_regions = Vec::new();
+    for outage in alerts.outages.values_mut() {
+        outage.node_names.retain(|name| offline.contains_key(name));
+        if outage.node_names.is_empty() {
+            recovered_regions.push(outage.region.clone());
+        }
+    }
+    for region in recovered_regions {
+        let outage = alerts.outages.remove(&region).expect("Exists");
+        cinfo!("The outage of region {} is recovered", region);
+        send(frontend_service, outage.node_names.clone(), "alert_regionalOutageRecovered", &outage);
+    }
+
+    for name in offline.keys() {
+        let is_known = alerts.pending.contains_key(name)
+            || alerts.alerted.contains(name)
+            || alerts.outages.values().any(|outage| outage.node_names.contains(name));
+        if !is_known {
+            alerts.pending.insert(name.clone(), now);
+        }
+    }
+
+    let mut pending_by_region: HashMap<String, Vec<NodeName>> = HashMap::new();
+    let mut regions = HashMap::new();
+    for name in alerts.pending.keys() {
+        let region = region_of(db_service, name)?;
+        if let Some(region) = &region {
+            pending_by_region.entry(region.clone()).or_default().push(name.clone());
+        }
+        regions.insert(name.clone(), region);
+    }
+    for (region, names) in pending_by_region {
+        if let Some(outage) = alerts.outages.get_mut(&region) {
+            outage.node_names.extend(names.iter().cloned());
+        } else if names.len() >= REGIONAL_OUTAGE_MIN_NODES {
+            alerts.outages.insert(region.clone(), RegionalOutage {
+                region: region.clone(),
+                node_names: names.clone(),
+                started_at: chrono::Local::now(),
+            });
+        } else {
+            continue
+        }
+        for name in &names {
+            alerts.pending.remove(name);
+        }
+        let outage = &alerts.outages[&region];
+        cwarn!("Region {} is in an outage : {:?}", region, outage.node_names);
+        send(frontend_service, outage.node_names.clone(), "alert_regionalOutage", outage);
+    }
+
+    let expired: Vec<NodeName> = alerts
+        .pending
+        .iter()
+        .filter(|(_, since)| now.duration_since(**since) >= CORRELATION_WINDOW)
+        .map(|(name, _)| name.clone())
+        .collect();
+    for name in expired {
+        alerts.pending.remove(&name);
+        cwarn!("{} is offline", name);
+        send(frontend_service, vec![name.clone()], "alert_nodeOffline", &NodeAlert {
+            status: offline[&name],
+            region: regions.remove(&name).unwrap_or_default(),
+            node_name: name.clone(),
+        });
+        alerts.alerted.insert(name);
+    }
+    Ok(())
+}
+
+fn region_of(db_service: &db::ServiceSender, name: &NodeName) -> Result<Option<String>, db::Error> {
+    let tags = db_service.get_tags(name)?;
+    let region = tags.iter().find(|tag| tag.starts_with(REGION_TAG_PREFIX));
+    Ok(region.map(|tag| tag[REGION_TAG_PREFIX.len()..].to_string()))
+}
+
+/// The alert is sent to the frontends which see all of the nodes
+fn send<T: Serialize>(frontend_service: &frontend::ServiceSender, node_names: Vec<NodeName>, method: &str, params: &T) {
+    let message = jsonrpc::serialize_notification(method, params);
+    frontend_service.send(frontend::Message::SendNodeEvent(node_names, message)).expect("Should success send event");
+}
@@ -0,0 +1,62 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct RateLimitOption {
+    pub requests_per_second: f64,
+    /// The number of requests which can be sent at once after idling
+    pub burst: f64,
+}
+
+/// A token bucket. Each request consumes its weight, and the tokens are refilled at the rate.
+pub struct RateLimiter {
+    option: Option<RateLimitOption>,
+    /// The tokens and when they were counted
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(option: Option<RateLimitOption>) -> Self {
+        let tokens = option.map(|option| option.burst).unwrap_or_default();
+        Self {
+            option,
+            bucket: Mutex::new((tokens, Instant::now())),
+        }
+    }
+
+    /// Returns how long the client should wait if there are not enough tokens.
+    pub fn acquire(&self, weight: f64) -> Result<(), Duration> {
+        let option = match self.option {
+            Some(option) => option,
+            None => return Ok(()),
+        };
+        let mut bucket = self.bucket.lock().expect("Should success get bucket");
+        let (tokens, counted_at) = *bucket;
+        let now = Instant::now();
+        let elapsed = now.duration_since(counted_at);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        let tokens = (tokens + elapsed * option.requests_per_second).min(option.burst);
+        // A method heavier than the burst can be called when the bucket is full
+        let weight = weight.min(option.burst);
+        if tokens < weight {
+            *bucket = (tokens, now);
+            let wait = (weight - tokens) / option.requests_per_second;
+            return Err(Duration::from_millis((wait * 1000.0).ceil() as u64))
+        }
+        *bucket = (tokens - weight, now);
+        Ok(())
+    }
+
+    /// True if the bucket is full, so forgetting the limiter doesn't change anything
+    pub fn is_idle(&self) -> bool {
+        match self.option {
+            Some(option) => {
+                let (tokens, counted_at) = *self.bucket.lock().expect("Should success get bucket");
+                let elapsed = counted_at.elapsed();
+                let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+                tokens + elapsed * option.requests_per_second >= option.burst
+            }
+            None => true,
+        }
+    }
+}
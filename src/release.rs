@@ -0,0 +1,190 @@
+use std::process::Command;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use super::common_rpc_types::NodeName;
+use super::db;
+use super::frontend;
+use super::jsonrpc;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReleaseTrackingOption {
+    /// The git remote of CodeChain, e.g. "https://github.com/CodeChain-io/codechain.git"
+    pub remote: String,
+    /// Tracks the head of the branch instead of the newest version tag
+    #[serde(default)]
+    pub branch: Option<String>,
+    #[serde(default = "default_check_interval_minutes")]
+    pub check_interval_minutes: u64,
+}
+
+fn default_check_interval_minutes() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Release {
+    /// The tag, or the branch when the branch is tracked
+    pub name: String,
+    pub commit_hash: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateAvailable<'a> {
+    release: &'a Release,
+    node_names: Vec<NodeName>,
+}
+
+/// The latest release which the hub found. It is None until the first check succeeds.
+#[derive(Default)]
+pub struct ReleaseTracker {
+    latest: RwLock<Option<Release>>,
+}
+
+impl ReleaseTracker {
+    pub fn latest(&self) -> Option<Release> {
+        self.latest.read().expect("Should success read the latest release").clone()
+    }
+
+    /// Returns the latest release if the node runs another commit.
+    /// The node which hasn't reported its version has no commit to compare.
+    pub fn update_for(&self, commit_hash: Option<&str>) -> Option<Release> {
+        let latest = self.latest()?;
+        let commit_hash = commit_hash.filter(|hash| !hash.is_empty())?;
+        if latest.commit_hash.starts_with(commit_hash) || commit_hash.starts_with(latest.commit_hash.as_str()) {
+            return None
+        }
+        Some(latest)
+    }
+}
+
+/// Checks the remote periodically. When a new release is found, the nodes which don't run it get
+/// UpdateAvailable events, and the frontends get a release_updateAvailable notification.
+pub fn run_thread(
+    option: ReleaseTrackingOption,
+    db_service: db::ServiceSender,
+    frontend_service: frontend::ServiceSender,
+) -> Arc<ReleaseTracker> {
+    let tracker: Arc<ReleaseTracker> = Default::default();
+    let thread_tracker = Arc::clone(&tracker);
+    thread::Builder::new()
+        .name("release".to_string())
+        .spawn(move || loop {
+            match fetch_latest(&option) {
+                Ok(release) => {
+                    if thread_tracker.latest().as_ref() != Some(&release) {
+                        cinfo!("The latest release of CodeChain is {} {}", release.name, release.commit_hash);
+                        *thread_tracker.latest.write().expect("Should success write the latest release") =
+                            Some(release.clone());
+                        notify(&thread_tracker, &release, &db_service, &frontend_service);
+                    }
+                }
+                Err(err) => cwarn!("Cannot check the releases of {} : {}", option.remote, err),
+            }
+            thread::sleep(Duration::from_secs(option.check_interval_minutes * 60));
+        })
+        .expect("Should success running release thread");
+    tracker
+}
+
+fn notify(
+    tracker: &ReleaseTracker,
+    release: &Release,
+    db_service: &db::ServiceSender,
+    frontend_service: &frontend::ServiceSender,
+) {
+    let states = match db_service.get_agents_state() {
+        Ok(states) => states,
+        Err(err) => {
+            cerror!("Cannot get the nodes to compare with the release : {:?}", err);
+            return
+        }
+    };
+    let mut node_names = Vec::new();
+    for state in states {
+        let commit_hash = state.version.as_ref().map(|version| version.hash.as_str());
+        if tracker.update_for(commit_hash).is_some() {
+            db_service.write_event(&state.name, db::NodeEventKind::UpdateAvailable, json!(release));
+            node_names.push(state.name);
+        }
+    }
+    if node_names.is_empty() {
+        return
+    }
+    let message = jsonrpc::serialize_notification("release_updateAvailable", UpdateAvailable {
+        release,
+        node_names: node_names.clone(),
+    });
+    frontend_service.send(frontend::Message::SendNodeEvent(node_names, message)).expect("Should success send event");
+}
+
+fn fetch_latest(option: &ReleaseTrackingOption) -> Result<Release, String> {
+    match &option.branch {
+        Some(branch) => {
+            let refs = ls_remote(&option.remote, "--heads", Some(branch))?;
+            let (commit_hash, _) =
+                refs.into_iter().next().ok_or_else(|| format!("The branch {} doesn't exist", branch))?;
+            Ok(Release {
+                name: branch.clone(),
+                commit_hash,
+            })
+        }
+        None => {
+            let refs = ls_remote(&option.remote, "--tags", None)?;
+            newest_tag(&refs).ok_or_else(|| "There is no version tag".to_string())
+        }
+    }
+}
+
+/// Returns the pairs of the hash and the ref name
+fn ls_remote(remote: &str, kind: &str, pattern: Option<&str>) -> Result<Vec<(String, String)>, String> {
+    let output = Command::new("git")
+        .arg("ls-remote")
+        .arg(kind)
+        .arg(remote)
+        .args(pattern)
+        .output()
+        .map_err(|err| format!("Cannot run git : {}", err))?;
+    if !output.status.success() {
+        return Err(format!("git ls-remote {} failed : {}", remote, String::from_utf8_lossy(&output.stderr)))
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split('\t');
+            let hash = columns.next()?;
+            let name = columns.next()?;
+            Some((hash.to_string(), name.to_string()))
+        })
+        .collect())
+}
+
+/// The tags like v1.2.3 are compared by their numbers. An annotated tag points the commit with `^{}`.
+fn newest_tag(refs: &[(String, String)]) -> Option<Release> {
+    let mut newest: Option<(Vec<u64>, Release)> = None;
+    for (hash, name) in refs {
+        let tag = match name.trim_start_matches("refs/tags/") {
+            tag if tag.ends_with("^{}") => continue,
+            tag => tag,
+        };
+        let version: Option<Vec<u64>> =
+            tag.trim_start_matches('v').split('.').map(|part| part.parse().ok()).collect();
+        let version = match version {
+            Some(version) => version,
+            None => continue,
+        };
+        if newest.as_ref().map_or(false, |(newest_version, _)| *newest_version >= version) {
+            continue
+        }
+        let peeled = format!("{}^{{}}", name);
+        let commit_hash = refs.iter().find(|(_, name)| *name == peeled).map_or(hash, |(hash, _)| hash);
+        newest = Some((version, Release {
+            name: tag.to_string(),
+            commit_hash: commit_hash.clone(),
+        }));
+    }
+    newest.map(|(_, release)| release)
+}
@@ -1,29 +1,95 @@
+use std::any::type_name;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
 
+use schemars::gen::SchemaGenerator;
+use schemars::JsonSchema;
 use serde::de::Deserialize;
 use serde::Serialize;
 use serde_json;
 use serde_json::Value;
 
+use super::rate_limit::{RateLimitOption, RateLimiter};
 use super::rpc::{RPCError, RPCResponse};
+use super::rpc_metrics::RpcMetrics;
 
 pub trait Route {
     type Context;
     fn run(&self, context: Self::Context, value: Value) -> RPCResponse<Value>;
+    fn param_types(&self) -> Vec<(&'static str, Value)>;
+    fn result_type(&self) -> (&'static str, Value);
+}
+
+/// Lists the type names and the JSON Schemas of the positional arguments.
+pub trait ParamTypes {
+    fn param_types() -> Vec<(&'static str, Value)>;
+}
+
+macro_rules! impl_param_types {
+    ($($arg:ident),+) => {
+        impl<$($arg: JsonSchema),+> ParamTypes for ($($arg,)+) {
+            fn param_types() -> Vec<(&'static str, Value)> {
+                vec![$((type_name::<$arg>(), schema_of::<$arg>())),+]
+            }
+        }
+    };
+}
+
+impl_param_types!(A);
+impl_param_types!(A, B);
+impl_param_types!(A, B, D);
+impl_param_types!(A, B, D, E);
+impl_param_types!(A, B, D, E, F);
+impl_param_types!(A, B, D, E, F, G);
+
+/// The JSON Schema of the type. The structs and the enums which it refers to are in its `definitions`.
+fn schema_of<T: JsonSchema>() -> Value {
+    let schema = SchemaGenerator::default().into_root_schema_for::<T>();
+    serde_json::to_value(schema).expect("Should success serialize schema")
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ParamDescription {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// The JSON Schema of the param
+    pub schema: Value,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MethodDescription {
+    pub method: String,
+    pub params: Vec<ParamDescription>,
+    pub result: String,
+    pub result_schema: Value,
+}
+
+struct RouteEntry<C> {
+    method: &'static str,
+    param_names: &'static [&'static str],
+    route: Box<Route<Context = C> + Send + Sync>,
 }
 
 pub struct Router<C> {
-    table: HashMap<&'static str, Box<Route<Context = C>>>,
+    table: HashMap<&'static str, RouteEntry<C>>,
+    rate_limit: Option<RateLimitOption>,
+    /// The weights of the expensive methods. The other methods weigh 1.
+    weights: HashMap<&'static str, f64>,
+    metrics: Arc<RpcMetrics>,
 }
 
 impl<Arg, Result, C> Route for fn(context: C, Arg) -> RPCResponse<Result>
 where
-    Result: Serialize,
-    for<'de> Arg: Deserialize<'de>,
+    Result: Serialize + JsonSchema,
+    for<'de> Arg: Deserialize<'de> + ParamTypes,
 {
     type Context = C;
     fn run(&self, context: Self::Context, value: Value) -> RPCResponse<Value> {
-        let arg = serde_json::from_value(value)?;
+        let arg = serde_json::from_value(value).map_err(|err| RPCError::InvalidParams(err.to_string()))?;
         let result = self(context, arg)?;
         if let Some(result) = result {
             Ok(Some(serde_json::to_value(result)?))
@@ -31,11 +97,19 @@ where
             Ok(None)
         }
     }
+
+    fn param_types(&self) -> Vec<(&'static str, Value)> {
+        Arg::param_types()
+    }
+
+    fn result_type(&self) -> (&'static str, Value) {
+        (type_name::<Result>(), schema_of::<Result>())
+    }
 }
 
 impl<Result, C> Route for fn(context: C) -> RPCResponse<Result>
 where
-    Result: Serialize,
+    Result: Serialize + JsonSchema,
 {
     type Context = C;
     fn run(&self, context: Self::Context, _value: Value) -> RPCResponse<Value> {
@@ -47,6 +121,14 @@ where
             Ok(None)
         }
     }
+
+    fn param_types(&self) -> Vec<(&'static str, Value)> {
+        Vec::new()
+    }
+
+    fn result_type(&self) -> (&'static str, Value) {
+        (type_name::<Result>(), schema_of::<Result>())
+    }
 }
 
 pub enum Error {
@@ -56,24 +138,136 @@ pub enum Error {
 
 impl<C> Router<C> {
     pub fn new() -> Self {
-        let table: HashMap<&'static str, Box<Route<Context = C>>> = HashMap::new();
+        let table: HashMap<&'static str, RouteEntry<C>> = HashMap::new();
         Self {
             table,
+            rate_limit: None,
+            weights: HashMap::new(),
+            metrics: Default::default(),
         }
     }
 
-    pub fn add_route(&mut self, method: &'static str, route: Box<Route<Context = C>>) {
-        self.table.insert(method, route);
+    pub fn set_rate_limit(&mut self, option: RateLimitOption) {
+        self.rate_limit = Some(option);
+    }
+
+    pub fn set_weight(&mut self, method: &'static str, weight: f64) {
+        self.weights.insert(method, weight);
+    }
+
+    /// The calls of the methods which this router ran
+    pub fn metrics(&self) -> Arc<RpcMetrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Creates the limiter of a client. It doesn't limit anything if the rate limit is not set.
+    pub fn rate_limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.rate_limit)
+    }
+
+    /// `param_names` lists the names of the positional arguments in order.
+    /// They are used to accept named-parameter objects.
+    pub fn add_route(
+        &mut self,
+        method: &'static str,
+        param_names: &'static [&'static str],
+        route: Box<Route<Context = C> + Send + Sync>,
+    ) {
+        self.table.insert(method, RouteEntry {
+            method,
+            param_names,
+            route,
+        });
     }
 
     pub fn run(&self, context: C, method: &str, arg: Value) -> Result<Option<Value>, Error> {
-        let route = self.table.get(method);
-        match route {
+        let entry = self.table.get(method);
+        match entry {
             None => Err(Error::MethodNotFound),
-            Some(route) => match route.run(context, arg) {
-                Ok(value) => Ok(value),
-                Err(err) => Err(Error::RPC(err)),
-            },
+            Some(entry) => {
+                let started_at = Instant::now();
+                let result = entry.route.run(context, to_positional(entry.param_names, arg));
+                self.metrics.record(entry.method, started_at.elapsed(), result.is_err());
+                result.map_err(Error::RPC)
+            }
+        }
+    }
+
+    /// Runs the method if the client didn't exceed the rate limit.
+    pub fn run_limited(
+        &self,
+        limiter: &RateLimiter,
+        context: C,
+        method: &str,
+        arg: Value,
+    ) -> Result<Option<Value>, Error> {
+        let weight = self.weights.get(method).cloned().unwrap_or(1.0);
+        if let Err(retry_after) = limiter.acquire(weight) {
+            cdebug!("{} is rate limited. Retry after {:?}", method, retry_after);
+            return Err(Error::RPC(RPCError::RateLimited(retry_after)))
+        }
+        self.run(context, method, arg)
+    }
+
+    pub fn describe(&self) -> Vec<MethodDescription> {
+        let mut descriptions: Vec<MethodDescription> = self
+            .table
+            .iter()
+            .map(|(method, entry)| {
+                let (result, result_schema) = entry.route.result_type();
+                MethodDescription {
+                    method: method.to_string(),
+                    params: entry
+                        .param_names
+                        .iter()
+                        .zip(entry.route.param_types())
+                        .map(|(name, (type_name, schema))| ParamDescription {
+                            name: name.to_string(),
+                            type_name: short_type_name(type_name),
+                            schema,
+                        })
+                        .collect(),
+                    result: short_type_name(result),
+                    result_schema,
+                }
+            })
+            .collect();
+        descriptions.sort_by(|a, b| a.method.cmp(&b.method));
+        descriptions
+    }
+}
+
+/// Strips the module paths, e.g. `alloc::vec::Vec<alloc::string::String>` becomes `Vec<String>`.
+fn short_type_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    let mut segment_start = 0;
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            short.truncate(segment_start);
+        } else {
+            short.push(c);
+            if !(c.is_alphanumeric() || c == '_') {
+                segment_start = short.len();
+            }
+        }
+    }
+    short
+}
+
+fn to_positional(param_names: &[&str], arg: Value) -> Value {
+    match arg {
+        Value::Object(mut params) => {
+            Value::Array(param_names.iter().map(|name| params.remove(*name).unwrap_or(Value::Null)).collect())
+        }
+        // The omitted trailing arguments are optional
+        Value::Array(mut params) => {
+            while params.len() < param_names.len() {
+                params.push(Value::Null);
+            }
+            Value::Array(params)
         }
+        arg => arg,
     }
 }
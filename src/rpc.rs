@@ -1,11 +1,14 @@
 use std::error::Error;
 use std::fmt;
 use std::result::Result;
+use std::time::Duration;
 
 use jsonrpc_core::types::{Error as JSONRPCError, ErrorCode};
 use serde_json::{Error as SerdeError, Value};
 
+use super::common_rpc_types::{AgentCapability, NodeName};
 use super::db::Error as DBError;
+use super::frontend::StartRequestRejection;
 use super::jsonrpc;
 
 pub type RPCResponse<T> = Result<Option<T>, RPCError>;
@@ -14,19 +17,59 @@ pub type RPCResult<T> = Result<T, RPCError>;
 
 pub enum RPCError {
     Internal(String),
-    FromAgent(JSONRPCError),
+    /// The agent is connected but it returned an error
+    FromAgent(Option<NodeName>, JSONRPCError),
+    /// The agent didn't respond
+    AgentUnreachable(Option<NodeName>, String),
     FromDB(DBError),
 
-    AgentNotFound,
+    AgentNotFound(NodeName),
+    CodeChainNotRunning(NodeName),
+    InvalidParams(String),
+    FeatureDisabled(&'static str),
+    NotFound(String),
+    /// The operation takes too many validators offline at once
+    QuorumGuard(String),
+    /// The client sent too many requests. It can retry after the duration
+    RateLimited(Duration),
+    /// The agent advertised that it doesn't have the capability
+    CapabilityNotSupported(Option<NodeName>, AgentCapability),
+    /// The start option of the node is rejected by the start policy
+    StartRequestRejected(NodeName, StartRequestRejection),
+    /// The request is queued as the pending action of the id until another operator approves it
+    ApprovalRequired(u64),
+    /// The agent didn't respond in the timeout of the command
+    AgentTimeout(Option<NodeName>, Duration),
+    /// The operation is cancelled by job_cancel
+    Cancelled(Option<NodeName>),
+    /// The token of the caller is scoped to a namespace
+    PermissionDenied(String),
 }
 
 impl fmt::Display for RPCError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             RPCError::Internal(err) => write!(f, "RPCError {}", err),
-            RPCError::FromAgent(err) => write!(f, "JSONRPCError from Agent {:?}", err),
+            RPCError::FromAgent(_, err) => write!(f, "JSONRPCError from Agent {:?}", err),
+            RPCError::AgentUnreachable(_, err) => write!(f, "Agent is unreachable {}", err),
             RPCError::FromDB(err) => write!(f, "JSONRPCError from DB {:?}", err),
-            RPCError::AgentNotFound => write!(f, "Agent not found"),
+            RPCError::AgentNotFound(_) => write!(f, "Agent not found"),
+            RPCError::CodeChainNotRunning(_) => write!(f, "CodeChain is not running"),
+            RPCError::InvalidParams(err) => write!(f, "Invalid params {}", err),
+            RPCError::FeatureDisabled(feature) => write!(f, "{} is disabled", feature),
+            RPCError::NotFound(err) => write!(f, "{} is not found", err),
+            RPCError::QuorumGuard(err) => write!(f, "Refused by the quorum guard : {}", err),
+            RPCError::RateLimited(retry_after) => write!(f, "Too many requests. Retry after {:?}", retry_after),
+            RPCError::CapabilityNotSupported(_, capability) => {
+                write!(f, "{} capability not supported by this agent", capability.as_str())
+            }
+            RPCError::StartRequestRejected(_, rejection) => write!(f, "The start request is rejected : {}", rejection),
+            RPCError::ApprovalRequired(id) => {
+                write!(f, "The action {} is waiting for the approval of another operator", id)
+            }
+            RPCError::AgentTimeout(_, timeout) => write!(f, "The agent didn't respond in {:?}", timeout),
+            RPCError::Cancelled(_) => write!(f, "The operation is cancelled"),
+            RPCError::PermissionDenied(err) => write!(f, "Permission denied : {}", err),
         }
     }
 }
@@ -43,25 +86,118 @@ pub fn response<T>(value: T) -> RPCResponse<T> {
     Ok(Some(value))
 }
 
+// These codes are a part of the API. Never change the existing values.
 const ERR_AGENT_NOT_FOUND: i64 = -1;
+const ERR_AGENT_ERROR: i64 = -2;
+const ERR_AGENT_UNREACHABLE: i64 = -3;
+const ERR_CODECHAIN_NOT_RUNNING: i64 = -4;
+const ERR_DB: i64 = -5;
+const ERR_FEATURE_DISABLED: i64 = -6;
+const ERR_NOT_FOUND: i64 = -7;
+const ERR_QUORUM_GUARD: i64 = -8;
+const ERR_RATE_LIMITED: i64 = -9;
+const ERR_CAPABILITY_NOT_SUPPORTED: i64 = -10;
+const ERR_START_REQUEST_REJECTED: i64 = -11;
+const ERR_APPROVAL_REQUIRED: i64 = -12;
+const ERR_AGENT_TIMEOUT: i64 = -13;
+const ERR_CANCELLED: i64 = -14;
+const ERR_PERMISSION_DENIED: i64 = -15;
 
 impl RPCError {
     pub fn to_jsonrpc_error(&self) -> JSONRPCError {
         match self {
             RPCError::Internal(str) => Self::create_internal_rpc_error(str),
-            RPCError::FromAgent(err) => {
-                let mut error = err.clone();
-                error.data = match error.data {
-                    None => Some(json!("Error from agent")),
-                    Some(inner_data) => Some(json!({
-                        "message": "This error is from the agent",
-                        "inner": inner_data,
-                    })),
-                };
-                error
+            RPCError::FromAgent(node_name, err) => Self::create_rpc_error(
+                ERR_AGENT_ERROR,
+                &err.message,
+                json!({
+                    "nodeName": node_name,
+                    "agentError": err,
+                }),
+            ),
+            RPCError::AgentUnreachable(node_name, err) => Self::create_rpc_error(
+                ERR_AGENT_UNREACHABLE,
+                &format!("{}", self),
+                json!({
+                    "nodeName": node_name,
+                    "reason": err,
+                }),
+            ),
+            RPCError::FromDB(_) => Self::create_rpc_error(ERR_DB, &format!("{}", self), Value::Null),
+            RPCError::AgentNotFound(node_name) => Self::create_rpc_error(
+                ERR_AGENT_NOT_FOUND,
+                &format!("{}", self),
+                json!({
+                    "nodeName": node_name,
+                }),
+            ),
+            RPCError::CodeChainNotRunning(node_name) => Self::create_rpc_error(
+                ERR_CODECHAIN_NOT_RUNNING,
+                &format!("{}", self),
+                json!({
+                    "nodeName": node_name,
+                }),
+            ),
+            RPCError::InvalidParams(err) => {
+                let mut ret = JSONRPCError::new(ErrorCode::InvalidParams);
+                ret.data = Some(Value::String(err.to_string()));
+                ret
+            }
+            RPCError::FeatureDisabled(_) => {
+                Self::create_rpc_error(ERR_FEATURE_DISABLED, &format!("{}", self), Value::Null)
+            }
+            RPCError::NotFound(_) => Self::create_rpc_error(ERR_NOT_FOUND, &format!("{}", self), Value::Null),
+            RPCError::QuorumGuard(_) => Self::create_rpc_error(ERR_QUORUM_GUARD, &format!("{}", self), Value::Null),
+            RPCError::RateLimited(retry_after) => Self::create_rpc_error(
+                ERR_RATE_LIMITED,
+                &format!("{}", self),
+                json!({
+                    "retryAfterMs": retry_after.as_secs() * 1000 + u64::from(retry_after.subsec_millis()),
+                }),
+            ),
+            RPCError::CapabilityNotSupported(node_name, capability) => Self::create_rpc_error(
+                ERR_CAPABILITY_NOT_SUPPORTED,
+                &format!("{}", self),
+                json!({
+                    "nodeName": node_name,
+                    "capability": capability,
+                }),
+            ),
+            RPCError::StartRequestRejected(node_name, rejection) => Self::create_rpc_error(
+                ERR_START_REQUEST_REJECTED,
+                &format!("{}", self),
+                json!({
+                    "nodeName": node_name,
+                    "part": rejection.part,
+                    "value": rejection.value,
+                    "reason": rejection.reason,
+                }),
+            ),
+            RPCError::ApprovalRequired(id) => Self::create_rpc_error(
+                ERR_APPROVAL_REQUIRED,
+                &format!("{}", self),
+                json!({
+                    "actionId": id,
+                }),
+            ),
+            RPCError::AgentTimeout(node_name, timeout) => Self::create_rpc_error(
+                ERR_AGENT_TIMEOUT,
+                &format!("{}", self),
+                json!({
+                    "nodeName": node_name,
+                    "timeoutMs": timeout.as_secs() * 1000 + u64::from(timeout.subsec_millis()),
+                }),
+            ),
+            RPCError::Cancelled(node_name) => Self::create_rpc_error(
+                ERR_CANCELLED,
+                &format!("{}", self),
+                json!({
+                    "nodeName": node_name,
+                }),
+            ),
+            RPCError::PermissionDenied(_) => {
+                Self::create_rpc_error(ERR_PERMISSION_DENIED, &format!("{}", self), Value::Null)
             }
-            RPCError::FromDB(err) => Self::create_internal_rpc_error(&self.to_string()),
-            RPCError::AgentNotFound => Self::create_rpc_error(ERR_AGENT_NOT_FOUND, &format!("{}", self)),
         }
     }
 
@@ -71,11 +207,27 @@ impl RPCError {
         ret
     }
 
-    fn create_rpc_error(code: i64, msg: &str) -> JSONRPCError {
+    fn create_rpc_error(code: i64, msg: &str, data: Value) -> JSONRPCError {
         let mut ret = JSONRPCError::new(ErrorCode::ServerError(code));
         ret.message = msg.to_string();
+        if !data.is_null() {
+            ret.data = Some(data);
+        }
         ret
     }
+
+    /// Attaches the node name to the errors from the agent.
+    pub fn from_agent_call(node_name: Option<NodeName>, err: jsonrpc::CallError) -> Self {
+        match err {
+            jsonrpc::CallError::Response(jsonrpc_error) => RPCError::FromAgent(node_name, jsonrpc_error),
+            jsonrpc::CallError::Timeout(timeout) => RPCError::AgentTimeout(node_name, timeout),
+            jsonrpc::CallError::Cancelled => RPCError::Cancelled(node_name),
+            jsonrpc::CallError::InternalSerde(_) => {
+                RPCError::Internal(format!("Internal error about jsonrpc call : {:?}", err))
+            }
+            _ => RPCError::AgentUnreachable(node_name, format!("{}", err)),
+        }
+    }
 }
 
 impl From<SerdeError> for RPCError {
@@ -86,15 +238,15 @@ impl From<SerdeError> for RPCError {
 
 impl From<jsonrpc::CallError> for RPCError {
     fn from(err: jsonrpc::CallError) -> Self {
-        match err {
-            jsonrpc::CallError::Response(jsonrpc_error) => RPCError::FromAgent(jsonrpc_error),
-            _ => RPCError::Internal(format!("Internal error about jsonrpc call : {:?}", err)),
-        }
+        RPCError::from_agent_call(None, err)
     }
 }
 
 impl From<DBError> for RPCError {
     fn from(err: DBError) -> Self {
-        RPCError::FromDB(err)
+        match err {
+            DBError::InvalidParams(message) => RPCError::InvalidParams(message),
+            err => RPCError::FromDB(err),
+        }
     }
 }
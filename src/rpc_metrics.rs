@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The upper bounds of the latency buckets in milliseconds. The last bucket has no bound.
+const LATENCY_BUCKETS_MS: [u64; 10] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 5000];
+
+#[derive(Clone, Debug, Default)]
+struct Counters {
+    calls: u64,
+    errors: u64,
+    total: Duration,
+    /// The number of the calls in each bucket, not cumulative. The last one counts the calls over all bounds.
+    buckets: [u64; 11],
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyBucket {
+    /// None for the bucket which has no upper bound
+    pub le_ms: Option<u64>,
+    /// The number of the calls which took at most `le_ms`
+    pub count: u64,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MethodStats {
+    pub method: String,
+    pub calls: u64,
+    pub errors: u64,
+    pub total_ms: f64,
+    pub latency: Vec<LatencyBucket>,
+}
+
+/// Counts the calls of each method of a router since the hub started.
+#[derive(Default)]
+pub struct RpcMetrics {
+    methods: Mutex<HashMap<&'static str, Counters>>,
+}
+
+impl RpcMetrics {
+    pub fn record(&self, method: &'static str, elapsed: Duration, is_error: bool) {
+        let mut methods = self.methods.lock().expect("Should success get rpc metrics");
+        let counters = methods.entry(method).or_insert_with(Default::default);
+        counters.calls += 1;
+        if is_error {
+            counters.errors += 1;
+        }
+        counters.total += elapsed;
+        // Compared as the durations, so 1.5ms is not counted in the 1ms bucket
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| elapsed <= Duration::from_millis(*bound))
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        counters.buckets[bucket] += 1;
+    }
+
+    /// The methods which have been called, sorted by name
+    pub fn stats(&self) -> Vec<MethodStats> {
+        let methods = self.methods.lock().expect("Should success get rpc metrics");
+        let mut stats: Vec<MethodStats> = methods
+            .iter()
+            .map(|(method, counters)| {
+                let mut count = 0;
+                let latency = counters
+                    .buckets
+                    .iter()
+                    .enumerate()
+                    .map(|(index, calls)| {
+                        count += calls;
+                        LatencyBucket {
+                            le_ms: LATENCY_BUCKETS_MS.get(index).cloned(),
+                            count,
+                        }
+                    })
+                    .collect();
+                MethodStats {
+                    method: method.to_string(),
+                    calls: counters.calls,
+                    errors: counters.errors,
+                    total_ms: as_millis(counters.total),
+                    latency,
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.method.cmp(&b.method));
+        stats
+    }
+
+    /// Renders the stats in the Prometheus text exposition format
+    pub fn prometheus(&self) -> String {
+        let stats = self.stats();
+        let mut text = String::new();
+        text.push_str("# HELP agent_hub_rpc_calls_total The number of the calls of the frontend RPC.\n");
+        text.push_str("# TYPE agent_hub_rpc_calls_total counter\n");
+        for method in &stats {
+            writeln!(text, "agent_hub_rpc_calls_total{{method=\"{}\"}} {}", method.method, method.calls).unwrap();
+        }
+        text.push_str("# HELP agent_hub_rpc_errors_total The number of the frontend RPC calls which failed.\n");
+        text.push_str("# TYPE agent_hub_rpc_errors_total counter\n");
+        for method in &stats {
+            writeln!(text, "agent_hub_rpc_errors_total{{method=\"{}\"}} {}", method.method, method.errors).unwrap();
+        }
+        text.push_str("# HELP agent_hub_rpc_duration_seconds The latency of the frontend RPC.\n");
+        text.push_str("# TYPE agent_hub_rpc_duration_seconds histogram\n");
+        for method in &stats {
+            for bucket in &method.latency {
+                let le = match bucket.le_ms {
+                    Some(le_ms) => (le_ms as f64 / 1000.0).to_string(),
+                    None => "+Inf".to_string(),
+                };
+                writeln!(
+                    text,
+                    "agent_hub_rpc_duration_seconds_bucket{{method=\"{}\",le=\"{}\"}} {}",
+                    method.method, le, bucket.count
+                )
+                .unwrap();
+            }
+            writeln!(
+                text,
+                "agent_hub_rpc_duration_seconds_sum{{method=\"{}\"}} {}",
+                method.method,
+                method.total_ms / 1000.0
+            )
+            .unwrap();
+            writeln!(text, "agent_hub_rpc_duration_seconds_count{{method=\"{}\"}} {}", method.method, method.calls)
+                .unwrap();
+        }
+        text
+    }
+}
+
+fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1e6
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64;
+use rand;
+use rand::Rng;
+
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+/// The version of the format, which seals the secret with the node name and the secret name as the associated data
+const BOUND_PREFIX: &str = "v2:";
+/// Shown instead of the values of the secrets
+pub const REDACTED: &str = "******";
+
+#[derive(Clone, Deserialize)]
+pub struct SecretsOption {
+    /// The base64 encoded key of AES-256-GCM which encrypts the secrets in the database
+    pub key: String,
+}
+
+impl fmt::Debug for SecretsOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SecretsOption").field("key", &REDACTED).finish()
+    }
+}
+
+impl SecretsOption {
+    pub fn validate(&self) -> Result<(), String> {
+        self.key().map(|_| ())
+    }
+
+    fn cipher(&self) -> Result<Aes256Gcm, String> {
+        let key = base64::decode(&self.key).map_err(|err| format!("Invalid secrets key : {}", err))?;
+        if key.len() != KEY_SIZE {
+            return Err(format!("The secrets key should be {} bytes", KEY_SIZE))
+        }
+        Ok(Aes256Gcm::new(Key::from_slice(&key)))
+    }
+
+    /// Returns the base64 encoded nonce, ciphertext and tag after the prefix.
+    /// The secret is bound to the node and its name, so a sealed value copied to another row can't be decrypted.
+    pub fn encrypt(&self, node_name: &str, secret_name: &str, plaintext: &str) -> Result<String, String> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        rand::thread_rng().fill(&mut nonce);
+        let aad = associated_data(node_name, secret_name);
+        let payload = Payload {
+            msg: plaintext.as_bytes(),
+            aad: &aad,
+        };
+        let ciphertext = self
+            .cipher()?
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| "The secret cannot be encrypted".to_string())?;
+
+        let mut sealed = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(format!("{}{}", BOUND_PREFIX, base64::encode(&sealed)))
+    }
+
+    /// Fails if the secret is encrypted with another key or for another node or name
+    pub fn decrypt(&self, node_name: &str, secret_name: &str, sealed: &str) -> Result<String, String> {
+        if !sealed.starts_with(BOUND_PREFIX) {
+            return Err("The secret is not sealed by the hub".to_string())
+        }
+        let sealed = base64::decode(&sealed[BOUND_PREFIX.len()..]).map_err(|err| format!("Invalid secret : {}", err))?;
+        if sealed.len() < NONCE_SIZE + TAG_SIZE {
+            return Err("The secret is truncated".to_string())
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        let aad = associated_data(node_name, secret_name);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &aad,
+        };
+        let plaintext = self
+            .cipher()?
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| "The secret cannot be decrypted with the key".to_string())?;
+        String::from_utf8(plaintext).map_err(|err| format!("Invalid secret : {}", err))
+    }
+}
+
+/// The node name and the secret name, separated by a byte which is not in the names
+fn associated_data(node_name: &str, secret_name: &str) -> Vec<u8> {
+    [node_name.as_bytes(), &[0], secret_name.as_bytes()].concat()
+}
+
+/// Replaces the values of all variables, for the logs which don't know the names of the secrets
+pub fn redact_all_env(env: &str) -> String {
+    env.split_whitespace()
+        .map(|variable| format!("{}={}", variable.split('=').next().unwrap_or_default(), REDACTED))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Replaces the values of the variables which are the secrets, e.g. "DB_PASSWORD=******"
+pub fn redact_env(env: &str, secret_names: &[String]) -> String {
+    env.split_whitespace()
+        .map(|variable| {
+            let name = variable.split('=').next().unwrap_or_default();
+            if secret_names.iter().any(|secret_name| secret_name == name) {
+                format!("{}={}", name, REDACTED)
+            } else {
+                variable.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono;
+use ws;
+use ws::CloseCode;
+
+use super::common_rpc_types::NodeName;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionRole {
+    Frontend,
+    Agent,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub id: u64,
+    pub role: SessionRole,
+    /// The address of the TCP peer, which is the proxy if the client is behind one
+    pub remote_address: Option<SocketAddr>,
+    /// The name of the token of the frontend, or the name of the node of the agent.
+    /// None when no token is configured and for the agents which haven't reported their names
+    pub identity: Option<String>,
+    pub connected_at: chrono::DateTime<chrono::Local>,
+    /// The event streams which the session receives. The agents receive none
+    pub subscription_count: usize,
+}
+
+/// The connected websockets of the frontends and the agents, which the admins can list and close.
+/// The long-poll sessions of the web listener are not included.
+#[derive(Default)]
+pub struct Sessions {
+    /// The last id and the sessions with their connections, the oldest first
+    sessions: Mutex<(u64, Vec<(Session, ws::Sender)>)>,
+    /// The addresses of the killed sessions and when they are allowed to connect again
+    bans: Mutex<HashMap<IpAddr, Instant>>,
+}
+
+impl Sessions {
+    pub fn open(
+        &self,
+        role: SessionRole,
+        remote_address: Option<SocketAddr>,
+        identity: Option<String>,
+        out: ws::Sender,
+    ) -> u64 {
+        let mut sessions = self.sessions.lock().expect("Should success get sessions");
+        sessions.0 += 1;
+        let session = Session {
+            id: sessions.0,
+            role,
+            remote_address,
+            identity,
+            connected_at: chrono::Local::now(),
+            subscription_count: 0,
+        };
+        cdebug!("Session {} of {:?} is opened from {:?}", session.id, role, remote_address);
+        let id = session.id;
+        sessions.1.push((session, out));
+        id
+    }
+
+    pub fn close(&self, id: u64) {
+        let mut sessions = self.sessions.lock().expect("Should success get sessions");
+        sessions.1.retain(|(session, _)| session.id != id);
+    }
+
+    /// `agent_name` finds the name of the node whose agent is connected through the connection,
+    /// and the subscribers are the connections which receive the events
+    pub fn list<F>(&self, agent_name: F, subscribers: &[ws::Sender]) -> Vec<Session>
+    where
+        F: Fn(&ws::Sender) -> Option<NodeName>, {
+        let sessions = self.sessions.lock().expect("Should success get sessions").1.clone();
+        sessions
+            .into_iter()
+            .map(|(mut session, out)| {
+                if session.role == SessionRole::Agent {
+                    session.identity = agent_name(&out);
+                }
+                session.subscription_count = subscribers.iter().filter(|subscriber| **subscriber == out).count();
+                session
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        let sessions = self.sessions.lock().expect("Should success get sessions");
+        sessions.1.iter().any(|(session, _)| session.id == id)
+    }
+
+    /// Returns false if there is no session with the id.
+    /// The address of the session is refused for the ban duration if it is given, so the killed client doesn't
+    /// reconnect at once. Every client behind the same NAT or proxy is refused together.
+    pub fn kill(&self, id: u64, reason: &str, ban: Option<Duration>) -> Result<bool, String> {
+        let sessions = self.sessions.lock().expect("Should success get sessions");
+        match sessions.1.iter().find(|(session, _)| session.id == id) {
+            Some((session, out)) => {
+                let (role, remote_address) = (session.role, session.remote_address);
+                cwarn!("Kill session {} of {:?} from {:?} with the ban {:?}", id, role, remote_address, ban);
+                if let (Some(remote_address), Some(ban)) = (session.remote_address, ban) {
+                    let until =
+                        Instant::now().checked_add(ban).ok_or_else(|| format!("The ban {:?} is too long", ban))?;
+                    let mut bans = self.bans.lock().expect("Should success get bans");
+                    bans.insert(remote_address.ip(), until);
+                }
+                out.close_with_reason(CloseCode::Policy, reason).map_err(|err| err.to_string())?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// The expired bans are removed when they are checked
+    pub fn is_banned(&self, ip: &IpAddr) -> bool {
+        let mut bans = self.bans.lock().expect("Should success get bans");
+        let now = Instant::now();
+        bans.retain(|_, until| *until > now);
+        bans.contains_key(ip)
+    }
+}
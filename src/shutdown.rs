@@ -0,0 +1,99 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ctrlc;
+use ws;
+use ws::CloseCode;
+
+use super::agent;
+use super::db;
+
+const AGENT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+const DB_FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+pub const SHUTTING_DOWN_REASON: &str = "The hub is shutting down";
+
+pub struct ShutdownNewArg {
+    /// Listeners reject new connections when it is set
+    pub shutting_down: Arc<AtomicBool>,
+    pub agent_listener: ws::Sender,
+    pub frontend_listener: ws::Sender,
+    /// The threads which run the event loops of the listeners
+    pub listener_threads: Vec<thread::JoinHandle<()>>,
+    pub agent_service: agent::ServiceSender,
+    pub db_service: db::ServiceSender,
+}
+
+/// Stops the hub gracefully on SIGINT or SIGTERM.
+/// The connections are closed and the listeners are drained first,
+/// so the last states, logs and requests are written before the DB writes are flushed.
+pub fn run_thread(arg: ShutdownNewArg) {
+    let (tx, rx) = channel();
+    ctrlc::set_handler(move || {
+        if let Err(err) = tx.send(()) {
+            cerror!("Cannot send the shutdown signal : {}", err);
+        }
+    })
+    .expect("Should success setting the signal handler");
+
+    thread::Builder::new()
+        .name("shutdown".to_string())
+        .spawn(move || {
+            if rx.recv().is_err() {
+                return
+            }
+            cinfo!("Shutting down");
+            shutdown(arg);
+            cinfo!("Bye");
+            process::exit(0);
+        })
+        .expect("Should success running shutdown thread");
+}
+
+fn shutdown(
+    ShutdownNewArg {
+        shutting_down,
+        agent_listener,
+        frontend_listener,
+        listener_threads,
+        agent_service,
+        db_service,
+    }: ShutdownNewArg,
+) {
+    shutting_down.store(true, Ordering::SeqCst);
+
+    if let Err(err) = frontend_listener.close_with_reason(CloseCode::Away, SHUTTING_DOWN_REASON) {
+        cerror!("Cannot close the frontend connections : {}", err);
+    }
+    if let Err(err) = agent_listener.close_with_reason(CloseCode::Away, SHUTTING_DOWN_REASON) {
+        cerror!("Cannot close the agent connections : {}", err);
+    }
+    let drain_started = Instant::now();
+    while agent_service.agent_count() > 0 {
+        if drain_started.elapsed() > AGENT_DRAIN_TIMEOUT {
+            cwarn!("{} agents are not cleaned up in {:?}", agent_service.agent_count(), AGENT_DRAIN_TIMEOUT);
+            break
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    // The close frames queued above are sent before the event loops stop
+    for listener in &[agent_listener, frontend_listener] {
+        if let Err(err) = listener.shutdown() {
+            cerror!("Cannot stop the listener : {}", err);
+        }
+    }
+    // The requests which the event loops are handling are answered and their writes are queued
+    for listener_thread in listener_threads {
+        if listener_thread.join().is_err() {
+            cerror!("The listener thread panicked");
+        }
+    }
+
+    if let Err(err) = db_service.flush(DB_FLUSH_TIMEOUT) {
+        cerror!("Cannot flush the DB writes : {}", err);
+    }
+}
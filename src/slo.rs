@@ -0,0 +1,51 @@
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+
+use super::db;
+use super::frontend;
+use super::jsonrpc;
+
+const SAMPLE_INTERVAL_SECONDS: u64 = 60;
+
+/// Samples the SLOs every minute and alerts when an error budget is consumed too fast.
+/// The minutes while the hub was down are recorded as bad before the first sample.
+pub fn run_thread(db_service: db::ServiceSender, frontend_service: frontend::ServiceSender) {
+    thread::Builder::new()
+        .name("slo".to_string())
+        .spawn(move || {
+            let mut alerting = HashSet::new();
+            db_service.record_slo_downtime();
+            loop {
+                thread::sleep(Duration::new(SAMPLE_INTERVAL_SECONDS, 0));
+                db_service.record_slo_samples();
+                match db_service.get_slo_statuses() {
+                    Ok(statuses) => check_burn_rates(&statuses, &mut alerting, &frontend_service),
+                    Err(err) => cerror!("Cannot get SLO statuses : {:?}", err),
+                }
+            }
+        })
+        .expect("Should success running slo thread");
+}
+
+fn check_burn_rates(
+    statuses: &[db::SloStatus],
+    alerting: &mut HashSet<String>,
+    frontend_service: &frontend::ServiceSender,
+) {
+    for status in statuses {
+        let name = &status.slo.name;
+        let too_fast = status.burn_rate.map(|burn_rate| burn_rate > status.slo.burn_rate_threshold).unwrap_or(false);
+        if too_fast && !alerting.contains(name) {
+            cwarn!("The error budget of SLO {} burns too fast {:?}", name, status.burn_rate);
+            alerting.insert(name.clone());
+            let message = jsonrpc::serialize_notification("slo_burnRateAlert", status);
+            frontend_service.send(frontend::Message::SendEvent(message)).expect("Should success send event");
+        } else if !too_fast && alerting.remove(name) {
+            cinfo!("The burn rate of SLO {} is recovered", name);
+            let message = jsonrpc::serialize_notification("slo_burnRateRecovered", status);
+            frontend_service.send(frontend::Message::SendEvent(message)).expect("Should success send event");
+        }
+    }
+    alerting.retain(|name| statuses.iter().any(|status| status.slo.name == *name));
+}
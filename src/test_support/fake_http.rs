@@ -0,0 +1,110 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: String,
+    /// With the query string
+    pub path: String,
+    pub body: String,
+}
+
+type Respond = Fn(&HttpRequest) -> String + Send + Sync;
+
+/// A fake of the HTTP interfaces of ClickHouse and Elasticsearch.
+/// It records the requests and answers every request with 200 and the body which `respond` returns.
+pub struct FakeHttpServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<HttpRequest>>>,
+}
+
+impl FakeHttpServer {
+    pub fn start<F>(respond: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> String + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Should success bind the fake server");
+        let url = format!("http://{}", listener.local_addr().expect("Should success get the fake server address"));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let respond: Arc<Respond> = Arc::new(respond);
+        let recorded = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let recorded = Arc::clone(&recorded);
+                let respond = Arc::clone(&respond);
+                thread::spawn(move || {
+                    if let Some(request) = read_request(&stream) {
+                        // HEAD, which checks the existence of an index, has no body
+                        let body = if request.method == "HEAD" {
+                            String::new()
+                        } else {
+                            respond(&request)
+                        };
+                        recorded.lock().unwrap().push(request);
+                        let _ = write!(
+                            &stream,
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                             Connection: close\r\n\r\n{}",
+                            body.len(),
+                            body
+                        );
+                    }
+                });
+            }
+        });
+        Self {
+            url,
+            requests,
+        }
+    }
+
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The bodies of the requests to the path which starts with `path`
+    pub fn bodies(&self, path: &str) -> Vec<String> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.path.starts_with(path))
+            .map(|request| request.body)
+            .collect()
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Option<HttpRequest> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let mut name_value = header.splitn(2, ':');
+        let name = name_value.next()?.trim().to_lowercase();
+        if name == "content-length" {
+            content_length = name_value.next()?.trim().parse().ok()?;
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).ok()?;
+    Some(HttpRequest {
+        method,
+        path,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
@@ -0,0 +1,106 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde_json;
+use serde_json::Value;
+use ws::{CloseCode, Handler, Handshake, Message, Sender};
+
+/// The hub also broadcasts the events to the connection, which don't have this id
+const REQUEST_ID: u64 = 1;
+const AUTHENTICATE_ID: u64 = 2;
+
+/// Calls the frontend RPC of the hub. Each call opens its own connection.
+pub struct FrontendClient {
+    url: String,
+    /// Sent with hub_authenticate before each call
+    token: Option<String>,
+}
+
+impl FrontendClient {
+    /// e.g. "ws://127.0.0.1:3012"
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            token: None,
+        }
+    }
+
+    /// Authenticates each connection with its first message like a browser
+    pub fn with_token(url: &str, token: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            token: Some(token.to_string()),
+        }
+    }
+
+    /// Returns the error object of the response if the call fails
+    pub fn call(&self, method: &str, params: Value) -> Result<Value, Value> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": REQUEST_ID,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+        let authentication = self.token.as_ref().map(|token| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": AUTHENTICATE_ID,
+                "method": "hub_authenticate",
+                "params": { "token": token },
+            })
+            .to_string()
+        });
+        let response = Rc::new(RefCell::new(None));
+        ws::connect(self.url.as_str(), |out| Call {
+            out,
+            authentication: authentication.clone(),
+            request: request.clone(),
+            response: Rc::clone(&response),
+        })
+        .expect("Should success connecting the hub");
+
+        let response = response.borrow_mut().take();
+        response.unwrap_or_else(|| panic!("The hub closed the connection before responding to {}", method))
+    }
+
+    /// Panics with the error if the call fails
+    pub fn call_ok(&self, method: &str, params: Value) -> Value {
+        self.call(method, params).unwrap_or_else(|error| panic!("{} failed : {}", method, error))
+    }
+}
+
+struct Call {
+    out: Sender,
+    authentication: Option<String>,
+    request: String,
+    response: Rc<RefCell<Option<Result<Value, Value>>>>,
+}
+
+impl Handler for Call {
+    fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+        match &self.authentication {
+            Some(authentication) => self.out.send(authentication.as_str()),
+            None => self.out.send(self.request.as_str()),
+        }
+    }
+
+    fn on_message(&mut self, msg: Message) -> ws::Result<()> {
+        let response: Value = match msg.as_text().ok().and_then(|text| serde_json::from_str(text).ok()) {
+            Some(response) => response,
+            None => return Ok(()),
+        };
+        if response["id"] == json!(AUTHENTICATE_ID) && response.get("error").is_none() {
+            return self.out.send(self.request.as_str())
+        }
+        if response["id"] != json!(REQUEST_ID) && response["id"] != json!(AUTHENTICATE_ID) {
+            return Ok(())
+        }
+        let result = match response.get("error") {
+            Some(error) => Err(error.clone()),
+            None => Ok(response["result"].clone()),
+        };
+        *self.response.borrow_mut() = Some(result);
+        self.out.close(CloseCode::Normal)
+    }
+}
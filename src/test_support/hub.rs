@@ -0,0 +1,95 @@
+use std::env;
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{self, Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use super::wait_until;
+
+const START_TIMEOUT: Duration = Duration::from_secs(30);
+
+static NEXT_HUB: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs the hub binary on free ports with a new SQLite database. The hub is killed when it is dropped.
+/// The hub runs in its own process because its modules are in the binary crate,
+/// and it installs the process-wide logger and signal handlers.
+pub struct TestHub {
+    child: Child,
+    dir: PathBuf,
+    pub frontend_url: String,
+    pub agent_url: String,
+}
+
+impl TestHub {
+    /// `binary` is the path of the hub, which the integration tests get from `CARGO_BIN_EXE_codechain-agent-hub`,
+    /// so cargo builds it before running the tests.
+    pub fn start(binary: &str) -> Self {
+        Self::start_with_config(binary, "")
+    }
+
+    /// `extra_config` is the TOML tables which are appended to the test config, e.g. `[log_storage]`
+    pub fn start_with_config(binary: &str, extra_config: &str) -> Self {
+        let index = NEXT_HUB.fetch_add(1, Ordering::SeqCst);
+        let dir = env::temp_dir().join(format!("agent-hub-test-{}-{}", process::id(), index));
+        fs::create_dir_all(&dir).expect("Should success create the test directory");
+        let (frontend_port, agent_port, web_port) = (free_port(), free_port(), free_port());
+        // The tests call the RPCs faster than the default rate limit
+        let config = format!(
+            "[listen]\n\
+             frontend = \"127.0.0.1:{}\"\n\
+             agent = \"127.0.0.1:{}\"\n\
+             web = \"127.0.0.1:{}\"\n\
+             [storage]\n\
+             type = \"sqlite\"\n\
+             path = {:?}\n\
+             [frontend.rate_limit]\n\
+             requests_per_second = 1000.0\n\
+             burst = 1000.0\n\
+             {}",
+            frontend_port,
+            agent_port,
+            web_port,
+            dir.join("hub.sqlite").to_string_lossy(),
+            extra_config
+        );
+        let config_path = dir.join("hub.toml");
+        fs::write(&config_path, config).expect("Should success write the test config");
+
+        let child = Command::new(binary)
+            .arg("--config")
+            .arg(&config_path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Should success running the hub");
+        let hub = Self {
+            child,
+            dir,
+            frontend_url: format!("ws://127.0.0.1:{}", frontend_port),
+            agent_url: format!("ws://127.0.0.1:{}", agent_port),
+        };
+        let listening = wait_until(START_TIMEOUT, || {
+            TcpStream::connect(("127.0.0.1", frontend_port)).is_ok()
+                && TcpStream::connect(("127.0.0.1", agent_port)).is_ok()
+        });
+        assert!(listening, "The hub didn't start listening");
+        hub
+    }
+}
+
+impl Drop for TestHub {
+    fn drop(&mut self) {
+        if let Err(err) = self.child.kill() {
+            eprintln!("Cannot kill the test hub : {}", err);
+        }
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Should success bind a free port");
+    listener.local_addr().expect("Should success get the free port").port()
+}
@@ -0,0 +1,248 @@
+use std::net::SocketAddr;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use chrono;
+use serde_json;
+use serde_json::Value;
+use ws::{CloseCode, Handler, Message, Sender};
+
+/// The agents of this protocol push the logs instead of being polled
+const LOG_PUSH_PROTOCOL_VERSION: u32 = 3;
+const ERR_METHOD_NOT_FOUND: i64 = -32601;
+
+/// A fake CodeChain node which a mock agent reports. Change it through `MockAgent::node` to change what the hub sees.
+#[derive(Debug, Clone)]
+pub struct MockNode {
+    pub name: String,
+    /// One of the node statuses, e.g. "Run" or "Stop"
+    pub status: String,
+    pub address: Option<SocketAddr>,
+    pub commit_hash: String,
+    pub best_block_number: u64,
+    pub peers: Vec<SocketAddr>,
+    /// The address which signs the blocks
+    pub coinbase: Option<String>,
+    /// The protocol which the agent speaks in the handshake
+    pub protocol_version: u32,
+    pub capabilities: Vec<String>,
+    /// The shell commands which the hub sent, with their params
+    pub commands: Vec<(String, Value)>,
+    /// The logs which the agent of an old protocol returns when it is polled
+    polled_logs: Vec<Value>,
+}
+
+impl MockNode {
+    /// A stopped node which speaks the newest protocol
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: "Stop".to_string(),
+            address: None,
+            commit_hash: "0000000000000000000000000000000000000000".to_string(),
+            best_block_number: 0,
+            peers: Vec::new(),
+            coinbase: None,
+            protocol_version: 5,
+            capabilities: vec!["snapshot".to_string()],
+            commands: Vec::new(),
+            polled_logs: Vec::new(),
+        }
+    }
+
+    /// The methods of the shell commands which the hub sent, in order
+    pub fn command_methods(&self) -> Vec<&str> {
+        self.commands.iter().map(|(method, _)| method.as_str()).collect()
+    }
+}
+
+/**
+ * Speaks the agent protocol in the process, so the tests can run the hub without real CodeChain nodes.
+ * It answers the requests of the hub from its MockNode, and the shell commands change the node.
+ */
+pub struct MockAgent {
+    node: Arc<Mutex<MockNode>>,
+    out: Sender,
+}
+
+impl MockAgent {
+    /// Connects to the agent listener of the hub, e.g. "ws://127.0.0.1:4012"
+    pub fn connect(url: &str, node: MockNode) -> Self {
+        let name = node.name.clone();
+        let node = Arc::new(Mutex::new(node));
+        let (tx, rx) = channel();
+        let url = url.to_string();
+        let connection_node = Arc::clone(&node);
+        thread::Builder::new()
+            .name(format!("mock agent {}", name))
+            .spawn(move || {
+                let result = ws::connect(url, |out| {
+                    tx.send(out.clone()).expect("Should success send the mock agent sender");
+                    Connection {
+                        out,
+                        node: Arc::clone(&connection_node),
+                    }
+                });
+                if let Err(err) = result {
+                    eprintln!("Mock agent {} failed : {}", name, err);
+                }
+            })
+            .expect("Should success running mock agent thread");
+        let out = rx.recv().expect("Should success connecting the mock agent");
+        Self {
+            node,
+            out,
+        }
+    }
+
+    pub fn node(&self) -> MutexGuard<MockNode> {
+        self.node.lock().expect("Should success get mock node")
+    }
+
+    /// Emits a log of the node. The agents of the protocol 3 or newer push it right away
+    pub fn emit_log(&self, level: &str, target: &str, message: &str) {
+        let log = json!({
+            "level": level,
+            "target": target,
+            "message": message,
+            "timestamp": chrono::Local::now().format("%Y-%m-%dT%H:%M:%S.%f%z").to_string(),
+            "threadName": "mock",
+        });
+        let mut node = self.node();
+        if node.protocol_version < LOG_PUSH_PROTOCOL_VERSION {
+            node.polled_logs.push(log);
+            return
+        }
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "log_push",
+            "params": {
+                "logs": [log],
+                "dropped": 0,
+            },
+        });
+        self.out.send(notification.to_string()).expect("Should success push log");
+    }
+
+    /// Disconnects as if the agent is killed
+    pub fn disconnect(&self) {
+        self.out.close(CloseCode::Normal).expect("Should success close mock agent");
+    }
+}
+
+struct Connection {
+    out: Sender,
+    node: Arc<Mutex<MockNode>>,
+}
+
+impl Handler for Connection {
+    fn on_message(&mut self, msg: Message) -> ws::Result<()> {
+        let request: Value = match msg.as_text().ok().and_then(|text| serde_json::from_str(text).ok()) {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+        // The notifications like log_setBackpressure don't need responses
+        if request["id"].is_null() {
+            return Ok(())
+        }
+        let method = request["method"].as_str().unwrap_or_default();
+        let mut node = self.node.lock().expect("Should success get mock node");
+        let response = match handle(&mut node, method, &request["params"]) {
+            Ok(result) => json!({
+                "jsonrpc": "2.0",
+                "result": result,
+                "id": request["id"],
+            }),
+            Err(error) => json!({
+                "jsonrpc": "2.0",
+                "error": error,
+                "id": request["id"],
+            }),
+        };
+        self.out.send(response.to_string())
+    }
+}
+
+fn handle(node: &mut MockNode, method: &str, params: &Value) -> Result<Value, Value> {
+    match method {
+        "agent_handshake" => Ok(json!({
+            "protocolVersion": node.protocol_version,
+            "minProtocolVersion": 1,
+            "agentVersion": "mock",
+            "capabilities": node.capabilities,
+        })),
+        "agent_getInfo" => Ok(json!({
+            "status": node.status,
+            "name": node.name,
+            "address": node.address,
+            "codechainCommitHash": node.commit_hash,
+            "systemTime": chrono::Utc::now(),
+        })),
+        "hardware_get" => {
+            let usage = json!({
+                "total": 0,
+                "available": 0,
+                "percentageUsed": 0.0,
+            });
+            Ok(json!({
+                "cpuUsage": [0.0],
+                "diskUsage": usage,
+                "memoryUsage": usage,
+            }))
+        }
+        "log_getBuffered" | "snapshot_list" => Ok(json!([])),
+        "shell_getCodeChainLog" => Ok(json!("")),
+        "shell_startCodeChain" | "shell_stopCodeChain" | "shell_updateCodeChain" | "snapshot_restore" => {
+            run_command(node, method, &params[0]);
+            Ok(Value::Null)
+        }
+        "codechain_callRPC" => {
+            let result = codechain_rpc(node, params[0].as_str().unwrap_or_default());
+            Ok(json!({
+                "innerResponse": {
+                    "jsonrpc": "2.0",
+                    "result": result,
+                    "id": 1,
+                },
+            }))
+        }
+        _ => Err(json!({
+            "code": ERR_METHOD_NOT_FOUND,
+            "message": "Method not found",
+        })),
+    }
+}
+
+fn run_command(node: &mut MockNode, method: &str, params: &Value) {
+    match method {
+        "shell_startCodeChain" => node.status = "Run".to_string(),
+        "shell_stopCodeChain" => node.status = "Stop".to_string(),
+        "shell_updateCodeChain" => {
+            node.commit_hash = params["commitHash"].as_str().unwrap_or_default().to_string();
+            node.status = "Run".to_string();
+        }
+        _ => {}
+    }
+    node.commands.push((method.to_string(), params.clone()));
+}
+
+/// The RPCs of CodeChain which the hub calls through the agent. The others return null
+fn codechain_rpc(node: &mut MockNode, method: &str) -> Value {
+    match method {
+        "net_getEstablishedPeers" => json!(node.peers),
+        "chain_getBestBlockId" => json!({
+            "hash": format!("0x{:064x}", node.best_block_number),
+            "number": node.best_block_number,
+        }),
+        "version" => json!("mock"),
+        "commitHash" => json!(node.commit_hash),
+        "engine_getCoinbase" => json!(node.coinbase),
+        "net_getWhitelist" | "net_getBlacklist" => json!({
+            "list": [],
+            "enabled": false,
+        }),
+        "slog" => json!(node.polled_logs.drain(..).collect::<Vec<_>>()),
+        _ => Value::Null,
+    }
+}
@@ -0,0 +1,29 @@
+mod fake_http;
+mod frontend;
+mod hub;
+mod mock_agent;
+
+pub use self::fake_http::{FakeHttpServer, HttpRequest};
+pub use self::frontend::FrontendClient;
+pub use self::hub::TestHub;
+pub use self::mock_agent::{MockAgent, MockNode};
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The hub updates the agents periodically, so the tests wait until the hub sees the change.
+/// Returns false if the condition isn't met in the timeout.
+pub fn wait_until<F>(timeout: Duration, mut condition: F) -> bool
+where
+    F: FnMut() -> bool, {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if condition() {
+            return true
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    condition()
+}
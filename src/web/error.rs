@@ -0,0 +1,56 @@
+use std::error::Error;
+use std::fmt;
+
+use iron;
+use iron::status;
+
+#[derive(Debug)]
+pub struct WebError {
+    value: String,
+}
+
+impl WebError {
+    pub fn new(s: &str) -> Self {
+        WebError {
+            value: s.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for WebError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl Error for WebError {}
+
+pub fn not_found(msg: &str) -> iron::IronError {
+    iron::IronError::new(WebError::new(msg), status::NotFound)
+}
+
+pub fn unauthorized(msg: &str) -> iron::IronError {
+    iron::IronError::new(WebError::new(msg), status::Unauthorized)
+}
+
+pub fn forbidden(msg: &str) -> iron::IronError {
+    iron::IronError::new(WebError::new(msg), status::Forbidden)
+}
+
+pub fn bad_request(msg: &str) -> iron::IronError {
+    iron::IronError::new(WebError::new(msg), status::BadRequest)
+}
+
+pub fn payload_too_large(msg: &str) -> iron::IronError {
+    iron::IronError::new(WebError::new(msg), status::PayloadTooLarge)
+}
+
+pub fn service_unavailable(msg: &str) -> iron::IronError {
+    iron::IronError::new(WebError::new(msg), status::ServiceUnavailable)
+}
+
+pub fn internal<E>(err: E) -> iron::IronError
+where
+    E: fmt::Debug, {
+    iron::IronError::new(WebError::new(&format!("{:?}", err)), status::InternalServerError)
+}
@@ -0,0 +1,50 @@
+use std::io::Read;
+use std::sync::Arc;
+
+use iron;
+use iron::prelude::*;
+use iron::status;
+use juniper::http::GraphQLRequest;
+use serde_json;
+
+use super::super::frontend;
+use super::super::frontend::graphql::{QueryContext, Schema};
+use super::super::rate_limit::RateLimiter;
+use super::error::{bad_request, internal};
+use super::rest::json_response;
+
+/**
+ * `POST /graphql` with `{ "query": ..., "variables": ... }`. Only the queries are served, and nothing is changed.
+ * A query weighs 1 in the rate limit, and the fields which read the storage take their costs as they are resolved.
+ */
+pub fn call(
+    schema: &Schema,
+    rate_limiter: &Arc<RateLimiter>,
+    context: frontend::Context,
+    req: &mut iron::Request,
+) -> IronResult<Response> {
+    let mut body = String::new();
+    req.body.read_to_string(&mut body).map_err(|err| bad_request(&format!("{}", err)))?;
+    let request: GraphQLRequest = serde_json::from_str(&body).map_err(|err| bad_request(&format!("{}", err)))?;
+
+    if let Err(retry_after) = rate_limiter.acquire(1.0) {
+        cdebug!("GraphQL is rate limited. Retry after {:?}", retry_after);
+        let retry_after_ms = retry_after.as_secs() * 1000 + u64::from(retry_after.subsec_millis());
+        let message = format!("Rate limited. Retry after {} ms", retry_after_ms);
+        return Ok(error_response(status::TooManyRequests, &message))
+    }
+
+    let context = QueryContext::new(context, Arc::clone(rate_limiter));
+    let response = request.execute(schema, &context);
+    cdebug!("The GraphQL query cost {}", context.cost());
+    let status = if response.is_ok() {
+        status::Ok
+    } else {
+        status::BadRequest
+    };
+    Ok(json_response(status, serde_json::to_string(&response).map_err(internal)?))
+}
+
+fn error_response(status: status::Status, message: &str) -> Response {
+    json_response(status, json!({ "errors": [{ "message": message }] }).to_string())
+}
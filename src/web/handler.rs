@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+
+use iron;
+use iron::method::Method;
+use iron::mime;
+use iron::prelude::*;
+use iron::status;
+use serde_json;
+
+use super::super::agent;
+use super::super::db;
+use super::super::frontend;
+use super::super::rate_limit::RateLimiter;
+use super::super::router::Router;
+use super::error::{bad_request, forbidden, internal, not_found, unauthorized};
+use super::graphql;
+use super::health::{self, ListenerStatus};
+use super::log_download;
+use super::log_export;
+use super::long_poll;
+use super::public_metrics::{self, PublicMetricsOption};
+use super::query_string;
+use super::rest;
+
+/// The limiters of the clients which didn't send requests recently are dropped above this number
+const MAX_IDLE_RATE_LIMITERS: usize = 1024;
+
+pub struct WebHandler {
+    agent_service_sender: Mutex<agent::ServiceSender>,
+    db_service_sender: Mutex<db::ServiceSender>,
+    frontend_service_sender: Mutex<frontend::ServiceSender>,
+    frontend_context: Mutex<frontend::Context>,
+    frontend_router: Arc<Router<frontend::Context>>,
+    public_metrics: Option<PublicMetricsOption>,
+    rest_gateway: bool,
+    graphql_schema: Option<frontend::graphql::Schema>,
+    listeners: Arc<ListenerStatus>,
+    shutting_down: Arc<AtomicBool>,
+    /// HTTP requests are limited by the address of the client
+    rate_limiters: Mutex<HashMap<IpAddr, Arc<RateLimiter>>>,
+}
+
+pub struct WebHandlerNewArg {
+    pub agent_service_sender: agent::ServiceSender,
+    pub db_service_sender: db::ServiceSender,
+    pub frontend_service_sender: frontend::ServiceSender,
+    pub frontend_context: frontend::Context,
+    pub frontend_router: Arc<Router<frontend::Context>>,
+    pub public_metrics: Option<PublicMetricsOption>,
+    /// Serves the frontend API as REST endpoints under /api
+    pub rest_gateway: bool,
+    /// Serves /graphql
+    pub graphql: bool,
+    pub listeners: Arc<ListenerStatus>,
+    pub shutting_down: Arc<AtomicBool>,
+}
+
+impl WebHandler {
+    pub fn new(
+        WebHandlerNewArg {
+            agent_service_sender,
+            db_service_sender,
+            frontend_service_sender,
+            frontend_context,
+            frontend_router,
+            public_metrics,
+            rest_gateway,
+            graphql,
+            listeners,
+            shutting_down,
+        }: WebHandlerNewArg,
+    ) -> Self {
+        Self {
+            agent_service_sender: Mutex::new(agent_service_sender),
+            db_service_sender: Mutex::new(db_service_sender),
+            frontend_service_sender: Mutex::new(frontend_service_sender),
+            frontend_context: Mutex::new(frontend_context),
+            frontend_router,
+            public_metrics,
+            rest_gateway,
+            graphql_schema: if graphql {
+                Some(frontend::graphql::schema())
+            } else {
+                None
+            },
+            listeners,
+            shutting_down,
+            rate_limiters: Default::default(),
+        }
+    }
+
+    fn get_log(&self, node_name: &str, req: &iron::Request) -> IronResult<iron::Response> {
+        ctrace!("Get log for agent-{}", node_name);
+
+        let agent = self
+            .agent_service_sender
+            .lock()
+            .expect("Should success get lock")
+            .get_agent(node_name.to_string())
+            .ok_or_else(|| not_found("Not Found"))?;
+
+        log_download::get(agent, req)
+    }
+
+    fn get_public_metrics(&self, option: &PublicMetricsOption) -> IronResult<iron::Response> {
+        let agents_state =
+            self.db_service_sender.lock().expect("Should success get lock").get_agents_state().map_err(internal)?;
+
+        let metrics = public_metrics::aggregate(&agents_state, option);
+        let body = serde_json::to_string(&metrics).map_err(internal)?;
+
+        let content_type = "application/json".parse::<mime::Mime>().unwrap();
+        Ok(Response::with((content_type, status::Ok, body)))
+    }
+
+    fn get_rpc_metrics(&self) -> IronResult<iron::Response> {
+        let body = self.frontend_router.metrics().prometheus();
+        let content_type = "text/plain; version=0.0.4".parse::<mime::Mime>().unwrap();
+        Ok(Response::with((content_type, status::Ok, body)))
+    }
+
+    fn frontend_context(&self, req: &iron::Request) -> IronResult<frontend::Context> {
+        let mut context = self.frontend_context.lock().expect("Should success get lock").clone();
+        let forwarded_for = req
+            .headers
+            .get_raw("X-Forwarded-For")
+            .and_then(|values| values.first())
+            .and_then(|value| String::from_utf8(value.clone()).ok());
+        let caller = context.config.frontend.caller(req.remote_addr, forwarded_for.as_ref().map(String::as_str));
+        context.caller = Some(caller);
+        context.peer_addr = Some(req.remote_addr);
+        context.sandbox = query_param(req, "sandbox").map_or(false, |sandbox| sandbox == "true");
+        let token = self.authenticate(req)?;
+        context.namespace = token.as_ref().and_then(|token| token.namespace.clone());
+        context.identity = token.map(|token| token.name);
+        Ok(context)
+    }
+
+    /// The token is given by the Authorization header like "Bearer secret". It is never in the URL, which is logged.
+    /// The frontend allowlist is checked first, so the web listener doesn't bypass it.
+    fn authenticate(&self, req: &iron::Request) -> IronResult<Option<frontend::ApiTokenOption>> {
+        let config = Arc::clone(&self.frontend_context.lock().expect("Should success get lock").config);
+        if !config.listen.frontend_allowlist.allows(&req.remote_addr.ip()) {
+            cwarn!("Refuse the request of {} which is not in the allowlist", req.remote_addr);
+            return Err(forbidden("The address is not allowed"))
+        }
+        let token = req
+            .headers
+            .get_raw("Authorization")
+            .and_then(|values| values.first())
+            .and_then(|value| String::from_utf8(value.clone()).ok())
+            .map(|value| value.trim_start_matches("Bearer ").to_string());
+        let context = self.frontend_context.lock().expect("Should success get lock");
+        frontend::namespace::authenticate(&context.config.frontend.api_tokens, token.as_ref().map(String::as_str))
+            .map(|token| token.cloned())
+            .map_err(|err| unauthorized(&err))
+    }
+
+    fn rate_limiter(&self, req: &iron::Request) -> Arc<RateLimiter> {
+        let mut rate_limiters = self.rate_limiters.lock().expect("Should success get lock");
+        if rate_limiters.len() > MAX_IDLE_RATE_LIMITERS {
+            rate_limiters.retain(|_, rate_limiter| !rate_limiter.is_idle());
+        }
+        let frontend_router = &self.frontend_router;
+        Arc::clone(
+            rate_limiters.entry(req.remote_addr.ip()).or_insert_with(|| Arc::new(frontend_router.rate_limiter())),
+        )
+    }
+
+    fn get_health(&self, readiness: bool) -> IronResult<iron::Response> {
+        let agent_service = self.agent_service_sender.lock().expect("Should success get lock").clone();
+        let db_service = self.db_service_sender.lock().expect("Should success get lock").clone();
+        if readiness {
+            health::readiness(&agent_service, &db_service, &self.listeners, &self.shutting_down)
+        } else {
+            health::liveness(&agent_service, &db_service)
+        }
+    }
+
+    fn frontend_service_sender(&self) -> frontend::ServiceSender {
+        self.frontend_service_sender.lock().expect("Should success get lock").clone()
+    }
+}
+
+impl iron::Handler for WebHandler {
+    fn handle(&self, req: &mut iron::Request) -> IronResult<iron::Response> {
+        let paths: Vec<String> = req.url.path().iter().map(|path| path.to_string()).collect();
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        if self.rest_gateway && paths.first() == Some(&"api") {
+            let context = self.frontend_context(req)?;
+            let rate_limiter = self.rate_limiter(req);
+            return rest::handle(&self.frontend_router, &rate_limiter, context, req, &paths[1..])
+        }
+
+        let method = req.method.clone();
+        match (method, paths.as_slice()) {
+            (Method::Get, ["log", node_name]) => {
+                let context = self.frontend_context(req)?;
+                frontend::namespace::check_node(&context, &node_name.to_string()).map_err(|_| not_found("Not Found"))?;
+                self.get_log(node_name, req)
+            }
+            (Method::Get, ["logs", "export"]) => {
+                let context = self.frontend_context(req)?;
+                log_export::get(context, req)
+            }
+            (Method::Get, ["healthz"]) => self.get_health(false),
+            (Method::Get, ["readyz"]) => self.get_health(true),
+            (Method::Get, ["metrics"]) => self.get_rpc_metrics(),
+            (Method::Get, ["metrics", "public"]) if self.public_metrics.is_some() => {
+                self.get_public_metrics(self.public_metrics.as_ref().expect("Already checked"))
+            }
+            (Method::Post, ["graphql"]) if self.graphql_schema.is_some() => {
+                let context = self.frontend_context(req)?;
+                let rate_limiter = self.rate_limiter(req);
+                graphql::call(self.graphql_schema.as_ref().expect("Already checked"), &rate_limiter, context, req)
+            }
+            (Method::Post, ["rpc"]) => {
+                let context = self.frontend_context(req)?;
+                let rate_limiter = self.rate_limiter(req);
+                long_poll::call(&self.frontend_router, &rate_limiter, context, req)
+            }
+            (Method::Post, ["rpc", "session"]) => {
+                let namespace = self.authenticate(req)?.and_then(|token| token.namespace);
+                long_poll::create_session(&self.frontend_service_sender(), namespace)
+            }
+            (Method::Get, ["rpc", "events"]) => {
+                self.authenticate(req)?;
+                let session_id = query_param(req, "session").ok_or_else(|| bad_request("session is required"))?;
+                long_poll::poll_events(&self.frontend_service_sender(), &session_id)
+            }
+            _ => {
+                cwarn!("Invalid web request {}", req.url);
+                Ok(Response::with(status::NotFound))
+            }
+        }
+    }
+}
+
+fn query_param(req: &iron::Request, key: &str) -> Option<String> {
+    let query = req.url.query().unwrap_or_default();
+    query_string::parse(query).into_iter().find(|(name, _)| name == key).map(|(_, value)| value)
+}
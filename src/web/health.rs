@@ -0,0 +1,133 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use iron;
+use iron::method::Method;
+use iron::mime;
+use iron::prelude::*;
+use iron::status;
+use serde_json;
+
+use super::super::agent;
+use super::super::db;
+use super::error::{internal, service_unavailable};
+use super::handler::WebHandler;
+
+/// The service thread which doesn't answer in this time is regarded as stuck
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The listeners set it after they bind their addresses
+#[derive(Default)]
+pub struct ListenerStatus {
+    pub agent: AtomicBool,
+    pub frontend: AtomicBool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Check {
+    name: &'static str,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn new<E: ToString>(name: &'static str, result: Result<(), E>) -> Self {
+        let error = result.err().map(|err| err.to_string());
+        Self {
+            name,
+            ok: error.is_none(),
+            error,
+        }
+    }
+}
+
+/// `GET /healthz` fails when a service thread is stuck, which only a restart fixes
+pub fn liveness(agent_service: &agent::ServiceSender, db_service: &db::ServiceSender) -> IronResult<iron::Response> {
+    respond(service_checks(agent_service, db_service))
+}
+
+/// `GET /readyz` also fails while the database or a listener is unavailable or the hub is shutting down
+pub fn readiness(
+    agent_service: &agent::ServiceSender,
+    db_service: &db::ServiceSender,
+    listeners: &ListenerStatus,
+    shutting_down: &AtomicBool,
+) -> IronResult<iron::Response> {
+    let mut checks = service_checks(agent_service, db_service);
+    checks.push(Check::new("database", db_service.ping_storage()));
+    checks.push(Check::new("agentListener", listening(&listeners.agent)));
+    checks.push(Check::new("frontendListener", listening(&listeners.frontend)));
+    let shutting_down = if shutting_down.load(Ordering::SeqCst) {
+        Err("The hub is shutting down")
+    } else {
+        Ok(())
+    };
+    checks.push(Check::new("shutdown", shutting_down));
+    respond(checks)
+}
+
+/// Serves the health checks while the hub waits for the leader election, and the web handler after it is set.
+/// The standby is live but not ready, so an orchestrator doesn't restart it but doesn't route to it either.
+#[derive(Clone, Default)]
+pub struct StandbyHandler {
+    handler: Arc<RwLock<Option<WebHandler>>>,
+}
+
+impl StandbyHandler {
+    /// Called when the hub becomes the leader and its services are running
+    pub fn set(&self, handler: WebHandler) {
+        *self.handler.write().expect("Should success lock the web handler") = Some(handler);
+    }
+}
+
+impl iron::Handler for StandbyHandler {
+    fn handle(&self, req: &mut iron::Request) -> IronResult<iron::Response> {
+        if let Some(handler) = self.handler.read().expect("Should success lock the web handler").as_ref() {
+            return iron::Handler::handle(handler, req)
+        }
+        let is_get = req.method == Method::Get;
+        match req.url.path().as_slice() {
+            ["healthz"] if is_get => respond(vec![Check::new("leader", Ok::<(), &str>(()))]),
+            ["readyz"] if is_get => respond(vec![Check::new("leader", Err("This hub is the standby"))]),
+            _ => Err(service_unavailable("This hub is the standby")),
+        }
+    }
+}
+
+fn service_checks(agent_service: &agent::ServiceSender, db_service: &db::ServiceSender) -> Vec<Check> {
+    vec![
+        Check::new("agentService", agent_service.ping(PING_TIMEOUT)),
+        Check::new("dbService", db_service.ping(PING_TIMEOUT)),
+    ]
+}
+
+fn listening(listener: &AtomicBool) -> Result<(), &'static str> {
+    if listener.load(Ordering::SeqCst) {
+        Ok(())
+    } else {
+        Err("Not listening")
+    }
+}
+
+fn respond(checks: Vec<Check>) -> IronResult<iron::Response> {
+    let failed: Vec<&str> = checks.iter().filter(|check| !check.ok).map(|check| check.name).collect();
+    let ok = failed.is_empty();
+    if !ok {
+        cwarn!("The health check failed : {:?}", failed);
+    }
+    let body = serde_json::to_string(&json!({
+        "status": if ok { "ok" } else { "unavailable" },
+        "checks": checks,
+    }))
+    .map_err(internal)?;
+    let content_type = "application/json".parse::<mime::Mime>().unwrap();
+    let status = if ok {
+        status::Ok
+    } else {
+        status::ServiceUnavailable
+    };
+    Ok(Response::with((content_type, status, body)))
+}
@@ -0,0 +1,155 @@
+use std::io;
+use std::io::Write;
+
+use base64;
+use iron;
+use iron::headers::ContentLength;
+use iron::mime;
+use iron::prelude::*;
+use iron::response::WriteBody;
+use iron::status;
+use jsonrpc_core::types::ErrorCode;
+use sha2::{Digest, Sha256};
+
+use super::super::agent::{AgentSender, LogRangeRequest, SendAgentRPC};
+use super::super::rpc::RPCError;
+use super::error::internal;
+
+/// The size of a range which is requested to the agent at once
+const CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/**
+ * Serves the log of CodeChain with the range requests, so a client can resume a broken download.
+ * The ETag changes when the log file is rotated, and X-Checksum-Sha256 is the checksum of the whole file.
+ * Each chunk from the agent is verified with its checksum before it is sent to the client.
+ */
+pub fn get(agent: AgentSender, req: &iron::Request) -> IronResult<iron::Response> {
+    let checksum = match agent.shell_get_codechain_log_checksum() {
+        Ok(checksum) => checksum,
+        Err(RPCError::FromAgent(_, ref err)) if err.code == ErrorCode::MethodNotFound => {
+            return get_whole(&agent, req)
+        }
+        Err(err) => return Err(internal(err)),
+    };
+    let etag = format!("\"{}\"", checksum.file_id);
+    let total_size = checksum.total_size;
+
+    let range = match header(req, "Range") {
+        Some(_) if header(req, "If-Range").map(|if_range| if_range != etag).unwrap_or(false) => None,
+        Some(range) => match parse_range(&range, total_size) {
+            Ok(range) => range,
+            Err(()) => {
+                let mut response = Response::with(status::RangeNotSatisfiable);
+                response.headers.set_raw("Content-Range", vec![format!("bytes */{}", total_size).into_bytes()]);
+                return Ok(response)
+            }
+        },
+        None => None,
+    };
+
+    let (status, start, end) = match range {
+        Some((start, end)) => (status::PartialContent, start, end),
+        None => (status::Ok, 0, total_size),
+    };
+    let mut response = Response::with((content_type(), status));
+    response.headers.set(ContentLength(end - start));
+    response.headers.set_raw("Accept-Ranges", vec![b"bytes".to_vec()]);
+    response.headers.set_raw("ETag", vec![etag.into_bytes()]);
+    response.headers.set_raw("X-Checksum-Sha256", vec![checksum.sha256.into_bytes()]);
+    if range.is_some() {
+        set_content_range(&mut response, start, end, total_size);
+    }
+    response.body = Some(Box::new(LogBody {
+        agent,
+        file_id: checksum.file_id,
+        offset: start,
+        end,
+    }));
+    Ok(response)
+}
+
+/// The agents which don't support the range requests send the whole log at once.
+fn get_whole(agent: &AgentSender, req: &iron::Request) -> IronResult<iron::Response> {
+    let log = agent.shell_get_codechain_log().map_err(|err| iron::IronError::new(err, status::InternalServerError))?;
+    let log = log.into_bytes();
+    let total_size = log.len() as u64;
+    let range = header(req, "Range").and_then(|range| parse_range(&range, total_size).ok()).and_then(|range| range);
+    match range {
+        Some((start, end)) => {
+            let body = log[start as usize..end as usize].to_vec();
+            let mut response = Response::with((content_type(), status::PartialContent, body));
+            set_content_range(&mut response, start, end, total_size);
+            Ok(response)
+        }
+        None => Ok(Response::with((content_type(), status::Ok, log))),
+    }
+}
+
+struct LogBody {
+    agent: AgentSender,
+    file_id: String,
+    offset: u64,
+    end: u64,
+}
+
+impl WriteBody for LogBody {
+    fn write_body(&mut self, res: &mut Write) -> io::Result<()> {
+        while self.offset < self.end {
+            let chunk = self
+                .agent
+                .shell_get_codechain_log_range(LogRangeRequest {
+                    offset: self.offset,
+                    length: CHUNK_SIZE.min(self.end - self.offset),
+                })
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+            if chunk.file_id != self.file_id {
+                return Err(io::Error::new(io::ErrorKind::Other, "The log file is rotated while downloading"))
+            }
+            let data = base64::decode(&chunk.data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            if format!("{:x}", Sha256::digest(&data)) != chunk.sha256 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "The checksum of the log chunk doesn't match"))
+            }
+            if data.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The log file is truncated while downloading"))
+            }
+            res.write_all(&data)?;
+            self.offset += data.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a single range of `bytes=start-end`, `bytes=start-` or `bytes=-suffix`, and returns [start, end).
+/// Returns None for the ranges which are ignored, e.g. multiple ranges.
+fn parse_range(range: &str, total_size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let spec = match range.trim().splitn(2, '=').collect::<Vec<_>>().as_slice() {
+        ["bytes", spec] if !spec.contains(',') => spec.trim().to_string(),
+        _ => return Ok(None),
+    };
+    let (first, last) = match spec.splitn(2, '-').collect::<Vec<_>>().as_slice() {
+        [first, last] => (first.trim().to_string(), last.trim().to_string()),
+        _ => return Ok(None),
+    };
+    let (start, end) = match (first.parse::<u64>(), last.parse::<u64>()) {
+        (Ok(start), Ok(last)) if start <= last => (start, (last + 1).min(total_size)),
+        (Ok(start), Err(_)) if last.is_empty() => (start, total_size),
+        (Err(_), Ok(suffix)) if first.is_empty() => (total_size.saturating_sub(suffix), total_size),
+        _ => return Ok(None),
+    };
+    if start >= total_size || start >= end {
+        return Err(())
+    }
+    Ok(Some((start, end)))
+}
+
+fn set_content_range(response: &mut iron::Response, start: u64, end: u64, total_size: u64) {
+    response.headers.set_raw("Content-Range", vec![format!("bytes {}-{}/{}", start, end - 1, total_size).into_bytes()]);
+}
+
+fn header(req: &iron::Request, name: &str) -> Option<String> {
+    req.headers.get_raw(name).and_then(|values| values.first()).map(|value| String::from_utf8_lossy(value).to_string())
+}
+
+fn content_type() -> mime::Mime {
+    "text/plain".parse::<mime::Mime>().unwrap()
+}
@@ -0,0 +1,148 @@
+use std::io;
+use std::io::Write;
+
+use iron;
+use iron::mime;
+use iron::prelude::*;
+use iron::response::WriteBody;
+use iron::status;
+use serde_json;
+
+use super::super::db;
+use super::super::frontend;
+use super::error::{bad_request, internal};
+use super::query_string;
+use super::rest;
+
+/// The logs are read from the storage in the pages of this size while they are written to the client
+const PAGE_SIZE: i32 = 1000;
+
+#[derive(Clone, Copy)]
+enum Format {
+    JsonLines,
+    Csv,
+}
+
+/**
+ * Streams the logs selected by the filters of `GET /api/logs` as JSON Lines or CSV.
+ * The logs are read page by page, so a large range is never held in memory at once.
+ * Each page starts after the last log of the previous page, so the logs inserted during the export don't shift
+ * the pages and no log is written twice or skipped.
+ * The first page is read before the response starts, so an invalid query fails with the status code.
+ */
+pub fn get(context: frontend::Context, req: &iron::Request) -> IronResult<iron::Response> {
+    let mut format = Format::JsonLines;
+    let mut pairs = Vec::new();
+    for (key, value) in query_string::parse(req.url.query().unwrap_or_default()) {
+        match (key.as_str(), value.as_str()) {
+            ("format", "jsonl") => format = Format::JsonLines,
+            ("format", "csv") => format = Format::Csv,
+            ("format", _) => return Err(bad_request(&format!("Unknown format {}", value))),
+            ("sandbox", _) | ("page", _) | ("itemPerPage", _) => {}
+            _ => pairs.push((key, value)),
+        }
+    }
+    let mut params: db::LogQueryParams =
+        serde_json::from_value(rest::log_query_params(pairs)?).map_err(|err| bad_request(&err.to_string()))?;
+    let is_visible = frontend::namespace::restrict_logs(&context, &mut params).map_err(internal)?;
+    params.item_per_page = Some(PAGE_SIZE);
+    params.page = Some(1);
+    params.order_by = Some(db::OrderBy::ASC);
+    let first_page = if is_visible {
+        context.db_service.get_logs(params.clone()).map_err(internal)?
+    } else {
+        Vec::new()
+    };
+
+    let (content_type, extension) = match format {
+        Format::JsonLines => ("application/x-ndjson", "jsonl"),
+        Format::Csv => ("text/csv", "csv"),
+    };
+    let mut response = Response::with((content_type.parse::<mime::Mime>().unwrap(), status::Ok));
+    response
+        .headers
+        .set_raw("Content-Disposition", vec![format!("attachment; filename=\"logs.{}\"", extension).into_bytes()]);
+    response.body = Some(Box::new(LogExportBody {
+        db_service: context.db_service,
+        params,
+        format,
+        page: first_page,
+    }));
+    Ok(response)
+}
+
+struct LogExportBody {
+    db_service: db::ServiceSender,
+    params: db::LogQueryParams,
+    format: Format,
+    /// The logs which are read but not written yet
+    page: Vec<db::Log>,
+}
+
+impl WriteBody for LogExportBody {
+    fn write_body(&mut self, res: &mut Write) -> io::Result<()> {
+        if let Format::Csv = self.format {
+            res.write_all(b"id,nodeName,level,target,timestamp,message,backfilled\n")?;
+        }
+        loop {
+            let is_last = self.page.len() < PAGE_SIZE as usize;
+            let after = next_cursor(self.params.after.take(), &self.page);
+            for log in self.page.drain(..) {
+                match self.format {
+                    Format::JsonLines => {
+                        serde_json::to_writer(&mut *res, &log)?;
+                        res.write_all(b"\n")?;
+                    }
+                    Format::Csv => {
+                        let fields = [
+                            log.id.to_string(),
+                            log.node_name,
+                            log.level,
+                            log.target,
+                            log.timestamp.to_rfc3339(),
+                            log.message,
+                            log.backfilled.to_string(),
+                        ];
+                        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                        writeln!(res, "{}", fields.join(","))?;
+                    }
+                }
+            }
+            if is_last {
+                return Ok(())
+            }
+            self.params.after = after;
+            self.page = self
+                .db_service
+                .get_logs(self.params.clone())
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+        }
+    }
+}
+
+/// The cursor after the last log of the page
+fn next_cursor(previous: Option<db::LogCursor>, page: &[db::Log]) -> Option<db::LogCursor> {
+    let last = match page.last() {
+        Some(last) => last,
+        None => return previous,
+    };
+    let read_in_page = page.iter().rev().take_while(|log| log.timestamp == last.timestamp).count() as i64;
+    let read_before = match previous {
+        Some(ref previous) if previous.timestamp == last.timestamp => previous.read_at_timestamp,
+        _ => 0,
+    };
+    Some(db::LogCursor {
+        timestamp: last.timestamp,
+        id: last.id,
+        read_at_timestamp: read_before + read_in_page,
+    })
+}
+
+/// Quotes the field which has a comma, a quote or a line break
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,77 @@
+use std::io::Read;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+use iron;
+use iron::mime;
+use iron::prelude::*;
+use iron::status;
+use rand;
+
+use super::super::frontend;
+use super::super::jsonrpc;
+use super::super::rate_limit::RateLimiter;
+use super::super::router::Router;
+use super::error::{bad_request, internal, not_found, payload_too_large};
+
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(25);
+
+/// POST /rpc/session
+/// The session receives the events of the nodes in the namespace
+pub fn create_session(frontend_service: &frontend::ServiceSender, namespace: Option<String>) -> IronResult<Response> {
+    let session_id = format!("{:016x}", rand::random::<u64>());
+    frontend_service.send(frontend::Message::AddPollSession(session_id.clone(), namespace)).map_err(internal)?;
+    cinfo!("Poll session {} is created", session_id);
+    Ok(json_response(
+        json!({
+            "sessionId": session_id,
+        })
+        .to_string(),
+    ))
+}
+
+/// POST /rpc
+/// The body is a JSON-RPC request which is routed exactly like the one from a websocket.
+/// It is limited to `frontend.max_message_size` like the websocket messages.
+pub fn call(
+    router: &Router<frontend::Context>,
+    rate_limiter: &RateLimiter,
+    context: frontend::Context,
+    req: &mut iron::Request,
+) -> IronResult<Response> {
+    let body = read_body(req, context.config.frontend.max_message_size)?;
+
+    match jsonrpc::handle(|method, arg| router.run_limited(rate_limiter, context.clone(), &method, arg), body) {
+        Some(response) => Ok(json_response(response)),
+        None => Ok(Response::with(status::NoContent)),
+    }
+}
+
+/// GET /rpc/events?session=<id>
+/// Waits until an event arrives or the timeout passes, and responds the events as a JSON array.
+/// The frontend service answers as soon as an event arrives, so the request doesn't poll the queue.
+pub fn poll_events(frontend_service: &frontend::ServiceSender, session_id: &str) -> IronResult<Response> {
+    let (tx, rx) = channel();
+    frontend_service.send(frontend::Message::TakePollEvents(session_id.to_string(), tx)).map_err(internal)?;
+    let events = match rx.recv_timeout(LONG_POLL_TIMEOUT) {
+        Ok(events) => events.ok_or_else(|| not_found("Session not found"))?,
+        Err(RecvTimeoutError::Timeout) => Vec::new(),
+        Err(RecvTimeoutError::Disconnected) => return Err(internal("The frontend service is stopped")),
+    };
+    Ok(json_response(format!("[{}]", events.join(","))))
+}
+
+fn json_response(body: String) -> Response {
+    let content_type = "application/json".parse::<mime::Mime>().unwrap();
+    Response::with((content_type, status::Ok, body))
+}
+
+/// Reads at most `max_size` bytes, so a client can't make the hub buffer an unbounded body
+fn read_body(req: &mut iron::Request, max_size: usize) -> IronResult<String> {
+    let mut body = Vec::new();
+    req.body.by_ref().take(max_size as u64 + 1).read_to_end(&mut body).map_err(|err| bad_request(&format!("{}", err)))?;
+    if body.len() > max_size {
+        return Err(payload_too_large(&format!("The body is larger than {} bytes", max_size)))
+    }
+    String::from_utf8(body).map_err(|err| bad_request(&format!("{}", err)))
+}
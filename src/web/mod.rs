@@ -0,0 +1,14 @@
+mod error;
+mod graphql;
+mod handler;
+mod health;
+mod log_download;
+mod log_export;
+mod long_poll;
+mod public_metrics;
+pub mod query_string;
+mod rest;
+
+pub use self::handler::{WebHandler, WebHandlerNewArg};
+pub use self::health::{ListenerStatus, StandbyHandler};
+pub use self::public_metrics::PublicMetricsOption;
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use rand;
+
+use super::super::common_rpc_types::NodeStatus;
+use super::super::db::AgentQueryResult;
+
+/**
+ * PublicMetricsOption controls the /metrics/public endpoint.
+ * The endpoint never exposes node names or addresses.
+ * When noise_epsilon is set, counts are blurred with Laplace noise(differential privacy) of the given epsilon,
+ * and the percentiles are coarsened, since a single node can move them by more than the noise hides.
+ * The percentiles of fewer than min_group_size nodes are not exposed, since each of them is the value of a node.
+ */
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PublicMetricsOption {
+    pub noise_epsilon: Option<f64>,
+    pub min_group_size: usize,
+}
+
+impl Default for PublicMetricsOption {
+    fn default() -> Self {
+        Self {
+            noise_epsilon: None,
+            min_group_size: 5,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicMetrics {
+    pub node_count: i64,
+    pub status_counts: HashMap<String, i64>,
+    pub version_count: i64,
+    pub best_block_number: Option<Percentiles>,
+    pub peer_count: Option<Percentiles>,
+    pub blurred: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Percentiles {
+    pub min: i64,
+    pub p50: i64,
+    pub p90: i64,
+    pub max: i64,
+}
+
+/// The percentiles are rounded to these multiples when the metrics are blurred
+const BLOCK_NUMBER_GRANULARITY: i64 = 100;
+const PEER_COUNT_GRANULARITY: i64 = 5;
+
+pub fn aggregate(agents_state: &[AgentQueryResult], option: &PublicMetricsOption) -> PublicMetrics {
+    let count = |value: usize| blur(value as i64, option.noise_epsilon);
+
+    let mut status_counts = HashMap::new();
+    for status in &[
+        NodeStatus::Starting,
+        NodeStatus::Run,
+        NodeStatus::Stop,
+        NodeStatus::Updating,
+        NodeStatus::Error,
+        NodeStatus::UFO,
+        NodeStatus::Reconnecting,
+    ] {
+        let status_count = agents_state.iter().filter(|state| state.status == *status).count();
+        status_counts.insert(format!("{:?}", status), count(status_count));
+    }
+
+    let mut versions: Vec<&String> =
+        agents_state.iter().filter_map(|state| state.version.as_ref().map(|version| &version.hash)).collect();
+    versions.sort();
+    versions.dedup();
+
+    let best_block_numbers =
+        agents_state.iter().filter_map(|state| state.best_block_id.map(|block_id| block_id.block_number)).collect();
+    let peer_counts = agents_state.iter().map(|state| state.peers.len() as i64).collect();
+
+    PublicMetrics {
+        node_count: count(agents_state.len()),
+        status_counts,
+        version_count: count(versions.len()),
+        best_block_number: percentiles(
+            best_block_numbers,
+            option.min_group_size,
+            coarsen(BLOCK_NUMBER_GRANULARITY, option.noise_epsilon),
+        ),
+        peer_count: percentiles(
+            peer_counts,
+            option.min_group_size,
+            coarsen(PEER_COUNT_GRANULARITY, option.noise_epsilon),
+        ),
+        blurred: option.noise_epsilon.is_some(),
+    }
+}
+
+/// With a few nodes, even min and max tell the exact value of a node, so nothing is returned.
+fn percentiles(mut values: Vec<i64>, min_group_size: usize, granularity: i64) -> Option<Percentiles> {
+    if values.is_empty() || values.len() < min_group_size {
+        return None
+    }
+    values.sort();
+    let round = |value: i64| (value + granularity / 2) / granularity * granularity;
+    let nearest_rank = |percentile: usize| round(values[(values.len() * percentile / 100).min(values.len() - 1)]);
+    Some(Percentiles {
+        min: round(values[0]),
+        p50: nearest_rank(50),
+        p90: nearest_rank(90),
+        max: round(values[values.len() - 1]),
+    })
+}
+
+/// The percentiles are exact when the metrics are not blurred
+fn coarsen(granularity: i64, epsilon: Option<f64>) -> i64 {
+    match epsilon {
+        Some(epsilon) if epsilon > 0.0 => granularity,
+        _ => 1,
+    }
+}
+
+/// Adds Laplace noise whose scale is 1 / epsilon, since adding or removing a node changes a count by at most 1.
+fn blur(value: i64, epsilon: Option<f64>) -> i64 {
+    let epsilon = match epsilon {
+        Some(epsilon) if epsilon > 0.0 => epsilon,
+        _ => return value,
+    };
+    let scale = 1.0 / epsilon;
+    let uniform = rand::random::<f64>() - 0.5;
+    let noise = -scale * uniform.signum() * (1.0 - 2.0 * uniform.abs()).ln();
+    (value as f64 + noise).round().max(0.0) as i64
+}
@@ -0,0 +1,117 @@
+use std::io::Read;
+
+use iron;
+use iron::method::Method;
+use iron::mime;
+use iron::prelude::*;
+use iron::status;
+use serde_json;
+use serde_json::Value;
+
+use super::super::frontend;
+use super::super::rate_limit::RateLimiter;
+use super::super::router::{Error as RouterError, Router};
+use super::super::rpc::RPCError;
+use super::error::{bad_request, not_found};
+use super::query_string;
+
+/// Handles `/api/...` requests by translating them into the frontend JSON-RPC methods.
+pub fn handle(
+    router: &Router<frontend::Context>,
+    rate_limiter: &RateLimiter,
+    context: frontend::Context,
+    req: &mut iron::Request,
+    paths: &[&str],
+) -> IronResult<Response> {
+    let (method, params) = route(req, paths)?;
+    ctrace!("REST {} {:?} is routed to {}", req.method, paths, method);
+
+    match router.run_limited(rate_limiter, context, method, params) {
+        Ok(Some(value)) => Ok(json_response(status::Ok, value.to_string())),
+        Ok(None) => Ok(Response::with(status::NoContent)),
+        Err(RouterError::MethodNotFound) => Err(not_found(&format!("{} is not found", method))),
+        Err(RouterError::RPC(err)) => {
+            let status = match err {
+                RPCError::AgentNotFound(_) | RPCError::NotFound(_) => status::NotFound,
+                RPCError::InvalidParams(_) | RPCError::StartRequestRejected(..) => status::BadRequest,
+                RPCError::CodeChainNotRunning(_) => status::Conflict,
+                RPCError::FeatureDisabled(_) | RPCError::PermissionDenied(_) => status::Forbidden,
+                RPCError::CapabilityNotSupported(..) => status::NotImplemented,
+                RPCError::AgentUnreachable(..) => status::BadGateway,
+                RPCError::AgentTimeout(..) => status::GatewayTimeout,
+                RPCError::Cancelled(_) => status::Conflict,
+                RPCError::RateLimited(_) => status::TooManyRequests,
+                RPCError::ApprovalRequired(_) => status::Accepted,
+                _ => status::InternalServerError,
+            };
+            let body = serde_json::to_string(&err.to_jsonrpc_error()).expect("Should success serialize");
+            Ok(json_response(status, body))
+        }
+    }
+}
+
+fn route(req: &mut iron::Request, paths: &[&str]) -> IronResult<(&'static str, Value)> {
+    let method = req.method.clone();
+    let route = match (method, paths) {
+        (Method::Get, ["nodes"]) => ("dashboard_getNetwork", json!([])),
+        (Method::Get, ["nodes", name]) => ("node_getInfo", json!([name])),
+        (Method::Get, ["nodes", name, "events"]) => ("node_getEvents", json!([name, {}])),
+        (Method::Post, ["nodes", name, "start"]) => ("node_start", json!([name, read_json_body(req)?])),
+        (Method::Post, ["nodes", name, "stop"]) => ("node_stop", json!([name])),
+        (Method::Post, ["nodes", name, "update"]) => {
+            let body = read_json_body(req)?;
+            ("node_update", json!([name, body["commitHash"]]))
+        }
+        (Method::Get, ["logs"]) => {
+            let pairs = query_string::parse(req.url.query().unwrap_or_default());
+            ("log_get", json!([log_query_params(pairs)?]))
+        }
+        (Method::Get, ["logs", "targets"]) => ("log_getTargets", json!([])),
+        (Method::Get, ["network", "export"]) => ("network_export", json!([])),
+        (Method::Post, ["network", "apply"]) => ("network_apply", json!([read_json_body(req)?])),
+        (Method::Get, ["audit"]) => ("audit_get", json!([{}])),
+        _ => return Err(not_found("Not Found")),
+    };
+    Ok(route)
+}
+
+fn read_json_body(req: &mut iron::Request) -> IronResult<Value> {
+    let mut body = String::new();
+    req.body.read_to_string(&mut body).map_err(|err| bad_request(&format!("{}", err)))?;
+    serde_json::from_str(&body).map_err(|err| bad_request(&format!("Invalid JSON body : {}", err)))
+}
+
+/// Converts `?nodeNames=a,b&levels=error&search=...&page=1` into LogQueryParams of log_get.
+pub fn log_query_params(pairs: Vec<(String, String)>) -> IronResult<Value> {
+    let mut params = json!({});
+    let mut filter = json!({
+        "nodeNames": [],
+        "levels": [],
+        "targets": [],
+    });
+    let mut time = json!({});
+
+    let list = |value: &str| -> Vec<String> { value.split(',').map(|item| item.to_string()).collect() };
+    let number = |key: &str, value: &str| -> IronResult<i32> {
+        value.parse().map_err(|_| bad_request(&format!("{} should be a number", key)))
+    };
+
+    for (key, value) in pairs {
+        match key.as_str() {
+            "nodeNames" | "levels" | "targets" | "tags" => filter[key.as_str()] = json!(list(&value)),
+            "threadName" => filter["threadName"] = json!(value),
+            "search" | "orderBy" => params[key.as_str()] = json!(value),
+            "page" | "itemPerPage" => params[key.as_str()] = json!(number(&key, &value)?),
+            "fromTime" | "toTime" => time[key.as_str()] = json!(value),
+            _ => return Err(bad_request(&format!("Unknown query parameter {}", key))),
+        }
+    }
+    params["filter"] = filter;
+    params["time"] = time;
+    Ok(params)
+}
+
+pub fn json_response(status: status::Status, body: String) -> Response {
+    let content_type = "application/json".parse::<mime::Mime>().unwrap();
+    Response::with((content_type, status, body))
+}
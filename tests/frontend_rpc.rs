@@ -0,0 +1,377 @@
+#![cfg(feature = "mock-agent")]
+
+extern crate codechain_agent_hub as chub;
+#[macro_use]
+extern crate serde_json;
+
+use std::time::Duration;
+
+use chub::test_support::{wait_until, FrontendClient, MockAgent, MockNode, TestHub};
+use serde_json::Value;
+
+/// The hub updates each agent once a second
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const ERR_AGENT_NOT_FOUND: i64 = -1;
+const ERR_CAPABILITY_NOT_SUPPORTED: i64 = -10;
+const ERR_PERMISSION_DENIED: i64 = -15;
+const ERR_METHOD_NOT_FOUND: i64 = -32601;
+const ERR_INVALID_PARAMS: i64 = -32602;
+
+fn start_hub() -> TestHub {
+    TestHub::start(env!("CARGO_BIN_EXE_codechain-agent-hub"))
+}
+
+fn connect(hub: &TestHub, client: &FrontendClient, node: MockNode) -> MockAgent {
+    let name = node.name.clone();
+    let agent = MockAgent::connect(&hub.agent_url, node);
+    assert!(wait_until(UPDATE_TIMEOUT, || client.call("node_getInfo", json!({ "name": name })).is_ok()));
+    agent
+}
+
+fn node_status(client: &FrontendClient, name: &str) -> Value {
+    client.call_ok("node_getInfo", json!({ "name": name }))["status"].clone()
+}
+
+#[test]
+fn connected_agents_are_on_the_dashboard() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let _first = connect(&hub, &client, MockNode::new("first"));
+    let _second = connect(&hub, &client, MockNode::new("second"));
+
+    let network = client.call_ok("dashboard_getNetwork", json!({}));
+    let mut names: Vec<&str> =
+        network["nodes"].as_array().unwrap().iter().map(|node| node["name"].as_str().unwrap()).collect();
+    names.sort();
+    assert_eq!(names, vec!["first", "second"]);
+}
+
+#[test]
+fn node_start_and_node_stop_run_the_shell_commands() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let agent = connect(&hub, &client, MockNode::new("node"));
+
+    let req = json!({ "env": "RUST_LOG=info", "args": "--port 3485" });
+    client.call_ok("node_start", json!({ "name": "node", "req": req }));
+    assert!(wait_until(UPDATE_TIMEOUT, || {
+        let info = client.call_ok("node_getInfo", json!({ "name": "node" }));
+        info["status"] == json!("Run") && info["startOption"]["args"] == json!("--port 3485")
+    }));
+
+    client.call_ok("node_stop", json!({ "name": "node" }));
+    assert!(wait_until(UPDATE_TIMEOUT, || node_status(&client, "node") == json!("Stop")));
+    assert_eq!(agent.node().command_methods(), vec!["shell_startCodeChain", "shell_stopCodeChain"]);
+}
+
+#[test]
+fn node_update_changes_the_commit() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let agent = connect(&hub, &client, MockNode::new("node"));
+
+    let commit_hash = "1111111111111111111111111111111111111111";
+    client.call_ok("node_update", json!({ "name": "node", "commitHash": commit_hash }));
+    assert_eq!(agent.node().commit_hash, commit_hash);
+    assert!(wait_until(UPDATE_TIMEOUT, || {
+        client.call_ok("node_getInfo", json!({ "name": "node" }))["version"]["hash"] == json!(commit_hash)
+    }));
+}
+
+#[test]
+fn node_update_returns_the_job() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let _agent = connect(&hub, &client, MockNode::new("node"));
+
+    let commit_hash = "2222222222222222222222222222222222222222";
+    let update = client.call_ok("node_update", json!({ "name": "node", "commitHash": commit_hash }));
+    let job = client.call_ok("job_get", json!({ "id": update["jobId"] }));
+    assert_eq!(job["method"], json!("node_update"));
+    assert_eq!(job["nodeName"], json!("node"));
+    // The mock agent doesn't report the progress, so the job is done when the agent accepts the update
+    assert_eq!(job["status"], json!("Done"));
+    assert_eq!(client.call_ok("job_list", json!({ "nodeName": "node" }))[0]["id"], update["jobId"]);
+}
+
+#[test]
+fn the_commands_to_an_unknown_node_fail() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+
+    let error = client.call("node_stop", json!({ "name": "unknown" })).unwrap_err();
+    assert_eq!(error["code"], json!(ERR_AGENT_NOT_FOUND));
+}
+
+#[test]
+fn pushed_logs_are_searchable() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let agent = connect(&hub, &client, MockNode::new("node"));
+
+    agent.emit_log("ERROR", "sync", "Block import failed");
+    let req = json!({
+        "filter": {
+            "nodeNames": ["node"],
+            "levels": ["error"],
+            "targets": [],
+        },
+        "search": "import",
+    });
+    assert!(wait_until(UPDATE_TIMEOUT, || {
+        let response = client.call_ok("log_get", json!({ "req": req }));
+        // The messages are prefixed with the thread name
+        response["logs"].as_array().unwrap().iter().any(|log| log["message"] == json!("mock Block import failed"))
+    }));
+}
+
+#[test]
+fn log_context_has_the_logs_around_the_log() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let agent = connect(&hub, &client, MockNode::new("node"));
+
+    agent.emit_log("INFO", "sync", "Importing a block");
+    agent.emit_log("ERROR", "sync", "Block import failed");
+    agent.emit_log("INFO", "sync", "Retrying the import");
+    let req = json!({
+        "filter": {
+            "nodeNames": ["node"],
+            "levels": ["error"],
+            "targets": [],
+        },
+    });
+    let mut log_id = None;
+    assert!(wait_until(UPDATE_TIMEOUT, || {
+        let response = client.call_ok("log_get", json!({ "req": req }));
+        log_id = response["logs"].as_array().unwrap().first().map(|log| log["id"].clone());
+        log_id.is_some()
+    }));
+
+    let log_context = client.call_ok("log_getContext", json!({ "logId": log_id, "before": 1, "after": 1 }));
+    assert_eq!(log_context["log"]["message"], json!("mock Block import failed"));
+    assert_eq!(log_context["before"][0]["message"], json!("mock Importing a block"));
+    assert_eq!(log_context["after"][0]["message"], json!("mock Retrying the import"));
+}
+
+#[test]
+fn sessions_have_the_frontends_and_the_agents() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let _agent = connect(&hub, &client, MockNode::new("node"));
+
+    assert!(wait_until(UPDATE_TIMEOUT, || {
+        let sessions = client.call_ok("hub_listSessions", json!({}));
+        let sessions = sessions.as_array().unwrap();
+        let has_frontend = sessions.iter().any(|session| session["role"] == json!("frontend"));
+        let has_agent =
+            sessions.iter().any(|session| session["role"] == json!("agent") && session["identity"] == json!("node"));
+        has_frontend && has_agent
+    }));
+}
+
+#[test]
+fn websockets_authenticate_with_the_first_message() {
+    let config = "[[frontend.api_tokens]]\nname = \"operator\"\ntoken = \"secret\"\n";
+    let hub = TestHub::start_with_config(env!("CARGO_BIN_EXE_codechain-agent-hub"), config);
+
+    let error = FrontendClient::new(&hub.frontend_url).call("ping", json!({})).unwrap_err();
+    assert_eq!(error["code"], json!(ERR_PERMISSION_DENIED));
+    let error = FrontendClient::with_token(&hub.frontend_url, "wrong").call("ping", json!({})).unwrap_err();
+    assert_eq!(error["code"], json!(ERR_PERMISSION_DENIED));
+    let client = FrontendClient::with_token(&hub.frontend_url, "secret");
+    assert_eq!(client.call_ok("ping", json!({})), json!("pong"));
+}
+
+#[test]
+fn polled_logs_of_old_agents_are_searchable() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let mut node = MockNode::new("old");
+    node.protocol_version = 2;
+    node.status = "Run".to_string();
+    let agent = connect(&hub, &client, node);
+
+    agent.emit_log("WARN", "net", "Peer is slow");
+    let req = json!({
+        "filter": {
+            "nodeNames": ["old"],
+            "levels": [],
+            "targets": ["net"],
+        },
+    });
+    assert!(wait_until(UPDATE_TIMEOUT, || {
+        let response = client.call_ok("log_get", json!({ "req": req }));
+        !response["logs"].as_array().unwrap().is_empty()
+    }));
+}
+
+#[test]
+fn node_bulk_edit_changes_nothing_in_preview() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let _first = connect(&hub, &client, MockNode::new("first"));
+    let _second = connect(&hub, &client, MockNode::new("second"));
+    client.call_ok("node_addTags", json!({ "name": "first", "tags": ["region:eu"] }));
+
+    let params = json!({
+        "selector": { "names": ["first", "second"] },
+        "patch": { "region": "us-east", "addTags": ["validator"] },
+        "preview": true,
+    });
+    // node_addTags is written after the response
+    assert!(wait_until(UPDATE_TIMEOUT, || {
+        client.call_ok("node_getInfo", json!({ "name": "first" }))["tags"] == json!(["region:eu"])
+    }));
+    let preview = client.call_ok("node_bulkEdit", params.clone());
+    assert_eq!(preview["nodes"][0]["tagsAfter"], json!(["region:us-east", "validator"]));
+    assert_eq!(client.call_ok("node_getInfo", json!({ "name": "first" }))["tags"], json!(["region:eu"]));
+
+    let mut params = params;
+    params["preview"] = json!(false);
+    client.call_ok("node_bulkEdit", params);
+    for name in &["first", "second"] {
+        let tags = client.call_ok("node_getInfo", json!({ "name": name }))["tags"].clone();
+        assert_eq!(tags, json!(["region:us-east", "validator"]));
+    }
+}
+
+#[test]
+fn network_export_has_the_start_options() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let _agent = connect(&hub, &client, MockNode::new("node"));
+    let req = json!({ "env": "", "args": "--no-discovery" });
+    client.call_ok("node_start", json!({ "name": "node", "req": req }));
+
+    // The start option is saved after the response
+    assert!(wait_until(UPDATE_TIMEOUT, || {
+        let scenario = client.call_ok("network_export", json!({}));
+        scenario["nodes"][0]["name"] == json!("node") && scenario["nodes"][0]["args"] == json!("--no-discovery")
+    }));
+}
+
+#[test]
+fn the_requests_which_need_a_missing_capability_fail() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let mut node = MockNode::new("node");
+    node.capabilities = Vec::new();
+    let _agent = connect(&hub, &client, node);
+
+    let params = json!({ "kind": "verifySnapshot", "cron": "0 0 * * * *", "node": "node" });
+    let error = client.call("schedule_create", params).unwrap_err();
+    assert_eq!(error["code"], json!(ERR_CAPABILITY_NOT_SUPPORTED));
+}
+
+#[test]
+fn every_method_is_described_and_answered() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let _agent = connect(&hub, &client, MockNode::new("node"));
+
+    let methods = client.call_ok("rpc_methods", json!({}));
+    let methods = methods.as_array().unwrap();
+    assert!(!methods.is_empty());
+    for method in methods {
+        let description = client.call_ok("rpc_describe", json!({ "method": method }));
+        assert_eq!(&description["method"], method);
+        // The methods with the required params fail without them, and the others run. Both should be answered.
+        if let Err(error) = client.call(method.as_str().unwrap(), json!({})) {
+            assert_ne!(error["code"], json!(ERR_METHOD_NOT_FOUND), "{} is listed but not found", method);
+        }
+    }
+    assert_eq!(client.call_ok("ping", json!({})), json!("pong"));
+}
+
+#[test]
+fn the_struct_params_are_described_with_their_fields() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+
+    let description = client.call_ok("rpc_describe", json!({ "method": "log_get" }));
+    let schema = &description["params"][0]["schema"];
+    assert!(schema["properties"]["itemPerPage"].is_object());
+    assert!(schema["definitions"]["LogFilter"]["properties"]["nodeNames"].is_object());
+    assert!(description["resultSchema"]["definitions"]["Log"]["properties"]["backfilled"].is_object());
+
+    let description = client.call_ok("rpc_describe", json!({ "method": "node_start" }));
+    assert_eq!(description["params"].as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn the_pages_start_from_one() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+
+    let filter = json!({ "nodeNames": [], "levels": [], "targets": [] });
+    let error = client.call("log_get", json!({ "req": { "filter": filter, "page": 0 } })).unwrap_err();
+    assert_eq!(error["code"], json!(ERR_INVALID_PARAMS));
+    let error = client.call("audit_get", json!({ "req": { "page": -1 } })).unwrap_err();
+    assert_eq!(error["code"], json!(ERR_INVALID_PARAMS));
+
+    // The offset of the last page doesn't overflow, and the item per page is clamped
+    let req = json!({ "filter": filter, "page": i32::max_value(), "itemPerPage": i32::max_value() });
+    assert_eq!(client.call_ok("log_get", json!({ "req": req }))["logs"], json!([]));
+}
+
+#[test]
+fn disconnected_agents_are_reported() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let agent = connect(&hub, &client, MockNode::new("node"));
+
+    agent.disconnect();
+    assert!(wait_until(UPDATE_TIMEOUT, || node_status(&client, "node") == json!("Error")));
+}
+
+#[test]
+fn nodes_are_found_by_the_node_keys() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let mut node = MockNode::new("validator");
+    node.status = "Run".to_string();
+    node.coinbase = Some("tccq9h7vnl68frvqapzv3tujrxtxtwqdnxw6yamrrgd".to_string());
+    let _validator = connect(&hub, &client, node);
+    let _other = connect(&hub, &client, MockNode::new("other"));
+
+    let found = client.call_ok("node_find", json!({ "query": "TCCQ9H7VNL68FRVQAPZV3TUJRXTXTWQDNXW6YAMRRGD" }));
+    assert_eq!(found.as_array().unwrap().len(), 1);
+    assert_eq!(found[0]["name"], json!("validator"));
+    assert_eq!(found[0]["matchedBy"], json!("nodeKey"));
+}
+
+#[test]
+fn the_bootnodes_are_the_running_nodes_of_the_same_namespace() {
+    let hub = start_hub();
+    let client = FrontendClient::new(&hub.frontend_url);
+    let running_node = |name: &str, address: &str| {
+        let mut node = MockNode::new(name);
+        node.status = "Run".to_string();
+        node.address = Some(address.parse().unwrap());
+        node
+    };
+    let mut starting = MockNode::new("starting");
+    starting.address = Some("10.0.0.1:3485".parse().unwrap());
+    let agent = connect(&hub, &client, starting);
+    let _same = connect(&hub, &client, running_node("same", "10.0.0.2:3485"));
+    let _other = connect(&hub, &client, running_node("other", "10.0.0.3:3485"));
+    let _stopped = connect(&hub, &client, MockNode::new("stopped"));
+    for name in &["starting", "same", "stopped"] {
+        client.call_ok("node_addTags", json!({ "name": name, "tags": ["namespace:team-a"] }));
+        assert!(wait_until(UPDATE_TIMEOUT, || {
+            client.call_ok("node_getInfo", json!({ "name": name }))["tags"] == json!(["namespace:team-a"])
+        }));
+    }
+    assert!(wait_until(UPDATE_TIMEOUT, || {
+        node_status(&client, "same") == json!("Run") && node_status(&client, "other") == json!("Run")
+    }));
+
+    let req = json!({ "env": "", "args": "--bootstrap-addresses {{network.bootnodes}}" });
+    client.call_ok("node_start", json!({ "name": "starting", "req": req }));
+    assert!(wait_until(UPDATE_TIMEOUT, || agent.node().command_methods().contains(&"shell_startCodeChain")));
+    let node = agent.node();
+    let (_, params) = node.commands.iter().find(|(method, _)| method == "shell_startCodeChain").unwrap();
+    assert_eq!(params["args"], json!("--bootstrap-addresses 10.0.0.2:3485"));
+}
@@ -0,0 +1,216 @@
+#![cfg(feature = "mock-agent")]
+
+extern crate codechain_agent_hub as chub;
+#[macro_use]
+extern crate serde_json;
+
+use std::time::Duration;
+
+use chub::test_support::{wait_until, FakeHttpServer, FrontendClient, MockAgent, MockNode, TestHub};
+use serde_json::Value;
+
+/// The hub updates each agent once a second
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The largest id of the hashed log storages, which a JavaScript number holds exactly
+const MAX_LOG_ID: u64 = (1 << 53) - 1;
+
+fn start_hub_with(log_storage: &str) -> TestHub {
+    TestHub::start_with_config(env!("CARGO_BIN_EXE_codechain-agent-hub"), log_storage)
+}
+
+fn clickhouse_config(server: &FakeHttpServer) -> String {
+    format!("[log_storage]\ntype = \"clickHouse\"\nurl = \"{}\"\ntable = \"logs\"\n", server.url)
+}
+
+fn elasticsearch_config(server: &FakeHttpServer) -> String {
+    format!("[log_storage]\ntype = \"elasticsearch\"\nurl = \"{}\"\nindex = \"logs\"\n", server.url)
+}
+
+fn connect(hub: &TestHub, client: &FrontendClient, node: MockNode) -> MockAgent {
+    let name = node.name.clone();
+    let agent = MockAgent::connect(&hub.agent_url, node);
+    assert!(wait_until(UPDATE_TIMEOUT, || client.call("node_getInfo", json!({ "name": name })).is_ok()));
+    agent
+}
+
+/// A row of the log which ClickHouse returns for every SELECT
+fn clickhouse_row(message: &str) -> String {
+    json!({
+        "id": MAX_LOG_ID,
+        "name": "node",
+        "level": "ERROR",
+        "target": "sync",
+        "message": message,
+        "thread_name": "mock",
+        "timestamp_micros": 1_600_000_000_000_000i64,
+        "backfilled": false,
+    })
+    .to_string()
+}
+
+fn elasticsearch_document(name: &str) -> Value {
+    json!({
+        "name": name,
+        "level": "ERROR",
+        "target": "sync",
+        "message": "Block import failed",
+        "thread_name": "mock",
+        "timestamp": "2020-09-13T21:26:40.000001+09:00",
+        "backfilled": false,
+        "hash": "00000000000000ff",
+        "log_id": 255,
+    })
+}
+
+#[test]
+fn clickhouse_receives_the_pushed_logs() {
+    let server = FakeHttpServer::start(|_| String::new());
+    let hub = start_hub_with(&clickhouse_config(&server));
+    let client = FrontendClient::new(&hub.frontend_url);
+    let agent = connect(&hub, &client, MockNode::new("node"));
+
+    agent.emit_log("ERROR", "sync", "Block import failed");
+    assert!(wait_until(UPDATE_TIMEOUT, || {
+        server
+            .bodies("/")
+            .iter()
+            .any(|body| body.starts_with("INSERT INTO logs FORMAT JSONEachRow") && body.contains("Block import failed"))
+    }));
+    let bodies = server.bodies("/");
+    assert!(bodies.iter().any(|body| body.starts_with("CREATE TABLE IF NOT EXISTS logs")));
+    assert!(bodies.iter().any(|body| body.contains("ADD COLUMN IF NOT EXISTS id UInt64 MATERIALIZED")));
+}
+
+#[test]
+fn clickhouse_logs_have_the_ids_of_the_table() {
+    let server = FakeHttpServer::start(|request| {
+        if request.body.starts_with("SELECT id") {
+            clickhouse_row("Block import failed")
+        } else {
+            String::new()
+        }
+    });
+    let hub = start_hub_with(&clickhouse_config(&server));
+    let client = FrontendClient::new(&hub.frontend_url);
+    let _agent = connect(&hub, &client, MockNode::new("node"));
+
+    let req = json!({ "filter": { "nodeNames": ["node"], "levels": [], "targets": [] } });
+    let response = client.call_ok("log_get", json!({ "req": req }));
+    assert_eq!(response["logs"][0]["id"], json!(MAX_LOG_ID));
+    assert_eq!(response["logs"][0]["message"], json!("mock Block import failed"));
+}
+
+#[test]
+fn clickhouse_log_context_has_the_logs_of_the_same_node() {
+    let server = FakeHttpServer::start(|request| {
+        if request.body.contains(&format!("WHERE id = {}", MAX_LOG_ID)) {
+            clickhouse_row("Block import failed")
+        } else if request.body.contains(") < (") {
+            clickhouse_row("Importing a block")
+        } else if request.body.contains(") > (") {
+            clickhouse_row("Retrying the import")
+        } else {
+            String::new()
+        }
+    });
+    let hub = start_hub_with(&clickhouse_config(&server));
+    let client = FrontendClient::new(&hub.frontend_url);
+    let _agent = connect(&hub, &client, MockNode::new("node"));
+
+    let log_context = client.call_ok("log_getContext", json!({ "logId": MAX_LOG_ID, "before": 1, "after": 1 }));
+    assert_eq!(log_context["log"]["message"], json!("mock Block import failed"));
+    assert_eq!(log_context["before"][0]["message"], json!("mock Importing a block"));
+    assert_eq!(log_context["after"][0]["message"], json!("mock Retrying the import"));
+    let around: Vec<String> = server.bodies("/").into_iter().filter(|body| body.contains("name = 'node'")).collect();
+    assert_eq!(around.len(), 2);
+    assert!(around.iter().all(|body| body.contains("LIMIT 1 ")));
+}
+
+#[test]
+fn elasticsearch_documents_are_identified_by_the_hashes() {
+    let server = FakeHttpServer::start(|request| {
+        if request.path.starts_with("/_bulk") {
+            json!({ "errors": false, "items": [] }).to_string()
+        } else {
+            json!({ "hits": { "hits": [] } }).to_string()
+        }
+    });
+    let hub = start_hub_with(&elasticsearch_config(&server));
+    let client = FrontendClient::new(&hub.frontend_url);
+    let agent = connect(&hub, &client, MockNode::new("node"));
+
+    agent.emit_log("ERROR", "sync", "Block import failed");
+    let mut bulk = None;
+    assert!(wait_until(UPDATE_TIMEOUT, || {
+        bulk = server.bodies("/_bulk").into_iter().find(|body| body.contains("Block import failed"));
+        bulk.is_some()
+    }));
+    let lines: Vec<Value> = bulk.unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let id = lines[0]["create"]["_id"].as_str().unwrap();
+    assert_eq!(lines[1]["hash"], json!(id));
+    assert_eq!(lines[1]["log_id"], json!(u64::from_str_radix(id, 16).unwrap() & MAX_LOG_ID));
+}
+
+#[test]
+fn elasticsearch_deep_pages_are_reached_with_search_after() {
+    let server = FakeHttpServer::start(|request| {
+        let query: Value = serde_json::from_str(&request.body).unwrap_or_default();
+        let hits: Vec<Value> = if query["_source"] == json!(false) {
+            (0..query["size"].as_u64().unwrap_or(0)).map(|index| json!({ "_id": index, "sort": [index] })).collect()
+        } else {
+            vec![json!({ "_source": elasticsearch_document("node"), "sort": [0] })]
+        };
+        json!({ "hits": { "hits": hits } }).to_string()
+    });
+    let hub = start_hub_with(&elasticsearch_config(&server));
+    let client = FrontendClient::new(&hub.frontend_url);
+    let _agent = connect(&hub, &client, MockNode::new("node"));
+
+    let req = json!({
+        "filter": { "nodeNames": ["node"], "levels": [], "targets": [] },
+        "page": 201,
+        "itemPerPage": 100,
+    });
+    let response = client.call_ok("log_get", json!({ "req": req }));
+    assert_eq!(response["logs"][0]["id"], json!(255));
+
+    let searches: Vec<Value> =
+        server.bodies("/logs/_search").iter().map(|body| serde_json::from_str(body).unwrap()).collect();
+    assert!(searches
+        .iter()
+        .all(|search| search["from"].as_u64().unwrap_or(0) + search["size"].as_u64().unwrap_or(0) <= 10000));
+    let last = searches.last().unwrap();
+    assert_eq!(last["from"], json!(0));
+    assert_eq!(last["size"], json!(100));
+    assert_eq!(last["search_after"], json!([9999]));
+}
+
+#[test]
+fn elasticsearch_renamed_logs_are_indexed_with_the_new_hashes() {
+    let server = FakeHttpServer::start(|request| {
+        if request.path.starts_with("/_bulk") {
+            return json!({ "errors": false, "items": [] }).to_string();
+        }
+        let query: Value = serde_json::from_str(&request.body).unwrap_or_default();
+        let hits = if query["query"]["term"]["name"] == json!("node") && query["search_after"].is_null() {
+            vec![json!({ "_id": "00000000000000ff", "_source": elasticsearch_document("node"), "sort": ["ff"] })]
+        } else {
+            Vec::new()
+        };
+        json!({ "hits": { "hits": hits } }).to_string()
+    });
+    let hub = start_hub_with(&elasticsearch_config(&server));
+    let client = FrontendClient::new(&hub.frontend_url);
+    let _agent = connect(&hub, &client, MockNode::new("node"));
+
+    client.call_ok("node_rename", json!({ "name": "node", "newName": "renamed" }));
+    assert!(server.bodies("/logs/_update_by_query").is_empty());
+    let bulk = server.bodies("/_bulk?refresh=true").pop().expect("The logs should be moved");
+    let lines: Vec<Value> = bulk.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let id = lines[0]["index"]["_id"].as_str().unwrap();
+    assert_ne!(id, "00000000000000ff");
+    assert_eq!(lines[1]["name"], json!("renamed"));
+    assert_eq!(lines[1]["hash"], json!(id));
+    assert_eq!(lines[2]["delete"]["_id"], json!("00000000000000ff"));
+}